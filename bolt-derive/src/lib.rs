@@ -76,8 +76,17 @@ pub fn derive_read_message(input: TokenStream) -> TokenStream {
         return error.into_compile_error().into();
     }
 
+    let names = vs.iter().map(|v| v.to_string());
     let output = quote! {
         impl #ident {
+            /// The name of the message variant
+            pub fn message_name(&self) -> &'static str {
+                match self {
+                    #(Message::#vs(_) => #names),*,
+                    Message::Unknown(_) => "Unknown",
+                }
+            }
+
             fn read_message(mut data: &mut Vec<u8>, message_type: u16) -> Result<Message> {
                 let message = match message_type {
                     #(#vs::TYPE => Message::#ts(from_vec_no_trailing(&mut data)?)),*,
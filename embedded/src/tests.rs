@@ -92,6 +92,7 @@ pub fn make_test_channel_setup(
         counterparty_selected_contest_delay: 6,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::StaticRemoteKey,
+        holder_funding_sat: None,
     }
 }
 
@@ -236,7 +237,15 @@ fn sign_funding(node: &Arc<Node>) {
     let uniclosekeys = vec![None, None];
 
     let witvec = node
-        .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+        .sign_onchain_tx(
+            &tx,
+            &ipaths,
+            &values_sat,
+            &spendtypes,
+            uniclosekeys,
+            &vec![opath],
+            &vec![],
+        )
         .expect("good sigs");
     assert_eq!(witvec.len(), 2);
 }
@@ -1,4 +1,5 @@
 use core::any::Any;
+use core::cmp;
 use core::fmt;
 use core::fmt::{Debug, Error, Formatter};
 
@@ -23,11 +24,13 @@ use log::{debug, trace, warn};
 use crate::monitor::ChainMonitor;
 use crate::node::Node;
 use crate::policy::error::policy_error;
-use crate::policy::validator::{ChainState, EnforcementState, Validator};
+use crate::policy::validator::{
+    ChainState, ClaimableBalances, EnforcementState, SpliceInfo, SpliceTxInfo, Validator,
+};
 use crate::prelude::*;
 use crate::tx::tx::{
     build_commitment_tx, get_commitment_transaction_number_obscure_factor, CommitmentInfo2,
-    HTLCInfo2,
+    HTLCInfo2, PreimageMap,
 };
 use crate::util::crypto_utils::{
     derive_private_revocation_key, derive_public_key, derive_revocation_pubkey,
@@ -101,6 +104,9 @@ impl TypedSignature {
 }
 
 /// The commitment type, based on the negotiated option
+///
+/// Simple taproot channels are not supported yet.
+// TODO simple taproot channels are deferred, see TODO.md
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CommitmentType {
     /// No longer used - dynamic to-remote key
@@ -109,6 +115,8 @@ pub enum CommitmentType {
     StaticRemoteKey,
     /// Anchors
     Anchors,
+    /// Anchors with zero-fee second-stage HTLC transactions
+    AnchorsZeroFeeHtlc,
 }
 
 /// The negotiated parameters for the [Channel]
@@ -136,6 +144,9 @@ pub struct ChannelSetup {
     pub counterparty_shutdown_script: Option<Script>,
     /// The negotiated commitment type
     pub commitment_type: CommitmentType,
+    /// Our contribution to the funding output if the channel is
+    /// dual-funded, or `None` if only the opener funds the channel
+    pub holder_funding_sat: Option<u64>,
 }
 
 // Need to define manually because ChannelPublicKeys doesn't derive Debug.
@@ -152,6 +163,7 @@ impl fmt::Debug for ChannelSetup {
             .field("counterparty_selected_contest_delay", &self.counterparty_selected_contest_delay)
             .field("counterparty_shutdown_script", &self.counterparty_shutdown_script)
             .field("commitment_type", &self.commitment_type)
+            .field("holder_funding_sat", &self.holder_funding_sat)
             .finish()
     }
 }
@@ -164,6 +176,81 @@ impl ChannelSetup {
     /// True if this channel uses anchors.
     pub fn option_anchor_outputs(&self) -> bool {
         self.commitment_type == CommitmentType::Anchors
+            || self.commitment_type == CommitmentType::AnchorsZeroFeeHtlc
+    }
+
+    /// True if the second-stage HTLC transactions pay no fee.  The fee is
+    /// attached by adding inputs when they are broadcast, which is why the
+    /// counterparty signatures use SIGHASH_SINGLE|SIGHASH_ANYONECANPAY.
+    pub fn option_anchors_zero_fee_htlc(&self) -> bool {
+        self.commitment_type == CommitmentType::AnchorsZeroFeeHtlc
+    }
+
+    /// The feerate for second-stage HTLC transactions, given the
+    /// commitment feerate
+    pub(crate) fn htlc_feerate_per_kw(&self, feerate_per_kw: u32) -> u32 {
+        if self.option_anchors_zero_fee_htlc() {
+            0
+        } else {
+            feerate_per_kw
+        }
+    }
+
+    /// The longer of the two `to_self_delay`s, after which both sides can
+    /// sweep their delayed outputs
+    pub(crate) fn max_to_self_delay(&self) -> u16 {
+        cmp::max(self.holder_selected_contest_delay, self.counterparty_selected_contest_delay)
+    }
+
+    /// True if both sides may contribute to the funding output
+    pub fn is_dual_funded(&self) -> bool {
+        self.holder_funding_sat.is_some()
+    }
+
+    /// Our contribution to the funding output.  In a single-funded channel
+    /// the opener contributes the whole channel value.
+    pub fn holder_contribution_sat(&self) -> u64 {
+        match self.holder_funding_sat {
+            Some(holder_funding_sat) => holder_funding_sat,
+            None if self.is_outbound => self.channel_value_sat,
+            None => 0,
+        }
+    }
+
+    /// The counterparty's contribution to the funding output
+    pub fn counterparty_contribution_sat(&self) -> Option<u64> {
+        self.channel_value_sat.checked_sub(self.holder_contribution_sat())
+    }
+
+    /// Our balance before the first update, in millisatoshi.  This is our
+    /// contribution, less `push_value_msat` if we opened the channel or
+    /// plus `push_value_msat` if the counterparty did.  The push must be
+    /// covered by the opener's contribution.
+    pub fn initial_holder_value_msat(&self) -> Result<u64, String> {
+        let holder_msat = self.holder_contribution_sat() * 1000;
+        let counterparty_msat = self.counterparty_contribution_sat().ok_or_else(|| {
+            format!(
+                "holder funding {} exceeds channel value {}",
+                self.holder_contribution_sat(),
+                self.channel_value_sat
+            )
+        })? * 1000;
+        if self.is_outbound {
+            holder_msat.checked_sub(self.push_value_msat).ok_or_else(|| {
+                format!(
+                    "beneficial channel value underflow: {} - {}",
+                    holder_msat, self.push_value_msat
+                )
+            })
+        } else {
+            if self.push_value_msat > counterparty_msat {
+                return Err(format!(
+                    "push_value_msat {} exceeds counterparty funding {}",
+                    self.push_value_msat, counterparty_msat
+                ));
+            }
+            Ok(holder_msat + self.push_value_msat)
+        }
     }
 }
 
@@ -229,6 +316,8 @@ pub struct ChannelStub {
     // Incomplete, channel_value_sat is placeholder.
     /// The initial channel ID, used to find the channel in the node
     pub id0: ChannelId,
+    /// The chain height when the stub was created, used to expire stale stubs
+    pub blockheight: u32,
 }
 
 // Need to define manually because InMemorySigner doesn't derive Debug.
@@ -237,6 +326,7 @@ impl fmt::Debug for ChannelStub {
         f.debug_struct("ChannelStub")
             .field("keys", &DebugInMemorySigner(&self.keys))
             .field("id0", &self.id0)
+            .field("blockheight", &self.blockheight)
             .finish()
     }
 }
@@ -305,6 +395,22 @@ impl ChannelStub {
     }
 }
 
+/// Our balance in a channel, in satoshi
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelBalance {
+    /// Our claimable balance, see [`ClaimableBalances::claimable`]
+    pub claimable_sat: u64,
+    /// HTLCs we offered and that were not fulfilled yet.  These are
+    /// included in the claimable balance, since they may still fail back to us.
+    pub offered_htlc_sat: u64,
+    /// HTLCs offered to us for which we don't know the preimage yet.
+    /// These are not included in the claimable balance.
+    pub received_htlc_sat: u64,
+    /// Whether the channel was closed on-chain, in which case the claimable
+    /// balance is pending a sweep to the wallet
+    pub is_closed: bool,
+}
+
 /// After [Node::ready_channel]
 #[derive(Clone)]
 pub struct Channel {
@@ -529,6 +635,51 @@ impl Channel {
         ))
     }
 
+    // LDK signs second-stage HTLC txs with the commitment feerate.  For
+    // zero-fee-HTLC anchor channels, sign the zero-fee variants instead.
+    fn sign_zero_fee_htlc_txs(
+        &self,
+        commitment_tx: &CommitmentTransaction,
+        is_counterparty: bool,
+    ) -> Result<Vec<Signature>, Status> {
+        let trusted_tx = commitment_tx.trust();
+        let keys = trusted_tx.keys();
+        let commitment_txid = trusted_tx.txid();
+        let (to_self_delay, sighash_type) = if is_counterparty {
+            (self.setup.holder_selected_contest_delay, EcdsaSighashType::SinglePlusAnyoneCanPay)
+        } else {
+            (self.setup.counterparty_selected_contest_delay, EcdsaSighashType::All)
+        };
+        let htlc_key = derive_private_key(
+            &self.secp_ctx,
+            &keys.per_commitment_point,
+            &self.keys.htlc_base_key,
+        )
+        .map_err(|err| internal_error(format!("derive_private_key failed: {}", err)))?;
+
+        let mut sigs = Vec::new();
+        for htlc in commitment_tx.htlcs() {
+            let htlc_tx = build_htlc_transaction(
+                &commitment_txid,
+                0,
+                to_self_delay,
+                htlc,
+                true,
+                &keys.broadcaster_delayed_payment_key,
+                &keys.revocation_key,
+            );
+            let redeemscript = get_htlc_redeemscript(htlc, true, keys);
+            let sighash = Message::from_slice(
+                &SighashCache::new(&htlc_tx)
+                    .segwit_signature_hash(0, &redeemscript, htlc.amount_msat / 1000, sighash_type)
+                    .unwrap()[..],
+            )
+            .map_err(|err| internal_error(format!("sighash failed: {}", err)))?;
+            sigs.push(self.secp_ctx.sign_ecdsa(&sighash, &htlc_key));
+        }
+        Ok(sigs)
+    }
+
     /// Sign a counterparty commitment transaction after rebuilding it
     /// from the supplied arguments.
     // TODO anchors support once LDK supports it
@@ -563,14 +714,16 @@ impl Channel {
         let incoming_payment_summary =
             self.enforcement_state.incoming_payments_summary(None, Some(&info2));
 
-        validator.validate_counterparty_commitment_tx(
-            &self.enforcement_state,
-            commitment_number,
-            &remote_per_commitment_point,
-            &self.setup,
-            &self.get_chain_state(),
-            &info2,
-        )?;
+        validator
+            .validate_counterparty_commitment_tx(
+                &self.enforcement_state,
+                commitment_number,
+                &remote_per_commitment_point,
+                &self.setup,
+                &self.get_chain_state(),
+                &info2,
+            )
+            .map_err(|ve| ve.with_commitment_number(commitment_number))?;
 
         let htlcs = Self::htlcs_info2_to_oic(offered_htlcs, received_htlcs);
 
@@ -583,12 +736,16 @@ impl Channel {
             htlcs,
         );
 
-        let (sig, htlc_sigs) = self
+        let (sig, mut htlc_sigs) = self
             .keys
             .sign_counterparty_commitment(&commitment_tx, Vec::new(), &self.secp_ctx)
             .map_err(|_| internal_error("failed to sign"))?;
+        if self.setup.option_anchors_zero_fee_htlc() {
+            htlc_sigs = self.sign_zero_fee_htlc_txs(&commitment_tx, true)?;
+        }
 
         let outgoing_payment_summary = self.enforcement_state.payments_summary(None, Some(&info2));
+        state.drain_excess_amount(self.get_chain_state().current_height, &*validator);
         state.validate_payments(
             &self.id0,
             &incoming_payment_summary,
@@ -723,7 +880,7 @@ impl Channel {
 
             let recomposed_htlc_tx = build_htlc_transaction(
                 &commitment_txid,
-                feerate_per_kw,
+                self.setup.htlc_feerate_per_kw(feerate_per_kw),
                 to_self_delay,
                 htlc,
                 self.setup.option_anchor_outputs(),
@@ -825,7 +982,7 @@ impl Channel {
                     &self.get_chain_state(),
                     &info2,
                 );
-                ve
+                ve.with_commitment_number(commitment_number)
             })?;
 
         let htlcs =
@@ -851,6 +1008,7 @@ impl Channel {
         )?;
 
         let outgoing_payment_summary = self.enforcement_state.payments_summary(Some(&info2), None);
+        state.drain_excess_amount(self.get_chain_state().current_height, &*validator);
         state.validate_payments(
             &self.id0,
             &incoming_payment_summary,
@@ -915,10 +1073,13 @@ impl Channel {
         );
 
         // Sign the recomposed commitment.
-        let (sig, htlc_sigs) = self
+        let (sig, mut htlc_sigs) = self
             .keys
             .sign_holder_commitment_and_htlcs(&recomposed_holder_tx, &self.secp_ctx)
             .map_err(|_| internal_error("failed to sign"))?;
+        if self.setup.option_anchors_zero_fee_htlc() {
+            htlc_sigs = self.sign_zero_fee_htlc_txs(&recomposed_holder_tx, false)?;
+        }
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
@@ -951,14 +1112,16 @@ impl Channel {
             feerate_per_kw,
         )?;
 
-        self.validator().validate_holder_commitment_tx(
-            &self.enforcement_state,
-            commitment_number,
-            &per_commitment_point,
-            &self.setup,
-            &self.get_chain_state(),
-            &info2,
-        )?;
+        self.validator()
+            .validate_holder_commitment_tx(
+                &self.enforcement_state,
+                commitment_number,
+                &per_commitment_point,
+                &self.setup,
+                &self.get_chain_state(),
+                &info2,
+            )
+            .map_err(|ve| ve.with_commitment_number(commitment_number))?;
 
         let htlcs = Self::htlcs_info2_to_oic(offered_htlcs, received_htlcs);
 
@@ -988,10 +1151,13 @@ impl Channel {
             &self.keys.counterparty_pubkeys().funding_pubkey,
         );
 
-        let (sig, htlc_sigs) = self
+        let (sig, mut htlc_sigs) = self
             .keys
             .sign_holder_commitment_and_htlcs(&holder_commitment_tx, &self.secp_ctx)
             .map_err(|_| internal_error("failed to sign"))?;
+        if self.setup.option_anchors_zero_fee_htlc() {
+            htlc_sigs = self.sign_zero_fee_htlc_txs(&holder_commitment_tx, false)?;
+        }
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
@@ -1322,6 +1488,110 @@ impl Channel {
 
         // self.monitor.add_funding(tx, vout);
     }
+
+    /// Move the channel to the new funding output of a validated splice.
+    ///
+    /// `keys` must be derived for the new channel value.  The enforcement
+    /// state carries over, with a note of the splice so that the balances of
+    /// the commitments from before the splice can be adjusted.
+    pub(crate) fn apply_splice(&mut self, mut keys: InMemorySigner, info: &SpliceTxInfo) {
+        let estate = &self.enforcement_state;
+        let splice = SpliceInfo {
+            prior_funding_outpoint: self.setup.funding_outpoint,
+            prior_channel_value_sat: self.setup.channel_value_sat,
+            holder_delta_sat: info.holder_delta_sat,
+            next_holder_commit_num: estate.next_holder_commit_num,
+            next_counterparty_commit_num: estate.next_counterparty_commit_num,
+            holder_input_values: info.holder_input_values.clone(),
+        };
+        self.setup.funding_outpoint = info.funding_outpoint;
+        self.setup.channel_value_sat = info.channel_value_sat;
+        let channel_transaction_parameters =
+            Node::channel_setup_to_channel_transaction_parameters(&self.setup, keys.pubkeys());
+        keys.ready_channel(&channel_transaction_parameters);
+        self.keys = keys;
+        self.enforcement_state.splice = Some(splice);
+    }
+
+    /// Move the channel back to the funding output from before the last
+    /// splice.
+    ///
+    /// `keys` must be derived for the prior channel value.  No commitment
+    /// spending the new funding output may have been signed or validated.
+    pub(crate) fn abort_splice(&mut self, mut keys: InMemorySigner) -> Result<(), Status> {
+        let estate = &self.enforcement_state;
+        let splice = estate
+            .splice
+            .as_ref()
+            .ok_or_else(|| invalid_argument(format!("channel {} was not spliced", self.id())))?;
+        // policy-splice-abort-uncommitted
+        if !estate.holder_commit_is_pre_splice() || !estate.counterparty_commit_is_pre_splice() {
+            return Err(policy_error(format!(
+                "commitments spending the new funding output were already made"
+            ))
+            .into());
+        }
+        if self.monitor.get_state().funding_outpoint == Some(self.setup.funding_outpoint) {
+            return Err(policy_error(format!("splice already confirmed")).into());
+        }
+        self.setup.funding_outpoint = splice.prior_funding_outpoint;
+        self.setup.channel_value_sat = splice.prior_channel_value_sat;
+        let channel_transaction_parameters =
+            Node::channel_setup_to_channel_transaction_parameters(&self.setup, keys.pubkeys());
+        keys.ready_channel(&channel_transaction_parameters);
+        self.keys = keys;
+        self.enforcement_state.splice = None;
+        Ok(())
+    }
+
+    /// Sign the input of the splice transaction that spends the prior
+    /// funding output.
+    ///
+    /// We must have validated a holder commitment spending the new funding
+    /// output, so that we can close the channel if the splice confirms.
+    pub fn sign_splice_funding_input(&self, tx: &Transaction) -> Result<Signature, Status> {
+        let splice =
+            self.enforcement_state.splice.as_ref().ok_or_else(|| {
+                invalid_argument(format!("channel {} was not spliced", self.id()))
+            })?;
+        if tx.txid() != self.setup.funding_outpoint.txid {
+            return Err(invalid_argument(format!(
+                "tx {} is not the splice {}",
+                tx.txid(),
+                self.setup.funding_outpoint.txid
+            )));
+        }
+
+        // policy-splice-commitment-countersigned
+        if self.enforcement_state.holder_commit_is_pre_splice() {
+            return Err(policy_error(format!(
+                "no holder commitment validated for the new funding output"
+            ))
+            .into());
+        }
+
+        let input = tx
+            .input
+            .iter()
+            .position(|i| i.previous_output == splice.prior_funding_outpoint)
+            .ok_or_else(|| invalid_argument("splice does not spend the prior funding output"))?;
+        let funding_redeemscript = make_funding_redeemscript(
+            &self.keys.pubkeys().funding_pubkey,
+            &self.keys.counterparty_pubkeys().funding_pubkey,
+        );
+        let sighash = Message::from_slice(
+            &SighashCache::new(tx)
+                .segwit_signature_hash(
+                    input,
+                    &funding_redeemscript,
+                    splice.prior_channel_value_sat,
+                    EcdsaSighashType::All,
+                )
+                .unwrap()[..],
+        )
+        .map_err(|err| internal_error(format!("sighash failed: {}", err)))?;
+        Ok(self.secp_ctx.sign_ecdsa(&sighash, &self.keys.funding_key))
+    }
 }
 
 // Phase 1
@@ -1489,7 +1759,7 @@ impl Channel {
                     &self.get_chain_state(),
                     &info2,
                 );
-                ve
+                ve.with_commitment_number(commitment_number)
             })?;
 
         let htlcs =
@@ -1536,6 +1806,7 @@ impl Channel {
             .map_err(|_| internal_error(format!("sign_counterparty_commitment failed")))?;
 
         let outgoing_payment_summary = self.enforcement_state.payments_summary(None, Some(&info2));
+        state.drain_excess_amount(self.get_chain_state().current_height, &*validator);
         state.validate_payments(
             &self.id0,
             &incoming_payment_summary,
@@ -1624,7 +1895,7 @@ impl Channel {
                     &self.get_chain_state(),
                     &info2,
                 );
-                ve
+                ve.with_commitment_number(commitment_number)
             })?;
 
         let htlcs =
@@ -1723,6 +1994,7 @@ impl Channel {
         )?;
 
         let outgoing_payment_summary = self.enforcement_state.payments_summary(Some(&info2), None);
+        state.drain_excess_amount(self.get_chain_state().current_height, &*validator);
         state.validate_payments(
             &self.id0,
             &incoming_payment_summary,
@@ -1759,11 +2031,9 @@ impl Channel {
     ) -> Result<(), Status> {
         // TODO - need to store the revealed secret.
 
-        self.validator().validate_counterparty_revocation(
-            &self.enforcement_state,
-            revoke_num,
-            old_secret,
-        )?;
+        self.validator()
+            .validate_counterparty_revocation(&self.enforcement_state, revoke_num, old_secret)
+            .map_err(|ve| ve.with_commitment_number(revoke_num))?;
         self.enforcement_state.set_next_counterparty_revoke_num(revoke_num + 1)?;
 
         trace_enforcement_state!(&self.enforcement_state);
@@ -1972,7 +2242,44 @@ impl Channel {
     pub fn htlcs_fulfilled(&mut self, preimages: Vec<PaymentPreimage>) {
         let validator = self.validator();
         let node = self.get_node();
-        node.htlcs_fulfilled(&self.id0, preimages, validator);
+        let height = self.get_chain_state().current_height;
+        node.htlcs_fulfilled(&self.id0, preimages, validator, height);
+    }
+
+    /// Our claimable balances in the current commitment txs, given the
+    /// preimages known to the node
+    pub fn claimable_balances(&self) -> ClaimableBalances {
+        let node = self.get_node();
+        let state = node.get_state();
+        self.enforcement_state.current_claimable_balances(&*state, &self.setup)
+    }
+
+    /// Our balance in this channel, including in-flight HTLCs
+    pub fn balance(&self) -> ChannelBalance {
+        let claimable_sat = self.claimable_balances().claimable;
+        let is_closed = self.monitor.as_chain_state().closing_depth > 0;
+        let node = self.get_node();
+        let state = node.get_state();
+        let estate = &self.enforcement_state;
+        // Prefer the holder commitment, since that is the one we can broadcast
+        let (offered, received) = match (
+            estate.current_holder_commit_info.as_ref(),
+            estate.current_counterparty_commit_info.as_ref(),
+        ) {
+            (Some(info), _) => (&info.offered_htlcs, &info.received_htlcs),
+            (None, Some(info)) => (&info.received_htlcs, &info.offered_htlcs),
+            (None, None) =>
+                return ChannelBalance { claimable_sat, is_closed, ..Default::default() },
+        };
+        let unfulfilled = |htlcs: &Vec<HTLCInfo2>| -> u64 {
+            htlcs.iter().filter(|h| !state.has_preimage(&h.payment_hash)).map(|h| h.value_sat).sum()
+        };
+        ChannelBalance {
+            claimable_sat,
+            offered_htlc_sat: unfulfilled(offered),
+            received_htlc_sat: unfulfilled(received),
+            is_closed,
+        }
     }
 
     fn dummy_sig() -> Signature {
@@ -143,6 +143,8 @@ mod sign_mutual_close_tests;
 #[cfg(test)]
 mod sign_onchain_tx_tests;
 #[cfg(test)]
+mod splice_tests;
+#[cfg(test)]
 mod validate_counterparty_revocation_tests;
 #[cfg(test)]
 mod validate_holder_commitment_tests;
@@ -6,6 +6,7 @@ use crate::bitcoin::hashes::_export::_core::cmp::Ordering;
 use crate::chain::tracker::ChainListener;
 use crate::policy::validator::ChainState;
 use crate::prelude::*;
+use crate::tx::script::ANCHOR_OUTPUT_VALUE_SATOSHI;
 use crate::Arc;
 
 /// State
@@ -13,7 +14,7 @@ use crate::Arc;
 pub struct State {
     /// Chain height
     pub height: u32,
-    /// funding txids
+    /// funding txids, including splices
     pub funding_txids: Vec<Txid>,
     /// the funding output index for each funding tx
     pub funding_vouts: Vec<u32>,
@@ -21,13 +22,74 @@ pub struct State {
     pub funding_inputs: Set<OutPoint>,
     /// Number of confirmations of the funding transaction
     pub funding_height: Option<u32>,
-    /// The actual funding outpoint on-chain
+    /// The actual funding outpoint on-chain, moved by confirmed splices
     pub funding_outpoint: Option<OutPoint>,
     /// Number of confirmations of a transaction that double-spends
     /// a funding input
     pub funding_double_spent_height: Option<u32>,
     /// Number of confirmations of the closing transaction
     pub closing_height: Option<u32>,
+    /// Outputs of a unilateral closing transaction, and of the second-stage
+    /// HTLC transactions spending it, that have to be swept, with the height
+    /// at which they were swept
+    pub closing_outpoints: OrderedMap<OutPoint, Option<u32>>,
+    /// Height of the last splice transaction, if it confirmed
+    pub splice_height: Option<u32>,
+}
+
+impl State {
+    // Whether the outpoint is one of the funding outputs, possibly spliced away
+    fn is_funding_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.funding_txids
+            .iter()
+            .zip(self.funding_vouts.iter())
+            .any(|(txid, vout)| outpoint.txid == *txid && outpoint.vout == *vout)
+    }
+
+    fn depth(&self, height: Option<u32>) -> u32 {
+        height.map(|h| self.height + 1 - h).unwrap_or(0)
+    }
+}
+
+// A commitment transaction encodes the obscured commitment number in the
+// locktime and sequence, with these upper bytes
+fn is_commitment_tx(tx: &Transaction) -> bool {
+    tx.lock_time >> 24 == 0x20 && tx.input.len() == 1 && tx.input[0].sequence >> 24 == 0x80
+}
+
+// The outputs of a unilateral close that someone has to sweep.
+//
+// These are the P2WSH outputs other than anchors: our delayed output,
+// the counterparty's delayed output (which we have to claim if it was
+// revoked), the HTLC outputs and the anchors-style to_remote output.
+// A mutual close pays directly to the wallets, so nothing has to be swept.
+fn sweepable_outpoints(tx: &Transaction) -> Vec<OutPoint> {
+    if !is_commitment_tx(tx) {
+        return vec![];
+    }
+    p2wsh_outpoints(tx)
+}
+
+// The outputs of a transaction spending closing outputs that have to be
+// swept in turn.
+//
+// A second-stage HTLC transaction pays to a P2WSH delayed output, which
+// is swept by us after the delay, or by the counterparty if it was revoked.
+// A sweep to the wallet has no such outputs.
+fn second_stage_outpoints(tx: &Transaction) -> Vec<OutPoint> {
+    p2wsh_outpoints(tx)
+}
+
+fn p2wsh_outpoints(tx: &Transaction) -> Vec<OutPoint> {
+    let txid = tx.txid();
+    tx.output
+        .iter()
+        .enumerate()
+        .filter(|(_, out)| {
+            out.script_pubkey.is_v0_p2wsh() && out.value != ANCHOR_OUTPUT_VALUE_SATOSHI
+        })
+        .map(|(vout, _)| OutPoint::new(txid, vout as u32))
+        .collect()
 }
 
 /// Keep track of channel on-chain events.
@@ -73,6 +135,8 @@ impl ChainMonitor {
             funding_outpoint: None,
             funding_double_spent_height: None,
             closing_height: None,
+            closing_outpoints: OrderedMap::new(),
+            splice_height: None,
         };
 
         Self { funding_outpoint, state: Arc::new(Mutex::new(state)) }
@@ -109,6 +173,29 @@ impl ChainMonitor {
         state.funding_inputs.extend(tx.input.iter().map(|i| i.previous_output));
     }
 
+    /// Add a splice transaction to keep track of.
+    ///
+    /// The splice spends the current funding outpoint, so when it confirms
+    /// the funding moves to the output at `vout` rather than the channel
+    /// being closed.
+    pub fn add_splice(&self, tx: &Transaction, vout: u32) {
+        let mut state = self.state.lock().expect("lock");
+        assert_eq!(state.funding_txids.len(), state.funding_vouts.len());
+        state.funding_txids.push(tx.txid());
+        state.funding_vouts.push(vout);
+    }
+
+    /// Stop keeping track of an aborted splice transaction, which must not
+    /// have confirmed.
+    pub fn remove_splice(&self, txid: &Txid) {
+        let mut state = self.state.lock().expect("lock");
+        assert_ne!(state.funding_outpoint.map(|o| o.txid), Some(*txid), "splice confirmed");
+        if let Some(ind) = state.funding_txids.iter().position(|i| i == txid) {
+            state.funding_txids.remove(ind);
+            state.funding_vouts.remove(ind);
+        }
+    }
+
     /// Returns the number of confirmations of the funding transaction, or zero
     /// if it wasn't confirmed yet.
     pub fn funding_depth(&self) -> u32 {
@@ -123,6 +210,17 @@ impl ChainMonitor {
         state.funding_double_spent_height.map(|h| state.height + 1 - h).unwrap_or(0)
     }
 
+    /// Whether the channel was closed on-chain and all closing outputs
+    /// were swept, with all of these transactions at least `min_depth` deep.
+    ///
+    /// Such a channel needs no further signing and can be archived.
+    pub fn is_done(&self, min_depth: u32) -> bool {
+        let state = self.state.lock().expect("lock");
+        state.closing_height.is_some()
+            && state.depth(state.closing_height) >= min_depth
+            && state.closing_outpoints.values().all(|h| h.is_some() && state.depth(*h) >= min_depth)
+    }
+
     /// Convert to a ChainState, to be used for validation
     pub fn as_chain_state(&self) -> ChainState {
         let state = self.state.lock().expect("lock");
@@ -134,6 +232,7 @@ impl ChainMonitor {
                 .map(|h| state.height + 1 - h)
                 .unwrap_or(0),
             closing_depth: state.closing_height.map(|h| state.height + 1 - h).unwrap_or(0),
+            splice_depth: state.depth(state.splice_height),
         }
     }
 }
@@ -155,7 +254,13 @@ impl ChainListener for ChainMonitor {
                     outpoint.vout < tx.output.len() as u32,
                     "tx doesn't have funding output index"
                 );
-                state.funding_height = Some(state.height);
+                // A splice moves the funding, but the channel stays as old
+                // as the original funding
+                if spent.iter().any(|i| Some(*i) == state.funding_outpoint) {
+                    state.splice_height = Some(state.height);
+                } else {
+                    state.funding_height = Some(state.height);
+                }
                 state.funding_outpoint = Some(outpoint);
                 outpoints.push(outpoint);
             } else if spent.iter().any(|i| state.funding_inputs.contains(&i)) {
@@ -170,6 +275,21 @@ impl ChainListener for ChainMonitor {
             } else if spent.iter().any(|i| Some(*i) == state.funding_outpoint) {
                 // Closed on-chain
                 state.closing_height = Some(state.height);
+                let sweepable = sweepable_outpoints(tx);
+                state.closing_outpoints = sweepable.iter().map(|o| (*o, None)).collect();
+                outpoints.extend(sweepable);
+            } else if spent.iter().any(|i| state.closing_outpoints.contains_key(i)) {
+                // Swept some closing outputs
+                let height = state.height;
+                for outpoint in spent {
+                    if let Some(swept_height) = state.closing_outpoints.get_mut(&outpoint) {
+                        *swept_height = Some(height);
+                    }
+                }
+                // Keep track of second-stage outputs until they are swept too
+                let second_stage = second_stage_outpoints(tx);
+                state.closing_outpoints.extend(second_stage.iter().map(|o| (*o, None)));
+                outpoints.extend(second_stage);
             } else {
                 panic!("unknown tx confirmed")
             }
@@ -185,9 +305,17 @@ impl ChainListener for ChainMonitor {
             let txid = tx.txid();
             if let Some(_) = state.funding_txids.iter().position(|i| *i == txid) {
                 // A funding tx was reorged-out
-                assert_eq!(state.funding_height, Some(state.height));
-                state.funding_height = None;
-                state.funding_outpoint = None;
+                let prior_funding = spent.iter().find(|i| state.is_funding_outpoint(i)).cloned();
+                if prior_funding.is_some() {
+                    // A splice - the funding moves back to the prior outpoint
+                    assert_eq!(state.splice_height, Some(state.height));
+                    state.splice_height = None;
+                    state.funding_outpoint = prior_funding;
+                } else {
+                    assert_eq!(state.funding_height, Some(state.height));
+                    state.funding_height = None;
+                    state.funding_outpoint = None;
+                }
             } else if spent.iter().any(|i| state.funding_inputs.contains(&i)) {
                 // A funding double-spent was reorged-out
                 // we may have seen some other funding input double-spent, so
@@ -201,6 +329,24 @@ impl ChainListener for ChainMonitor {
                 // A closing tx was reorged-out
                 assert_eq!(state.closing_height, Some(state.height));
                 state.closing_height = None;
+                state.closing_outpoints.clear();
+            } else if spent.iter().any(|i| state.closing_outpoints.contains_key(i)) {
+                // A sweep was reorged-out, along with any second-stage outputs.
+                // Their own sweeps were reorged-out before, since transactions
+                // are removed in reverse order.
+                // BTreeMap::retain is newer than our MSRV
+                let outputs: Vec<OutPoint> =
+                    state.closing_outpoints.keys().filter(|o| o.txid == txid).cloned().collect();
+                for outpoint in outputs {
+                    state.closing_outpoints.remove(&outpoint);
+                }
+                let height = state.height;
+                for outpoint in spent {
+                    if let Some(swept_height) = state.closing_outpoints.get_mut(&outpoint) {
+                        assert_eq!(*swept_height, Some(height));
+                        *swept_height = None;
+                    }
+                }
             } else {
                 panic!("unknown reorged tx");
             }
@@ -213,6 +359,9 @@ impl SendSync for ChainMonitor {}
 
 #[cfg(test)]
 mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Script, TxOut, WPubkeyHash, WScriptHash};
+
     use crate::util::test_utils::*;
 
     use super::*;
@@ -237,6 +386,34 @@ mod tests {
         assert_eq!(monitor.funding_depth(), 0);
     }
 
+    #[test]
+    fn test_splice() {
+        let tx = make_tx(vec![make_txin(1), make_txin(2)]);
+        let outpoint = OutPoint::new(tx.txid(), 0);
+        let mut splice_input = make_txin(3);
+        splice_input.previous_output = outpoint;
+        let splice_tx = make_tx(vec![splice_input]);
+        let splice_outpoint = OutPoint::new(splice_tx.txid(), 0);
+        let monitor = ChainMonitor::new(outpoint, 0);
+        monitor.add_funding(&tx, 0);
+        monitor.on_add_block(vec![&tx]);
+        monitor.add_splice(&splice_tx, 0);
+        let watches = monitor.on_add_block(vec![&splice_tx]);
+        assert_eq!(watches, vec![splice_outpoint]);
+        assert_eq!(monitor.get_state().funding_outpoint, Some(splice_outpoint));
+        assert_eq!(monitor.funding_depth(), 2);
+        assert_eq!(monitor.as_chain_state().closing_depth, 0);
+        assert_eq!(monitor.as_chain_state().splice_depth, 1);
+        monitor.on_remove_block(vec![&splice_tx]);
+        assert_eq!(monitor.get_state().funding_outpoint, Some(outpoint));
+        assert_eq!(monitor.funding_depth(), 1);
+        assert_eq!(monitor.as_chain_state().splice_depth, 0);
+
+        monitor.remove_splice(&splice_tx.txid());
+        assert_eq!(monitor.get_state().funding_txids, vec![tx.txid()]);
+        assert_eq!(monitor.get_state().funding_vouts, vec![0]);
+    }
+
     #[test]
     fn test_funding_double_spent() {
         let tx = make_tx(vec![make_txin(1), make_txin(2)]);
@@ -258,4 +435,114 @@ mod tests {
         monitor.on_remove_block(vec![]);
         assert_eq!(monitor.funding_double_spent_depth(), 0);
     }
+
+    #[test]
+    fn test_closing_and_sweeps() {
+        let tx = make_tx(vec![make_txin(1)]);
+        let outpoint = OutPoint::new(tx.txid(), 0);
+        let monitor = ChainMonitor::new(outpoint, 0);
+        monitor.add_funding(&tx, 0);
+        monitor.on_add_block(vec![&tx]);
+        assert!(!monitor.is_done(0));
+
+        let p2wsh = |value| TxOut {
+            value,
+            script_pubkey: Script::new_v0_p2wsh(&WScriptHash::hash(&value.to_be_bytes())),
+        };
+        let mut closing_input = make_txin(0);
+        closing_input.previous_output = outpoint;
+        closing_input.sequence = 0x80000001;
+        let mut closing_tx = make_tx(vec![closing_input]);
+        closing_tx.lock_time = 0x20000001;
+        closing_tx.output = vec![
+            p2wsh(10_000),
+            p2wsh(ANCHOR_OUTPUT_VALUE_SATOSHI),
+            TxOut { value: 20_000, script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::hash(&[1])) },
+        ];
+        let closing_outpoint = OutPoint::new(closing_tx.txid(), 0);
+        let watches = monitor.on_add_block(vec![&closing_tx]);
+        assert_eq!(watches, vec![closing_outpoint]);
+        assert!(!monitor.is_done(0));
+
+        let mut sweep_input = make_txin(0);
+        sweep_input.previous_output = closing_outpoint;
+        let sweep_tx = make_tx(vec![sweep_input]);
+        monitor.on_add_block(vec![&sweep_tx]);
+        assert!(monitor.is_done(1));
+        assert!(!monitor.is_done(2));
+        monitor.on_add_block(vec![]);
+        assert!(monitor.is_done(2));
+
+        monitor.on_remove_block(vec![]);
+        monitor.on_remove_block(vec![&sweep_tx]);
+        assert!(!monitor.is_done(0));
+        monitor.on_remove_block(vec![&closing_tx]);
+        assert!(monitor.get_state().closing_outpoints.is_empty());
+        assert_eq!(monitor.as_chain_state().closing_depth, 0);
+    }
+
+    #[test]
+    fn test_second_stage_htlc() {
+        let tx = make_tx(vec![make_txin(1)]);
+        let outpoint = OutPoint::new(tx.txid(), 0);
+        let monitor = ChainMonitor::new(outpoint, 0);
+        monitor.add_funding(&tx, 0);
+        monitor.on_add_block(vec![&tx]);
+
+        let p2wsh = |value| TxOut {
+            value,
+            script_pubkey: Script::new_v0_p2wsh(&WScriptHash::hash(&value.to_be_bytes())),
+        };
+        let mut closing_input = make_txin(0);
+        closing_input.previous_output = outpoint;
+        closing_input.sequence = 0x80000001;
+        let mut closing_tx = make_tx(vec![closing_input]);
+        closing_tx.lock_time = 0x20000001;
+        closing_tx.output = vec![p2wsh(10_000)];
+        let htlc_outpoint = OutPoint::new(closing_tx.txid(), 0);
+        monitor.on_add_block(vec![&closing_tx]);
+
+        // The HTLC transaction pays to a delayed output
+        let mut htlc_input = make_txin(0);
+        htlc_input.previous_output = htlc_outpoint;
+        let mut htlc_tx = make_tx(vec![htlc_input]);
+        htlc_tx.output = vec![p2wsh(9_000)];
+        let delayed_outpoint = OutPoint::new(htlc_tx.txid(), 0);
+        let watches = monitor.on_add_block(vec![&htlc_tx]);
+        assert_eq!(watches, vec![delayed_outpoint]);
+        monitor.on_add_block(vec![]);
+        assert!(!monitor.is_done(1));
+
+        let mut sweep_input = make_txin(0);
+        sweep_input.previous_output = delayed_outpoint;
+        let sweep_tx = make_tx(vec![sweep_input]);
+        monitor.on_add_block(vec![&sweep_tx]);
+        assert!(monitor.is_done(1));
+
+        monitor.on_remove_block(vec![&sweep_tx]);
+        monitor.on_remove_block(vec![]);
+        monitor.on_remove_block(vec![&htlc_tx]);
+        assert_eq!(
+            monitor.get_state().closing_outpoints.iter().collect::<Vec<_>>(),
+            vec![(&htlc_outpoint, &None)]
+        );
+    }
+
+    #[test]
+    fn test_mutual_close() {
+        let tx = make_tx(vec![make_txin(1)]);
+        let outpoint = OutPoint::new(tx.txid(), 0);
+        let monitor = ChainMonitor::new(outpoint, 0);
+        monitor.add_funding(&tx, 0);
+        monitor.on_add_block(vec![&tx]);
+
+        let mut closing_input = make_txin(0);
+        closing_input.previous_output = outpoint;
+        closing_input.sequence = 0xffffffff;
+        let closing_tx = make_tx(vec![closing_input]);
+        let watches = monitor.on_add_block(vec![&closing_tx]);
+        assert!(watches.is_empty());
+        assert!(monitor.is_done(1));
+        assert!(!monitor.is_done(2));
+    }
 }
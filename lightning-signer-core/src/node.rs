@@ -14,12 +14,14 @@ use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::ecdsa::{RecoverableSignature, Signature};
-use bitcoin::secp256k1::{schnorr, All, Message, PublicKey, Secp256k1, SecretKey};
-use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
+use bitcoin::secp256k1::{schnorr, All, KeyPair, Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey, KeySource};
 use bitcoin::util::key::XOnlyPublicKey;
-use bitcoin::util::sighash::SighashCache;
-use bitcoin::{secp256k1, Address, PrivateKey, Transaction, TxOut};
-use bitcoin::{EcdsaSighashType, Network, OutPoint, Script};
+use bitcoin::util::psbt::{Input as PsbtInput, PartiallySignedTransaction};
+use bitcoin::util::schnorr::{SchnorrSig, TapTweak};
+use bitcoin::util::sighash::{Prevouts, SchnorrSighashType, SighashCache};
+use bitcoin::{secp256k1, Address, PrivateKey, Transaction, TxOut, Witness};
+use bitcoin::{EcdsaSig, EcdsaSighashType, Network, OutPoint, Script};
 use lightning::chain;
 use lightning::chain::keysinterface::{
     BaseSign, KeyMaterial, KeysInterface, Recipient, SpendableOutputDescriptor,
@@ -37,11 +39,14 @@ use lightning_invoice::{Invoice, RawDataPart, RawHrp, RawInvoice, SignedRawInvoi
 use log::{debug, info, trace, warn};
 
 use crate::chain::tracker::ChainTracker;
-use crate::channel::{Channel, ChannelBase, ChannelId, ChannelSetup, ChannelSlot, ChannelStub};
+use crate::channel::{
+    Channel, ChannelBalance, ChannelBase, ChannelId, ChannelSetup, ChannelSlot, ChannelStub,
+};
 use crate::monitor::ChainMonitor;
-use crate::persist::model::NodeEntry;
+use crate::persist::model::{ChannelEntry, KeysendEntry, NodeEntry, PolicyChangeEntry};
 use crate::persist::Persist;
 use crate::policy::error::{policy_error, unbalanced_error, ValidationError};
+use crate::policy::simple_validator::SimplePolicy;
 use crate::policy::validator::{BalanceDelta, ValidatorFactory};
 use crate::policy::validator::{EnforcementState, Validator};
 use crate::prelude::*;
@@ -49,8 +54,11 @@ use crate::signer::derive::KeyDerivationStyle;
 use crate::signer::my_keys_manager::MyKeysManager;
 use crate::sync::{Arc, Weak};
 use crate::tx::tx::PreimageMap;
+use crate::util::bolt12::{self, Bolt12Invoice, Bolt12InvoiceRequest};
 use crate::util::crypto_utils::signature_to_bitcoin_vec;
-use crate::util::status::{failed_precondition, internal_error, invalid_argument, Status};
+use crate::util::status::{
+    failed_precondition, internal_error, invalid_argument, resource_exhausted, ErrorDetails, Status,
+};
 use crate::wallet::Wallet;
 
 /// Node configuration parameters.
@@ -80,6 +88,17 @@ pub struct InvoiceState {
     pub is_fulfilled: bool,
 }
 
+/// An outgoing payment without an invoice
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeysendState {
+    /// Payee's public key
+    pub payee: PublicKey,
+    /// Amount to pay
+    pub amount_msat: u64,
+    /// The block height at which the keysend was added
+    pub added_height: u32,
+}
+
 /// Keeps track of incoming and outgoing HTLCs for a routed payment
 #[derive(Clone)]
 pub struct RoutedPayment {
@@ -132,6 +151,58 @@ impl RoutedPayment {
     }
 }
 
+/// Routing income earned through a channel, attributed to the channel
+/// the payment was forwarded over
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoutingIncome {
+    /// The number of fulfilled forwards
+    pub forwards: u64,
+    /// The amount forwarded in satoshi
+    pub forwarded_sat: u64,
+    /// The fees earned in satoshi
+    pub fees_sat: u64,
+}
+
+/// A report of the node's routing income
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoutingIncomeReport {
+    /// Routing income per outgoing channel
+    pub channels: OrderedMap<ChannelId, RoutingIncome>,
+    /// The total fees earned in satoshi
+    pub total_fees_sat: u64,
+    /// The current excess amount in satoshi
+    pub excess_amount: u64,
+    /// The total drained from the excess amount in satoshi
+    pub drained_amount: u64,
+}
+
+/// The depth beyond the longest `to_self_delay` of a channel at which its
+/// closing and sweep transactions are final enough for it to be archived.
+///
+/// This matches the maximum reorg size of the chain tracker.
+pub const CHANNEL_ARCHIVE_MARGIN: u32 = 100;
+
+/// The depth at which a splice transaction is final enough to forget the
+/// funding output it spends, and to allow another splice.
+pub const SPLICE_BURIED_DEPTH: u32 = 6;
+
+/// A report of what the signer believes the node owns in its channels.
+///
+/// Stubs are not included, since they hold no funds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BalanceReport {
+    /// The balance of each channel, by its original ID
+    pub channels: OrderedMap<ChannelId, ChannelBalance>,
+    /// The claimable balance of open channels in satoshi
+    pub claimable_sat: u64,
+    /// The total of outgoing in-flight HTLCs in satoshi
+    pub offered_htlc_sat: u64,
+    /// The total of incoming in-flight HTLCs in satoshi
+    pub received_htlc_sat: u64,
+    /// The claimable balance of channels closed on-chain, pending a sweep
+    pub sweeping_sat: u64,
+}
+
 /// Enforcement state for a node
 // TODO move allowlist into this struct
 pub struct NodeState {
@@ -139,15 +210,24 @@ pub struct NodeState {
     pub invoices: Map<PaymentHash, InvoiceState>,
     /// Issued invoices for incoming payments indexed by their payment hash
     pub issued_invoices: Map<PaymentHash, InvoiceState>,
+    /// BOLT 12 invoice requests we signed, indexed by their merkle root
+    pub bolt12_invoice_requests: Map<[u8; 32], Bolt12InvoiceRequest>,
+    /// Added keysend payments indexed by their payment hash
+    pub keysends: Map<PaymentHash, KeysendState>,
     /// Payment states
     pub payments: Map<PaymentHash, RoutedPayment>,
     /// Accumulator of excess payment amount in satoshi, for tracking certain
     /// payment corner cases.
     /// If this falls below zero, the attempted commit is failed.
-    // TODO fee accumulation adjustment
-    // As we accumulate routing fees, this value grows without bounds.  We should
-    // take accumulated fees out over time to keep this bounded.
+    /// Routing fees accumulate here, and are drained over time according
+    /// to [Validator::excess_drain_schedule] to keep this bounded.
     pub excess_amount: u64,
+    /// Total drained from the excess amount in satoshi
+    pub drained_amount: u64,
+    /// The block height the excess amount was last drained at
+    pub last_drain_height: Option<u32>,
+    /// Routing income per outgoing channel
+    pub routing_income: OrderedMap<ChannelId, RoutingIncome>,
     /// Prefix for emitted logs lines
     pub log_prefix: String,
 }
@@ -164,8 +244,13 @@ impl NodeState {
         NodeState {
             invoices: Map::new(),
             issued_invoices: Map::new(),
+            bolt12_invoice_requests: Map::new(),
+            keysends: Map::new(),
             payments: Map::new(),
             excess_amount: 0,
+            drained_amount: 0,
+            last_drain_height: None,
+            routing_income: OrderedMap::new(),
             log_prefix: String::new(),
         }
     }
@@ -174,8 +259,13 @@ impl NodeState {
         NodeState {
             invoices: self.invoices,
             issued_invoices: self.issued_invoices,
+            bolt12_invoice_requests: self.bolt12_invoice_requests,
+            keysends: self.keysends,
             payments: self.payments,
             excess_amount: self.excess_amount,
+            drained_amount: self.drained_amount,
+            last_drain_height: self.last_drain_height,
+            routing_income: self.routing_income,
             log_prefix,
         }
     }
@@ -208,8 +298,9 @@ impl NodeState {
     /// Validate outgoing in-flight payment amounts as a result of a new commitment tx.
     ///
     /// The following policies are checked:
-    /// - no overpayment for any invoice.
-    /// - Sends without invoices (e.g. keysend) are only allowed if
+    /// - no overpayment for any invoice, BOLT 11 or BOLT 12.
+    /// - no overpayment for any keysend added with [Node::add_keysend].
+    /// - Sends without invoices or added keysends are only allowed if
     /// `policy.require_invoices` is false.
     pub fn validate_payments(
         &self,
@@ -241,7 +332,11 @@ impl NodeState {
             } else {
                 (incoming_for_chan, outgoing_for_chan)
             };
-            let invoiced_amount = self.invoices.get(&hash).map(|i| i.amount_msat);
+            let invoiced_amount = self
+                .invoices
+                .get(&hash)
+                .map(|i| i.amount_msat)
+                .or_else(|| self.keysends.get(&hash).map(|k| k.amount_msat));
             if validator.validate_payment_balance(incoming, outgoing, invoiced_amount).is_err() {
                 unbalanced.push(hash);
             }
@@ -345,6 +440,118 @@ impl NodeState {
             let payment = self.payments.get_mut(hash).expect("created above");
             payment.apply(channel_id, incoming, outgoing);
         }
+
+        // A keysend whose HTLCs were all removed without a preimage failed,
+        // and no longer counts against the keysend budgets
+        let mut failed_keysends = Vec::new();
+        for hash in self.keysends.keys() {
+            if outgoing_payment_summary.contains_key(hash) {
+                continue;
+            }
+            if let Some(payment) = self.payments.get_mut(hash) {
+                if payment.outgoing.get(channel_id).map(|a| *a > 0).unwrap_or(false) {
+                    let incoming = payment.incoming.get(channel_id).map(|a| *a).unwrap_or(0);
+                    payment.apply(channel_id, incoming, 0);
+                    if !payment.is_fulfilled() && payment.incoming_outgoing().1 == 0 {
+                        failed_keysends.push(*hash);
+                    }
+                }
+            }
+        }
+        for hash in failed_keysends {
+            info!("{} releasing failed keysend {}", self.log_prefix, hash.0.to_hex());
+            self.keysends.remove(&hash);
+        }
+    }
+
+    /// Forget keysends added before the budget window that are not in flight.
+    /// Keysends are kept forever if there is no window.
+    pub fn prune_keysends(&mut self, height: u32, window_blocks: Option<u32>) {
+        let window_blocks = match window_blocks {
+            Some(window_blocks) => window_blocks,
+            None => return,
+        };
+        let payments = &self.payments;
+        self.keysends.retain(|hash, keysend| {
+            let in_flight =
+                payments.get(hash).map(|p| p.incoming_outgoing().1 > 0).unwrap_or(false);
+            in_flight || keysend.added_height.saturating_add(window_blocks) > height
+        });
+    }
+
+    // Attribute the fee to the outgoing channels, in proportion to the
+    // amount forwarded over each.  Any rounding remainder goes to the first.
+    fn record_routing_income(
+        routing_income: &mut OrderedMap<ChannelId, RoutingIncome>,
+        outgoing: &OrderedMap<ChannelId, u64>,
+        fee: u64,
+    ) {
+        let total: u64 = outgoing.values().sum();
+        if total == 0 {
+            return;
+        }
+        let shares: Vec<(&ChannelId, u64, u64)> = outgoing
+            .iter()
+            .filter(|(_, amount)| **amount > 0)
+            .map(|(channel_id, amount)| {
+                let share = (fee as u128 * *amount as u128 / total as u128) as u64;
+                (channel_id, *amount, share)
+            })
+            .collect();
+        let remainder = fee - shares.iter().map(|(_, _, share)| share).sum::<u64>();
+        for (ndx, (channel_id, amount, share)) in shares.into_iter().enumerate() {
+            let income = routing_income.entry(channel_id.clone()).or_default();
+            income.forwards += 1;
+            income.forwarded_sat += amount;
+            income.fees_sat += if ndx == 0 { share + remainder } else { share };
+        }
+    }
+
+    /// Drain the excess amount according to the validator's schedule, as of
+    /// block `height`.  Draining happens in whole intervals since the last
+    /// drain.
+    pub fn drain_excess_amount(&mut self, height: u32, validator: &dyn Validator) {
+        let (interval_blocks, percent) = match validator.excess_drain_schedule() {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        let last_drain_height = match self.last_drain_height {
+            // reorgs don't undo a drain
+            Some(h) if h <= height => h,
+            _ => {
+                self.last_drain_height = Some(height);
+                return;
+            }
+        };
+        let intervals = (height - last_drain_height) / interval_blocks;
+        if intervals == 0 {
+            return;
+        }
+        let mut excess_amount = self.excess_amount;
+        // The excess amount reaches zero well before this many intervals
+        for _ in 0..intervals.min(1000) {
+            excess_amount -= excess_amount * percent.min(100) as u64 / 100;
+        }
+        let drained = self.excess_amount - excess_amount;
+        if drained > 0 {
+            info!(
+                "{} drain excess {} -{} after {} intervals",
+                self.log_prefix, self.excess_amount, drained, intervals
+            );
+        }
+        self.excess_amount = excess_amount;
+        self.drained_amount = self.drained_amount.saturating_add(drained);
+        self.last_drain_height = Some(last_drain_height + intervals * interval_blocks);
+    }
+
+    /// Report the routing income
+    pub fn routing_income_report(&self) -> RoutingIncomeReport {
+        RoutingIncomeReport {
+            channels: self.routing_income.clone(),
+            total_fees_sat: self.routing_income.values().map(|i| i.fees_sat).sum(),
+            excess_amount: self.excess_amount,
+            drained_amount: self.drained_amount,
+        }
     }
 
     /// Fulfills an HTLC.
@@ -373,7 +580,9 @@ impl NodeState {
                 );
             } else {
                 let (incoming, outgoing) = payment.incoming_outgoing();
-                if self.invoices.contains_key(&payment_hash) {
+                if self.invoices.contains_key(&payment_hash)
+                    || self.keysends.contains_key(&payment_hash)
+                {
                     if incoming > 0 {
                         info!(
                             "{} preimage invoice+routing {} +{} -{} msat",
@@ -406,6 +615,8 @@ impl NodeState {
                         self.excess_amount =
                             self.excess_amount.checked_sub(outgoing).expect("underflow");
                     }
+                    let fee = incoming.saturating_sub(outgoing);
+                    Self::record_routing_income(&mut self.routing_income, &payment.outgoing, fee);
                 }
                 payment.preimage = Some(preimage);
             }
@@ -509,7 +720,9 @@ pub struct Node {
     allowlist: Mutex<UnorderedSet<Allowable>>,
     tracker: Mutex<ChainTracker<ChainMonitor>>,
     pub(crate) state: Mutex<NodeState>,
+    policy_changes: Mutex<Vec<PolicyChangeEntry>>,
     node_id: PublicKey,
+    secp_ctx: Secp256k1<All>,
 }
 
 impl Wallet for Node {
@@ -519,15 +732,17 @@ impl Wallet for Node {
             return Ok(false);
         }
 
-        let secp_ctx = Secp256k1::signing_only();
-        let pubkey = self.get_wallet_pubkey(&secp_ctx, child_path)?;
+        let pubkey = self.get_wallet_pubkey(&self.secp_ctx, child_path)?;
 
-        // Lightning layer-1 wallets can spend native segwit or wrapped segwit addresses.
+        // Lightning layer-1 wallets can spend native segwit, wrapped segwit
+        // or taproot addresses.
         let native_addr = Address::p2wpkh(&pubkey, self.network()).expect("p2wpkh failed");
         let wrapped_addr = Address::p2shwpkh(&pubkey, self.network()).expect("p2shwpkh failed");
+        let taproot_addr = self.get_taproot_address(child_path)?;
 
         Ok(*script_pubkey == native_addr.script_pubkey()
-            || *script_pubkey == wrapped_addr.script_pubkey())
+            || *script_pubkey == wrapped_addr.script_pubkey()
+            || *script_pubkey == taproot_addr.script_pubkey())
     }
 
     fn get_native_address(&self, child_path: &Vec<u32>) -> Result<Address, Status> {
@@ -550,6 +765,16 @@ impl Wallet for Node {
         Ok(Address::p2shwpkh(&pubkey, self.network()).expect("p2wpkh failed"))
     }
 
+    fn get_taproot_address(&self, child_path: &Vec<u32>) -> Result<Address, Status> {
+        if child_path.len() == 0 {
+            return Err(invalid_argument("empty child path"));
+        }
+
+        let keypair = self.get_taproot_wallet_keypair(&self.secp_ctx, child_path)?;
+        let internal_key = XOnlyPublicKey::from_keypair(&keypair);
+        Ok(Address::p2tr(&self.secp_ctx, internal_key, None, self.network()))
+    }
+
     /// Returns true if script_pubkey is in the node's allowlist.
     fn allowlist_contains(&self, script_pubkey: &Script) -> bool {
         self.allowlist.lock().unwrap().contains(&Allowable::Script(script_pubkey.clone()))
@@ -636,7 +861,9 @@ impl Node {
             allowlist: Mutex::new(UnorderedSet::from_iter(allowlist)),
             tracker: Mutex::new(tracker),
             state,
+            policy_changes: Mutex::new(Vec::new()),
             node_id,
+            secp_ctx: Secp256k1::new(),
         }
     }
 
@@ -650,13 +877,97 @@ impl Node {
         self.keys_manager.get_bolt12_pubkey()
     }
 
-    /// BOLT 12 sign
+    /// BOLT 12 sign a merkle root.
+    ///
+    /// If the policy requires seen BOLT 12 invoices, an invoice is only
+    /// signed if its fields were already seen by [Node::sign_bolt12_tlv].
     pub fn sign_bolt12(
         &self,
         messagename: &[u8],
         fieldname: &[u8],
         merkleroot: &[u8; 32],
         publictweak_opt: Option<&[u8]>,
+    ) -> Result<schnorr::Signature, Status> {
+        let require_seen = self
+            .validator_factory
+            .lock()
+            .unwrap()
+            .make_validator(self.network(), self.get_id(), None)
+            .require_seen_bolt12_invoices();
+        // policy-bolt12-invoice-seen
+        if messagename == b"invoice" && require_seen {
+            let state = self.state.lock().unwrap();
+            if !state.issued_invoices.values().any(|i| i.invoice_hash == *merkleroot) {
+                return Err(policy_error(format!(
+                    "sign_bolt12: invoice with merkle root {} was not seen",
+                    merkleroot.to_hex()
+                ))
+                .into());
+            }
+        }
+        self.do_sign_bolt12(messagename, fieldname, merkleroot, publictweak_opt)
+    }
+
+    /// BOLT 12 sign a TLV stream.
+    ///
+    /// An invoice starts tracking the incoming payment for its payment hash,
+    /// as with [Node::sign_invoice].  An invoice_request is remembered so
+    /// that the invoice answering it can be checked by
+    /// [Node::add_bolt12_invoice].
+    pub fn sign_bolt12_tlv(
+        &self,
+        messagename: &[u8],
+        fieldname: &[u8],
+        tlv_stream: &[u8],
+        publictweak_opt: Option<&[u8]>,
+    ) -> Result<schnorr::Signature, Status> {
+        let merkle_root = match messagename {
+            b"invoice" => {
+                let invoice = Bolt12Invoice::parse(tlv_stream).map_err(invalid_argument)?;
+                let hash = PaymentHash(invoice.payment_hash);
+                info!(
+                    "{} signing a bolt12 invoice {} -> {}",
+                    self.log_prefix(),
+                    hash.0.to_hex(),
+                    invoice.amount_msat
+                );
+                let mut state = self.state.lock().unwrap();
+                if let Some(invoice_state) = state.issued_invoices.get(&hash) {
+                    if invoice_state.invoice_hash != invoice.merkle_root {
+                        return Err(failed_precondition(
+                            "already have a different invoice for same secret".to_string(),
+                        ));
+                    }
+                } else {
+                    state.issued_invoices.insert(hash, Self::invoice_state_from_bolt12(&invoice));
+                }
+                invoice.merkle_root
+            }
+            b"invoice_request" => {
+                let request = Bolt12InvoiceRequest::parse(tlv_stream).map_err(invalid_argument)?;
+                let merkle_root = request.merkle_root;
+                info!(
+                    "{} signing a bolt12 invoice_request {} for {:?}",
+                    self.log_prefix(),
+                    merkle_root.to_hex(),
+                    request.amount_msat
+                );
+                self.state.lock().unwrap().bolt12_invoice_requests.insert(merkle_root, request);
+                merkle_root
+            }
+            _ => bolt12::merkle_root(
+                &bolt12::parse_tlv_stream(tlv_stream).map_err(invalid_argument)?,
+            ),
+        };
+        self.do_sign_bolt12(messagename, fieldname, &merkle_root, publictweak_opt)
+    }
+
+    fn do_sign_bolt12(
+        &self,
+        messagename: &[u8],
+        fieldname: &[u8],
+        merkleroot: &[u8; 32],
+        publictweak_opt: Option<&[u8]>,
     ) -> Result<schnorr::Signature, Status> {
         self.keys_manager
             .sign_bolt12(messagename, fieldname, merkleroot, publictweak_opt)
@@ -669,6 +980,12 @@ impl Node {
         *vfac = validator_factory;
     }
 
+    // Enforce `policy` with the same kind of validator the node already uses
+    fn set_validator_policy(&self, policy: SimplePolicy) {
+        let validator_factory = self.validator_factory.lock().unwrap().with_policy(policy);
+        self.set_validator_factory(validator_factory);
+    }
+
     /// Get the node ID, which is the same as the node public key
     pub fn get_id(&self) -> PublicKey {
         self.node_id
@@ -689,6 +1006,177 @@ impl Node {
         self.state.lock().unwrap()
     }
 
+    /// Report the routing income per channel and the excess amount
+    pub fn routing_income_report(&self) -> RoutingIncomeReport {
+        self.get_state().routing_income_report()
+    }
+
+    /// Report our balance across channels, for reconciliation against
+    /// the node's own accounting
+    pub fn balance_report(&self) -> BalanceReport {
+        let mut report = BalanceReport::default();
+        for slot in self.channels().values() {
+            let slot = slot.lock().unwrap();
+            if let ChannelSlot::Ready(chan) = &*slot {
+                // A ready channel is under both of its IDs
+                if report.channels.contains_key(&chan.id0) {
+                    continue;
+                }
+                let balance = chan.balance();
+                if balance.is_closed {
+                    report.sweeping_sat += balance.claimable_sat;
+                } else {
+                    report.claimable_sat += balance.claimable_sat;
+                }
+                report.offered_htlc_sat += balance.offered_htlc_sat;
+                report.received_htlc_sat += balance.received_htlc_sat;
+                report.channels.insert(chan.id0.clone(), balance);
+            }
+        }
+        report
+    }
+
+    /// Archive channels that were closed on-chain, once the closing transaction
+    /// and all sweeps are at least the longest `to_self_delay` of the channel
+    /// plus [`CHANNEL_ARCHIVE_MARGIN`] deep.
+    ///
+    /// Archived channels are removed from the active channels and the chain
+    /// tracker, and are moved to the persister's archive, where they can still
+    /// be queried with [`Node::archived_channels`].
+    ///
+    /// Returns the original IDs of the archived channels.
+    pub fn archive_closed_channels(&self) -> Result<Vec<ChannelId>, Status> {
+        let mut tracker = self.tracker.lock().unwrap();
+        let mut channels = self.channels.lock().unwrap();
+
+        let mut done = Vec::new();
+        for slot_arc in channels.values() {
+            let slot = slot_arc.lock().unwrap();
+            if let ChannelSlot::Ready(chan) = &*slot {
+                // A ready channel is under both of its IDs
+                if done.iter().any(|(id0, _, _)| *id0 == chan.id0) {
+                    continue;
+                }
+                let min_depth = chan.setup.max_to_self_delay() as u32 + CHANNEL_ARCHIVE_MARGIN;
+                // The tracker has the current monitor state
+                let is_done = tracker
+                    .listeners
+                    .get_key_value(&chan.monitor)
+                    .map(|(monitor, _)| monitor.is_done(min_depth))
+                    .unwrap_or(false);
+                if is_done {
+                    done.push((chan.id0.clone(), chan.id.clone(), chan.monitor.clone()));
+                }
+            }
+        }
+
+        let mut archived = Vec::new();
+        for (id0, id, monitor) in done {
+            self.persister
+                .archive_channel(&self.get_id(), &id0)
+                .map_err(|_| internal_error("archive failed"))?;
+            info!("{} archived channel {}", self.log_prefix(), id0);
+            channels.remove(&id0);
+            id.map(|id| channels.remove(&id));
+            tracker.listeners.remove(&monitor);
+            archived.push(id0);
+        }
+
+        if !archived.is_empty() {
+            self.persister
+                .update_tracker(&self.get_id(), &tracker)
+                .map_err(|_| internal_error("tracker persist failed"))?;
+        }
+        Ok(archived)
+    }
+
+    /// The channels archived by [`Node::archive_closed_channels`], for audits
+    pub fn archived_channels(&self) -> Vec<(ChannelId, ChannelEntry)> {
+        self.persister.get_node_archived_channels(&self.get_id())
+    }
+
+    /// Forget a channel that never became usable, for example because the
+    /// channel open failed.
+    ///
+    /// A stub can always be forgotten.  A ready channel is never forgotten
+    /// once a commitment or mutual close was signed, since it may have funds
+    /// at stake, and otherwise only if the policy allows it.
+    pub fn forget_channel(&self, channel_id: &ChannelId) -> Result<(), Status> {
+        let mut tracker = self.tracker.lock().unwrap();
+        let mut channels = self.channels.lock().unwrap();
+        let slot_arc = channels
+            .get(channel_id)
+            .ok_or_else(|| invalid_argument(format!("no such channel: {}", channel_id)))?
+            .clone();
+        let slot = slot_arc.lock().unwrap();
+        let (id0, id) = match &*slot {
+            ChannelSlot::Stub(stub) => (stub.id0.clone(), None),
+            ChannelSlot::Ready(chan) => {
+                let estate = &chan.enforcement_state;
+                if estate.next_holder_commit_num > 0 || estate.next_counterparty_commit_num > 0 {
+                    return Err(failed_precondition(format!(
+                        "channel has signed commitments: holder {} counterparty {}",
+                        estate.next_holder_commit_num, estate.next_counterparty_commit_num
+                    )));
+                }
+                if estate.mutual_close_signed {
+                    return Err(failed_precondition("channel has signed a mutual close"));
+                }
+                chan.validator().validate_forget_channel(estate)?;
+                tracker.listeners.remove(&chan.monitor);
+                (chan.id0.clone(), chan.id.clone())
+            }
+        };
+        self.persister
+            .delete_channel(&self.get_id(), &id0)
+            .map_err(|_| internal_error("delete failed"))?;
+        info!("{} forgot channel {}", self.log_prefix(), id0);
+        channels.remove(&id0);
+        id.map(|id| channels.remove(&id));
+        if let ChannelSlot::Ready(_) = &*slot {
+            self.persister
+                .update_tracker(&self.get_id(), &tracker)
+                .map_err(|_| internal_error("tracker persist failed"))?;
+        }
+        Ok(())
+    }
+
+    /// Forget stubs that did not become ready within the number of blocks
+    /// specified by the policy.
+    ///
+    /// Returns the IDs of the forgotten stubs.
+    pub fn forget_stale_stubs(&self) -> Result<Vec<ChannelId>, Status> {
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        let timeout = match validator.stub_timeout_blocks() {
+            Some(timeout) => timeout,
+            None => return Ok(Vec::new()),
+        };
+        let height = self.get_tracker().height();
+        let mut channels = self.channels.lock().unwrap();
+
+        let stale: Vec<ChannelId> = channels
+            .values()
+            .filter_map(|slot_arc| match &*slot_arc.lock().unwrap() {
+                ChannelSlot::Stub(stub) if height >= stub.blockheight.saturating_add(timeout) =>
+                    Some(stub.id0.clone()),
+                _ => None,
+            })
+            .collect();
+
+        for id0 in stale.iter() {
+            self.persister
+                .delete_channel(&self.get_id(), id0)
+                .map_err(|_| internal_error("delete failed"))?;
+            info!("{} forgot stale stub {}", self.log_prefix(), id0);
+            channels.remove(id0);
+        }
+        Ok(stale)
+    }
+
     #[allow(dead_code)]
     pub(crate) fn get_secure_random_bytes(&self) -> [u8; 32] {
         self.keys_manager.get_secure_random_bytes()
@@ -723,7 +1211,7 @@ impl Node {
             ChannelSlot::Stub(stub) => stub as &mut ChannelBase,
             ChannelSlot::Ready(chan) => chan as &mut ChannelBase,
         };
-        f(base)
+        f(base).map_err(|s| s.with_channel_id(channel_id))
     }
 
     /// Execute a function with an existing ready channel.
@@ -738,7 +1226,7 @@ impl Node {
         match &mut *slot {
             ChannelSlot::Stub(_) =>
                 Err(invalid_argument(format!("channel not ready: {}", &channel_id))),
-            ChannelSlot::Ready(chan) => f(chan),
+            ChannelSlot::Ready(chan) => f(chan).map_err(|s| s.with_channel_id(channel_id)),
         }
     }
 
@@ -768,6 +1256,13 @@ impl Node {
         arc_self: &Arc<Node>,
     ) -> Result<(ChannelId, Option<ChannelStub>), Status> {
         let channel_id = opt_channel_id.unwrap_or_else(|| self.keys_manager.get_channel_id());
+        let blockheight = self.get_tracker().height();
+        let max_stubs = self
+            .validator_factory
+            .lock()
+            .unwrap()
+            .make_validator(self.network(), self.get_id(), None)
+            .max_stubs();
         let mut channels = self.channels.lock().unwrap();
 
         // Is there an existing channel slot?
@@ -788,6 +1283,26 @@ impl Node {
             };
         }
 
+        if let Some(max_stubs) = max_stubs {
+            let stubs = channels
+                .values()
+                .filter(|slot| matches!(&*slot.lock().unwrap(), ChannelSlot::Stub(_)))
+                .count();
+            if stubs >= max_stubs {
+                return Err(resource_exhausted(format!(
+                    "{} channel stubs already open, limit is {}",
+                    stubs, max_stubs
+                ))
+                .with_details(ErrorDetails {
+                    kind: Some("resource".to_string()),
+                    rule: Some("stub-limit".to_string()),
+                    value: Some(stubs.to_string()),
+                    allowed: Some(max_stubs.to_string()),
+                    ..Default::default()
+                }));
+            }
+        }
+
         let channel_value_sat = 0; // Placeholder value, not known yet.
         let keys =
             self.keys_manager.get_channel_keys_with_id(channel_id.clone(), channel_value_sat);
@@ -797,6 +1312,7 @@ impl Node {
             secp_ctx: Secp256k1::new(),
             keys,
             id0: channel_id.clone(),
+            blockheight,
         };
         // TODO this clone is expensive
         channels.insert(channel_id.clone(), Arc::new(Mutex::new(ChannelSlot::Stub(stub.clone()))));
@@ -815,8 +1331,11 @@ impl Node {
         channel_value_sat: u64,
         channel_setup: Option<ChannelSetup>,
         enforcement_state: EnforcementState,
+        blockheight: Option<u32>,
         arc_self: &Arc<Node>,
     ) -> Result<Arc<Mutex<ChannelSlot>>, ()> {
+        // Stubs persisted before the creation height was recorded start their timeout now
+        let blockheight = blockheight.unwrap_or_else(|| self.get_tracker().height());
         let mut channels = self.channels.lock().unwrap();
         assert!(!channels.contains_key(&channel_id0));
         let mut keys =
@@ -829,6 +1348,7 @@ impl Node {
                     secp_ctx: Secp256k1::new(),
                     keys,
                     id0: channel_id0.clone(),
+                    blockheight,
                 };
                 // TODO this clone is expensive
                 let slot = Arc::new(Mutex::new(ChannelSlot::Stub(stub.clone())));
@@ -889,8 +1409,16 @@ impl Node {
             .collect::<Result<_, _>>()
             .expect("allowable parse error");
         let tracker = persister.get_tracker(node_id).expect("tracker");
-        // FIXME persist node state
-        let state = NodeState::new();
+        // FIXME persist the rest of the node state
+        let mut state = NodeState::new();
+        for entry in persister.get_node_keysends(node_id) {
+            let keysend = KeysendState {
+                payee: entry.payee,
+                amount_msat: entry.amount_msat,
+                added_height: entry.added_height,
+            };
+            state.keysends.insert(entry.payment_hash, keysend);
+        }
 
         let node = Arc::new(Node::new_from_persistence(
             config,
@@ -903,6 +1431,12 @@ impl Node {
         ));
         assert_eq!(&node.get_id(), node_id);
         info!("Restore node {}", node_id);
+        let policy_changes = persister.get_node_policy_changes(node_id);
+        if let Some(change) = policy_changes.last() {
+            info!("  Restore policy changed by {} at {}", change.changed_by, change.timestamp);
+            node.set_validator_policy(change.policy.clone());
+        }
+        *node.policy_changes.lock().unwrap() = policy_changes;
         for (channel_id0, channel_entry) in persister.get_node_channels(node_id) {
             info!("  Restore channel {}", channel_id0);
             node.restore_channel(
@@ -911,6 +1445,7 @@ impl Node {
                 channel_entry.channel_value_satoshis,
                 channel_entry.channel_setup,
                 channel_entry.enforcement_state,
+                channel_entry.blockheight,
                 &node,
             )
             .expect("restore channel");
@@ -981,21 +1516,9 @@ impl Node {
             let funding_outpoint = setup.funding_outpoint;
             let monitor = ChainMonitor::new(funding_outpoint, tracker.height());
             monitor.add_funding_outpoint(&funding_outpoint);
-            let to_holder_msat = if setup.is_outbound {
-                // This is also checked in the validator, but we have to check
-                // here because we need it to create the validator
-                (setup.channel_value_sat * 1000).checked_sub(setup.push_value_msat).ok_or_else(
-                    || {
-                        policy_error(format!(
-                            "beneficial channel value underflow: {} - {}",
-                            setup.channel_value_sat * 1000,
-                            setup.push_value_msat
-                        ))
-                    },
-                )?
-            } else {
-                setup.push_value_msat
-            };
+            // This is also checked in the validator, but we have to check
+            // here because we need it to create the validator
+            let to_holder_msat = setup.initial_holder_value_msat().map_err(policy_error)?;
             let initial_holder_value_sat = validator.minimum_initial_balance(to_holder_msat);
             let enforcement_state = EnforcementState::new(initial_holder_value_sat);
             Channel {
@@ -1070,6 +1593,10 @@ impl Node {
     ///   script parameters and the redeemscript.
     /// * `opaths` - derivation path for change, one per output.  Empty for
     ///   non-change outputs.
+    /// * `prev_txs` - the transaction spent by each input, if known, or an
+    ///   empty vector.  The input amounts are taken from these after checking
+    ///   them against the outpoints.  Required by policy for non-segwit inputs,
+    ///   since their signatures don't commit to the amount.
     pub fn sign_onchain_tx(
         &self,
         tx: &bitcoin::Transaction,
@@ -1078,9 +1605,35 @@ impl Node {
         spendtypes: &Vec<SpendType>,
         uniclosekeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
         opaths: &Vec<Vec<u32>>,
+        prev_txs: &Vec<Option<Transaction>>,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        self.sign_onchain_tx_with_spent(
+            tx,
+            ipaths,
+            values_sat,
+            spendtypes,
+            uniclosekeys,
+            opaths,
+            prev_txs,
+            &vec![],
+        )
+    }
+
+    // As sign_onchain_tx, with the outputs spent by the inputs where known,
+    // including inputs which are not ours.  These are needed for taproot
+    // signatures, which commit to all of the spent outputs.
+    fn sign_onchain_tx_with_spent(
+        &self,
+        tx: &bitcoin::Transaction,
+        ipaths: &Vec<Vec<u32>>,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        uniclosekeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
+        opaths: &Vec<Vec<u32>>,
+        prev_txs: &Vec<Option<Transaction>>,
+        spent_outputs: &Vec<Option<TxOut>>,
     ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
         let channels_lock = self.channels.lock().unwrap();
-        let secp_ctx = Secp256k1::signing_only();
 
         // Funding transactions cannot be associated with just a single channel;
         // a single transaction may fund multiple channels
@@ -1101,7 +1654,85 @@ impl Node {
             })
             .collect();
 
-        validator.validate_onchain_tx(self, channels.clone(), tx, values_sat, opaths)?;
+        let values_sat = &input_values_from_prev_txs(tx, values_sat, spendtypes, prev_txs)?;
+
+        validator.validate_onchain_tx(
+            self,
+            channels.clone(),
+            tx,
+            values_sat,
+            spendtypes,
+            prev_txs,
+            opaths,
+        )?;
+
+        let witvec = self.sign_wallet_inputs(
+            tx,
+            ipaths,
+            values_sat,
+            spendtypes,
+            uniclosekeys,
+            prev_txs,
+            spent_outputs,
+        )?;
+
+        // The tracker may be updated for multiple channels
+        let mut tracker = self.tracker.lock().unwrap();
+
+        // This locks channels in a random order, so we have to keep a global
+        // lock to ensure no deadlock.  We grab the self.channels mutex above
+        // for this purpose.
+        // TODO(devrandom) consider sorting instead
+        for (vout, slot_opt) in channels.iter().enumerate() {
+            if let Some(slot_mutex) = slot_opt {
+                let slot = slot_mutex.lock().unwrap();
+                match &*slot {
+                    ChannelSlot::Stub(_) => panic!("this can't happen"),
+                    ChannelSlot::Ready(chan) => {
+                        let inputs =
+                            OrderedSet::from_iter(tx.input.iter().map(|i| i.previous_output));
+                        tracker.add_listener_watches(chan.monitor.clone(), inputs);
+                        chan.funding_signed(tx, vout as u32)
+                    }
+                }
+            }
+        }
+
+        // the channels added some watches - persist
+        self.persister
+            .update_tracker(&self.get_id(), &tracker)
+            .map_err(|_| internal_error("tracker persist failed"))?;
+
+        // TODO(devrandom) self.persist_channel(node_id, chan);
+        Ok(witvec)
+    }
+
+    // Sign the wallet inputs of an already validated transaction.
+    // Inputs marked as SpendType::Invalid get an empty witness stack.
+    fn sign_wallet_inputs(
+        &self,
+        tx: &bitcoin::Transaction,
+        ipaths: &Vec<Vec<u32>>,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        uniclosekeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
+        prev_txs: &Vec<Option<Transaction>>,
+        spent_outputs: &Vec<Option<TxOut>>,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        let secp_ctx = Secp256k1::signing_only();
+        let prevouts = if spendtypes.contains(&SpendType::P2tr) {
+            Some(self.onchain_prevouts(
+                tx,
+                ipaths,
+                values_sat,
+                spendtypes,
+                &uniclosekeys,
+                prev_txs,
+                spent_outputs,
+            )?)
+        } else {
+            None
+        };
 
         let mut witvec: Vec<Vec<Vec<u8>>> = Vec::new();
         for (idx, uck) in uniclosekeys.into_iter().enumerate() {
@@ -1110,6 +1741,14 @@ impl Node {
                 // marked as SpendType::Invalid (we skip these), push
                 // an empty witness element instead.
                 witvec.push(vec![]);
+            } else if spendtypes[idx] == SpendType::P2tr {
+                if uck.is_some() {
+                    return Err(invalid_argument("unilateral close key cannot be spent as p2tr"));
+                }
+                // The BIP341 sighash commits to all of the spent outputs
+                let prevouts = prevouts.as_ref().expect("taproot prevouts");
+                let sig = self.sign_taproot_input(tx, idx, prevouts, &ipaths[idx])?;
+                witvec.push(vec![sig]);
             } else {
                 let value_sat = values_sat[idx];
                 let (privkey, mut witness) = match uck {
@@ -1168,39 +1807,469 @@ impl Node {
                 witvec.push(witness);
             }
         }
+        Ok(witvec)
+    }
 
-        // The tracker may be updated for multiple channels
-        let mut tracker = self.tracker.lock().unwrap();
-
-        // This locks channels in a random order, so we have to keep a global
-        // lock to ensure no deadlock.  We grab the self.channels mutex above
-        // for this purpose.
-        // TODO(devrandom) consider sorting instead
-        for (vout, slot_opt) in channels.iter().enumerate() {
-            if let Some(slot_mutex) = slot_opt {
-                let slot = slot_mutex.lock().unwrap();
-                match &*slot {
-                    ChannelSlot::Stub(_) => panic!("this can't happen"),
-                    ChannelSlot::Ready(chan) => {
-                        let inputs =
-                            OrderedSet::from_iter(tx.input.iter().map(|i| i.previous_output));
-                        tracker.add_listener_watches(chan.monitor.clone(), inputs);
-                        chan.funding_signed(tx, vout as u32)
-                    }
-                }
-            }
+    /// Validate a splice transaction for a ready channel and move the
+    /// channel to the new funding output.
+    ///
+    /// The splice spends the current funding outpoint and creates a new
+    /// funding output at `new_funding_vout`.  It may add funds from our
+    /// wallet, or take channel funds to our wallet or to allowlisted
+    /// addresses.  The enforcement state carries over, and commitments
+    /// signed after this call spend the new funding output.
+    ///
+    /// Sign the transaction with [Node::sign_splice_tx] once a holder
+    /// commitment for the new funding output was validated.  Until then,
+    /// the splice can be replaced after [Node::abort_splice].  Another
+    /// splice is only allowed once this one is [SPLICE_BURIED_DEPTH] deep.
+    ///
+    /// * `holder_delta_sat` - the change in our channel balance agreed
+    ///   with the counterparty, negative for a splice-out.  Our inputs,
+    ///   less our share of the fee and this change, must go to our wallet
+    ///   or allowlisted outputs.
+    /// * `values_sat` - the amount in satoshi per input
+    /// * `spendtypes` - spend type per input, or `Invalid` for the
+    ///   funding input and for inputs that are not ours
+    /// * `opaths` - derivation path for change, one per output.  Empty for
+    ///   non-change outputs.
+    pub fn splice_channel(
+        &self,
+        channel_id: &ChannelId,
+        tx: &Transaction,
+        new_funding_vout: u32,
+        holder_delta_sat: i64,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), Status> {
+        if values_sat.len() != tx.input.len() || spendtypes.len() != tx.input.len() {
+            return Err(invalid_argument(format!(
+                "{} inputs but {} values and {} spend types",
+                tx.input.len(),
+                values_sat.len(),
+                spendtypes.len()
+            )));
+        }
+        if opaths.len() != tx.output.len() {
+            return Err(invalid_argument(format!(
+                "{} outputs but {} output paths",
+                tx.output.len(),
+                opaths.len()
+            )));
         }
 
-        // the channels added some watches - persist
+        // lock order is tracker -> channels, as in ready_channel
+        let tracker = self.tracker.lock().unwrap();
+        self.with_ready_channel(channel_id, |chan| {
+            let validator = chan.validator();
+            let info = validator.decode_splice_tx(
+                self,
+                &chan.keys,
+                &chan.setup,
+                tx,
+                new_funding_vout,
+                holder_delta_sat,
+                values_sat,
+                spendtypes,
+                opaths,
+            )?;
+            validator.validate_splice_tx(
+                &chan.setup,
+                &chan.monitor.as_chain_state(),
+                &chan.enforcement_state,
+                &info,
+            )?;
+            debug!("{}: splicing channel {}: {:?}", short_function!(), channel_id, info);
+
+            let keys = self
+                .keys_manager
+                .get_channel_keys_with_id(chan.id0.clone(), info.channel_value_sat);
+            chan.apply_splice(keys, &info);
+            chan.monitor.add_splice(tx, new_funding_vout);
+            self.persister
+                .update_channel(&self.get_id(), chan)
+                .map_err(|_| internal_error("persist failed"))
+        })?;
+        // the monitor state changed
         self.persister
             .update_tracker(&self.get_id(), &tracker)
-            .map_err(|_| internal_error("tracker persist failed"))?;
+            .map_err(|_| internal_error("tracker persist failed"))
+    }
 
-        // TODO(devrandom) self.persist_channel(node_id, chan);
-        Ok(witvec)
+    /// Abort the last splice of a channel and move the channel back to the
+    /// prior funding output.
+    ///
+    /// This is only allowed before any commitment spending the new funding
+    /// output was signed or validated, so the splice transaction was not
+    /// signed and cannot confirm.  To replace the splice (RBF), abort it and
+    /// call [Node::splice_channel] with the replacement.
+    pub fn abort_splice(&self, channel_id: &ChannelId) -> Result<(), Status> {
+        // lock order is tracker -> channels, as in ready_channel
+        let tracker = self.tracker.lock().unwrap();
+        self.with_ready_channel(channel_id, |chan| {
+            let prior_channel_value_sat = chan
+                .enforcement_state
+                .splice
+                .as_ref()
+                .ok_or_else(|| invalid_argument(format!("channel {} was not spliced", channel_id)))?
+                .prior_channel_value_sat;
+            let keys = self
+                .keys_manager
+                .get_channel_keys_with_id(chan.id0.clone(), prior_channel_value_sat);
+            let splice_txid = chan.setup.funding_outpoint.txid;
+            chan.abort_splice(keys)?;
+            chan.monitor.remove_splice(&splice_txid);
+            debug!(
+                "{}: aborted splice {} of channel {}",
+                short_function!(),
+                splice_txid,
+                channel_id
+            );
+            self.persister
+                .update_channel(&self.get_id(), chan)
+                .map_err(|_| internal_error("persist failed"))
+        })?;
+        // the monitor state changed
+        self.persister
+            .update_tracker(&self.get_id(), &tracker)
+            .map_err(|_| internal_error("tracker persist failed"))
+    }
+
+    /// Sign a splice transaction that was validated with
+    /// [Node::splice_channel].
+    ///
+    /// Returns our signature for the funding input, and a witness stack for
+    /// each input, which is empty for inputs that are not ours.  The arguments
+    /// are as for [Node::sign_onchain_tx].  Only the inputs which were ours
+    /// when the splice was validated are signed, with the validated values.
+    pub fn sign_splice_tx(
+        &self,
+        channel_id: &ChannelId,
+        tx: &Transaction,
+        ipaths: &Vec<Vec<u32>>,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        prev_txs: &Vec<Option<Transaction>>,
+    ) -> Result<(Signature, Vec<Vec<Vec<u8>>>), Status> {
+        if values_sat.len() != tx.input.len() || spendtypes.len() != tx.input.len() {
+            return Err(invalid_argument(format!(
+                "{} inputs but {} values and {} spend types",
+                tx.input.len(),
+                values_sat.len(),
+                spendtypes.len()
+            )));
+        }
+        let (sig, splice) = self.with_ready_channel(channel_id, |chan| {
+            let sig = chan.sign_splice_funding_input(tx)?;
+            let splice = chan.enforcement_state.splice.clone().expect("checked when signing");
+            Ok((sig, splice))
+        })?;
+
+        // The transaction is the validated one, so only the spend types and
+        // values could differ from what was validated
+        let mut values_sat = values_sat.clone();
+        for (idx, input) in tx.input.iter().enumerate() {
+            match splice.holder_input_values.get(idx) {
+                Some(Some(value)) if spendtypes[idx] != SpendType::Invalid =>
+                    values_sat[idx] = *value,
+                Some(None) if spendtypes[idx] == SpendType::Invalid => {
+                    // we know the value of the funding input better than the caller
+                    if input.previous_output == splice.prior_funding_outpoint {
+                        values_sat[idx] = splice.prior_channel_value_sat;
+                    }
+                }
+                _ =>
+                    return Err(invalid_argument(format!(
+                        "input {}: spend type {:?} differs from the validated splice",
+                        idx, spendtypes[idx]
+                    ))),
+            }
+        }
+        let values_sat = input_values_from_prev_txs(tx, &values_sat, spendtypes, prev_txs)?;
+        let uniclosekeys = vec![None; tx.input.len()];
+        let witvec = self.sign_wallet_inputs(
+            tx,
+            ipaths,
+            &values_sat,
+            spendtypes,
+            uniclosekeys,
+            prev_txs,
+            &vec![],
+        )?;
+        Ok((sig, witvec))
+    }
+
+    /// Sign the wallet inputs of a PSBT.
+    ///
+    /// Wallet inputs are recognized by a spent output (`non_witness_utxo` or
+    /// `witness_utxo`) that matches the wallet address at one of the
+    /// `bip32_derivation` (or `tap_key_origins`) paths.  Change outputs are
+    /// identified by their derivation paths in the same way.  Other inputs are
+    /// left for someone else to sign.
+    ///
+    /// Returns a copy of the PSBT with `partial_sigs` filled in for our
+    /// segwit inputs and `tap_key_sig` for our taproot inputs.
+    pub fn sign_psbt(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, Status> {
+        self.sign_psbt_with_keys(psbt, &vec![], vec![])
     }
 
-    fn channel_setup_to_channel_transaction_parameters(
+    /// Sign a PSBT, given signing information which the PSBT doesn't carry.
+    ///
+    /// * `ipaths` - the wallet derivation path per input, or empty to use the
+    ///   PSBT derivation paths of that input.  May be empty.
+    /// * `uniclosekeys` - an optional unilateral close key per input, as for
+    ///   [`Node::sign_onchain_tx`].  May be empty.  If the PSBT input has a
+    ///   `witness_script`, it must match the redeemscript in the witness stack.
+    ///
+    /// Inputs signed with a unilateral close key get a `final_script_witness`,
+    /// since the PSBT can't describe their witness.  Otherwise as for
+    /// [`Node::sign_psbt`].
+    pub fn sign_psbt_with_keys(
+        &self,
+        psbt: &PartiallySignedTransaction,
+        ipaths: &Vec<Vec<u32>>,
+        uniclosekeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
+    ) -> Result<PartiallySignedTransaction, Status> {
+        let tx = &psbt.unsigned_tx;
+        if psbt.inputs.len() != tx.input.len() || psbt.outputs.len() != tx.output.len() {
+            return Err(invalid_argument("psbt input or output count mismatch"));
+        }
+        if (!ipaths.is_empty() && ipaths.len() != tx.input.len())
+            || (!uniclosekeys.is_empty() && uniclosekeys.len() != tx.input.len())
+        {
+            return Err(invalid_argument(
+                "psbt input paths or unilateral close keys count mismatch",
+            ));
+        }
+        let uniclosekeys =
+            if uniclosekeys.is_empty() { vec![None; tx.input.len()] } else { uniclosekeys };
+
+        let mut input_paths = Vec::new();
+        let mut values_sat = Vec::new();
+        let mut spendtypes = Vec::new();
+        let mut spent_outputs = Vec::new();
+        for (idx, input) in psbt.inputs.iter().enumerate() {
+            // prefer the full previous tx, which can be checked against the outpoint
+            let spent = match (&input.non_witness_utxo, &input.witness_utxo) {
+                (Some(prev_tx), _) => Some(spent_output(tx, idx, prev_tx)?.clone()),
+                (None, Some(utxo)) => Some(utxo.clone()),
+                (None, None) => None,
+            };
+            let (spendtype, ipath) = match (&spent, &uniclosekeys[idx]) {
+                (None, None) => (SpendType::Invalid, vec![]),
+                (None, Some(_)) =>
+                    return Err(invalid_argument(format!(
+                        "input {} has a unilateral close key but no spent output",
+                        idx
+                    ))),
+                (Some(spent), Some((_, stack))) => {
+                    let spendtype =
+                        unilateral_close_spend_type(idx, input, &spent.script_pubkey, stack)?;
+                    (spendtype, vec![])
+                }
+                (Some(spent), None) => {
+                    let ipath = ipaths.get(idx).filter(|p| !p.is_empty());
+                    let (spendtype, path) =
+                        self.psbt_input_spend(input, ipath, &spent.script_pubkey)?;
+                    // a path from the caller claims the input is ours
+                    if let (SpendType::Invalid, Some(path)) = (spendtype, ipath) {
+                        return Err(invalid_argument(format!(
+                            "input {}: path {:?} does not match the spent output",
+                            idx, path
+                        )));
+                    }
+                    (spendtype, path)
+                }
+            };
+            // only our inputs count towards the holder input value
+            let value_sat = match &spent {
+                Some(spent) if spendtype != SpendType::Invalid => spent.value,
+                _ => 0,
+            };
+            input_paths.push(ipath);
+            values_sat.push(value_sat);
+            spendtypes.push(spendtype);
+            spent_outputs.push(spent);
+        }
+        let prev_txs = psbt.inputs.iter().map(|i| i.non_witness_utxo.clone()).collect();
+        let opaths = psbt
+            .outputs
+            .iter()
+            .map(|o| psbt_derivation_path(&o.bip32_derivation, &o.tap_key_origins))
+            .collect::<Result<Vec<_>, _>>()?;
+        let is_uniclose: Vec<bool> = uniclosekeys.iter().map(|k| k.is_some()).collect();
+
+        let witvec = self.sign_onchain_tx_with_spent(
+            tx,
+            &input_paths,
+            &values_sat,
+            &spendtypes,
+            uniclosekeys,
+            &opaths,
+            &prev_txs,
+            &spent_outputs,
+        )?;
+
+        let mut signed = psbt.clone();
+        for (idx, stack) in witvec.into_iter().enumerate() {
+            match spendtypes[idx] {
+                SpendType::Invalid => {}
+                _ if is_uniclose[idx] => {
+                    signed.inputs[idx].final_script_witness = Some(Witness::from_vec(stack));
+                }
+                SpendType::P2tr => {
+                    let sig = SchnorrSig::from_slice(&stack[0])
+                        .map_err(|err| internal_error(format!("bad schnorr sig: {}", err)))?;
+                    signed.inputs[idx].tap_key_sig = Some(sig);
+                }
+                _ => {
+                    let sig = EcdsaSig::from_slice(&stack[0])
+                        .map_err(|err| internal_error(format!("bad ecdsa sig: {}", err)))?;
+                    let pubkey = bitcoin::PublicKey::from_slice(&stack[1])
+                        .map_err(|err| internal_error(format!("bad pubkey: {}", err)))?;
+                    signed.inputs[idx].partial_sigs.insert(pubkey, sig);
+                }
+            }
+        }
+        Ok(signed)
+    }
+
+    // Determine whether a PSBT input spends one of our wallet outputs, and how.
+    // The given path is used instead of the PSBT derivation paths, if any.
+    fn psbt_input_spend(
+        &self,
+        input: &PsbtInput,
+        ipath: Option<&Vec<u32>>,
+        script_pubkey: &Script,
+    ) -> Result<(SpendType, Vec<u32>), Status> {
+        let secp_ctx = Secp256k1::signing_only();
+        let paths: Vec<Vec<u32>> = match ipath {
+            Some(path) => vec![path.clone()],
+            None => input
+                .bip32_derivation
+                .values()
+                .chain(input.tap_key_origins.values().map(|(_, source)| source))
+                .filter_map(key_source_child_path)
+                .collect(),
+        };
+        let key_path_len = self.node_config.key_derivation_style.get_key_path_len();
+        for path in paths.into_iter().filter(|p| p.len() == key_path_len) {
+            let spendtype = if script_pubkey.is_v1_p2tr() {
+                if self.get_taproot_address(&path)?.script_pubkey() == *script_pubkey {
+                    SpendType::P2tr
+                } else {
+                    SpendType::Invalid
+                }
+            } else if self.get_native_address(&path)?.script_pubkey() == *script_pubkey {
+                SpendType::P2wpkh
+            } else if self.get_wrapped_address(&path)?.script_pubkey() == *script_pubkey {
+                SpendType::P2shP2wpkh
+            } else if Address::p2pkh(&self.get_wallet_pubkey(&secp_ctx, &path)?, self.network())
+                .script_pubkey()
+                == *script_pubkey
+            {
+                SpendType::P2pkh
+            } else {
+                SpendType::Invalid
+            };
+            if spendtype != SpendType::Invalid {
+                return Ok((spendtype, path));
+            }
+        }
+        Ok((SpendType::Invalid, vec![]))
+    }
+
+    // Reconstruct the outputs spent by the transaction, as needed for the
+    // BIP341 signature hash.  Every input must be ours.
+    fn onchain_prevouts(
+        &self,
+        tx: &bitcoin::Transaction,
+        ipaths: &Vec<Vec<u32>>,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        uniclosekeys: &Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
+        prev_txs: &Vec<Option<Transaction>>,
+        spent_outputs: &Vec<Option<TxOut>>,
+    ) -> Result<Vec<TxOut>, Status> {
+        let secp_ctx = Secp256k1::signing_only();
+        let mut prevouts = Vec::new();
+        for idx in 0..tx.input.len() {
+            // a previous tx has already been checked against the outpoint
+            if let Some(Some(prev_tx)) = prev_txs.get(idx) {
+                prevouts.push(spent_output(tx, idx, prev_tx)?.clone());
+                continue;
+            }
+            // a wrong spent output only makes our signature invalid
+            if let Some(Some(spent)) = spent_outputs.get(idx) {
+                prevouts.push(spent.clone());
+                continue;
+            }
+            let script_pubkey = match (spendtypes[idx], &uniclosekeys[idx]) {
+                (SpendType::Invalid, _) =>
+                    return Err(invalid_argument(format!(
+                        "p2tr signing needs the spent output for input {}",
+                        idx
+                    ))),
+                (SpendType::P2wsh, Some((_, stack))) => {
+                    let redeemscript = stack.last().ok_or_else(|| {
+                        invalid_argument(format!("empty witness stack for input {}", idx))
+                    })?;
+                    Script::from(redeemscript.clone()).to_v0_p2wsh()
+                }
+                (SpendType::P2wpkh, Some((key, _))) => {
+                    let pubkey =
+                        bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp_ctx, key));
+                    Address::p2wpkh(&pubkey, self.network()).expect("p2wpkh failed").script_pubkey()
+                }
+                (SpendType::P2tr, None) => self.get_taproot_address(&ipaths[idx])?.script_pubkey(),
+                (SpendType::P2pkh, None) => {
+                    let pubkey = self.get_wallet_pubkey(&secp_ctx, &ipaths[idx])?;
+                    Address::p2pkh(&pubkey, self.network()).script_pubkey()
+                }
+                (SpendType::P2wpkh, None) => self.get_native_address(&ipaths[idx])?.script_pubkey(),
+                (SpendType::P2shP2wpkh, None) =>
+                    self.get_wrapped_address(&ipaths[idx])?.script_pubkey(),
+                (st, _) =>
+                    return Err(invalid_argument(format!(
+                    "p2tr signing can't determine the spent output for input {} spend_type={:?}",
+                    idx, st
+                ))),
+            };
+            prevouts.push(TxOut { value: values_sat[idx], script_pubkey });
+        }
+        Ok(prevouts)
+    }
+
+    // Sign a BIP86 key path spend, returning the witness signature
+    fn sign_taproot_input(
+        &self,
+        tx: &bitcoin::Transaction,
+        idx: usize,
+        prevouts: &Vec<TxOut>,
+        child_path: &Vec<u32>,
+    ) -> Result<Vec<u8>, Status> {
+        // tweaking needs a verification capable context
+        let secp_ctx = &self.secp_ctx;
+        let keypair = self.get_taproot_wallet_keypair(secp_ctx, child_path)?;
+        let tweaked = keypair.tap_tweak(secp_ctx, None).into_inner();
+        let sighash = SighashCache::new(tx)
+            .taproot_key_spend_signature_hash(
+                idx,
+                &Prevouts::All(prevouts),
+                SchnorrSighashType::Default,
+            )
+            .map_err(|err| internal_error(format!("taproot sighash failed: {}", err)))?;
+        let message = Message::from_slice(&sighash)
+            .map_err(|err| internal_error(format!("sighash P2tr failed: {}", err)))?;
+        let sig = secp_ctx.sign_schnorr_no_aux_rand(&message, &tweaked);
+        Ok(SchnorrSig { sig, hash_ty: SchnorrSighashType::Default }.to_vec())
+    }
+
+    pub(crate) fn channel_setup_to_channel_transaction_parameters(
         setup: &ChannelSetup,
         holder_pubkeys: &ChannelPublicKeys,
     ) -> ChannelTransactionParameters {
@@ -1222,9 +2291,9 @@ impl Node {
         channel_transaction_parameters
     }
 
-    pub(crate) fn get_wallet_privkey(
+    pub(crate) fn get_wallet_privkey<C: secp256k1::Signing>(
         &self,
-        secp_ctx: &Secp256k1<secp256k1::SignOnly>,
+        secp_ctx: &Secp256k1<C>,
         child_path: &Vec<u32>,
     ) -> Result<PrivateKey, Status> {
         if child_path.len() != self.node_config.key_derivation_style.get_key_path_len() {
@@ -1245,14 +2314,37 @@ impl Node {
         Ok(PrivateKey::new(xkey.private_key, self.network()))
     }
 
-    pub(crate) fn get_wallet_pubkey(
+    pub(crate) fn get_wallet_pubkey<C: secp256k1::Signing>(
         &self,
-        secp_ctx: &Secp256k1<secp256k1::SignOnly>,
+        secp_ctx: &Secp256k1<C>,
         child_path: &Vec<u32>,
     ) -> Result<bitcoin::PublicKey, Status> {
         Ok(self.get_wallet_privkey(secp_ctx, child_path)?.public_key(secp_ctx))
     }
 
+    pub(crate) fn get_taproot_wallet_keypair(
+        &self,
+        secp_ctx: &Secp256k1<All>,
+        child_path: &Vec<u32>,
+    ) -> Result<KeyPair, Status> {
+        if child_path.len() != self.node_config.key_derivation_style.get_key_path_len() {
+            return Err(invalid_argument(format!(
+                "get_taproot_wallet_keypair: bad child_path len : {}",
+                child_path.len()
+            )));
+        }
+        // Start with the base taproot xpriv for this wallet.
+        let mut xkey = self.keys_manager.get_taproot_account_extended_key().clone();
+
+        // Derive the rest of the child_path.
+        for elem in child_path {
+            xkey = xkey
+                .ckd_priv(&secp_ctx, ChildNumber::from_normal_idx(*elem).unwrap())
+                .map_err(|err| internal_error(format!("derive child_path failed: {}", err)))?;
+        }
+        Ok(KeyPair::from_secret_key(secp_ctx, xkey.private_key))
+    }
+
     /// Get the node secret key
     /// This function will be eliminated once the node key related items
     /// are implemented.  This includes onion decoding and p2p handshake.
@@ -1377,6 +2469,13 @@ impl Node {
         self.channels.lock().unwrap()
     }
 
+    /// The number of channels, counting a ready channel once although it
+    /// can be found under two IDs
+    pub fn channel_count(&self) -> usize {
+        let channels = self.channels.lock().unwrap();
+        channels.iter().filter(|(id, slot)| slot.lock().unwrap().id() == **id).count()
+    }
+
     /// Perform an ECDH operation between the node key and a public key
     /// This can be used for onion packet decoding
     pub fn ecdh(&self, other_key: &PublicKey) -> Vec<u8> {
@@ -1451,6 +2550,45 @@ impl Node {
         Ok(())
     }
 
+    /// Replace the node's policy.  The change is persisted along with
+    /// who made it and when, in seconds since the epoch.
+    ///
+    /// `changed_by` is self-asserted by the caller and recorded as is, it is
+    /// not authenticated here.
+    pub fn set_policy(
+        &self,
+        policy: SimplePolicy,
+        changed_by: String,
+        timestamp: u64,
+    ) -> Result<(), Status> {
+        policy.validate().map_err(|e| invalid_argument(format!("invalid policy: {}", e)))?;
+        let mut changes = self.policy_changes.lock().unwrap();
+        let change = PolicyChangeEntry { policy, changed_by, timestamp };
+        self.persister
+            .add_node_policy_change(&self.get_id(), &change)
+            .map_err(|_| internal_error("persist failed"))?;
+        info!(
+            "node {} policy changed by {} at {}: {:?}",
+            self.get_id(),
+            change.changed_by,
+            change.timestamp,
+            change.policy
+        );
+        self.set_validator_policy(change.policy.clone());
+        changes.push(change);
+        Ok(())
+    }
+
+    /// The node's policy, if it was set with [Node::set_policy]
+    pub fn get_policy(&self) -> Option<SimplePolicy> {
+        self.policy_changes.lock().unwrap().last().map(|c| c.policy.clone())
+    }
+
+    /// The history of policy changes, oldest first
+    pub fn policy_changes(&self) -> Vec<PolicyChangeEntry> {
+        self.policy_changes.lock().unwrap().clone()
+    }
+
     /// Chain tracker with lock
     pub fn get_tracker(&self) -> MutexGuard<'_, ChainTracker<ChainMonitor>> {
         self.tracker.lock().unwrap()
@@ -1465,8 +2603,10 @@ impl Node {
         channel_id: &ChannelId,
         preimages: Vec<PaymentPreimage>,
         validator: Arc<dyn Validator>,
+        height: u32,
     ) {
         let mut state = self.state.lock().unwrap();
+        state.drain_excess_amount(height, &*validator);
         for preimage in preimages.into_iter() {
             state.htlc_fulfilled(channel_id, preimage, Arc::clone(&validator));
         }
@@ -1476,7 +2616,7 @@ impl Node {
     /// Used by the signer to map HTLCs to destination payees, so that payee
     /// public keys can be allowlisted for policy control.
     pub fn add_invoice(&self, raw_invoice: SignedRawInvoice) -> Result<(), Status> {
-        let (hash, invoice_state, invoice_hash) = Self::invoice_state_from_invoice(raw_invoice)?;
+        let (hash, invoice_state, _invoice_hash) = Self::invoice_state_from_invoice(raw_invoice)?;
 
         info!(
             "{} adding invoice {} -> {}",
@@ -1485,8 +2625,130 @@ impl Node {
             invoice_state.amount_msat
         );
         let mut state = self.state.lock().unwrap();
-        if let Some(invoice_state) = state.invoices.get(&hash) {
-            return if invoice_state.invoice_hash == invoice_hash {
+        Self::insert_invoice(&mut state, hash, invoice_state)
+    }
+
+    /// Add a BOLT 12 invoice, as a TLV stream.
+    /// The invoice must be signed by the payee.  If it answers an
+    /// invoice_request we signed, it may not ask for more than the
+    /// requested amount.
+    pub fn add_bolt12_invoice(&self, tlv_stream: &[u8]) -> Result<(), Status> {
+        let invoice = Bolt12Invoice::parse(tlv_stream).map_err(invalid_argument)?;
+        invoice.verify_signature().map_err(invalid_argument)?;
+        let hash = PaymentHash(invoice.payment_hash);
+
+        info!(
+            "{} adding bolt12 invoice {} -> {}",
+            self.log_prefix(),
+            hash.0.to_hex(),
+            invoice.amount_msat
+        );
+        let mut state = self.state.lock().unwrap();
+        // policy-bolt12-requested-amount
+        if let Some(request) =
+            state.bolt12_invoice_requests.get(&invoice.invoice_request_merkle_root)
+        {
+            if let Some(requested_msat) = request.amount_msat {
+                if invoice.amount_msat > requested_msat {
+                    return Err(policy_error(format!(
+                        "add_bolt12_invoice: invoice amount {} exceeds requested amount {}",
+                        invoice.amount_msat, requested_msat
+                    ))
+                    .into());
+                }
+            }
+        }
+        Self::insert_invoice(&mut state, hash, Self::invoice_state_from_bolt12(&invoice))
+    }
+
+    /// Add a keysend payment, which has no invoice.
+    /// If invoices are required, the payee must be allowlisted or the
+    /// payment must fit in the keysend budgets.  An added keysend counts
+    /// against the budgets for the policy's keysend window, unless it fails.
+    ///
+    /// A failed keysend is only released in memory, and counts again until
+    /// the end of its window if the node is restored before the next
+    /// keysend is added.
+    pub fn add_keysend(
+        &self,
+        payee: PublicKey,
+        payment_hash: PaymentHash,
+        amount_msat: u64,
+    ) -> Result<(), Status> {
+        info!(
+            "{} adding keysend {} to {} -> {}",
+            self.log_prefix(),
+            payment_hash.0.to_hex(),
+            payee,
+            amount_msat
+        );
+        let allowlist = self.allowlist.lock().unwrap().clone();
+        let is_allowlisted = |payee: &PublicKey| allowlist.contains(&Allowable::Payee(*payee));
+        let height = self.get_tracker().height();
+        let keysend = KeysendState { payee, amount_msat, added_height: height };
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        let window_blocks = validator.keysend_window_blocks();
+
+        let mut state = self.state.lock().unwrap();
+        if state.invoices.contains_key(&payment_hash) {
+            return Err(failed_precondition("already have an invoice for same hash".to_string()));
+        }
+        if let Some(existing) = state.keysends.get(&payment_hash) {
+            return if existing.payee == payee && existing.amount_msat == amount_msat {
+                Ok(())
+            } else {
+                Err(failed_precondition(
+                    "already have a different keysend for same hash".to_string(),
+                ))
+            };
+        }
+        state.prune_keysends(height, window_blocks);
+        let (payee_total_msat, total_msat) = state
+            .keysends
+            .values()
+            .filter(|k| !is_allowlisted(&k.payee))
+            .filter(|k| {
+                window_blocks.map(|w| k.added_height.saturating_add(w) > height).unwrap_or(true)
+            })
+            .fold((0u64, 0u64), |(payee_total, total), k| {
+                let payee_amount = if k.payee == payee { k.amount_msat } else { 0 };
+                (payee_total.saturating_add(payee_amount), total.saturating_add(k.amount_msat))
+            });
+        validator.validate_keysend(
+            is_allowlisted(&payee),
+            amount_msat,
+            payee_total_msat,
+            total_msat,
+        )?;
+        state.keysends.insert(payment_hash, keysend);
+        state.payments.entry(payment_hash).or_insert_with(|| RoutedPayment::new());
+        let entries = state
+            .keysends
+            .iter()
+            .map(|(hash, k)| KeysendEntry {
+                payment_hash: *hash,
+                payee: k.payee,
+                amount_msat: k.amount_msat,
+                added_height: k.added_height,
+            })
+            .collect();
+        self.persister
+            .update_node_keysends(&self.get_id(), entries)
+            .map_err(|_| internal_error("keysend persist failed"))
+    }
+
+    // Start tracking an outgoing payment for an invoice
+    fn insert_invoice(
+        state: &mut NodeState,
+        hash: PaymentHash,
+        invoice_state: InvoiceState,
+    ) -> Result<(), Status> {
+        if let Some(existing) = state.invoices.get(&hash) {
+            return if existing.invoice_hash == invoice_state.invoice_hash {
                 Ok(())
             } else {
                 Err(failed_precondition(
@@ -1499,6 +2761,18 @@ impl Node {
         Ok(())
     }
 
+    // The merkle root serves as the invoice hash
+    fn invoice_state_from_bolt12(invoice: &Bolt12Invoice) -> InvoiceState {
+        InvoiceState {
+            invoice_hash: invoice.merkle_root,
+            amount_msat: invoice.amount_msat,
+            payee: invoice.node_id,
+            duration_since_epoch: invoice.created_at,
+            expiry_duration: invoice.relative_expiry,
+            is_fulfilled: false,
+        }
+    }
+
     // Validate the invoice and create a tracking state for it
     fn invoice_state_from_invoice(
         raw_invoice: SignedRawInvoice,
@@ -1552,6 +2826,132 @@ impl Debug for Node {
     }
 }
 
+// The output spent by an input, after checking that the previous tx matches the outpoint
+fn spent_output<'a>(
+    tx: &Transaction,
+    idx: usize,
+    prev_tx: &'a Transaction,
+) -> Result<&'a TxOut, Status> {
+    let outpoint = &tx.input[idx].previous_output;
+    if prev_tx.txid() != outpoint.txid {
+        return Err(invalid_argument(format!(
+            "input {}: previous tx {} does not match outpoint {}",
+            idx,
+            prev_tx.txid(),
+            outpoint
+        )));
+    }
+    prev_tx.output.get(outpoint.vout as usize).ok_or_else(|| {
+        invalid_argument(format!("input {}: previous tx has no output {}", idx, outpoint.vout))
+    })
+}
+
+// Take our input amounts from the previous transactions, where supplied.
+// An amount given by the caller must agree with the previous transaction.
+fn input_values_from_prev_txs(
+    tx: &Transaction,
+    values_sat: &Vec<u64>,
+    spendtypes: &Vec<SpendType>,
+    prev_txs: &Vec<Option<Transaction>>,
+) -> Result<Vec<u64>, Status> {
+    if !prev_txs.is_empty() && prev_txs.len() != tx.input.len() {
+        return Err(invalid_argument(format!(
+            "prev_txs len {} != inputs len {}",
+            prev_txs.len(),
+            tx.input.len()
+        )));
+    }
+    let mut values = values_sat.clone();
+    for (idx, prev_tx) in prev_txs.iter().enumerate() {
+        if let Some(prev_tx) = prev_tx {
+            let value = spent_output(tx, idx, prev_tx)?.value;
+            if spendtypes[idx] == SpendType::Invalid {
+                // not ours, so it doesn't count towards our inputs
+                continue;
+            }
+            if values[idx] != 0 && values[idx] != value {
+                return Err(invalid_argument(format!(
+                    "input {}: value {} does not match previous tx value {}",
+                    idx, values[idx], value
+                )));
+            }
+            values[idx] = value;
+        }
+    }
+    Ok(values)
+}
+
+// The wallet child path of a key source, if it has no hardened steps
+fn key_source_child_path(source: &KeySource) -> Option<Vec<u32>> {
+    let (_fingerprint, path) = source;
+    path.into_iter()
+        .map(|c| match c {
+            ChildNumber::Normal { index } => Some(*index),
+            ChildNumber::Hardened { .. } => None,
+        })
+        .collect()
+}
+
+/// The wallet path of a PSBT output, from its BIP32 derivation or taproot
+/// key origin, or empty if it is not ours.
+///
+/// Our wallet paths have no hardened steps, so an output with a full BIP32
+/// path from another wallet is not ours.  More than one derivation path is
+/// an error.
+pub fn psbt_derivation_path<K, V>(
+    bip32_derivation: &OrderedMap<PublicKey, KeySource>,
+    tap_key_origins: &OrderedMap<K, (V, KeySource)>,
+) -> Result<Vec<u32>, Status> {
+    let mut sources =
+        bip32_derivation.values().chain(tap_key_origins.values().map(|(_, source)| source));
+    let source = match sources.next() {
+        Some(source) => source,
+        None => return Ok(Vec::new()),
+    };
+    if sources.next().is_some() {
+        return Err(invalid_argument("psbt output has more than one derivation path"));
+    }
+    Ok(key_source_child_path(source).unwrap_or_default())
+}
+
+// The spend type of a PSBT input signed with a unilateral close key, after
+// checking the redeemscript against the PSBT and the spent output
+fn unilateral_close_spend_type(
+    idx: usize,
+    input: &PsbtInput,
+    script_pubkey: &Script,
+    stack: &Vec<Vec<u8>>,
+) -> Result<SpendType, Status> {
+    if !script_pubkey.is_v0_p2wsh() {
+        if input.witness_script.is_some() {
+            return Err(invalid_argument(format!(
+                "input {}: witness_script for a non-p2wsh unilateral close output",
+                idx
+            )));
+        }
+        return Ok(SpendType::P2wpkh);
+    }
+    let redeemscript = stack
+        .last()
+        .map(|s| Script::from(s.clone()))
+        .ok_or_else(|| invalid_argument(format!("empty witness stack for input {}", idx)))?;
+    if let Some(witness_script) = input.witness_script.as_ref() {
+        if *witness_script != redeemscript {
+            return Err(invalid_argument(format!(
+                "input {}: witness_script does not match the unilateral close script",
+                idx
+            )));
+        }
+    }
+    if redeemscript.to_v0_p2wsh() != *script_pubkey {
+        return Err(invalid_argument(format!(
+            "input {}: spent output does not match the unilateral close script",
+            idx
+        )));
+    }
+    Ok(SpendType::P2wsh)
+}
+
 /// The type of address, for layer-1 input signing
 #[derive(PartialEq, Clone, Copy, Debug)]
 #[repr(i32)]
@@ -1566,6 +2966,8 @@ pub enum SpendType {
     P2shP2wpkh = 4,
     /// Pay to witness script hash
     P2wsh = 5,
+    /// Pay to taproot, BIP86 key path spend
+    P2tr = 6,
 }
 
 impl TryFrom<i32> for SpendType {
@@ -1578,6 +2980,7 @@ impl TryFrom<i32> for SpendType {
             x if x == SpendType::P2wpkh as i32 => SpendType::P2wpkh,
             x if x == SpendType::P2shP2wpkh as i32 => SpendType::P2shP2wpkh,
             x if x == SpendType::P2wsh as i32 => SpendType::P2wsh,
+            x if x == SpendType::P2tr as i32 => SpendType::P2tr,
             _ => return Err(()),
         };
         Ok(res)
@@ -1597,6 +3000,7 @@ mod tests {
     use bitcoin::secp256k1;
     use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
     use bitcoin::secp256k1::SecretKey;
+    use bitcoin::util::bip32::{DerivationPath, Fingerprint};
     use bitcoin::util::sighash::SighashCache;
     use bitcoin::{Address, EcdsaSighashType, OutPoint};
     use lightning::ln::chan_utils::derive_private_key;
@@ -1605,7 +3009,10 @@ mod tests {
     use test_log::test;
 
     use crate::channel::ChannelBase;
+    use crate::policy::null_validator::NullValidatorFactory;
     use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
+    use crate::tx::tx::{CommitmentInfo2, HTLCInfo2};
+    use crate::util::key_utils::make_test_pubkey;
     use crate::util::status::{internal_error, invalid_argument, Code, Status};
     use crate::util::test_utils::*;
 
@@ -1760,6 +3167,676 @@ mod tests {
         (hrp_bytes, invoice_data)
     }
 
+    fn tlv(typ: u8, value: &[u8]) -> Vec<u8> {
+        let mut record = vec![typ, value.len() as u8];
+        record.extend_from_slice(value);
+        record
+    }
+
+    fn tu64(val: u64) -> Vec<u8> {
+        val.to_be_bytes().iter().cloned().skip_while(|b| *b == 0).collect()
+    }
+
+    fn make_test_bolt12_invoice_request(payer_node: &Node, amount_msat: u64) -> Vec<u8> {
+        let mut invreq = tlv(0, &[7; 8]);
+        invreq.extend(tlv(82, &tu64(amount_msat)));
+        invreq.extend(tlv(88, &payer_node.get_id().serialize()));
+        invreq
+    }
+
+    // The unsigned invoice answering `invreq`
+    fn make_test_bolt12_invoice(
+        payee_node: &Node,
+        invreq: &[u8],
+        payment_hash: PaymentHash,
+        amount_msat: u64,
+    ) -> Vec<u8> {
+        let mut invoice = invreq.to_vec();
+        invoice.extend(tlv(164, &tu64(123456789)));
+        invoice.extend(tlv(168, &payment_hash.0));
+        invoice.extend(tlv(170, &tu64(amount_msat)));
+        invoice.extend(tlv(176, &payee_node.get_id().serialize()));
+        invoice
+    }
+
+    fn sign_test_bolt12_invoice(payee_node: &Node, invoice: &[u8]) -> Vec<u8> {
+        let sig = payee_node.sign_bolt12_tlv(b"invoice", b"signature", invoice, None).unwrap();
+        let mut signed = invoice.to_vec();
+        signed.extend(tlv(240, sig.as_ref()));
+        signed
+    }
+
+    #[test]
+    fn sign_bolt12_invoice_seen_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let invreq = make_test_bolt12_invoice_request(&node, 10_000);
+        let invoice = make_test_bolt12_invoice(&node, &invreq, PaymentHash([3; 32]), 10_000);
+        let merkle_root = bolt12::merkle_root(&bolt12::parse_tlv_stream(&invoice).unwrap());
+
+        // hsmd clients only send the merkle root
+        node.sign_bolt12(b"invoice", b"signature", &merkle_root, None).expect("sign merkle root");
+
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_seen_bolt12_invoices = true;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let res = node.sign_bolt12(b"invoice", b"signature", &merkle_root, None);
+        assert_failed_precondition_err!(
+            res,
+            format!(
+                "policy failure: sign_bolt12: invoice with merkle root {} was not seen",
+                merkle_root.to_hex()
+            )
+            .as_str()
+        );
+
+        let sig = node.sign_bolt12_tlv(b"invoice", b"signature", &invoice, None).unwrap();
+        assert!(node.state.lock().unwrap().issued_invoices.contains_key(&PaymentHash([3; 32])));
+        let sig2 = node.sign_bolt12(b"invoice", b"signature", &merkle_root, None).unwrap();
+        assert_eq!(sig, sig2);
+
+        let signed = sign_test_bolt12_invoice(&node, &invoice);
+        Bolt12Invoice::parse(&signed).unwrap().verify_signature().expect("valid signature");
+
+        // another invoice for the same payment hash
+        let invoice2 = make_test_bolt12_invoice(&node, &invreq, PaymentHash([3; 32]), 20_000);
+        let res = node.sign_bolt12_tlv(b"invoice", b"signature", &invoice2, None);
+        assert_failed_precondition_err!(res, "already have a different invoice for same secret");
+    }
+
+    #[test]
+    fn keysend_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = true;
+        policy.max_keysend_per_payee_msat = 30_000;
+        policy.max_keysend_msat = 50_000;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let allowlisted = make_test_pubkey(10);
+        let payee1 = make_test_pubkey(11);
+        let payee2 = make_test_pubkey(12);
+        node.add_allowlist(&vec![format!("payee:{}", allowlisted)]).unwrap();
+
+        // policy-keysend-budget
+        node.add_keysend(allowlisted, PaymentHash([1; 32]), 1_000_000).expect("allowlisted");
+        node.add_keysend(payee1, PaymentHash([2; 32]), 20_000).expect("within budget");
+        node.add_keysend(payee1, PaymentHash([2; 32]), 20_000).expect("same keysend");
+        let res = node.add_keysend(payee1, PaymentHash([2; 32]), 10_000);
+        assert_failed_precondition_err!(res, "already have a different keysend for same hash");
+        let res = node.add_keysend(payee1, PaymentHash([3; 32]), 20_000);
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_keysend: keysend of 20000 exceeds the per-payee budget: \
+             20000 already sent, budget 30000"
+        );
+        node.add_keysend(payee2, PaymentHash([4; 32]), 30_000).expect("within budget");
+        let res = node.add_keysend(payee1, PaymentHash([5; 32]), 1_000);
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_keysend: keysend of 1000 exceeds the global budget: \
+             50000 already sent, budget 50000"
+        );
+
+        let validator = node.validator_factory.lock().unwrap().make_validator(
+            Network::Testnet,
+            node.get_id(),
+            None,
+        );
+        let mut state = node.state.lock().unwrap();
+        assert!(state.payments.contains_key(&PaymentHash([2; 32])));
+
+        // the keysend amount plus the maximum routing fee
+        let result = state.validate_and_apply_payments(
+            &channel_id,
+            &Map::new(),
+            &vec![(PaymentHash([2; 32]), 31)].into_iter().collect(),
+            &Default::default(),
+            validator.clone(),
+        );
+        assert_eq!(result, Err(unbalanced_error(vec![PaymentHash([2; 32])])));
+        let result = state.validate_and_apply_payments(
+            &channel_id,
+            &Map::new(),
+            &vec![(PaymentHash([2; 32]), 30)].into_iter().collect(),
+            &Default::default(),
+            validator.clone(),
+        );
+        assert!(result.is_ok());
+
+        // a keysend that was not added
+        let result = state.validate_and_apply_payments(
+            &channel_id,
+            &Map::new(),
+            &vec![(PaymentHash([5; 32]), 1)].into_iter().collect(),
+            &Default::default(),
+            validator.clone(),
+        );
+        assert_eq!(result, Err(unbalanced_error(vec![PaymentHash([5; 32])])));
+    }
+
+    #[test]
+    fn keysend_release_and_window_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = true;
+        policy.max_keysend_per_payee_msat = 50_000;
+        policy.max_keysend_msat = 50_000;
+        policy.keysend_window_blocks = 10;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let payee = make_test_pubkey(11);
+        let hash1 = PaymentHash([1; 32]);
+        let hash2 = PaymentHash([2; 32]);
+
+        node.add_keysend(payee, hash1, 40_000).expect("within budget");
+        let res = node.add_keysend(payee, hash2, 20_000);
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_keysend: keysend of 20000 exceeds the per-payee budget: \
+             40000 already sent, budget 50000"
+        );
+
+        let validator = node.validator_factory.lock().unwrap().make_validator(
+            Network::Testnet,
+            node.get_id(),
+            None,
+        );
+        {
+            let mut state = node.state.lock().unwrap();
+            state
+                .validate_and_apply_payments(
+                    &channel_id,
+                    &Map::new(),
+                    &vec![(hash1, 40)].into_iter().collect(),
+                    &Default::default(),
+                    validator.clone(),
+                )
+                .expect("in flight");
+            // the HTLC is removed without a preimage
+            state
+                .validate_and_apply_payments(
+                    &channel_id,
+                    &Map::new(),
+                    &Map::new(),
+                    &Default::default(),
+                    validator.clone(),
+                )
+                .expect("failed");
+            assert!(!state.keysends.contains_key(&hash1));
+        }
+        node.add_keysend(payee, hash2, 20_000).expect("failed keysend released");
+
+        let height = node.get_tracker().height();
+        let mut state = node.state.lock().unwrap();
+        state
+            .validate_and_apply_payments(
+                &channel_id,
+                &Map::new(),
+                &vec![(hash2, 20)].into_iter().collect(),
+                &Default::default(),
+                validator.clone(),
+            )
+            .expect("in flight");
+        // an in-flight keysend is kept past the window
+        state.prune_keysends(height + 10, Some(10));
+        assert!(state.keysends.contains_key(&hash2));
+        state.payments.get_mut(&hash2).unwrap().preimage = Some(PaymentPreimage([0; 32]));
+        state
+            .validate_and_apply_payments(
+                &channel_id,
+                &Map::new(),
+                &Map::new(),
+                &Default::default(),
+                validator.clone(),
+            )
+            .expect("fulfilled");
+        // a fulfilled keysend still counts within the window
+        assert!(state.keysends.contains_key(&hash2));
+        state.prune_keysends(height + 9, Some(10));
+        assert!(state.keysends.contains_key(&hash2));
+        state.prune_keysends(height + 10, Some(10));
+        assert!(state.keysends.is_empty());
+    }
+
+    #[test]
+    fn set_policy_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        assert!(node.get_policy().is_none());
+
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.min_delay = policy.max_delay + 1;
+        let res = node.set_policy(policy.clone(), "alice".to_string(), 1000);
+        assert_invalid_argument_err!(
+            res,
+            format!(
+                "invalid policy: min_delay {} > max_delay {}",
+                policy.min_delay, policy.max_delay
+            )
+            .as_str()
+        );
+        assert!(node.policy_changes().is_empty());
+
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = true;
+        node.set_policy(policy.clone(), "alice".to_string(), 1000).expect("set policy");
+        policy.max_keysend_msat = 1000;
+        node.set_policy(policy.clone(), "bob".to_string(), 2000).expect("set policy");
+        assert_eq!(node.get_policy(), Some(policy.clone()));
+        let changes = node.policy_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].changed_by, "alice");
+        assert_eq!(changes[0].timestamp, 1000);
+        assert_eq!(changes[0].policy.max_keysend_msat, 0);
+        assert_eq!(
+            changes[1],
+            PolicyChangeEntry { policy, changed_by: "bob".to_string(), timestamp: 2000 }
+        );
+
+        // the new policy is in effect for existing channels
+        node.with_ready_channel(&channel_id, |chan| {
+            let res = chan.validator().validate_keysend(false, 2000, 0, 0);
+            assert_eq!(
+                res,
+                Err(policy_error(
+                    "validate_keysend: keysend of 2000 exceeds the per-payee budget: \
+                     0 already sent, budget 0"
+                ))
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn drain_excess_amount_test() {
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.excess_drain_interval_blocks = 144;
+        policy.excess_drain_percent = 10;
+        let validator = SimpleValidatorFactory::new_with_policy(policy).make_validator(
+            Network::Testnet,
+            make_test_pubkey(1),
+            None,
+        );
+        let mut state = NodeState::new();
+        state.excess_amount = 10_000;
+
+        // the first drain only starts the schedule
+        state.drain_excess_amount(100, &*validator);
+        assert_eq!(state.excess_amount, 10_000);
+        assert_eq!(state.last_drain_height, Some(100));
+
+        state.drain_excess_amount(243, &*validator);
+        assert_eq!(state.excess_amount, 10_000);
+
+        state.drain_excess_amount(244, &*validator);
+        assert_eq!(state.excess_amount, 9_000);
+        assert_eq!(state.last_drain_height, Some(244));
+
+        // two intervals, with the partial interval carried over
+        state.drain_excess_amount(540, &*validator);
+        assert_eq!(state.excess_amount, 7_290);
+        assert_eq!(state.drained_amount, 2_710);
+        assert_eq!(state.last_drain_height, Some(532));
+
+        // a reorg restarts the schedule without draining
+        state.drain_excess_amount(500, &*validator);
+        assert_eq!(state.excess_amount, 7_290);
+        assert_eq!(state.last_drain_height, Some(500));
+    }
+
+    #[test]
+    fn drain_excess_amount_reorg_and_restart_test() {
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.excess_drain_interval_blocks = 144;
+        policy.excess_drain_percent = 10;
+        let validator = SimpleValidatorFactory::new_with_policy(policy).make_validator(
+            Network::Testnet,
+            make_test_pubkey(1),
+            None,
+        );
+        let mut state = NodeState::new();
+        state.excess_amount = 10_000;
+        state.drain_excess_amount(1000, &*validator);
+        state.drain_excess_amount(1144, &*validator);
+        assert_eq!(state.excess_amount, 9_000);
+
+        // a reorg keeps the drain, and the next one is a whole interval later
+        state.drain_excess_amount(1100, &*validator);
+        state.drain_excess_amount(1144, &*validator);
+        state.drain_excess_amount(1243, &*validator);
+        assert_eq!(state.excess_amount, 9_000);
+        state.drain_excess_amount(1244, &*validator);
+        assert_eq!(state.excess_amount, 8_100);
+        assert_eq!(state.drained_amount, 1_900);
+
+        // the node state is not persisted, so a restarted node starts a new
+        // schedule instead of draining for the blocks since height zero
+        let mut state = NodeState::new();
+        state.excess_amount = 10_000;
+        state.drain_excess_amount(5000, &*validator);
+        assert_eq!(state.excess_amount, 10_000);
+        assert_eq!(state.last_drain_height, Some(5000));
+        state.drain_excess_amount(5144, &*validator);
+        assert_eq!(state.excess_amount, 9_000);
+    }
+
+    #[test]
+    fn routing_income_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let out_channel_id1 = ChannelId::new(&[1; 32]);
+        let out_channel_id2 = ChannelId::new(&[2; 32]);
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.enforce_balance = true;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let validator = node.validator_factory.lock().unwrap().make_validator(
+            Network::Testnet,
+            node.get_id(),
+            None,
+        );
+        let preimage = PaymentPreimage([7; 32]);
+        let hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
+        {
+            let mut state = node.get_state();
+            let mut payment = RoutedPayment::new();
+            payment.incoming.insert(channel_id.clone(), 3_010);
+            payment.outgoing.insert(out_channel_id1.clone(), 1_000);
+            payment.outgoing.insert(out_channel_id2.clone(), 2_000);
+            state.payments.insert(hash, payment);
+            state.htlc_fulfilled(&channel_id, preimage, validator);
+        }
+
+        let report = node.routing_income_report();
+        assert_eq!(report.total_fees_sat, 10);
+        assert_eq!(report.excess_amount, 10);
+        assert_eq!(report.drained_amount, 0);
+        assert_eq!(
+            report.channels.get(&out_channel_id1),
+            Some(&RoutingIncome { forwards: 1, forwarded_sat: 1_000, fees_sat: 4 })
+        );
+        assert_eq!(
+            report.channels.get(&out_channel_id2),
+            Some(&RoutingIncome { forwards: 1, forwarded_sat: 2_000, fees_sat: 6 })
+        );
+        assert!(report.channels.get(&channel_id).is_none());
+    }
+
+    #[test]
+    fn balance_report_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let preimage = PaymentPreimage([7; 32]);
+        let fulfilled_hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
+        let htlc =
+            |value_sat, payment_hash| HTLCInfo2 { value_sat, payment_hash, cltv_expiry: 500 };
+        let offered = vec![htlc(10_000, PaymentHash([1; 32]))];
+        let received = vec![htlc(20_000, fulfilled_hash), htlc(30_000, PaymentHash([3; 32]))];
+        {
+            let mut state = node.get_state();
+            let mut payment = RoutedPayment::new();
+            payment.preimage = Some(preimage);
+            state.payments.insert(fulfilled_hash, payment);
+        }
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.current_holder_commit_info = Some(CommitmentInfo2::new(
+                false,
+                make_test_pubkey(100),
+                1_000_000,
+                make_test_pubkey(101),
+                make_test_pubkey(102),
+                1_900_000,
+                setup.counterparty_selected_contest_delay,
+                offered.clone(),
+                received.clone(),
+                7500,
+            ));
+            Ok(())
+        })
+        .unwrap();
+
+        let report = node.balance_report();
+        // Our output, the fee we paid as funder, the offered HTLC that may
+        // fail back and the received HTLC we know the preimage of
+        let expected = ChannelBalance {
+            claimable_sat: 1_900_000 + 40_000 + 10_000 + 20_000,
+            offered_htlc_sat: 10_000,
+            received_htlc_sat: 30_000,
+            is_closed: false,
+        };
+        assert_eq!(report.channels.get(&channel_id), Some(&expected));
+        assert_eq!(report.claimable_sat, expected.claimable_sat);
+        assert_eq!(report.offered_htlc_sat, 10_000);
+        assert_eq!(report.received_htlc_sat, 30_000);
+        assert_eq!(report.sweeping_sat, 0);
+    }
+
+    #[test]
+    fn psbt_derivation_path_test() {
+        let no_taproot = OrderedMap::<u8, ((), KeySource)>::new();
+        let source = |path| (Fingerprint::default(), DerivationPath::from_str(path).unwrap());
+        let mut derivation = OrderedMap::new();
+        assert_eq!(psbt_derivation_path(&derivation, &no_taproot).unwrap(), Vec::<u32>::new());
+
+        derivation.insert(make_test_pubkey(1), source("m/7"));
+        assert_eq!(psbt_derivation_path(&derivation, &no_taproot).unwrap(), vec![7]);
+
+        // a full BIP32 path from another wallet is not ours
+        derivation.insert(make_test_pubkey(1), source("m/84'/0'/0'/0/7"));
+        assert_eq!(psbt_derivation_path(&derivation, &no_taproot).unwrap(), Vec::<u32>::new());
+
+        derivation.insert(make_test_pubkey(2), source("m/8"));
+        assert_invalid_argument_err!(
+            psbt_derivation_path(&derivation, &no_taproot),
+            "psbt output has more than one derivation path"
+        );
+    }
+
+    #[test]
+    fn balance_report_channel_id_test() {
+        let setup = make_test_channel_setup();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let id0 = ChannelId::new(&[1; 32]);
+        let id = ChannelId::new(&[2; 32]);
+        node.new_channel(Some(id0.clone()), &node).expect("new_channel");
+        node.ready_channel(id0.clone(), Some(id.clone()), setup.clone(), &vec![])
+            .expect("ready channel");
+        node.with_ready_channel(&id, |chan| {
+            chan.enforcement_state.current_holder_commit_info = Some(CommitmentInfo2::new(
+                false,
+                make_test_pubkey(100),
+                1_000_000,
+                make_test_pubkey(101),
+                make_test_pubkey(102),
+                1_900_000,
+                setup.counterparty_selected_contest_delay,
+                vec![],
+                vec![HTLCInfo2 {
+                    value_sat: 30_000,
+                    payment_hash: PaymentHash([3; 32]),
+                    cltv_expiry: 500,
+                }],
+                7500,
+            ));
+            Ok(())
+        })
+        .unwrap();
+
+        // The channel is under both IDs, but is only counted once
+        let report = node.balance_report();
+        assert_eq!(report.channels.len(), 1);
+        let balance = report.channels.get(&id0).expect("balance");
+        assert_eq!(report.claimable_sat, balance.claimable_sat);
+        assert_eq!(report.received_htlc_sat, 30_000);
+    }
+
+    #[test]
+    fn archive_closed_channels_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let monitor =
+            node.with_ready_channel(&channel_id, |chan| Ok(chan.monitor.clone())).unwrap();
+        assert_eq!(node.get_tracker().listeners.len(), 1);
+
+        let setup = make_test_channel_setup();
+        let min_depth = setup.max_to_self_delay() as u32 + CHANNEL_ARCHIVE_MARGIN;
+        monitor.get_state().closing_height = Some(1);
+        monitor.get_state().height = min_depth - 1;
+        assert_eq!(node.archive_closed_channels().unwrap(), vec![]);
+        assert!(node.get_channel(&channel_id).is_ok());
+
+        monitor.get_state().height = min_depth;
+        assert_eq!(node.archive_closed_channels().unwrap(), vec![channel_id.clone()]);
+        assert!(node.get_channel(&channel_id).is_err());
+        assert!(node.channels().is_empty());
+        assert!(node.get_tracker().listeners.is_empty());
+    }
+
+    #[test]
+    fn forget_channel_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let (stub_id, _) = node.new_channel(None, &node).unwrap();
+        assert_eq!(node.channels().len(), 2);
+        node.forget_channel(&stub_id).unwrap();
+        assert!(node.get_channel(&stub_id).is_err());
+        let res = node.forget_channel(&stub_id);
+        assert_invalid_argument_err!(res, format!("no such channel: {}", stub_id).as_str());
+
+        // even a permissive policy doesn't allow forgetting a signed channel
+        node.set_validator_factory(Arc::new(NullValidatorFactory {}));
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.next_holder_commit_num = 1;
+            Ok(())
+        })
+        .unwrap();
+        let res = node.forget_channel(&channel_id);
+        assert_failed_precondition_err!(
+            res,
+            "channel has signed commitments: holder 1 counterparty 0"
+        );
+        assert!(node.get_channel(&channel_id).is_ok());
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.next_holder_commit_num = 0;
+            chan.enforcement_state.mutual_close_signed = true;
+            Ok(())
+        })
+        .unwrap();
+        let res = node.forget_channel(&channel_id);
+        assert_failed_precondition_err!(res, "channel has signed a mutual close");
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.mutual_close_signed = false;
+            Ok(())
+        })
+        .unwrap();
+        node.forget_channel(&channel_id).unwrap();
+        assert!(node.channels().is_empty());
+        assert!(node.get_tracker().listeners.is_empty());
+    }
+
+    #[test]
+    fn forget_stale_stubs_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.stub_timeout_blocks = 0;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(
+            policy.clone(),
+        )));
+        node.get_tracker().height = 100;
+        let (old_id, _) = node.new_channel(None, &node).unwrap();
+        node.get_tracker().height = 150;
+        let (new_id, _) = node.new_channel(None, &node).unwrap();
+        node.get_tracker().height = 200;
+        assert!(node.forget_stale_stubs().unwrap().is_empty());
+
+        policy.stub_timeout_blocks = 100;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        assert_eq!(node.forget_stale_stubs().unwrap(), vec![old_id.clone()]);
+        assert!(node.get_channel(&old_id).is_err());
+        assert!(node.get_channel(&new_id).is_ok());
+    }
+
+    #[test]
+    fn max_stubs_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_stubs = 2;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let (first_id, _) = node.new_channel(None, &node).unwrap();
+        node.new_channel(None, &node).unwrap();
+        let err = node.new_channel(None, &node).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        assert_eq!(err.message(), "2 channel stubs already open, limit is 2");
+        assert_eq!(err.details().rule.as_deref(), Some("stub-limit"));
+
+        // An existing stub can still be reused
+        assert!(node.new_channel(Some(first_id.clone()), &node).is_ok());
+        node.forget_channel(&first_id).unwrap();
+        assert!(node.new_channel(None, &node).is_ok());
+    }
+
+    #[test]
+    fn add_bolt12_invoice_test() {
+        let payee_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let hash = PaymentHash([2; 32]);
+        let invreq = make_test_bolt12_invoice_request(&node, 10_000);
+        node.sign_bolt12_tlv(b"invoice_request", b"signature", &invreq, None).unwrap();
+
+        // policy-bolt12-requested-amount
+        let invoice = make_test_bolt12_invoice(&payee_node, &invreq, PaymentHash([4; 32]), 20_000);
+        let res = node.add_bolt12_invoice(&sign_test_bolt12_invoice(&payee_node, &invoice));
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: add_bolt12_invoice: invoice amount 20000 exceeds requested amount 10000"
+        );
+
+        let invoice = make_test_bolt12_invoice(&payee_node, &invreq, hash, 10_000);
+        let res = node.add_bolt12_invoice(&invoice);
+        assert_invalid_argument_err!(res, "missing signature");
+        let mut signed = sign_test_bolt12_invoice(&payee_node, &invoice);
+        let last = signed.len() - 1;
+        signed[last] ^= 1;
+        let res = node.add_bolt12_invoice(&signed);
+        assert_invalid_argument_err!(res, "invalid signature");
+
+        let signed = sign_test_bolt12_invoice(&payee_node, &invoice);
+        node.add_bolt12_invoice(&signed).expect("add invoice");
+        node.add_bolt12_invoice(&signed).expect("add invoice again");
+
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = true;
+        let invoice_validator = SimpleValidatorFactory::new_with_policy(policy).make_validator(
+            Network::Testnet,
+            node.get_id(),
+            None,
+        );
+        let mut state = node.state.lock().unwrap();
+        assert_eq!(state.invoices.get(&hash).unwrap().amount_msat, 10_000);
+
+        // the invoiced amount plus the maximum routing fee
+        let result = state.validate_and_apply_payments(
+            &channel_id,
+            &Map::new(),
+            &vec![(hash, 21)].into_iter().collect(),
+            &Default::default(),
+            invoice_validator.clone(),
+        );
+        assert_eq!(result, Err(unbalanced_error(vec![hash])));
+        let result = state.validate_and_apply_payments(
+            &channel_id,
+            &Map::new(),
+            &vec![(hash, 20)].into_iter().collect(),
+            &Default::default(),
+            invoice_validator.clone(),
+        );
+        assert!(result.is_ok());
+    }
+
     #[test]
     fn fulfill_test() {
         let payee_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
@@ -45,10 +45,42 @@ pub trait Persist: Sync + Send {
     ) -> Result<model::ChannelEntry, ()>;
     /// Get all channels for a node from store
     fn get_node_channels(&self, node_id: &PublicKey) -> Vec<(ChannelId, model::ChannelEntry)>;
+    /// Delete a channel that never became usable.
+    /// Will error if doesn't exist.
+    ///
+    /// * `id0` original channel ID supplied to [`Persist::new_channel()`]
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()>;
+    /// Move a channel out of the active channels and into the archive.
+    /// Will error if doesn't exist.
+    ///
+    /// * `id0` original channel ID supplied to [`Persist::new_channel()`]
+    fn archive_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()>;
+    /// Get all archived channels for a node from store
+    fn get_node_archived_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Vec<(ChannelId, model::ChannelEntry)>;
     /// Persist the allowlist to the store.
     fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()>;
     /// Get the allowlist from the store.
     fn get_node_allowlist(&self, node_id: &PublicKey) -> Vec<String>;
+    /// Append a policy change to the node's policy history.
+    /// The last change is the active policy.
+    fn add_node_policy_change(
+        &self,
+        node_id: &PublicKey,
+        change: &model::PolicyChangeEntry,
+    ) -> Result<(), ()>;
+    /// Get the node's policy history, oldest first.
+    fn get_node_policy_changes(&self, node_id: &PublicKey) -> Vec<model::PolicyChangeEntry>;
+    /// Persist the node's keysend payments, replacing the previous ones.
+    fn update_node_keysends(
+        &self,
+        node_id: &PublicKey,
+        keysends: Vec<model::KeysendEntry>,
+    ) -> Result<(), ()>;
+    /// Get the node's keysend payments from the store.
+    fn get_node_keysends(&self, node_id: &PublicKey) -> Vec<model::KeysendEntry>;
     /// Get all nodes from store
     fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)>;
     /// Clears the database.  Not for production use.
@@ -98,6 +130,21 @@ impl Persist for DummyPersister {
         Vec::new()
     }
 
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn archive_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn get_node_archived_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Vec<(ChannelId, model::ChannelEntry)> {
+        Vec::new()
+    }
+
     fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()> {
         Ok(())
     }
@@ -106,6 +153,30 @@ impl Persist for DummyPersister {
         Vec::new()
     }
 
+    fn add_node_policy_change(
+        &self,
+        node_id: &PublicKey,
+        change: &model::PolicyChangeEntry,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn get_node_policy_changes(&self, node_id: &PublicKey) -> Vec<model::PolicyChangeEntry> {
+        Vec::new()
+    }
+
+    fn update_node_keysends(
+        &self,
+        node_id: &PublicKey,
+        keysends: Vec<model::KeysendEntry>,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn get_node_keysends(&self, node_id: &PublicKey) -> Vec<model::KeysendEntry> {
+        Vec::new()
+    }
+
     fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)> {
         Vec::new()
     }
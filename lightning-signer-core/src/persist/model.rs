@@ -1,5 +1,9 @@
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::PaymentHash;
+
 use crate::channel::ChannelId;
 use crate::channel::ChannelSetup;
+use crate::policy::simple_validator::SimplePolicy;
 use crate::policy::validator::EnforcementState;
 use crate::prelude::*;
 
@@ -20,4 +24,30 @@ pub struct ChannelEntry {
     // Permanent channel ID if different from the initial channel ID
     pub id: Option<ChannelId>,
     pub enforcement_state: EnforcementState,
+    // The block height at which a stub was created, if still a stub
+    pub blockheight: Option<u32>,
+}
+
+/// A persistence layer entry for a change of a node's policy
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyChangeEntry {
+    /// The policy in effect after the change
+    pub policy: SimplePolicy,
+    /// The operator that made the change, as asserted by the caller
+    pub changed_by: String,
+    /// When the change was made, in seconds since the epoch
+    pub timestamp: u64,
+}
+
+/// A persistence layer entry for a keysend payment added to a node
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeysendEntry {
+    /// The payment hash
+    pub payment_hash: PaymentHash,
+    /// The payee's public key
+    pub payee: PublicKey,
+    /// The amount to pay
+    pub amount_msat: u64,
+    /// The block height at which the keysend was added
+    pub added_height: u32,
 }
@@ -6,6 +6,7 @@ use lightning::ln::PaymentHash;
 use ValidationErrorKind::*;
 
 use crate::prelude::*;
+use crate::util::status::ErrorDetails;
 
 /// Kind of validation error
 #[derive(Clone, Debug, PartialEq)]
@@ -34,6 +35,8 @@ impl PartialEq for ValidationError {
 pub struct ValidationError {
     /// The kind of error
     pub kind: ValidationErrorKind,
+    /// Machine readable details
+    pub details: ErrorDetails,
     /// A non-resolved backtrace
     #[cfg(feature = "backtrace")]
     pub bt: Backtrace,
@@ -59,10 +62,36 @@ impl ValidationError {
         };
         ValidationError {
             kind: modkind,
+            details: self.details.clone(),
             #[cfg(feature = "backtrace")]
             bt: self.bt.clone(),
         }
     }
+
+    /// Set the policy rule which failed
+    pub fn with_rule(mut self, rule: &str) -> ValidationError {
+        self.details.rule = Some(rule.to_string());
+        self
+    }
+
+    /// Set the offending value and the allowed value or limit
+    pub fn with_values(
+        mut self,
+        value: impl core::fmt::Display,
+        allowed: impl core::fmt::Display,
+    ) -> ValidationError {
+        self.details.value = Some(value.to_string());
+        self.details.allowed = Some(allowed.to_string());
+        self
+    }
+
+    /// Set the commitment number, unless already set
+    pub fn with_commitment_number(mut self, commitment_number: u64) -> ValidationError {
+        if self.details.commitment_number.is_none() {
+            self.details.commitment_number = Some(commitment_number);
+        }
+        self
+    }
 }
 
 impl core::fmt::Display for ValidationError {
@@ -100,9 +129,14 @@ impl Into<String> for ValidationError {
     }
 }
 
+fn kind_details(kind: &str) -> ErrorDetails {
+    ErrorDetails { kind: Some(kind.to_string()), ..Default::default() }
+}
+
 pub(crate) fn transaction_format_error(msg: impl Into<String>) -> ValidationError {
     ValidationError {
         kind: TransactionFormat(msg.into()),
+        details: kind_details("transaction_format"),
         #[cfg(feature = "backtrace")]
         bt: Backtrace::new_unresolved(),
     }
@@ -111,6 +145,7 @@ pub(crate) fn transaction_format_error(msg: impl Into<String>) -> ValidationErro
 pub(crate) fn script_format_error(msg: impl Into<String>) -> ValidationError {
     ValidationError {
         kind: ScriptFormat(msg.into()),
+        details: kind_details("script_format"),
         #[cfg(feature = "backtrace")]
         bt: Backtrace::new_unresolved(),
     }
@@ -119,6 +154,7 @@ pub(crate) fn script_format_error(msg: impl Into<String>) -> ValidationError {
 pub(crate) fn mismatch_error(msg: impl Into<String>) -> ValidationError {
     ValidationError {
         kind: Mismatch(msg.into()),
+        details: kind_details("mismatch"),
         #[cfg(feature = "backtrace")]
         bt: Backtrace::new_unresolved(),
     }
@@ -127,6 +163,7 @@ pub(crate) fn mismatch_error(msg: impl Into<String>) -> ValidationError {
 pub(crate) fn policy_error(msg: impl Into<String>) -> ValidationError {
     ValidationError {
         kind: Policy(msg.into()),
+        details: kind_details("policy"),
         #[cfg(feature = "backtrace")]
         bt: Backtrace::new_unresolved(),
     }
@@ -135,6 +172,7 @@ pub(crate) fn policy_error(msg: impl Into<String>) -> ValidationError {
 pub(crate) fn unbalanced_error(hashes: Vec<PaymentHash>) -> ValidationError {
     ValidationError {
         kind: Unbalanced("".to_string(), hashes),
+        details: kind_details("unbalanced"),
         #[cfg(feature = "backtrace")]
         bt: Backtrace::new_unresolved(),
     }
@@ -142,6 +180,9 @@ pub(crate) fn unbalanced_error(hashes: Vec<PaymentHash>) -> ValidationError {
 
 #[allow(unused)]
 macro_rules! transaction_format_err {
+	(rule = $rule:literal, $($arg:tt)*) => (
+            transaction_format_err!($($arg)*).map_err(|ve| ve.with_rule($rule))
+        );
 	($($arg:tt)*) => (
             Err(transaction_format_error(format!(
                 "{}: {}",
@@ -153,6 +194,9 @@ macro_rules! transaction_format_err {
 
 #[allow(unused)]
 macro_rules! script_format_err {
+	(rule = $rule:literal, $($arg:tt)*) => (
+            script_format_err!($($arg)*).map_err(|ve| ve.with_rule($rule))
+        );
 	($($arg:tt)*) => (
             Err(script_format_error(format!(
                 "{}: {}",
@@ -164,6 +208,9 @@ macro_rules! script_format_err {
 
 #[allow(unused)]
 macro_rules! mismatch_err {
+	(rule = $rule:literal, $($arg:tt)*) => (
+            mismatch_err!($($arg)*).map_err(|ve| ve.with_rule($rule))
+        );
 	($($arg:tt)*) => (
             Err(mismatch_error(format!(
                 "{}: {}",
@@ -175,6 +222,9 @@ macro_rules! mismatch_err {
 
 #[allow(unused)]
 macro_rules! policy_err {
+	(rule = $rule:literal, $($arg:tt)*) => (
+            policy_err!($($arg)*).map_err(|ve| ve.with_rule($rule))
+        );
 	($($arg:tt)*) => (
             Err(policy_error(format!(
                 "{}: {}",
@@ -217,4 +267,35 @@ mod tests {
             "policy failure: testing"
         );
     }
+
+    #[test]
+    fn validation_error_details_test() {
+        let ve = policy_error("fee below minimum: 10 < 100")
+            .with_rule("policy-onchain-fee-range")
+            .with_values(10, 100)
+            .with_commitment_number(7)
+            .prepend_msg("validate_onchain_tx: ".to_string())
+            .with_commitment_number(8);
+        assert_eq!(ve.details.kind.as_deref(), Some("policy"));
+        assert_eq!(ve.details.rule.as_deref(), Some("policy-onchain-fee-range"));
+        assert_eq!(ve.details.value.as_deref(), Some("10"));
+        assert_eq!(ve.details.allowed.as_deref(), Some("100"));
+        assert_eq!(ve.details.commitment_number, Some(7));
+    }
+
+    #[test]
+    fn policy_err_rule_test() {
+        fn check_limit(value: u32) -> Result<(), ValidationError> {
+            if value > 10 {
+                return policy_err!(rule = "policy-test-limit", "value {} too large", value);
+            }
+            policy_err!("value {} too small", value)
+        }
+        let ve = check_limit(11).unwrap_err();
+        assert_eq!(ve.kind, Policy("check_limit: value 11 too large".to_string()));
+        assert_eq!(ve.details.rule.as_deref(), Some("policy-test-limit"));
+        let ve = check_limit(1).unwrap_err();
+        assert_eq!(ve.details.kind.as_deref(), Some("policy"));
+        assert_eq!(ve.details.rule, None);
+    }
 }
@@ -4,9 +4,10 @@ use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
-use crate::policy::simple_validator::SimpleValidatorFactory;
-use crate::policy::validator::EnforcementState;
+use crate::node::SpendType;
+use crate::policy::simple_validator::{SimplePolicy, SimpleValidatorFactory};
 use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{EnforcementState, SpliceTxInfo};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
@@ -39,6 +40,11 @@ impl ValidatorFactory for NullValidatorFactory {
     ) -> Arc<dyn Validator> {
         Arc::new(null_validator())
     }
+
+    // There is no policy to enforce
+    fn with_policy(&self, _policy: SimplePolicy) -> Arc<dyn ValidatorFactory> {
+        Arc::new(NullValidatorFactory {})
+    }
 }
 
 /// A null validator
@@ -64,11 +70,49 @@ impl Validator for NullValidator {
         _channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         _tx: &Transaction,
         _values_sat: &Vec<u64>,
+        _spendtypes: &Vec<SpendType>,
+        _prev_txs: &Vec<Option<Transaction>>,
         _opaths: &Vec<Vec<u32>>,
     ) -> Result<(), ValidationError> {
         Ok(())
     }
 
+    fn decode_splice_tx(
+        &self,
+        wallet: &Wallet,
+        keys: &InMemorySigner,
+        setup: &ChannelSetup,
+        tx: &Transaction,
+        new_funding_vout: u32,
+        holder_delta_sat: i64,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<SpliceTxInfo, ValidationError> {
+        // Delegate to SimplePolicy
+        self.0.decode_splice_tx(
+            wallet,
+            keys,
+            setup,
+            tx,
+            new_funding_vout,
+            holder_delta_sat,
+            values_sat,
+            spendtypes,
+            opaths,
+        )
+    }
+
+    fn validate_splice_tx(
+        &self,
+        _setup: &ChannelSetup,
+        _cstate: &ChainState,
+        _estate: &EnforcementState,
+        _info: &SpliceTxInfo,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn decode_commitment_tx(
         &self,
         keys: &InMemorySigner,
@@ -224,6 +268,16 @@ impl Validator for NullValidator {
         Ok(())
     }
 
+    fn validate_keysend(
+        &self,
+        _is_allowlisted: bool,
+        _amount_msat: u64,
+        _payee_total_msat: u64,
+        _total_msat: u64,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn minimum_initial_balance(&self, _holder_value_msat: u64) -> u64 {
         0
     }
@@ -4,10 +4,11 @@ use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::node::SpendType;
 use crate::policy::error::policy_error;
-use crate::policy::simple_validator::SimpleValidatorFactory;
-use crate::policy::validator::EnforcementState;
+use crate::policy::simple_validator::{SimplePolicy, SimpleValidatorFactory};
 use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{EnforcementState, SpliceTxInfo};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
@@ -42,6 +43,12 @@ impl ValidatorFactory for OnchainValidatorFactory {
         };
         Arc::new(validator)
     }
+
+    fn with_policy(&self, policy: SimplePolicy) -> Arc<dyn ValidatorFactory> {
+        Arc::new(OnchainValidatorFactory {
+            inner_factory: SimpleValidatorFactory::new_with_policy(policy),
+        })
+    }
 }
 
 /// An on-chain validator, subsumes the policy checks of SimpleValidator
@@ -79,9 +86,47 @@ impl Validator for OnchainValidator {
         channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         tx: &Transaction,
         values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        prev_txs: &Vec<Option<Transaction>>,
         opaths: &Vec<Vec<u32>>,
     ) -> Result<(), ValidationError> {
-        self.inner.validate_onchain_tx(wallet, channels, tx, values_sat, opaths)
+        self.inner
+            .validate_onchain_tx(wallet, channels, tx, values_sat, spendtypes, prev_txs, opaths)
+    }
+
+    fn decode_splice_tx(
+        &self,
+        wallet: &Wallet,
+        keys: &InMemorySigner,
+        setup: &ChannelSetup,
+        tx: &Transaction,
+        new_funding_vout: u32,
+        holder_delta_sat: i64,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<SpliceTxInfo, ValidationError> {
+        self.inner.decode_splice_tx(
+            wallet,
+            keys,
+            setup,
+            tx,
+            new_funding_vout,
+            holder_delta_sat,
+            values_sat,
+            spendtypes,
+            opaths,
+        )
+    }
+
+    fn validate_splice_tx(
+        &self,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        estate: &EnforcementState,
+        info: &SpliceTxInfo,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_splice_tx(setup, cstate, estate, info)
     }
 
     fn decode_commitment_tx(
@@ -277,6 +322,40 @@ impl Validator for OnchainValidator {
         self.inner.validate_payment_balance(incoming, outgoing, invoiced_amount)
     }
 
+    fn validate_keysend(
+        &self,
+        is_allowlisted: bool,
+        amount_msat: u64,
+        payee_total_msat: u64,
+        total_msat: u64,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_keysend(is_allowlisted, amount_msat, payee_total_msat, total_msat)
+    }
+
+    fn validate_forget_channel(&self, estate: &EnforcementState) -> Result<(), ValidationError> {
+        self.inner.validate_forget_channel(estate)
+    }
+
+    fn keysend_window_blocks(&self) -> Option<u32> {
+        self.inner.keysend_window_blocks()
+    }
+
+    fn stub_timeout_blocks(&self) -> Option<u32> {
+        self.inner.stub_timeout_blocks()
+    }
+
+    fn max_stubs(&self) -> Option<usize> {
+        self.inner.max_stubs()
+    }
+
+    fn require_seen_bolt12_invoices(&self) -> bool {
+        self.inner.require_seen_bolt12_invoices()
+    }
+
+    fn excess_drain_schedule(&self) -> Option<(u32, u8)> {
+        self.inner.excess_drain_schedule()
+    }
+
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
         self.inner.minimum_initial_balance(holder_value_msat)
     }
@@ -293,6 +372,7 @@ impl OnchainValidator {
         if commit_num > 0 {
             if cstate.funding_depth < self.policy.min_funding_depth as u32 {
                 return policy_err!(
+                    rule = "policy-commitment-spends-active-utxo",
                     "tried commitment {} when funding is not buried at depth {}",
                     commit_num,
                     cstate.funding_depth
@@ -301,6 +381,7 @@ impl OnchainValidator {
 
             if cstate.closing_depth > 0 {
                 return policy_err!(
+                    rule = "policy-commitment-spends-active-utxo",
                     "tried commitment {} after closed on-chain at depth {}",
                     commit_num,
                     cstate.closing_depth
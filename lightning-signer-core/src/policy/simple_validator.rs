@@ -2,7 +2,7 @@ use bitcoin::hashes::hex::ToHex;
 use bitcoin::policy::DUST_RELAY_TX_FEE;
 use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 use bitcoin::util::sighash::SighashCache;
-use bitcoin::{self, EcdsaSighashType, Network, Script, Sighash, Transaction};
+use bitcoin::{self, EcdsaSighashType, Network, OutPoint, Script, Sighash, Transaction};
 use lightning::chain::keysinterface::{BaseSign, InMemorySigner};
 use lightning::ln::chan_utils::{
     build_htlc_transaction, htlc_success_tx_weight, htlc_timeout_tx_weight,
@@ -12,8 +12,9 @@ use lightning::ln::PaymentHash;
 use log::{debug, info};
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
-use crate::policy::validator::EnforcementState;
+use crate::node::{SpendType, SPLICE_BURIED_DEPTH};
 use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{EnforcementState, SpliceTxInfo};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{
@@ -64,10 +65,14 @@ impl ValidatorFactory for SimpleValidatorFactory {
 
         Arc::new(validator)
     }
+
+    fn with_policy(&self, policy: SimplePolicy) -> Arc<dyn ValidatorFactory> {
+        Arc::new(SimpleValidatorFactory::new_with_policy(policy))
+    }
 }
 
 /// A simple policy to configure a SimpleValidator
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SimplePolicy {
     /// Minimum delay in blocks
     pub min_delay: u16,
@@ -91,15 +96,60 @@ pub struct SimplePolicy {
     pub min_fee: u64,
     /// Maximum fee in satoshi
     pub max_fee: u64,
-    /// Require invoices for payments, and disallow keysend
-    // TODO secure keysend
+    /// Require invoices for payments.  Keysend is then only allowed to
+    /// allowlisted payees, or within the keysend budgets below.
     pub require_invoices: bool,
     /// Enforce holder balance
     // TODO incoming payments
-    // TODO routing
     pub enforce_balance: bool,
     /// Maximum layer-2 fee
     pub max_routing_fee_msat: u64,
+    /// Maximum total keysend to a single payee that is not allowlisted,
+    /// when invoices are required
+    pub max_keysend_per_payee_msat: u64,
+    /// Maximum total keysend to all payees that are not allowlisted,
+    /// when invoices are required
+    pub max_keysend_msat: u64,
+    /// The keysend budgets apply to keysends added within this many
+    /// blocks, or to all keysends ever added if zero
+    pub keysend_window_blocks: u32,
+    /// Drain the accumulated routing income in the excess amount every
+    /// this many blocks, or never if zero
+    pub excess_drain_interval_blocks: u32,
+    /// The percentage of the excess amount drained every interval
+    pub excess_drain_percent: u8,
+    /// Forget channel stubs that did not become ready within this many
+    /// blocks, or never if zero, the default.  A peer that is slow to fund
+    /// a channel loses it, so this is opt-in.
+    pub stub_timeout_blocks: u32,
+    /// Maximum number of channel stubs a node may hold at once, or
+    /// unlimited if zero, the default
+    pub max_stubs: u32,
+    /// Only sign BOLT 12 invoices whose TLV stream was seen, rather than
+    /// any merkle root.  hsmd clients only send the merkle root.
+    pub require_seen_bolt12_invoices: bool,
+}
+
+impl SimplePolicy {
+    /// Check that the policy is self-consistent
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_delay > self.max_delay {
+            return Err(format!("min_delay {} > max_delay {}", self.min_delay, self.max_delay));
+        }
+        if self.min_feerate_per_kw > self.max_feerate_per_kw {
+            return Err(format!(
+                "min_feerate_per_kw {} > max_feerate_per_kw {}",
+                self.min_feerate_per_kw, self.max_feerate_per_kw
+            ));
+        }
+        if self.min_fee > self.max_fee {
+            return Err(format!("min_fee {} > max_fee {}", self.min_fee, self.max_fee));
+        }
+        if self.excess_drain_percent > 100 {
+            return Err(format!("excess_drain_percent {} > 100", self.excess_drain_percent));
+        }
+        Ok(())
+    }
 }
 
 /// A simple validator.
@@ -125,10 +175,12 @@ impl SimpleValidator {
         let policy = &self.policy;
 
         if delay < policy.min_delay as u32 {
-            return policy_err!("{} too small: {} < {}", name, delay, policy.min_delay);
+            return policy_err!("{} too small: {} < {}", name, delay, policy.min_delay)
+                .map_err(|ve| ve.with_values(delay, policy.min_delay));
         }
         if delay > policy.max_delay as u32 {
-            return policy_err!("{} too large: {} > {}", name, delay, policy.max_delay);
+            return policy_err!("{} too large: {} > {}", name, delay, policy.max_delay)
+                .map_err(|ve| ve.with_values(delay, policy.max_delay));
         }
 
         Ok(())
@@ -149,7 +201,8 @@ impl SimpleValidator {
                     name,
                     expiry,
                     current_height + policy.min_delay as u32
-                );
+                )
+                .map_err(|ve| ve.with_values(expiry, current_height + policy.min_delay as u32));
             }
             if expiry > current_height + policy.max_delay as u32 {
                 return policy_err!(
@@ -157,7 +210,8 @@ impl SimpleValidator {
                     name,
                     expiry,
                     current_height + policy.max_delay as u32
-                );
+                )
+                .map_err(|ve| ve.with_values(expiry, current_height + policy.max_delay as u32));
             }
         }
 
@@ -169,10 +223,12 @@ impl SimpleValidator {
             policy_error(format!("fee underflow: {} - {}", sum_inputs, sum_outputs))
         })?;
         if fee < self.policy.min_fee {
-            return policy_err!("fee below minimum: {} < {}", fee, self.policy.min_fee);
+            return policy_err!("fee below minimum: {} < {}", fee, self.policy.min_fee)
+                .map_err(|ve| ve.with_values(fee, self.policy.min_fee));
         }
         if fee > self.policy.max_fee {
-            return policy_err!("fee above maximum: {} > {}", fee, self.policy.max_fee);
+            return policy_err!("fee above maximum: {} > {}", fee, self.policy.max_fee)
+                .map_err(|ve| ve.with_values(fee, self.policy.max_fee));
         }
         Ok(())
     }
@@ -193,7 +249,8 @@ impl SimpleValidator {
                 "non-beneficial value above maximum: {} > {}",
                 non_beneficial,
                 self.policy.max_fee
-            );
+            )
+            .map_err(|ve| ve.with_values(non_beneficial, self.policy.max_fee));
         }
         Ok(())
     }
@@ -217,7 +274,11 @@ impl SimpleValidator {
     ) -> Result<(), ValidationError> {
         // policy-sweep-version
         if tx.version != 2 {
-            return transaction_format_err!("bad version: {}", tx.version);
+            return transaction_format_err!(
+                rule = "policy-sweep-version",
+                "bad version: {}",
+                tx.version
+            );
         }
 
         // LDK now provides multi-input txs, and we can't easily validate fees securely
@@ -240,7 +301,10 @@ impl SimpleValidator {
                     wallet_path,
                     script_debug(dest_script, wallet.network())
                 );
-                return policy_err!("destination is not in wallet or allowlist");
+                return policy_err!(
+                    rule = "policy-sweep-destination-allowlisted",
+                    "destination is not in wallet or allowlist"
+                );
             }
         }
 
@@ -248,6 +312,16 @@ impl SimpleValidator {
     }
 }
 
+// The smallest HTLC that isn't trimmed, given the weight of its second-stage tx
+fn htlc_dust_limit(setup: &ChannelSetup, htlc_tx_weight: u64) -> u64 {
+    if setup.option_anchors_zero_fee_htlc() {
+        // the second-stage tx doesn't take its fee from the HTLC value
+        MIN_DUST_LIMIT_SATOSHIS
+    } else {
+        MIN_DUST_LIMIT_SATOSHIS + DUST_RELAY_TX_FEE as u64 * htlc_tx_weight / 1000
+    }
+}
+
 // TODO - policy-onchain-change-path-predictable
 
 // TODO - policy-commitment-spends-active-utxo
@@ -294,14 +368,16 @@ impl Validator for SimpleValidator {
         self.validate_delay(
             "counterparty_selected_contest_delay",
             setup.counterparty_selected_contest_delay as u32,
-        )?;
+        )
+        .map_err(|ve| ve.with_rule("policy-channel-counterparty-contest-delay-range"))?;
 
         // policy-channel-holder-contest-delay-range
         // policy-commitment-to-self-delay-range relies on this value
         self.validate_delay(
             "holder_selected_contest_delay",
             setup.holder_selected_contest_delay as u32,
-        )?;
+        )
+        .map_err(|ve| ve.with_rule("policy-channel-holder-contest-delay-range"))?;
 
         // policy-mutual-destination-allowlisted
         if let Some(holder_shutdown_script) = &setup.holder_shutdown_script {
@@ -315,7 +391,10 @@ impl Validator for SimpleValidator {
                     holder_shutdown_key_path,
                     script_debug(holder_shutdown_script, wallet.network())
                 );
-                return policy_err!("holder_shutdown_script is not in wallet or allowlist");
+                return policy_err!(
+                    rule = "policy-mutual-destination-allowlisted",
+                    "holder_shutdown_script is not in wallet or allowlist"
+                );
             }
         }
         *debug_on_return = false;
@@ -324,7 +403,9 @@ impl Validator for SimpleValidator {
 
     fn validate_channel_value(&self, setup: &ChannelSetup) -> Result<(), ValidationError> {
         if setup.channel_value_sat > self.policy.max_channel_size_sat {
-            return policy_err!("channel value {} too large", setup.channel_value_sat);
+            return policy_err!("channel value {} too large", setup.channel_value_sat).map_err(
+                |ve| ve.with_values(setup.channel_value_sat, self.policy.max_channel_size_sat),
+            );
         }
         Ok(())
     }
@@ -335,16 +416,41 @@ impl Validator for SimpleValidator {
         channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         tx: &Transaction,
         holder_inputs_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        prev_txs: &Vec<Option<Transaction>>,
         opaths: &Vec<Vec<u32>>,
     ) -> Result<(), ValidationError> {
-        let mut debug_on_return = scoped_debug_return!(tx, holder_inputs_sat, opaths);
+        let mut debug_on_return = scoped_debug_return!(tx, holder_inputs_sat, spendtypes, opaths);
 
         // policy-onchain-format-standard
         if tx.version != 2 {
-            return policy_err!("invalid version: {}", tx.version);
+            return policy_err!(
+                rule = "policy-onchain-format-standard",
+                "invalid version: {}",
+                tx.version
+            );
+        }
+
+        // policy-onchain-input-amount-verified
+        // A non-segwit signature doesn't commit to the input amount, so the
+        // amount must come from the previous transaction.
+        for (idx, spendtype) in spendtypes.iter().enumerate() {
+            let has_prev_tx = prev_txs.get(idx).map(|t| t.is_some()).unwrap_or(false);
+            if *spendtype == SpendType::P2pkh && !has_prev_tx {
+                return policy_err!(
+                    rule = "policy-onchain-input-amount-verified",
+                    "non-segwit input[{}] requires the previous transaction",
+                    idx
+                );
+            }
         }
 
         let mut beneficial_sum = 0u64;
+        // our wallet and allowlisted outputs
+        let mut change_sum = 0u64;
+        // our contributions to the funding outputs
+        let mut contribution_sum = 0u64;
+        let mut has_dual_funded = false;
         for outndx in 0..tx.output.len() {
             let output = &tx.output[outndx];
             let opath = &opaths[outndx];
@@ -367,16 +473,22 @@ impl Validator for SimpleValidator {
                     policy_error(format!("output[{}]: wallet_can_spend error: {}", outndx, err))
                 })?;
                 if !spendable {
-                    return policy_err!("wallet cannot spend output[{}]", outndx);
+                    return policy_err!(
+                        rule = "policy-onchain-change-to-wallet",
+                        "wallet cannot spend output[{}]",
+                        outndx
+                    );
                 }
                 debug!("output {} ({}) is to our wallet", outndx, output.value);
                 beneficial_sum =
                     add_beneficial_output!(beneficial_sum, output.value, "wallet change")?;
+                change_sum = add_beneficial_output!(change_sum, output.value, "wallet change")?;
             } else if wallet.allowlist_contains(&output.script_pubkey) {
                 // Change output to allowlisted address
                 debug!("output {} ({}) is allowlisted", outndx, output.value);
                 beneficial_sum =
                     add_beneficial_output!(beneficial_sum, output.value, "allowlisted")?;
+                change_sum = add_beneficial_output!(change_sum, output.value, "allowlisted")?;
             } else if let Some(slot) = channel_slot {
                 // Possible funded channel balance
                 match &*slot.lock().unwrap() {
@@ -392,6 +504,7 @@ impl Validator for SimpleValidator {
                         // policy-onchain-output-match-commitment
                         if output.value != chan.setup.channel_value_sat {
                             return policy_err!(
+                                rule = "policy-onchain-output-match-commitment",
                                 "funding output amount mismatch w/ channel: {} != {}",
                                 output.value,
                                 chan.setup.channel_value_sat
@@ -407,6 +520,7 @@ impl Validator for SimpleValidator {
                             payload_for_p2wsh(&funding_redeemscript).script_pubkey();
                         if output.script_pubkey != script_pubkey {
                             return policy_err!(
+                                rule = "policy-onchain-output-scriptpubkey",
                                 "funding script_pubkey mismatch w/ channel: {} != {}",
                                 output.script_pubkey,
                                 script_pubkey
@@ -415,24 +529,41 @@ impl Validator for SimpleValidator {
 
                         // policy-onchain-initial-commitment-countersigned
                         if chan.enforcement_state.next_holder_commit_num != 1 {
-                            return policy_err!("initial holder commitment not validated",);
+                            return policy_err!(
+                                rule = "policy-onchain-initial-commitment-countersigned",
+                                "initial holder commitment not validated",
+                            );
                         }
 
-                        let push_val_sat = chan.setup.push_value_msat / 1000;
-                        let our_value = if chan.setup.is_outbound {
-                            chan.setup
-                                .channel_value_sat
-                                .checked_sub(push_val_sat)
-                                .expect("push value underflow checked in ready_channel")
-                        } else {
+                        // policy-onchain-dual-funding-contribution
+                        // In a dual-funded channel both sides contribute, and
+                        // our share is our contribution adjusted by the push.
+                        // Our inputs and change outputs are accounted for
+                        // below, the counterparty's are not beneficial to us.
+                        if !chan.setup.is_outbound && !chan.setup.is_dual_funded() {
                             return policy_err!(
-                                "can't sign for inbound channel: dual-funding not supported yet",
+                                rule = "policy-onchain-dual-funding-contribution",
+                                "can't sign for inbound channel: no holder contribution",
                             );
-                            // push_val_sat
+                        }
+                        let our_value = match chan.setup.initial_holder_value_msat() {
+                            Ok(value_msat) => value_msat / 1000,
+                            Err(msg) =>
+                                return policy_err!(
+                                    rule = "policy-onchain-dual-funding-contribution",
+                                    "{}",
+                                    msg
+                                ),
                         };
                         debug!("output {} ({}) funds channel {}", outndx, output.value, chan.id());
                         beneficial_sum =
                             add_beneficial_output!(beneficial_sum, our_value, "channel value")?;
+                        contribution_sum = add_beneficial_output!(
+                            contribution_sum,
+                            chan.setup.holder_contribution_sat(),
+                            "channel contribution"
+                        )?;
+                        has_dual_funded |= chan.setup.is_dual_funded();
                     }
                     _ => panic!("this can't happen"),
                 };
@@ -449,8 +580,276 @@ impl Validator for SimpleValidator {
                 .checked_add(*val)
                 .ok_or_else(|| policy_error(format!("funding sum inputs overflow")))?;
         }
-        self.validate_beneficial_value(sum_inputs, beneficial_sum)
-            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        // policy-onchain-dual-funding-contribution
+        // What we spend on the funding outputs must cover our contributions,
+        // or the counterparty would be funding part of our balance.
+        // Single-funded channels are covered by the beneficial value check.
+        if has_dual_funded {
+            let spent_sat = sum_inputs.saturating_sub(change_sum);
+            if spent_sat < contribution_sum {
+                return policy_err!(
+                    rule = "policy-onchain-dual-funding-contribution",
+                    "our inputs less change {} do not cover our funding contribution {}",
+                    spent_sat,
+                    contribution_sum
+                );
+            }
+        }
+
+        self.validate_beneficial_value(sum_inputs, beneficial_sum).map_err(|ve| {
+            ve.with_rule("policy-onchain-beneficial-value")
+                .prepend_msg(format!("{}: ", containing_function!()))
+        })?;
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
+    fn decode_splice_tx(
+        &self,
+        wallet: &Wallet,
+        keys: &InMemorySigner,
+        setup: &ChannelSetup,
+        tx: &Transaction,
+        new_funding_vout: u32,
+        holder_delta_sat: i64,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<SpliceTxInfo, ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(
+            setup,
+            tx,
+            new_funding_vout,
+            holder_delta_sat,
+            values_sat,
+            spendtypes,
+            opaths
+        );
+
+        // policy-onchain-format-standard
+        if tx.version != 2 {
+            return policy_err!(
+                rule = "policy-onchain-format-standard",
+                "invalid version: {}",
+                tx.version
+            );
+        }
+
+        // policy-splice-funding-input
+        let funding_input =
+            match tx.input.iter().position(|i| i.previous_output == setup.funding_outpoint) {
+                Some(idx) => idx,
+                None =>
+                    return policy_err!(
+                        rule = "policy-splice-funding-input",
+                        "funding outpoint {} is not spent",
+                        setup.funding_outpoint
+                    ),
+            };
+        if spendtypes[funding_input] != SpendType::Invalid {
+            return policy_err!(
+                rule = "policy-splice-funding-input",
+                "funding input[{}] has a wallet spend type",
+                funding_input
+            );
+        }
+
+        // policy-splice-funding-output
+        let funding_output = match tx.output.get(new_funding_vout as usize) {
+            Some(output) => output,
+            None =>
+                return policy_err!(
+                    rule = "policy-splice-funding-output",
+                    "funding output[{}] does not exist",
+                    new_funding_vout
+                ),
+        };
+        let funding_redeemscript = make_funding_redeemscript(
+            &keys.pubkeys().funding_pubkey,
+            &keys.counterparty_pubkeys().funding_pubkey,
+        );
+        let script_pubkey = payload_for_p2wsh(&funding_redeemscript).script_pubkey();
+        if funding_output.script_pubkey != script_pubkey {
+            return policy_err!(
+                rule = "policy-splice-funding-output",
+                "funding script_pubkey mismatch w/ channel: {} != {}",
+                funding_output.script_pubkey,
+                script_pubkey
+            );
+        }
+
+        let mut sum_inputs = 0u64;
+        let mut holder_inputs_sat = 0u64;
+        let mut holder_input_values = Vec::new();
+        for (idx, value) in values_sat.iter().enumerate() {
+            // we know the value of the shared input better than the caller
+            let value = if idx == funding_input { setup.channel_value_sat } else { *value };
+            sum_inputs = sum_inputs
+                .checked_add(value)
+                .ok_or_else(|| policy_error(format!("splice sum inputs overflow")))?;
+            if spendtypes[idx] != SpendType::Invalid {
+                holder_inputs_sat = holder_inputs_sat
+                    .checked_add(value)
+                    .ok_or_else(|| policy_error(format!("splice holder inputs overflow")))?;
+                holder_input_values.push(Some(value));
+            } else {
+                holder_input_values.push(None);
+            }
+        }
+
+        let mut sum_outputs = 0u64;
+        let mut holder_outputs_sat = 0u64;
+        for (outndx, output) in tx.output.iter().enumerate() {
+            sum_outputs = sum_outputs
+                .checked_add(output.value)
+                .ok_or_else(|| policy_error(format!("splice sum outputs overflow")))?;
+            if outndx == new_funding_vout as usize {
+                continue;
+            }
+            let opath = &opaths[outndx];
+            let is_ours = if opath.len() > 0 {
+                let spendable = wallet.can_spend(opath, &output.script_pubkey).map_err(|err| {
+                    policy_error(format!("output[{}]: wallet_can_spend error: {}", outndx, err))
+                })?;
+                if !spendable {
+                    return policy_err!(
+                        rule = "policy-onchain-change-to-wallet",
+                        "wallet cannot spend output[{}]",
+                        outndx
+                    );
+                }
+                true
+            } else {
+                wallet.allowlist_contains(&output.script_pubkey)
+            };
+            if is_ours {
+                debug!("output {} ({}) is ours", outndx, output.value);
+                holder_outputs_sat = holder_outputs_sat
+                    .checked_add(output.value)
+                    .ok_or_else(|| policy_error(format!("splice holder outputs overflow")))?;
+            } else {
+                debug!("output {} ({}) is not ours", outndx, output.value);
+            }
+        }
+
+        let fee_sat = sum_inputs.checked_sub(sum_outputs).ok_or_else(|| {
+            policy_error(format!(
+                "sum of outputs {} is larger than sum of inputs {}",
+                sum_outputs, sum_inputs
+            ))
+        })?;
+
+        *debug_on_return = false;
+        Ok(SpliceTxInfo {
+            funding_outpoint: OutPoint { txid: tx.txid(), vout: new_funding_vout },
+            channel_value_sat: funding_output.value,
+            holder_inputs_sat,
+            holder_outputs_sat,
+            fee_sat,
+            holder_delta_sat,
+            holder_input_values,
+        })
+    }
+
+    fn validate_splice_tx(
+        &self,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        estate: &EnforcementState,
+        info: &SpliceTxInfo,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(setup, cstate, estate, info);
+
+        if estate.mutual_close_signed {
+            return policy_err!("mutual close already signed");
+        }
+
+        // policy-splice-quiescent
+        let holder_info = estate
+            .current_holder_commit_info
+            .as_ref()
+            .ok_or_else(|| policy_error("current_holder_commit_info missing"))?;
+        let counterparty_info = estate
+            .current_counterparty_commit_info
+            .as_ref()
+            .ok_or_else(|| policy_error("current_counterparty_commit_info missing"))?;
+        if !holder_info.htlcs_is_empty() || !counterparty_info.htlcs_is_empty() {
+            return policy_err!(
+                rule = "policy-splice-quiescent",
+                "cannot splice with HTLCs in flight"
+            );
+        }
+
+        // policy-splice-previous-committed
+        if estate.holder_commit_is_pre_splice() {
+            return policy_err!(
+                rule = "policy-splice-previous-committed",
+                "previous splice has no holder commitment yet"
+            );
+        }
+
+        // policy-splice-previous-buried
+        if estate.splice.is_some() && cstate.splice_depth < SPLICE_BURIED_DEPTH {
+            return policy_err!(
+                rule = "policy-splice-previous-buried",
+                "previous splice has {} confirmations, {} required",
+                cstate.splice_depth,
+                SPLICE_BURIED_DEPTH
+            );
+        }
+
+        // policy-splice-fee-range
+        if info.fee_sat > self.policy.max_fee {
+            return policy_err!(
+                rule = "policy-splice-fee-range",
+                "fee above maximum: {} > {}",
+                info.fee_sat,
+                self.policy.max_fee
+            )
+            .map_err(|ve| ve.with_values(info.fee_sat, self.policy.max_fee));
+        }
+
+        // policy-splice-channel-value
+        if info.channel_value_sat > self.policy.max_channel_size_sat {
+            return policy_err!(
+                rule = "policy-splice-channel-value",
+                "channel value {} too large",
+                info.channel_value_sat
+            )
+            .map_err(|ve| {
+                ve.with_values(info.channel_value_sat, self.policy.max_channel_size_sat)
+            });
+        }
+
+        // policy-splice-holder-balance
+        let holder_value_sat =
+            holder_info.to_broadcaster_value_sat.min(counterparty_info.to_countersigner_value_sat);
+        if info.holder_delta_sat < -(holder_value_sat as i64) {
+            return policy_err!(
+                rule = "policy-splice-holder-balance",
+                "splice takes {} out of a balance of {}",
+                -info.holder_delta_sat,
+                holder_value_sat
+            );
+        }
+
+        // policy-splice-holder-outputs
+        // Whatever our side puts in must come back to our wallet or
+        // allowlisted outputs, except for our share of the fee.
+        let holder_fee_sat = info.holder_fee_sat();
+        if holder_fee_sat < 0 || holder_fee_sat > info.fee_sat as i64 {
+            return policy_err!(
+                rule = "policy-splice-holder-outputs",
+                "our inputs of {} less our outputs of {} don't match a balance change of {} \
+                 and a fee of {}",
+                info.holder_inputs_sat,
+                info.holder_outputs_sat,
+                info.holder_delta_sat,
+                info.fee_sat
+            );
+        }
 
         *debug_on_return = false;
         Ok(())
@@ -474,7 +873,11 @@ impl Validator for SimpleValidator {
 
         // policy-commitment-version
         if tx.version != 2 {
-            return policy_err!("bad commitment version: {}", tx.version);
+            return policy_err!(
+                rule = "policy-commitment-version",
+                "bad commitment version: {}",
+                tx.version
+            );
         }
 
         let mut info = CommitmentInfo::new(is_counterparty);
@@ -538,6 +941,7 @@ impl Validator for SimpleValidator {
         // but gives better diagnostic.
         if commit_num > estate.next_counterparty_revoke_num + 1 {
             return policy_err!(
+                rule = "policy-commitment-previous-revoked",
                 "invalid attempt to sign counterparty commit_num {} \
                          with next_counterparty_revoke_num {}",
                 commit_num,
@@ -552,6 +956,7 @@ impl Validator for SimpleValidator {
             let prev_commit_point = estate.get_previous_counterparty_point(commit_num)?;
             if *commitment_point != prev_commit_point {
                 return policy_err!(
+                    rule = "policy-commitment-retry-same",
                     "retry of sign_counterparty_commitment {} with changed point: \
                              prev {} != new {}",
                     commit_num,
@@ -565,6 +970,7 @@ impl Validator for SimpleValidator {
             if *info2 != prev_commit_info {
                 debug_vals!(*info2, prev_commit_info);
                 return policy_err!(
+                    rule = "policy-commitment-retry-same",
                     "retry of sign_counterparty_commitment {} with changed info",
                     commit_num,
                 );
@@ -623,7 +1029,11 @@ impl Validator for SimpleValidator {
                 &estate.current_holder_commit_info.as_ref().expect("current_holder_commit_info");
             if info2 != *holder_commit_info {
                 debug_vals!(*info2, holder_commit_info);
-                return policy_err!("retry holder commitment {} with changed info", commit_num);
+                return policy_err!(
+                    rule = "policy-commitment-retry-same",
+                    "retry holder commitment {} with changed info",
+                    commit_num
+                );
             }
         }
 
@@ -633,6 +1043,7 @@ impl Validator for SimpleValidator {
         if commit_num + 2 <= estate.next_holder_commit_num {
             debug_failed_vals!(estate, commit_num);
             return policy_err!(
+                rule = "policy-commitment-holder-not-revoked",
                 "can't sign revoked commitment_number {}, \
                  next_holder_commit_num is {}",
                 commit_num,
@@ -645,7 +1056,7 @@ impl Validator for SimpleValidator {
         // a new state.
         if commit_num == estate.next_holder_commit_num && estate.mutual_close_signed {
             debug_failed_vals!(estate);
-            return policy_err!("mutual close already signed");
+            return policy_err!(rule = "policy-revoke-not-closed", "mutual close already signed");
         }
 
         *debug_on_return = false;
@@ -678,6 +1089,7 @@ impl Validator for SimpleValidator {
         if supplied_commit_point != prev_commit_point {
             debug_failed_vals!(state, revoke_num, commitment_secret);
             return policy_err!(
+                rule = "policy-commitment-previous-revoked",
                 "revocation commit point mismatch for commit_num {}: supplied {}, previous {}",
                 revoke_num,
                 supplied_commit_point,
@@ -710,8 +1122,16 @@ impl Validator for SimpleValidator {
         } else {
             EcdsaSighashType::All
         };
+        // The holder attaches fee inputs and change outputs to a zero-fee HTLC
+        // tx before asking for our signature.  Recompose only the HTLC input
+        // and output, and sign the whole transaction with SIGHASH_ALL.
+        let recompose_sighash_type = if !is_counterparty && setup.option_anchors_zero_fee_htlc() {
+            EcdsaSighashType::SinglePlusAnyoneCanPay
+        } else {
+            sighash_type
+        };
         let original_tx_sighash = SighashCache::new(tx)
-            .segwit_signature_hash(0, &redeemscript, htlc_amount_sat, sighash_type)
+            .segwit_signature_hash(0, &redeemscript, htlc_amount_sat, recompose_sighash_type)
             .unwrap();
 
         let offered = if parse_offered_htlc_script(redeemscript, setup.option_anchor_outputs())
@@ -737,7 +1157,12 @@ impl Validator for SimpleValidator {
         let cltv_expiry = if offered { tx.lock_time } else { 0 };
         let transaction_output_index = tx.input[0].previous_output.vout;
         let commitment_txid = tx.input[0].previous_output.txid;
-        let total_fee = htlc_amount_sat - tx.output[0].value;
+        let total_fee = htlc_amount_sat.checked_sub(tx.output[0].value).ok_or_else(|| {
+            transaction_format_error(format!(
+                "output value {} is larger than the htlc amount {}",
+                tx.output[0].value, htlc_amount_sat
+            ))
+        })?;
 
         // Derive the feerate_per_kw used to generate this
         // transaction.  Compensate for the total_fee being rounded
@@ -769,7 +1194,7 @@ impl Validator for SimpleValidator {
         );
 
         let recomposed_tx_sighash = SighashCache::new(&recomposed_tx)
-            .segwit_signature_hash(0, &redeemscript, htlc_amount_sat, sighash_type)
+            .segwit_signature_hash(0, &redeemscript, htlc_amount_sat, recompose_sighash_type)
             .unwrap();
 
         if recomposed_tx_sighash != original_tx_sighash {
@@ -821,12 +1246,20 @@ impl Validator for SimpleValidator {
         // - policy-htlc-revocation-pubkey
         // - policy-htlc-delayed-pubkey
 
-        Ok((feerate_per_kw, htlc, recomposed_tx_sighash, sighash_type))
+        let sighash = if recompose_sighash_type == sighash_type {
+            recomposed_tx_sighash
+        } else {
+            SighashCache::new(tx)
+                .segwit_signature_hash(0, &redeemscript, htlc_amount_sat, sighash_type)
+                .unwrap()
+        };
+
+        Ok((feerate_per_kw, htlc, sighash, sighash_type))
     }
 
     fn validate_htlc_tx(
         &self,
-        _setup: &ChannelSetup,
+        setup: &ChannelSetup,
         _cstate: &ChainState,
         _is_counterparty: bool,
         htlc: &HTLCOutputInCommitment,
@@ -841,23 +1274,39 @@ impl Validator for SimpleValidator {
         // there, only in the commitment tx output.
         // policy-htlc-locktime
         if htlc.offered && htlc.cltv_expiry == 0 {
-            return policy_err!("offered lock_time must be non-zero");
-        }
-
-        // policy-htlc-fee-range
-        if feerate_per_kw < self.policy.min_feerate_per_kw {
             return policy_err!(
-                "feerate_per_kw of {} is smaller than the minimum of {}",
-                feerate_per_kw,
-                self.policy.min_feerate_per_kw
+                rule = "policy-htlc-locktime",
+                "offered lock_time must be non-zero"
             );
         }
-        if feerate_per_kw > self.policy.max_feerate_per_kw {
-            return policy_err!(
-                "feerate_per_kw of {} is larger than the maximum of {}",
-                feerate_per_kw,
-                self.policy.max_feerate_per_kw
-            );
+
+        // policy-htlc-fee-range
+        if setup.option_anchors_zero_fee_htlc() {
+            // the fee is attached by adding inputs at broadcast time
+            if feerate_per_kw != 0 {
+                return policy_err!(
+                    rule = "policy-htlc-fee-range",
+                    "feerate_per_kw of {} is not zero for a zero-fee HTLC tx",
+                    feerate_per_kw
+                );
+            }
+        } else {
+            if feerate_per_kw < self.policy.min_feerate_per_kw {
+                return policy_err!(
+                    rule = "policy-htlc-fee-range",
+                    "feerate_per_kw of {} is smaller than the minimum of {}",
+                    feerate_per_kw,
+                    self.policy.min_feerate_per_kw
+                );
+            }
+            if feerate_per_kw > self.policy.max_feerate_per_kw {
+                return policy_err!(
+                    rule = "policy-htlc-fee-range",
+                    "feerate_per_kw of {} is larger than the maximum of {}",
+                    feerate_per_kw,
+                    self.policy.max_feerate_per_kw
+                );
+            }
         }
 
         *debug_on_return = false;
@@ -1078,6 +1527,7 @@ impl Validator for SimpleValidator {
 
         if to_holder_value_sat > 0 && holder_script.is_none() {
             return policy_err!(
+                rule = "policy-mutual-destination-allowlisted",
                 "missing holder_script with {} to_holder_value_sat",
                 to_holder_value_sat
             );
@@ -1085,6 +1535,7 @@ impl Validator for SimpleValidator {
 
         if to_counterparty_value_sat > 0 && counterparty_script.is_none() {
             return policy_err!(
+                rule = "policy-mutual-destination-allowlisted",
                 "missing counterparty_script with {} to_counterparty_value_sat",
                 to_counterparty_value_sat
             );
@@ -1094,21 +1545,29 @@ impl Validator for SimpleValidator {
         // holder script matches.
         if setup.holder_shutdown_script.is_some() && to_holder_value_sat > 0 {
             if *holder_script != setup.holder_shutdown_script {
-                return policy_err!("holder_script doesn't match upfront holder_shutdown_script");
+                return policy_err!(
+                    rule = "policy-mutual-destination-allowlisted",
+                    "holder_script doesn't match upfront holder_shutdown_script"
+                );
             }
         }
 
         // policy-mutual-no-pending-htlcs
         if !holder_info.htlcs_is_empty() || !counterparty_info.htlcs_is_empty() {
-            return policy_err!("cannot close with pending htlcs");
+            return policy_err!(
+                rule = "policy-mutual-no-pending-htlcs",
+                "cannot close with pending htlcs"
+            );
         }
 
         // policy-mutual-fee-range
         let sum_outputs = to_holder_value_sat
             .checked_add(to_counterparty_value_sat)
             .ok_or_else(|| policy_error("consumed overflow".to_string()))?;
-        self.validate_fee(setup.channel_value_sat, sum_outputs)
-            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        self.validate_fee(setup.channel_value_sat, sum_outputs).map_err(|ve| {
+            ve.with_rule("policy-mutual-fee-range")
+                .prepend_msg(format!("{}: ", containing_function!()))
+        })?;
 
         // policy-mutual-value-matches-commitment
         // To make this test independent of variable fees we compare the side that
@@ -1121,6 +1580,7 @@ impl Validator for SimpleValidator {
                 counterparty_info.to_broadcaster_value_sat,
             ) {
                 return policy_err!(
+                    rule = "policy-mutual-value-matches-commitment",
                     "to_counterparty_value {} \
                      is {} than counterparty_info.broadcaster_value_sat {}",
                     to_counterparty_value_sat,
@@ -1133,6 +1593,7 @@ impl Validator for SimpleValidator {
                 holder_info.to_countersigner_value_sat,
             ) {
                 return policy_err!(
+                    rule = "policy-mutual-value-matches-commitment",
                     "to_counterparty_value {} \
                      is {} than holder_info.countersigner_value_sat {}",
                     to_counterparty_value_sat,
@@ -1147,6 +1608,7 @@ impl Validator for SimpleValidator {
                 .outside_epsilon_range(to_holder_value_sat, holder_info.to_broadcaster_value_sat)
             {
                 return policy_err!(
+                    rule = "policy-mutual-value-matches-commitment",
                     "to_holder_value {} is {} than holder_info.broadcaster_value_sat {}",
                     to_holder_value_sat,
                     descr,
@@ -1158,6 +1620,7 @@ impl Validator for SimpleValidator {
                 counterparty_info.to_countersigner_value_sat,
             ) {
                 return policy_err!(
+                    rule = "policy-mutual-value-matches-commitment",
                     "to_holder_value {} is {} than counterparty_info.countersigner_value_sat {}",
                     to_holder_value_sat,
                     descr,
@@ -1173,7 +1636,10 @@ impl Validator for SimpleValidator {
                 .map_err(|err| policy_error(format!("wallet can_spend error: {}", err)))?
                 && !wallet.allowlist_contains(script)
             {
-                return policy_err!("holder output not to wallet or in allowlist");
+                return policy_err!(
+                    rule = "policy-mutual-destination-allowlisted",
+                    "holder output not to wallet or in allowlist"
+                );
             }
         }
 
@@ -1201,6 +1667,7 @@ impl Validator for SimpleValidator {
         // policy-sweep-locktime
         if tx.lock_time > cstate.current_height {
             return transaction_format_err!(
+                rule = "policy-sweep-locktime",
                 "bad locktime: {} > {}",
                 tx.lock_time,
                 cstate.current_height
@@ -1211,6 +1678,7 @@ impl Validator for SimpleValidator {
         let seq = tx.input[0].sequence;
         if seq != setup.counterparty_selected_contest_delay as u32 {
             return transaction_format_err!(
+                rule = "policy-sweep-sequence",
                 "bad sequence: {} != {}",
                 seq,
                 setup.counterparty_selected_contest_delay
@@ -1250,12 +1718,17 @@ impl Validator for SimpleValidator {
         {
             // It's a received htlc (counterparty perspective)
             if cltv_expiry < 0 || cltv_expiry > u32::MAX as i64 {
-                return transaction_format_err!("bad cltv_expiry: {}", cltv_expiry);
+                return transaction_format_err!(
+                    rule = "policy-sweep-locktime",
+                    "bad cltv_expiry: {}",
+                    cltv_expiry
+                );
             }
 
             // policy-sweep-locktime
             if tx.lock_time > cltv_expiry as u32 {
                 return transaction_format_err!(
+                    rule = "policy-sweep-locktime",
                     "bad locktime: {} > {}",
                     tx.lock_time,
                     cltv_expiry as u32
@@ -1273,6 +1746,7 @@ impl Validator for SimpleValidator {
             // policy-sweep-locktime
             if tx.lock_time > cstate.current_height {
                 return transaction_format_err!(
+                    rule = "policy-sweep-locktime",
                     "bad locktime: {} > {}",
                     tx.lock_time,
                     cstate.current_height
@@ -1291,7 +1765,12 @@ impl Validator for SimpleValidator {
             SimpleValidator::NON_ANCHOR_SEQS.to_vec()
         };
         if !valid_seqs.contains(&seq) {
-            return transaction_format_err!("bad sequence: {} not in {:?}", seq, valid_seqs,);
+            return transaction_format_err!(
+                rule = "policy-sweep-sequence",
+                "bad sequence: {} not in {:?}",
+                seq,
+                valid_seqs,
+            );
         }
 
         *debug_on_return = false;
@@ -1318,6 +1797,7 @@ impl Validator for SimpleValidator {
         // policy-sweep-locktime
         if tx.lock_time > cstate.current_height {
             return transaction_format_err!(
+                rule = "policy-sweep-locktime",
                 "bad locktime: {} > {}",
                 tx.lock_time,
                 cstate.current_height
@@ -1328,7 +1808,12 @@ impl Validator for SimpleValidator {
         let seq = tx.input[0].sequence;
         let valid_seqs = SimpleValidator::NON_ANCHOR_SEQS.to_vec();
         if !valid_seqs.contains(&seq) {
-            return transaction_format_err!("bad sequence: {} not in {:?}", seq, valid_seqs);
+            return transaction_format_err!(
+                rule = "policy-sweep-sequence",
+                "bad sequence: {} not in {:?}",
+                seq,
+                valid_seqs
+            );
         }
 
         *debug_on_return = false;
@@ -1341,16 +1826,87 @@ impl Validator for SimpleValidator {
         outgoing: u64,
         invoiced_amount_msat: Option<u64>,
     ) -> Result<(), ValidationError> {
-        let max_to_invoice = if let Some(a) = invoiced_amount_msat {
-            (a + self.policy.max_routing_fee_msat) / 1000
+        match invoiced_amount_msat {
+            Some(a) => {
+                // policy-routing-max-fee
+                // Paying an invoice or keysend may cost at most
+                // max_routing_fee_msat over the amount, even if invoices
+                // are not required
+                let max_to_invoice = (a + self.policy.max_routing_fee_msat) / 1000;
+                if incoming + max_to_invoice < outgoing {
+                    return policy_err!(
+                        rule = "policy-routing-max-fee",
+                        "net outgoing {} exceeds amount plus maximum routing fee {}",
+                        outgoing - incoming,
+                        max_to_invoice
+                    );
+                }
+            }
+            None => {
+                // policy-routing-balanced
+                if self.policy.require_invoices && incoming < outgoing {
+                    return policy_err!(rule = "policy-routing-balanced", "incoming < outgoing");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_keysend(
+        &self,
+        is_allowlisted: bool,
+        amount_msat: u64,
+        payee_total_msat: u64,
+        total_msat: u64,
+    ) -> Result<(), ValidationError> {
+        // policy-keysend-budget
+        if !self.policy.require_invoices || is_allowlisted {
+            return Ok(());
+        }
+        let payee_new_total_msat = payee_total_msat.saturating_add(amount_msat);
+        if payee_new_total_msat > self.policy.max_keysend_per_payee_msat {
+            return policy_err!(
+                rule = "policy-keysend-budget",
+                "keysend of {} exceeds the per-payee budget: {} already sent, budget {}",
+                amount_msat,
+                payee_total_msat,
+                self.policy.max_keysend_per_payee_msat
+            );
+        }
+        let new_total_msat = total_msat.saturating_add(amount_msat);
+        if new_total_msat > self.policy.max_keysend_msat {
+            return policy_err!(
+                rule = "policy-keysend-budget",
+                "keysend of {} exceeds the global budget: {} already sent, budget {}",
+                amount_msat,
+                total_msat,
+                self.policy.max_keysend_msat
+            );
+        }
+        Ok(())
+    }
+
+    fn keysend_window_blocks(&self) -> Option<u32> {
+        if self.policy.keysend_window_blocks > 0 {
+            Some(self.policy.keysend_window_blocks)
         } else {
-            0
-        };
-        // policy-routing-balanced
-        if self.policy.require_invoices && incoming + max_to_invoice < outgoing {
-            policy_err!("incoming < outgoing")
+            None
+        }
+    }
+
+    fn stub_timeout_blocks(&self) -> Option<u32> {
+        if self.policy.stub_timeout_blocks > 0 {
+            Some(self.policy.stub_timeout_blocks)
         } else {
-            Ok(())
+            None
+        }
+    }
+
+    fn max_stubs(&self) -> Option<usize> {
+        if self.policy.max_stubs > 0 {
+            Some(self.policy.max_stubs as usize)
+        } else {
+            None
         }
     }
 
@@ -1358,6 +1914,18 @@ impl Validator for SimpleValidator {
         self.policy.enforce_balance
     }
 
+    fn require_seen_bolt12_invoices(&self) -> bool {
+        self.policy.require_seen_bolt12_invoices
+    }
+
+    fn excess_drain_schedule(&self) -> Option<(u32, u8)> {
+        if self.policy.excess_drain_interval_blocks > 0 {
+            Some((self.policy.excess_drain_interval_blocks, self.policy.excess_drain_percent))
+        } else {
+            None
+        }
+    }
+
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
         holder_value_msat / 1000
     }
@@ -1387,7 +1955,11 @@ impl SimpleValidator {
                 "to_broadcaster_value_sat {} less than dust limit {}",
                 info.to_broadcaster_value_sat,
                 MIN_DUST_LIMIT_SATOSHIS
-            );
+            )
+            .map_err(|ve| {
+                ve.with_rule("policy-commitment-outputs-trimmed")
+                    .with_values(info.to_broadcaster_value_sat, MIN_DUST_LIMIT_SATOSHIS)
+            });
         }
         if info.to_countersigner_value_sat > 0
             && info.to_countersigner_value_sat < MIN_DUST_LIMIT_SATOSHIS
@@ -1396,25 +1968,32 @@ impl SimpleValidator {
                 "to_countersigner_value_sat {} less than dust limit {}",
                 info.to_countersigner_value_sat,
                 MIN_DUST_LIMIT_SATOSHIS
-            );
+            )
+            .map_err(|ve| {
+                ve.with_rule("policy-commitment-outputs-trimmed")
+                    .with_values(info.to_countersigner_value_sat, MIN_DUST_LIMIT_SATOSHIS)
+            });
         }
 
         // policy-commitment-htlc-count-limit
-        if info.offered_htlcs.len() + info.received_htlcs.len() > policy.max_htlcs {
-            return Err(policy_error("too many HTLCs".to_string()));
+        let num_htlcs = info.offered_htlcs.len() + info.received_htlcs.len();
+        if num_htlcs > policy.max_htlcs {
+            return Err(policy_error("too many HTLCs".to_string())
+                .with_rule("policy-commitment-htlc-count-limit")
+                .with_values(num_htlcs, policy.max_htlcs));
         }
 
         let mut htlc_value_sat: u64 = 0;
 
-        let offered_htlc_dust_limit = MIN_DUST_LIMIT_SATOSHIS
-            + (DUST_RELAY_TX_FEE as u64 * htlc_timeout_tx_weight(setup.option_anchor_outputs())
-                / 1000);
+        let offered_htlc_dust_limit =
+            htlc_dust_limit(setup, htlc_timeout_tx_weight(setup.option_anchor_outputs()));
         for htlc in &info.offered_htlcs {
             // TODO - this check should be converted into two checks, one the first time
             // the HTLC is introduced and the other every time it is encountered.
             //
             // policy-commitment-htlc-cltv-range
-            self.validate_expiry("offered HTLC", htlc.cltv_expiry, cstate.current_height)?;
+            self.validate_expiry("offered HTLC", htlc.cltv_expiry, cstate.current_height)
+                .map_err(|ve| ve.with_rule("policy-commitment-htlc-cltv-range"))?;
 
             htlc_value_sat = htlc_value_sat
                 .checked_add(htlc.value_sat)
@@ -1426,19 +2005,23 @@ impl SimpleValidator {
                     "offered htlc.value_sat {} less than dust limit {}",
                     htlc.value_sat,
                     offered_htlc_dust_limit
-                );
+                )
+                .map_err(|ve| {
+                    ve.with_rule("policy-commitment-outputs-trimmed")
+                        .with_values(htlc.value_sat, offered_htlc_dust_limit)
+                });
             }
         }
 
-        let received_htlc_dust_limit = MIN_DUST_LIMIT_SATOSHIS
-            + (DUST_RELAY_TX_FEE as u64 * htlc_success_tx_weight(setup.option_anchor_outputs())
-                / 1000);
+        let received_htlc_dust_limit =
+            htlc_dust_limit(setup, htlc_success_tx_weight(setup.option_anchor_outputs()));
         for htlc in &info.received_htlcs {
             // TODO - this check should be converted into two checks, one the first time
             // the HTLC is introduced and the other every time it is encountered.
             //
             // policy-commitment-htlc-cltv-range
-            self.validate_expiry("received HTLC", htlc.cltv_expiry, cstate.current_height)?;
+            self.validate_expiry("received HTLC", htlc.cltv_expiry, cstate.current_height)
+                .map_err(|ve| ve.with_rule("policy-commitment-htlc-cltv-range"))?;
 
             htlc_value_sat = htlc_value_sat
                 .checked_add(htlc.value_sat)
@@ -1450,13 +2033,20 @@ impl SimpleValidator {
                     "received htlc.value_sat {} less than dust limit {}",
                     htlc.value_sat,
                     received_htlc_dust_limit
-                );
+                )
+                .map_err(|ve| {
+                    ve.with_rule("policy-commitment-outputs-trimmed")
+                        .with_values(htlc.value_sat, received_htlc_dust_limit)
+                });
             }
         }
 
         // policy-commitment-htlc-inflight-limit
         if htlc_value_sat > policy.max_htlc_value_sat {
-            return policy_err!("sum of HTLC values {} too large", htlc_value_sat);
+            return policy_err!("sum of HTLC values {} too large", htlc_value_sat).map_err(|ve| {
+                ve.with_rule("policy-commitment-htlc-inflight-limit")
+                    .with_values(htlc_value_sat, policy.max_htlc_value_sat)
+            });
         }
 
         // policy-commitment-fee-range
@@ -1466,8 +2056,10 @@ impl SimpleValidator {
             .ok_or_else(|| policy_error("channel value overflow".to_string()))?
             .checked_add(htlc_value_sat)
             .ok_or_else(|| policy_error("channel value overflow on HTLC".to_string()))?;
-        self.validate_fee(setup.channel_value_sat, sum_outputs)
-            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        self.validate_fee(setup.channel_value_sat, sum_outputs).map_err(|ve| {
+            ve.with_rule("policy-commitment-fee-range")
+                .prepend_msg(format!("{}: ", containing_function!()))
+        })?;
 
         let (_holder_value_sat, counterparty_value_sat) = info.value_to_parties();
 
@@ -1481,7 +2073,18 @@ impl SimpleValidator {
             // If we are the funder, the value to us of the initial
             // commitment transaction should be equal to our funding
             // value.
-            if setup.is_outbound {
+            if setup.is_dual_funded() {
+                // Both sides funded the channel, so the counterparty is
+                // entitled to its contribution adjusted by the push
+                let holder_value_msat = setup.initial_holder_value_msat().map_err(policy_error)?;
+                let counterparty_max_sat = setup.channel_value_sat - holder_value_msat / 1000;
+                if counterparty_value_sat > counterparty_max_sat {
+                    return policy_err!(
+                        "initial commitment may only send {} to counterparty",
+                        counterparty_max_sat
+                    );
+                }
+            } else if setup.is_outbound {
                 // Ensure that no extra value is sent to fundee, the
                 // no-initial-htlcs and fee checks above will ensure
                 // that our share is valid.
@@ -1489,6 +2092,7 @@ impl SimpleValidator {
                 // The fundee is only entitled to push_value
                 if counterparty_value_sat > setup.push_value_msat / 1000 {
                     return policy_err!(
+                        rule = "policy-commitment-initial-funding-value",
                         "initial commitment may only send push_value_msat ({}) to fundee",
                         setup.push_value_msat
                     );
@@ -1519,6 +2123,14 @@ pub fn make_simple_policy(network: Network) -> SimplePolicy {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            max_keysend_per_payee_msat: 0,
+            max_keysend_msat: 0,
+            keysend_window_blocks: 144,
+            excess_drain_interval_blocks: 144,
+            excess_drain_percent: 10,
+            stub_timeout_blocks: 0,
+            max_stubs: 0,
+            require_seen_bolt12_invoices: false,
         }
     } else {
         SimplePolicy {
@@ -1537,6 +2149,14 @@ pub fn make_simple_policy(network: Network) -> SimplePolicy {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            max_keysend_per_payee_msat: 0,
+            max_keysend_msat: 0,
+            keysend_window_blocks: 144,
+            excess_drain_interval_blocks: 144,
+            excess_drain_percent: 10,
+            stub_timeout_blocks: 0,
+            max_stubs: 0,
+            require_seen_bolt12_invoices: false,
         }
     }
 }
@@ -1568,6 +2188,14 @@ mod tests {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            max_keysend_per_payee_msat: 0,
+            max_keysend_msat: 0,
+            keysend_window_blocks: 0,
+            excess_drain_interval_blocks: 0,
+            excess_drain_percent: 0,
+            stub_timeout_blocks: 0,
+            max_stubs: 0,
+            require_seen_bolt12_invoices: false,
         };
 
         SimpleValidator {
@@ -3,13 +3,15 @@ extern crate scopeguard;
 use core::cmp::{max, min};
 
 use bitcoin::secp256k1::{PublicKey, SecretKey};
-use bitcoin::{self, EcdsaSighashType, Network, Script, Sighash, Transaction};
+use bitcoin::{self, EcdsaSighashType, Network, OutPoint, Script, Sighash, Transaction};
 use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 use lightning::ln::PaymentHash;
 use log::debug;
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::node::SpendType;
+use crate::policy::simple_validator::SimplePolicy;
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2, HTLCInfo2, PreimageMap};
@@ -41,6 +43,9 @@ pub trait Validator {
     /// * `channels` the funded channel for each funding output, or
     ///   None for change outputs
     /// * `values_sat` - the amount in satoshi per input
+    /// * `spendtypes` - spend type per input, `Invalid` if not ours
+    /// * `prev_txs` - the transaction spent by each input, if known, or
+    ///   an empty vector.  These were already checked against the outpoints.
     /// * `opaths` - derivation path for change, one per output,
     ///   empty for non-change or allowlisted outputs
     fn validate_onchain_tx(
@@ -49,9 +54,43 @@ pub trait Validator {
         channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         tx: &Transaction,
         values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        prev_txs: &Vec<Option<Transaction>>,
         opaths: &Vec<Vec<u32>>,
     ) -> Result<(), ValidationError>;
 
+    /// Phase 1 decoding of a splice transaction, which spends the current
+    /// funding output of the channel and creates a new one.
+    ///
+    /// * `new_funding_vout` - the index of the new funding output
+    /// * `holder_delta_sat` - the agreed change in our channel balance
+    /// * `values_sat` - the amount in satoshi per input
+    /// * `spendtypes` - spend type per input, `Invalid` if not ours.
+    ///   The input spending the current funding output is not ours.
+    /// * `opaths` - derivation path for change, one per output,
+    ///   empty for non-change or allowlisted outputs
+    fn decode_splice_tx(
+        &self,
+        wallet: &Wallet,
+        keys: &InMemorySigner,
+        setup: &ChannelSetup,
+        tx: &Transaction,
+        new_funding_vout: u32,
+        holder_delta_sat: i64,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<SpliceTxInfo, ValidationError>;
+
+    /// Phase 2 validation of a splice transaction
+    fn validate_splice_tx(
+        &self,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        estate: &EnforcementState,
+        info: &SpliceTxInfo,
+    ) -> Result<(), ValidationError>;
+
     /// Phase 1 CommitmentInfo
     fn decode_commitment_tx(
         &self,
@@ -190,12 +229,66 @@ pub trait Validator {
         invoiced_amount_msat: Option<u64>,
     ) -> Result<(), ValidationError>;
 
+    /// Validation of a keysend payment, which is not covered by an invoice.
+    /// `payee_total_msat` is the total of earlier keysend payments to this
+    /// payee, and `total_msat` the total to all payees that are not
+    /// allowlisted, within the keysend window.
+    fn validate_keysend(
+        &self,
+        is_allowlisted: bool,
+        amount_msat: u64,
+        payee_total_msat: u64,
+        total_msat: u64,
+    ) -> Result<(), ValidationError>;
+
+    /// The number of blocks within which added keysends count against the
+    /// keysend budgets, or None if all keysends ever added count.
+    fn keysend_window_blocks(&self) -> Option<u32> {
+        None
+    }
+
+    /// Validate that a channel can be forgotten.  [`Node::forget_channel`]
+    /// already keeps any channel that has signed a commitment or a mutual
+    /// close, so this is only for further restrictions.
+    ///
+    /// [`Node::forget_channel`]: crate::node::Node::forget_channel
+    fn validate_forget_channel(&self, _estate: &EnforcementState) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// The number of blocks after which a stub that never became ready is
+    /// forgotten, or None to keep stubs until explicitly forgotten.
+    fn stub_timeout_blocks(&self) -> Option<u32> {
+        None
+    }
+
+    /// The maximum number of channel stubs a node may hold at once, or
+    /// None if unlimited.
+    fn max_stubs(&self) -> Option<usize> {
+        None
+    }
+
     /// Whether the policy specifies that holder balance should be tracked and
     /// enforced.
     fn enforce_balance(&self) -> bool {
         false
     }
 
+    /// Whether BOLT 12 invoices are only signed if their TLV stream was
+    /// seen, so that the incoming payment can be tracked.
+    fn require_seen_bolt12_invoices(&self) -> bool {
+        false
+    }
+
+    /// How the excess amount, which accumulates routing income, drains
+    /// over time.  Every `interval_blocks` blocks, `percent` of the excess
+    /// amount is drained, so that accumulated income cannot absorb
+    /// arbitrarily large losses later.  Returns `(interval_blocks, percent)`,
+    /// or None to never drain.
+    fn excess_drain_schedule(&self) -> Option<(u32, u8)> {
+        None
+    }
+
     /// The minimum initial commitment transaction balance to us, given
     /// the funding amount.
     /// The result is in satoshi.
@@ -213,6 +306,8 @@ pub struct ChainState {
     pub funding_double_spent_depth: u32,
     /// Zero or the number of confirmations of a closing tx
     pub closing_depth: u32,
+    /// Zero or the number of confirmations of the last splice tx
+    pub splice_depth: u32,
 }
 
 /// A factory for validators
@@ -224,6 +319,60 @@ pub trait ValidatorFactory: Send + Sync {
         node_id: PublicKey,
         channel_id: Option<ChannelId>,
     ) -> Arc<dyn Validator>;
+
+    /// A factory of the same kind, enforcing `policy` instead
+    fn with_policy(&self, policy: SimplePolicy) -> Arc<dyn ValidatorFactory>;
+}
+
+/// A decoded splice transaction
+#[derive(Clone, Debug)]
+pub struct SpliceTxInfo {
+    /// The new funding outpoint
+    pub funding_outpoint: OutPoint,
+    /// The new channel value, in satoshi
+    pub channel_value_sat: u64,
+    /// The sum of our inputs, in satoshi
+    pub holder_inputs_sat: u64,
+    /// The sum of our wallet and allowlisted outputs, in satoshi
+    pub holder_outputs_sat: u64,
+    /// The transaction fee, in satoshi
+    pub fee_sat: u64,
+    /// The agreed change in our channel balance, in satoshi
+    pub holder_delta_sat: i64,
+    /// The value of each input, in satoshi, if it is ours
+    pub holder_input_values: Vec<Option<u64>>,
+}
+
+impl SpliceTxInfo {
+    /// Our share of the fee, in satoshi.  This is what our side puts into
+    /// the splice, from our inputs and our channel balance, that does not
+    /// come back to our wallet or allowlisted outputs.
+    pub fn holder_fee_sat(&self) -> i64 {
+        self.holder_inputs_sat as i64 - self.holder_outputs_sat as i64 - self.holder_delta_sat
+    }
+}
+
+/// The last splice of a channel.
+///
+/// The commitments made before the splice spend the prior funding output,
+/// so their balances are adjusted by `holder_delta_sat` until they are
+/// replaced.  The prior funding output stays relevant until the splice is
+/// buried, since the splice may never confirm.
+#[derive(Clone, Debug)]
+pub struct SpliceInfo {
+    /// The funding outpoint spent by the splice
+    pub prior_funding_outpoint: OutPoint,
+    /// The channel value before the splice, in satoshi
+    pub prior_channel_value_sat: u64,
+    /// The change in our channel balance, in satoshi
+    pub holder_delta_sat: i64,
+    /// The next holder commitment number at the time of the splice
+    pub next_holder_commit_num: u64,
+    /// The next counterparty commitment number at the time of the splice
+    pub next_counterparty_commit_num: u64,
+    /// The value of each input of the splice, in satoshi, if it is ours,
+    /// as validated.  Only these inputs are signed.
+    pub holder_input_values: Vec<Option<u64>>,
 }
 
 /// Enforcement state for a channel
@@ -243,6 +392,7 @@ pub struct EnforcementState {
     pub previous_counterparty_commit_info: Option<CommitmentInfo2>,
     pub mutual_close_signed: bool,
     pub initial_holder_value: u64,
+    pub splice: Option<SpliceInfo>,
 }
 
 impl EnforcementState {
@@ -262,9 +412,28 @@ impl EnforcementState {
             previous_counterparty_commit_info: None,
             mutual_close_signed: false,
             initial_holder_value,
+            splice: None,
         }
     }
 
+    /// Whether the current holder commitment spends the funding output
+    /// from before the last splice
+    pub fn holder_commit_is_pre_splice(&self) -> bool {
+        self.splice
+            .as_ref()
+            .map(|s| self.next_holder_commit_num <= s.next_holder_commit_num)
+            .unwrap_or(false)
+    }
+
+    /// Whether the current counterparty commitment spends the funding output
+    /// from before the last splice
+    pub fn counterparty_commit_is_pre_splice(&self) -> bool {
+        self.splice
+            .as_ref()
+            .map(|s| self.next_counterparty_commit_num <= s.next_counterparty_commit_num)
+            .unwrap_or(false)
+    }
+
     /// Returns the minimum amount to_holder from both commitments or
     /// None if the amounts are not within epsilon_sat.
     pub fn minimum_to_holder_value(&self, epsilon_sat: u64) -> Option<u64> {
@@ -321,7 +490,12 @@ impl EnforcementState {
     ) -> Result<(), ValidationError> {
         let current = self.next_holder_commit_num;
         if num != current && num != current + 1 {
-            return policy_err!("invalid progression: {} to {}", current, num);
+            return policy_err!(
+                rule = "policy-commitment-sequence",
+                "invalid progression: {} to {}",
+                current,
+                num
+            );
         }
         // TODO - should we enforce policy-v2-commitment-retry-same here?
         debug!("next_holder_commit_num {} -> {}", current, num);
@@ -354,7 +528,7 @@ impl EnforcementState {
         current_commitment_info: CommitmentInfo2,
     ) -> Result<(), ValidationError> {
         if num == 0 {
-            return policy_err!("can't set next to 0");
+            return policy_err!(rule = "policy-commitment-sequence", "can't set next to 0");
         }
 
         // The initial commitment is special, it can advance even though next_revoke is 0.
@@ -363,6 +537,7 @@ impl EnforcementState {
         // Ensure that next_commit is ok relative to next_revoke
         if num < self.next_counterparty_revoke_num + delta {
             return policy_err!(
+                rule = "policy-commitment-sequence",
                 "{} too small relative to next_counterparty_revoke_num {}",
                 num,
                 self.next_counterparty_revoke_num
@@ -370,6 +545,7 @@ impl EnforcementState {
         }
         if num > self.next_counterparty_revoke_num + 2 {
             return policy_err!(
+                rule = "policy-commitment-sequence",
                 "{} too large relative to next_counterparty_revoke_num {}",
                 num,
                 self.next_counterparty_revoke_num
@@ -392,7 +568,11 @@ impl EnforcementState {
                     current_point,
                     self.current_counterparty_point.unwrap()
                 );
-                return policy_err!("retry {}: point different than prior", num);
+                return policy_err!(
+                    rule = "policy-v2-commitment-retry-same",
+                    "retry {}: point different than prior",
+                    num
+                );
             }
         } else if num == current + 1 {
             self.previous_counterparty_point = self.current_counterparty_point;
@@ -400,7 +580,12 @@ impl EnforcementState {
             self.current_counterparty_point = Some(current_point);
             self.current_counterparty_commit_info = Some(current_commitment_info);
         } else {
-            return policy_err!("invalid progression: {} to {}", current, num);
+            return policy_err!(
+                rule = "policy-commitment-sequence",
+                "invalid progression: {} to {}",
+                current,
+                num
+            );
         }
 
         self.next_counterparty_commit_num = num;
@@ -622,41 +807,30 @@ impl EnforcementState {
             new_holder_tx.is_none() || new_counterparty_tx.is_none(),
             "must have at most one new tx"
         );
+        let balance = |tx: &CommitmentInfo2, is_pre_splice: bool| {
+            self.commitment_claimable_balance(preimage_map, tx, is_pre_splice, channel_setup)
+        };
+        let holder_pre_splice = self.holder_commit_is_pre_splice();
+        let cp_pre_splice = self.counterparty_commit_is_pre_splice();
+
         // Our balance in the holder commitment tx
-        let cur_holder_bal = self.current_holder_commit_info.as_ref().map(|tx| {
-            tx.claimable_balance(
-                preimage_map,
-                channel_setup.is_outbound,
-                channel_setup.channel_value_sat,
-            )
-        });
+        let cur_holder_bal =
+            self.current_holder_commit_info.as_ref().map(|tx| balance(tx, holder_pre_splice));
         // Our balance in the counterparty commitment tx
-        let cur_cp_bal = self.current_counterparty_commit_info.as_ref().map(|tx| {
-            tx.claimable_balance(
-                preimage_map,
-                channel_setup.is_outbound,
-                channel_setup.channel_value_sat,
-            )
-        });
+        let cur_cp_bal =
+            self.current_counterparty_commit_info.as_ref().map(|tx| balance(tx, cp_pre_splice));
         // Our overall balance is the lower of the two
         let cur_bal_opt = min_opt(cur_holder_bal, cur_cp_bal);
 
         // Perform balance calculations given the new transaction
-        let new_holder_bal = new_holder_tx.or(self.current_holder_commit_info.as_ref()).map(|tx| {
-            tx.claimable_balance(
-                preimage_map,
-                channel_setup.is_outbound,
-                channel_setup.channel_value_sat,
-            )
-        });
-        let new_cp_bal =
-            new_counterparty_tx.or(self.current_counterparty_commit_info.as_ref()).map(|tx| {
-                tx.claimable_balance(
-                    preimage_map,
-                    channel_setup.is_outbound,
-                    channel_setup.channel_value_sat,
-                )
-            });
+        let new_holder_bal = match new_holder_tx {
+            Some(tx) => Some(balance(tx, false)),
+            None => cur_holder_bal,
+        };
+        let new_cp_bal = match new_counterparty_tx {
+            Some(tx) => Some(balance(tx, false)),
+            None => cur_cp_bal,
+        };
         let new_bal =
             min_opt(new_holder_bal, new_cp_bal).expect("already checked that we have a new tx");
 
@@ -676,6 +850,69 @@ impl EnforcementState {
 
         BalanceDelta(cur_bal, new_bal)
     }
+
+    /// Our claimable balances in the current commitment txs
+    pub fn current_claimable_balances<T: PreimageMap>(
+        &self,
+        preimage_map: &T,
+        channel_setup: &ChannelSetup,
+    ) -> ClaimableBalances {
+        let holder = self.current_holder_commit_info.as_ref().map(|tx| {
+            self.commitment_claimable_balance(
+                preimage_map,
+                tx,
+                self.holder_commit_is_pre_splice(),
+                channel_setup,
+            )
+        });
+        let counterparty = self.current_counterparty_commit_info.as_ref().map(|tx| {
+            self.commitment_claimable_balance(
+                preimage_map,
+                tx,
+                self.counterparty_commit_is_pre_splice(),
+                channel_setup,
+            )
+        });
+        let claimable = min_opt(holder, counterparty).unwrap_or(self.initial_holder_value);
+        ClaimableBalances { holder, counterparty, claimable }
+    }
+
+    // Our balance in a commitment tx.  Commitments from before the last
+    // splice are adjusted for our contribution to the splice.
+    fn commitment_claimable_balance<T: PreimageMap>(
+        &self,
+        preimage_map: &T,
+        tx: &CommitmentInfo2,
+        is_pre_splice: bool,
+        channel_setup: &ChannelSetup,
+    ) -> u64 {
+        match &self.splice {
+            Some(splice) if is_pre_splice => {
+                let bal = tx.claimable_balance(
+                    preimage_map,
+                    channel_setup.is_outbound,
+                    splice.prior_channel_value_sat,
+                );
+                max(bal as i64 + splice.holder_delta_sat, 0) as u64
+            }
+            _ => tx.claimable_balance(
+                preimage_map,
+                channel_setup.is_outbound,
+                channel_setup.channel_value_sat,
+            ),
+        }
+    }
+}
+
+/// Our claimable balances in the current commitment txs, in satoshi
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClaimableBalances {
+    /// In the current holder commitment tx, if any
+    pub holder: Option<u64>,
+    /// In the current counterparty commitment tx, if any
+    pub counterparty: Option<u64>,
+    /// The lower of the two, or our initial value before the first commitment
+    pub claimable: u64,
 }
 
 /// Claimable balance before and after a new commitment tx, in satoshi
@@ -704,11 +941,62 @@ fn min_opt(a_opt: Option<u64>, b_opt: Option<u64>) -> Option<u64> {
 mod tests {
     use test_log::test;
 
+    use crate::node::NodeState;
     use crate::util::key_utils::*;
     use crate::util::test_utils::*;
 
     use super::*;
 
+    #[test]
+    fn current_claimable_balances_test() {
+        let setup = make_test_channel_setup();
+        let preimages = NodeState::new();
+        let mut state = EnforcementState::new(1_000);
+        assert_eq!(
+            state.current_claimable_balances(&preimages, &setup),
+            ClaimableBalances { holder: None, counterparty: None, claimable: 1_000 }
+        );
+
+        // the outbound side also claims the fee
+        state.current_counterparty_commit_info = Some(CommitmentInfo2::new(
+            true,
+            make_test_pubkey(0x20),
+            1_990_000,
+            make_test_pubkey(0x21),
+            make_test_pubkey(0x22),
+            1_000_000,
+            6,
+            vec![],
+            vec![],
+            7500,
+        ));
+        assert_eq!(
+            state.current_claimable_balances(&preimages, &setup),
+            ClaimableBalances { holder: None, counterparty: Some(2_000_000), claimable: 2_000_000 }
+        );
+
+        state.current_holder_commit_info = Some(CommitmentInfo2::new(
+            false,
+            make_test_pubkey(0x20),
+            1_040_000,
+            make_test_pubkey(0x21),
+            make_test_pubkey(0x22),
+            1_950_000,
+            7,
+            vec![],
+            vec![],
+            7500,
+        ));
+        assert_eq!(
+            state.current_claimable_balances(&preimages, &setup),
+            ClaimableBalances {
+                holder: Some(1_960_000),
+                counterparty: Some(2_000_000),
+                claimable: 1_960_000
+            }
+        );
+    }
+
     #[test]
     fn enforcement_state_previous_counterparty_point_test() {
         let mut state = EnforcementState::new(0);
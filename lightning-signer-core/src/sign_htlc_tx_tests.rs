@@ -2,7 +2,7 @@
 mod tests {
     use bitcoin::hashes::hex::ToHex;
     use bitcoin::hashes::Hash;
-    use bitcoin::{self, Transaction};
+    use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut, Witness};
     use lightning::ln::chan_utils::{
         build_htlc_transaction, get_htlc_redeemscript, get_revokeable_redeemscript,
         ChannelTransactionParameters, HTLCOutputInCommitment, TxCreationKeys,
@@ -12,6 +12,7 @@ mod tests {
 
     use crate::channel::{ChannelBase, ChannelSetup, CommitmentType};
     use crate::policy::validator::ChainState;
+    use crate::util::crypto_utils::payload_for_p2wpkh;
     use crate::util::key_utils::*;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
@@ -31,14 +32,52 @@ mod tests {
         sign_local_htlc_tx_test(&setup);
     }
 
+    #[test]
+    fn sign_local_htlc_tx_zero_fee_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::AnchorsZeroFeeHtlc;
+        sign_local_htlc_tx_test(&setup);
+    }
+
+    #[test]
+    fn sign_local_htlc_tx_zero_fee_with_fee_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::AnchorsZeroFeeHtlc;
+        let res = sign_local_htlc_tx_with_feerate(&setup, 1000, false);
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_htlc_tx: \
+             feerate_per_kw of 1000 is not zero for a zero-fee HTLC tx"
+        );
+    }
+
+    #[test]
+    fn sign_local_htlc_tx_zero_fee_with_fee_input_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::AnchorsZeroFeeHtlc;
+        let res = sign_local_htlc_tx_with_feerate(&setup, 0, true);
+        assert_status_ok!(res);
+    }
+
     fn sign_local_htlc_tx_test(setup: &ChannelSetup) {
+        let res = sign_local_htlc_tx_with_feerate(setup, setup.htlc_feerate_per_kw(1000), false);
+        assert_status_ok!(res);
+    }
+
+    // If with_fee_input is set, attach a fee input and a change output to the
+    // HTLC tx, as the broadcaster of a zero-fee HTLC tx does.  The holder
+    // signature is always SIGHASH_ALL over the whole transaction.
+    fn sign_local_htlc_tx_with_feerate(
+        setup: &ChannelSetup,
+        feerate_per_kw: u32,
+        with_fee_input: bool,
+    ) -> Result<(), Status> {
         let (node, channel_id) =
             init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
 
         let htlc_amount_sat = 10 * 1000;
 
         let commitment_txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
-        let feerate_per_kw = 1000;
         let htlc = HTLCOutputInCommitment {
             offered: true,
             amount_msat: htlc_amount_sat * 1000,
@@ -61,7 +100,7 @@ mod tests {
             })
             .expect("point");
 
-        let htlc_tx = build_htlc_transaction(
+        let mut htlc_tx = build_htlc_transaction(
             &commitment_txid,
             feerate_per_kw,
             to_self_delay,
@@ -70,6 +109,21 @@ mod tests {
             &txkeys.broadcaster_delayed_payment_key,
             &txkeys.revocation_key,
         );
+        if with_fee_input {
+            htlc_tx.input.push(TxIn {
+                previous_output: OutPoint {
+                    txid: bitcoin::Txid::from_slice(&[3u8; 32]).unwrap(),
+                    vout: 1,
+                },
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: Witness::default(),
+            });
+            htlc_tx.output.push(TxOut {
+                value: 50_000,
+                script_pubkey: payload_for_p2wpkh(&make_test_pubkey(9)).script_pubkey(),
+            });
+        }
 
         let htlc_redeemscript =
             get_htlc_redeemscript(&htlc, setup.option_anchor_outputs(), &txkeys);
@@ -82,35 +136,32 @@ mod tests {
 
         let htlc_pubkey = get_channel_htlc_pubkey(&node, &channel_id, &per_commitment_point);
 
-        let sig = node
-            .with_ready_channel(&channel_id, |chan| {
-                chan.sign_holder_htlc_tx(
-                    &htlc_tx,
-                    n,
-                    None,
-                    &htlc_redeemscript,
-                    htlc_amount_sat,
-                    &output_witscript,
-                )
-            })
-            .unwrap();
+        let sig = node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_holder_htlc_tx(
+                &htlc_tx,
+                n,
+                None,
+                &htlc_redeemscript,
+                htlc_amount_sat,
+                &output_witscript,
+            )
+        })?;
 
         check_signature(&htlc_tx, 0, sig, &htlc_pubkey, htlc_amount_sat, &htlc_redeemscript);
 
-        let sig1 = node
-            .with_ready_channel(&channel_id, |chan| {
-                chan.sign_holder_htlc_tx(
-                    &htlc_tx,
-                    999,
-                    Some(per_commitment_point),
-                    &htlc_redeemscript,
-                    htlc_amount_sat,
-                    &output_witscript,
-                )
-            })
-            .unwrap();
+        let sig1 = node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_holder_htlc_tx(
+                &htlc_tx,
+                999,
+                Some(per_commitment_point),
+                &htlc_redeemscript,
+                htlc_amount_sat,
+                &output_witscript,
+            )
+        })?;
 
         check_signature(&htlc_tx, 0, sig1, &htlc_pubkey, htlc_amount_sat, &htlc_redeemscript);
+        Ok(())
     }
 
     #[allow(dead_code)]
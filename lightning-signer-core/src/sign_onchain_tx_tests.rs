@@ -4,16 +4,24 @@ mod tests {
     use bitcoin::blockdata::script::Builder;
     use bitcoin::hashes::hash160::Hash as Hash160;
     use bitcoin::hashes::Hash;
-    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use bitcoin::secp256k1::{schnorr, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+    use bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint};
     use bitcoin::util::psbt::serialize::Serialize;
-    use bitcoin::{self, Address, Network, OutPoint, Script, Transaction, TxIn, TxOut, Witness};
+    use bitcoin::util::psbt::PartiallySignedTransaction;
+    use bitcoin::util::sighash::{Prevouts, SchnorrSighashType, SighashCache};
+    use bitcoin::{
+        self, Address, EcdsaSighashType, Network, OutPoint, Script, Transaction, TxIn, TxOut,
+        Witness,
+    };
 
     use test_log::test;
 
     use crate::channel::CommitmentType;
-    use crate::node::SpendType;
+    use crate::node::{Node, SpendType};
+    use crate::util::key_utils::make_test_bitcoin_pubkey;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
+    use crate::wallet::Wallet;
 
     #[allow(unused_imports)]
     use log::debug;
@@ -46,7 +54,15 @@ mod tests {
         let uniclosekeys = vec![None, None];
 
         let witvec = node
-            .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![],
+            )
             .expect("good sigs");
         assert_eq!(witvec.len(), 2);
 
@@ -91,7 +107,15 @@ mod tests {
         let uniclosekeys = vec![None];
 
         let witvec = node
-            .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![],
+            )
             .expect("good sigs");
         assert_eq!(witvec.len(), 1);
 
@@ -143,6 +167,7 @@ mod tests {
                 &spendtypes,
                 uniclosekeys.clone(),
                 &vec![opath.clone()],
+                &vec![],
             ),
             "policy failure: validate_onchain_tx: \
              validate_beneficial_value: non-beneficial value above maximum: 281000 > 200000"
@@ -182,7 +207,15 @@ mod tests {
         let uniclosekeys = vec![Some((uniclosekey, vec![uniclosepubkey.serialize()]))];
 
         let witvec = node
-            .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![],
+            )
             .expect("good sigs");
         assert_eq!(witvec.len(), 1);
 
@@ -201,16 +234,32 @@ mod tests {
         Ok(())
     }
 
+    // A previous transaction paying to our p2pkh wallet address at index 0
+    fn make_test_p2pkh_prev_tx(node: &Node, value: u64) -> Transaction {
+        let secp_ctx = Secp256k1::signing_only();
+        let pubkey = node.get_wallet_pubkey(&secp_ctx, &vec![0]).unwrap();
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![TxOut {
+                value,
+                script_pubkey: Address::p2pkh(&pubkey, Network::Testnet).script_pubkey(),
+            }],
+        }
+    }
+
     #[test]
     fn sign_funding_tx_p2pkh_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
-        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let prev_tx = make_test_p2pkh_prev_tx(&node, 200);
         let ipaths = vec![vec![0u32]];
-        let values_sat = vec![200u64];
+        // the amount is taken from the previous tx
+        let values_sat = vec![0u64];
 
         let input1 = TxIn {
-            previous_output: OutPoint { txid, vout: 0 },
+            previous_output: OutPoint { txid: prev_tx.txid(), vout: 0 },
             script_sig: Script::new(),
             sequence: 0,
             witness: Witness::default(),
@@ -221,27 +270,99 @@ mod tests {
         let uniclosekeys = vec![None];
 
         let witvec = node
-            .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![Some(prev_tx.clone())],
+            )
             .expect("good sigs");
         assert_eq!(witvec.len(), 1);
 
-        let address = |n: u32| {
-            Address::p2pkh(&node.get_wallet_pubkey(&secp_ctx, &vec![n]).unwrap(), Network::Testnet)
-        };
-
         tx.input[0].script_sig = Builder::new()
             .push_slice(witvec[0][0].as_slice())
             .push_slice(witvec[0][1].as_slice())
             .into_script();
         println!("{:?}", tx.input[0].script_sig);
-        let outs = vec![TxOut { value: 100, script_pubkey: address(0).script_pubkey() }];
-        println!("{:?}", &outs[0].script_pubkey);
-        let verify_result = tx.verify(|p| Some(outs[p.vout as usize].clone()));
+        let verify_result = tx.verify(|p| Some(prev_tx.output[p.vout as usize].clone()));
         assert!(verify_result.is_ok());
 
         Ok(())
     }
 
+    #[test]
+    fn sign_funding_tx_p2pkh_without_prev_tx_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let prev_tx = make_test_p2pkh_prev_tx(&node, 200);
+        let input1 = TxIn {
+            previous_output: OutPoint { txid: prev_tx.txid(), vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input1], 100);
+
+        assert_failed_precondition_err!(
+            node.sign_onchain_tx(
+                &tx,
+                &vec![vec![0u32]],
+                &vec![200u64],
+                &vec![SpendType::P2pkh],
+                vec![None],
+                &vec![opath],
+                &vec![],
+            ),
+            "policy failure: validate_onchain_tx: \
+             non-segwit input[0] requires the previous transaction"
+        );
+    }
+
+    #[test]
+    fn sign_funding_tx_bad_prev_tx_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let prev_tx = make_test_p2pkh_prev_tx(&node, 200);
+        let input1 = TxIn {
+            previous_output: OutPoint { txid: prev_tx.txid(), vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input1], 100);
+        let sign = |values_sat: Vec<u64>, prev_tx: Transaction| {
+            node.sign_onchain_tx(
+                &tx,
+                &vec![vec![0u32]],
+                &values_sat,
+                &vec![SpendType::P2pkh],
+                vec![None],
+                &vec![opath.clone()],
+                &vec![Some(prev_tx)],
+            )
+        };
+
+        // the caller supplied amount disagrees with the previous tx
+        assert_invalid_argument_err!(
+            sign(vec![300], prev_tx.clone()),
+            "input 0: value 300 does not match previous tx value 200"
+        );
+
+        // the previous tx is not the one being spent
+        let other_tx = make_test_p2pkh_prev_tx(&node, 300);
+        assert_invalid_argument_err!(
+            sign(vec![0], other_tx.clone()),
+            format!(
+                "input 0: previous tx {} does not match outpoint {}:0",
+                other_tx.txid(),
+                prev_tx.txid()
+            )
+        );
+    }
+
     #[test]
     fn sign_funding_tx_p2sh_p2wpkh_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
@@ -265,7 +386,15 @@ mod tests {
         let uniclosekeys = vec![None];
 
         let witvec = node
-            .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![],
+            )
             .expect("good sigs");
         assert_eq!(witvec.len(), 1);
 
@@ -303,6 +432,108 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_funding_tx_p2tr_test() -> Result<(), ()> {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let ipaths = vec![vec![0u32], vec![1u32]];
+        let ival0 = 100u64;
+        let ival1 = 300u64;
+        let chanamt = 300u64;
+        let values_sat = vec![ival0, ival1];
+
+        let input1 = TxIn {
+            previous_output: OutPoint { txid, vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+
+        let input2 = TxIn {
+            previous_output: OutPoint { txid, vout: 1 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input1, input2], chanamt);
+        let spendtypes = vec![SpendType::P2wpkh, SpendType::P2tr];
+        let uniclosekeys = vec![None, None];
+
+        let witvec = node
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![],
+            )
+            .expect("good sigs");
+        assert_eq!(witvec.len(), 2);
+        // key path spends have just the signature in the witness
+        assert_eq!(witvec[1].len(), 1);
+        assert_eq!(witvec[1][0].len(), 64);
+
+        let taproot_address = node.get_taproot_address(&ipaths[1]).unwrap();
+        assert!(node.can_spend(&ipaths[1], &taproot_address.script_pubkey()).unwrap());
+
+        let outs = vec![
+            TxOut {
+                value: ival0,
+                script_pubkey: node.get_native_address(&ipaths[0]).unwrap().script_pubkey(),
+            },
+            TxOut { value: ival1, script_pubkey: taproot_address.script_pubkey() },
+        ];
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(1, &Prevouts::All(&outs), SchnorrSighashType::Default)
+            .unwrap();
+        let message = Message::from_slice(&sighash).unwrap();
+        let sig = schnorr::Signature::from_slice(&witvec[1][0]).unwrap();
+        let output_key =
+            XOnlyPublicKey::from_slice(&taproot_address.script_pubkey().as_bytes()[2..]).unwrap();
+        let verify_ctx = Secp256k1::verification_only();
+        assert!(verify_ctx.verify_schnorr(&sig, &message, &output_key).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_funding_tx_p2tr_unknown_input_test() -> Result<(), ()> {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let ipaths = vec![vec![0u32], vec![1u32]];
+        let values_sat = vec![100u64, 300u64];
+
+        let inputs = (0..2)
+            .map(|vout| TxIn {
+                previous_output: OutPoint { txid, vout },
+                script_sig: Script::new(),
+                sequence: 0,
+                witness: Witness::default(),
+            })
+            .collect();
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, inputs, 300);
+        // the first input is signed by someone else, so its spent output is unknown
+        let spendtypes = vec![SpendType::Invalid, SpendType::P2tr];
+        let uniclosekeys = vec![None, None];
+
+        let res = node.sign_onchain_tx(
+            &tx,
+            &ipaths,
+            &values_sat,
+            &spendtypes,
+            uniclosekeys,
+            &vec![opath],
+            &vec![],
+        );
+        assert_invalid_argument_err!(res, "p2tr signing needs the spent output for input 0");
+
+        Ok(())
+    }
+
     #[test]
     fn sign_funding_tx_psbt_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
@@ -341,7 +572,15 @@ mod tests {
         let uniclosekeys = vec![None, None, None];
 
         let witvec = node
-            .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys,
+                &vec![opath],
+                &vec![],
+            )
             .expect("good sigs");
         // Should have three witness stack items.
         assert_eq!(witvec.len(), 3);
@@ -359,6 +598,164 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_psbt_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let inputs = (0..3)
+            .map(|vout| TxIn {
+                previous_output: OutPoint { txid, vout },
+                script_sig: Script::new(),
+                sequence: 0,
+                witness: Witness::default(),
+            })
+            .collect();
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, inputs, 300);
+
+        let key_source =
+            |n: u32| (Fingerprint::default(), DerivationPath::from(vec![ChildNumber::from(n)]));
+        let pubkey = |n: u32| node.get_wallet_pubkey(&secp_ctx, &vec![n]).unwrap();
+        let internal_key = XOnlyPublicKey::from_keypair(
+            &node.get_taproot_wallet_keypair(&Secp256k1::new(), &vec![2]).unwrap(),
+        );
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone()).unwrap();
+        // someone else's input
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 500,
+            script_pubkey: Address::p2wpkh(&make_test_bitcoin_pubkey(1), Network::Testnet)
+                .unwrap()
+                .script_pubkey(),
+        });
+        psbt.inputs[1].witness_utxo = Some(TxOut {
+            value: 100,
+            script_pubkey: node.get_native_address(&vec![1]).unwrap().script_pubkey(),
+        });
+        psbt.inputs[1].bip32_derivation.insert(pubkey(1).inner, key_source(1));
+        psbt.inputs[2].witness_utxo = Some(TxOut {
+            value: 300,
+            script_pubkey: node.get_taproot_address(&vec![2]).unwrap().script_pubkey(),
+        });
+        psbt.inputs[2].tap_key_origins.insert(internal_key, (vec![], key_source(2)));
+        psbt.outputs[0].bip32_derivation.insert(pubkey(opath[0]).inner, key_source(opath[0]));
+
+        let signed = node.sign_psbt(&psbt).expect("signed psbt");
+
+        assert!(signed.inputs[0].partial_sigs.is_empty());
+        assert!(signed.inputs[0].tap_key_sig.is_none());
+
+        let sig = signed.inputs[1].partial_sigs.get(&pubkey(1)).expect("partial sig");
+        let script_code = Address::p2pkh(&pubkey(1), Network::Testnet).script_pubkey();
+        let sighash = SighashCache::new(&tx)
+            .segwit_signature_hash(1, &script_code, 100, EcdsaSighashType::All)
+            .unwrap();
+        let message = Message::from_slice(&sighash).unwrap();
+        let verify_ctx = Secp256k1::verification_only();
+        assert!(verify_ctx.verify_ecdsa(&message, &sig.sig, &pubkey(1).inner).is_ok());
+
+        assert!(signed.inputs[2].partial_sigs.is_empty());
+        assert!(signed.inputs[2].tap_key_sig.is_some());
+    }
+
+    #[test]
+    fn sign_psbt_wrong_input_path_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let input = TxIn {
+            previous_output: OutPoint { txid, vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input], 100);
+
+        let key_source = DerivationPath::from(vec![ChildNumber::from(opath[0])]);
+        let pubkey = node.get_wallet_pubkey(&secp_ctx, &opath).unwrap();
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 200,
+            script_pubkey: node.get_taproot_address(&vec![1]).unwrap().script_pubkey(),
+        });
+        psbt.outputs[0].bip32_derivation.insert(pubkey.inner, (Fingerprint::default(), key_source));
+
+        assert_invalid_argument_err!(
+            node.sign_psbt_with_keys(&psbt, &vec![vec![2]], vec![]),
+            "input 0: path [2] does not match the spent output"
+        );
+        assert!(node.sign_psbt_with_keys(&psbt, &vec![vec![1]], vec![]).is_ok());
+    }
+
+    #[test]
+    fn sign_psbt_unknown_change_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let input = TxIn {
+            previous_output: OutPoint { txid, vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+        let (_opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input], 100);
+
+        let key_source =
+            |n: u32| (Fingerprint::default(), DerivationPath::from(vec![ChildNumber::from(n)]));
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 200,
+            script_pubkey: node.get_native_address(&vec![1]).unwrap().script_pubkey(),
+        });
+        let pubkey = node.get_wallet_pubkey(&secp_ctx, &vec![1]).unwrap();
+        psbt.inputs[0].bip32_derivation.insert(pubkey.inner, key_source(1));
+        // the change output claims a path that doesn't match its address
+        psbt.outputs[0].bip32_derivation.insert(pubkey.inner, key_source(7));
+
+        assert_failed_precondition_err!(
+            node.sign_psbt(&psbt),
+            "policy failure: validate_onchain_tx: wallet cannot spend output[0]"
+        );
+    }
+
+    #[test]
+    fn sign_psbt_witness_script_mismatch_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let input = TxIn {
+            previous_output: OutPoint { txid, vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Witness::default(),
+        };
+        let (_opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input], 100);
+
+        let redeemscript = Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_1).into_script();
+        let other_script = Builder::new().push_opcode(opcodes::all::OP_PUSHBYTES_0).into_script();
+        let key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        let uniclosekeys = vec![Some((key, vec![vec![], redeemscript.to_bytes()]))];
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: 200, script_pubkey: redeemscript.to_v0_p2wsh() });
+        psbt.inputs[0].witness_script = Some(other_script.clone());
+
+        let res = node.sign_psbt_with_keys(&psbt, &vec![], uniclosekeys.clone());
+        assert_invalid_argument_err!(
+            res,
+            "input 0: witness_script does not match the unilateral close script"
+        );
+
+        psbt.inputs[0].witness_script = None;
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: 200, script_pubkey: other_script.to_v0_p2wsh() });
+        let res = node.sign_psbt_with_keys(&psbt, &vec![], uniclosekeys);
+        assert_invalid_argument_err!(
+            res,
+            "input 0: spent output does not match the unilateral close script"
+        );
+    }
+
     #[allow(dead_code)]
     struct FundingTxMutationState<'a> {
         chan_ctx: &'a mut TestChannelContext,
@@ -536,13 +933,84 @@ mod tests {
     }
 
     #[test]
-    fn dual_funding_not_supported() {
+    fn inbound_single_funded() {
         assert_failed_precondition_err!(
             sign_funding_tx_with_mutator(|fms| {
                 fms.chan_ctx.setup.is_outbound = false;
             }),
             "policy failure: validate_onchain_tx: \
-             can't sign for inbound channel: dual-funding not supported yet"
+             can't sign for inbound channel: no holder contribution"
+        );
+    }
+
+    // policy-onchain-dual-funding-contribution
+    #[test]
+    fn success_dual_funded_outbound() {
+        assert_status_ok!(sign_funding_tx_with_mutator(|fms| {
+            // the counterparty contributes 1_000_000, which comes back to us as change
+            fms.chan_ctx.setup.holder_funding_sat = Some(2_000_000);
+            fms.tx.output[1].value += 1_000_000;
+        }));
+    }
+
+    // policy-onchain-dual-funding-contribution
+    #[test]
+    fn success_dual_funded_inbound_with_push() {
+        assert_status_ok!(sign_funding_tx_with_mutator(|fms| {
+            // the counterparty opens with 2_000_000 and pushes 100_000 to us
+            fms.chan_ctx.setup.is_outbound = false;
+            fms.chan_ctx.setup.holder_funding_sat = Some(1_000_000);
+            fms.chan_ctx.setup.push_value_msat = 100_000_000;
+            fms.tx.output[1].value += 1_900_000;
+        }));
+    }
+
+    // policy-onchain-dual-funding-contribution
+    #[test]
+    fn dual_funded_counterparty_contribution_taken() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                // our change does not account for our reduced share
+                fms.chan_ctx.setup.holder_funding_sat = Some(2_000_000);
+            }),
+            "policy failure: validate_onchain_tx: \
+             validate_beneficial_value: non-beneficial value above maximum: 1001000 > 200000"
+        );
+    }
+
+    // policy-onchain-dual-funding-contribution
+    #[test]
+    fn dual_funded_contribution_short() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                // the counterparty's contribution pays for part of ours
+                fms.chan_ctx.setup.holder_funding_sat = Some(2_000_000);
+                fms.tx.output[1].value += 1_500_000;
+            }),
+            "policy failure: validate_onchain_tx: \
+             our inputs less change 1501000 do not cover our funding contribution 2000000"
+        );
+    }
+
+    #[test]
+    fn dual_funded_push_exceeds_counterparty_contribution() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                fms.chan_ctx.setup.is_outbound = false;
+                fms.chan_ctx.setup.holder_funding_sat = Some(2_000_000);
+                fms.chan_ctx.setup.push_value_msat = 1_500_000_000;
+            }),
+            "policy failure: push_value_msat 1500000000 exceeds counterparty funding 1000000000"
+        );
+    }
+
+    #[test]
+    fn dual_funded_holder_funding_exceeds_channel_value() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                fms.chan_ctx.setup.holder_funding_sat = Some(3_000_001);
+            }),
+            "policy failure: holder funding 3000001 exceeds channel value 3000000"
         );
     }
 
@@ -332,6 +332,22 @@ impl KeyDerivationStyle {
             KeyDerivationStyle::Lnd => get_account_extended_key_lnd(secp_ctx, network, seed),
         }
     }
+
+    /// The account key for taproot wallet outputs
+    pub(crate) fn get_taproot_account_extended_key(
+        &self,
+        secp_ctx: &Secp256k1<secp256k1::All>,
+        network: Network,
+        seed: &[u8],
+    ) -> ExtendedPrivKey {
+        match self {
+            // CLN derives its P2TR outputs from the same BIP32 chain as its segwit outputs
+            KeyDerivationStyle::Native => get_account_extended_key_native(secp_ctx, network, seed),
+            KeyDerivationStyle::Ldk => get_account_extended_key_native(secp_ctx, network, seed),
+            KeyDerivationStyle::Lnd =>
+                get_account_extended_key_lnd_purpose(secp_ctx, network, seed, 86),
+        }
+    }
 }
 
 // This function will panic if the ExtendedPrivKey::new_master fails.
@@ -356,10 +372,20 @@ pub(crate) fn get_account_extended_key_lnd(
     secp_ctx: &Secp256k1<secp256k1::All>,
     network: Network,
     node_seed: &[u8],
+) -> ExtendedPrivKey {
+    get_account_extended_key_lnd_purpose(secp_ctx, network, node_seed, 84)
+}
+
+// The BIP44 style account key, for BIP84 (native segwit) or BIP86 (taproot) purposes.
+// This function will panic if the ExtendedPrivKey::new_master fails.
+fn get_account_extended_key_lnd_purpose(
+    secp_ctx: &Secp256k1<secp256k1::All>,
+    network: Network,
+    node_seed: &[u8],
+    purpose: u32,
 ) -> ExtendedPrivKey {
     // Must match btcsuite/btcwallet/waddrmgr/scoped_manager.go
     let master = ExtendedPrivKey::new_master(network, node_seed).unwrap();
-    let purpose = 84;
     let cointype = 0;
     let account = 0;
     master
@@ -404,7 +430,10 @@ pub(crate) fn derive_key_lnd(
 
 #[cfg(test)]
 mod tests {
+    use core::str::FromStr;
+
     use bitcoin::hashes::hex::ToHex;
+    use bitcoin::util::bip32::DerivationPath;
     use bitcoin::Network::Testnet;
 
     use super::*;
@@ -422,6 +451,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn taproot_account_key_test() {
+        let secp_ctx = Secp256k1::new();
+        let seed = [0u8; 32];
+        let bip32_seed = hkdf_sha256(&seed, "bip32 seed".as_bytes(), &[]);
+        let master = ExtendedPrivKey::new_master(Testnet, &bip32_seed).unwrap();
+        // the same m/0/0 chain as the segwit wallet outputs, as CLN does
+        let shared_path = DerivationPath::from_str("m/0/0").unwrap();
+        let expected = master.derive_priv(&secp_ctx, &shared_path).unwrap();
+        for style in &[KeyDerivationStyle::Native, KeyDerivationStyle::Ldk] {
+            assert_eq!(style.get_taproot_account_extended_key(&secp_ctx, Testnet, &seed), expected);
+        }
+
+        // lnd always uses coin type 0
+        let master = ExtendedPrivKey::new_master(Testnet, &seed).unwrap();
+        let lnd_path = DerivationPath::from_str("m/86'/0'/0'").unwrap();
+        assert_eq!(
+            KeyDerivationStyle::Lnd.get_taproot_account_extended_key(&secp_ctx, Testnet, &seed),
+            master.derive_priv(&secp_ctx, &lnd_path).unwrap()
+        );
+    }
+
     #[test]
     fn node_keys_lnd_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::new();
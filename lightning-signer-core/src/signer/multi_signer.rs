@@ -14,17 +14,37 @@ use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::ValidatorFactory;
 use crate::prelude::*;
 use crate::sync::Arc;
-use crate::util::status::{invalid_argument, Status};
+use crate::util::status::{invalid_argument, permission_denied, resource_exhausted, Status};
+
+/// Resource limits of a tenant
+#[derive(Clone, Debug, PartialEq)]
+pub struct TenantQuota {
+    /// Maximum number of nodes
+    pub max_nodes: usize,
+    /// Maximum number of channels, across all of the tenant's nodes
+    pub max_channels: usize,
+}
+
+// A tenant owns its nodes, which are persisted in the tenant's own namespace
+struct Tenant {
+    persister: Arc<dyn Persist>,
+    quota: TenantQuota,
+    node_ids: OrderedSet<PublicKey>,
+}
 
 /// A signer for multiple nodes.
 ///
 /// If you need just one node, use [Node] directly.
+///
+/// Nodes can optionally belong to tenants, see [MultiSigner::add_tenant].
 pub struct MultiSigner {
     pub(crate) nodes: Mutex<Map<PublicKey, Arc<Node>>>,
     pub(crate) persister: Arc<dyn Persist>,
     pub(crate) test_mode: bool,
     pub(crate) initial_allowlist: Vec<String>,
     validator_factory: Arc<dyn ValidatorFactory>,
+    // Lock order: tenants before nodes
+    tenants: Mutex<Map<String, Tenant>>,
 }
 
 impl MultiSigner {
@@ -67,6 +87,7 @@ impl MultiSigner {
             test_mode,
             initial_allowlist,
             validator_factory,
+            tenants: Mutex::new(Map::new()),
         }
     }
 
@@ -136,8 +157,23 @@ impl MultiSigner {
         node_config: NodeConfig,
         seed: &[u8],
     ) -> Result<PublicKey, Status> {
+        let tenants = self.tenants.lock().unwrap();
         let node =
             Node::new(node_config, &seed, &self.persister, vec![], self.validator_factory.clone());
+        if let Some(tenant_id) = Self::owner(&tenants, &node.get_id()) {
+            return Err(permission_denied(format!("node belongs to tenant {}", tenant_id)));
+        }
+        self.insert_node(node, &self.persister, node_config, seed)
+    }
+
+    // Persist a new node and add it, replacing an existing node in test mode
+    fn insert_node(
+        &self,
+        node: Node,
+        persister: &Arc<dyn Persist>,
+        node_config: NodeConfig,
+        seed: &[u8],
+    ) -> Result<PublicKey, Status> {
         let node_id = node.get_id();
         let mut nodes = self.nodes.lock().unwrap();
         if self.test_mode {
@@ -151,12 +187,130 @@ impl MultiSigner {
             }
         }
         node.add_allowlist(&self.initial_allowlist).expect("valid initialallowlist");
-        self.persister.new_node(&node_id, &node_config, seed);
-        self.persister.new_chain_tracker(&node_id, &node.get_tracker());
+        persister.new_node(&node_id, &node_config, seed);
+        persister.new_chain_tracker(&node_id, &node.get_tracker());
         nodes.insert(node_id, Arc::new(node));
         Ok(node_id)
     }
 
+    /// Add a tenant, whose nodes are persisted by `persister`, separately from
+    /// other tenants.  The tenant's existing nodes are restored.
+    pub fn add_tenant(
+        &self,
+        tenant_id: &str,
+        persister: Arc<dyn Persist>,
+        quota: TenantQuota,
+    ) -> Result<(), Status> {
+        let mut tenants = self.tenants.lock().unwrap();
+        if tenants.contains_key(tenant_id) {
+            return Err(invalid_argument(format!("tenant exists: {}", tenant_id)));
+        }
+        let restored = Node::restore_nodes(Arc::clone(&persister), self.validator_factory.clone());
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(node_id) = restored.keys().find(|id| nodes.contains_key(id)) {
+            return Err(invalid_argument(format!(
+                "tenant {} node {} is already hosted",
+                tenant_id, node_id
+            )));
+        }
+        let node_ids = restored.keys().cloned().collect();
+        nodes.extend(restored);
+        info!("added tenant {}", tenant_id);
+        tenants.insert(tenant_id.to_string(), Tenant { persister, quota, node_ids });
+        Ok(())
+    }
+
+    /// Get all tenant IDs
+    pub fn get_tenant_ids(&self) -> Vec<String> {
+        let tenants = self.tenants.lock().unwrap();
+        tenants.keys().cloned().collect()
+    }
+
+    /// Create a node with a specific seed for a tenant, within the tenant's quota
+    pub fn new_tenant_node_from_seed(
+        &self,
+        tenant_id: &str,
+        node_config: NodeConfig,
+        seed: &[u8],
+    ) -> Result<PublicKey, Status> {
+        let mut tenants = self.tenants.lock().unwrap();
+        let tenant = Self::tenant(&tenants, tenant_id)?;
+        let persister = Arc::clone(&tenant.persister);
+        let node =
+            Node::new(node_config, &seed, &persister, vec![], self.validator_factory.clone());
+        let node_id = node.get_id();
+        match Self::owner(&tenants, &node_id) {
+            Some(owner) if owner == tenant_id => {}
+            Some(_) => return Err(permission_denied("node belongs to another tenant")),
+            None => {
+                if self.nodes.lock().unwrap().contains_key(&node_id) {
+                    return Err(permission_denied("node does not belong to the tenant"));
+                }
+                if tenant.node_ids.len() >= tenant.quota.max_nodes {
+                    return Err(resource_exhausted(format!(
+                        "tenant {} is at its quota of {} nodes",
+                        tenant_id, tenant.quota.max_nodes
+                    )));
+                }
+            }
+        }
+        self.insert_node(node, &persister, node_config, seed)?;
+        tenants.get_mut(tenant_id).expect("tenant").node_ids.insert(node_id);
+        Ok(node_id)
+    }
+
+    /// Get the IDs of a tenant's nodes
+    pub fn get_tenant_node_ids(&self, tenant_id: &str) -> Result<Vec<PublicKey>, Status> {
+        let tenants = self.tenants.lock().unwrap();
+        Ok(Self::tenant(&tenants, tenant_id)?.node_ids.iter().cloned().collect())
+    }
+
+    /// Get a node, if it belongs to the tenant
+    pub fn get_tenant_node(
+        &self,
+        tenant_id: &str,
+        node_id: &PublicKey,
+    ) -> Result<Arc<Node>, Status> {
+        let tenants = self.tenants.lock().unwrap();
+        if !Self::tenant(&tenants, tenant_id)?.node_ids.contains(node_id) {
+            return Err(permission_denied(format!(
+                "node {} does not belong to tenant {}",
+                node_id, tenant_id
+            )));
+        }
+        self.get_node(node_id)
+    }
+
+    /// Check that the tenant can create another channel
+    pub fn check_tenant_channel_quota(&self, tenant_id: &str) -> Result<(), Status> {
+        let tenants = self.tenants.lock().unwrap();
+        let tenant = Self::tenant(&tenants, tenant_id)?;
+        let nodes = self.nodes.lock().unwrap();
+        let count: usize = tenant
+            .node_ids
+            .iter()
+            .filter_map(|id| nodes.get(id))
+            .map(|node| node.channel_count())
+            .sum();
+        if count >= tenant.quota.max_channels {
+            return Err(resource_exhausted(format!(
+                "tenant {} is at its quota of {} channels",
+                tenant_id, tenant.quota.max_channels
+            )));
+        }
+        Ok(())
+    }
+
+    fn tenant<'a>(tenants: &'a Map<String, Tenant>, tenant_id: &str) -> Result<&'a Tenant, Status> {
+        tenants
+            .get(tenant_id)
+            .ok_or_else(|| permission_denied(format!("no such tenant: {}", tenant_id)))
+    }
+
+    fn owner<'a>(tenants: &'a Map<String, Tenant>, node_id: &PublicKey) -> Option<&'a str> {
+        tenants.iter().find(|(_, t)| t.node_ids.contains(node_id)).map(|(id, _)| id.as_str())
+    }
+
     /// Get all node IDs
     pub fn get_node_ids(&self) -> Vec<PublicKey> {
         let nodes = self.nodes.lock().unwrap();
@@ -294,6 +448,46 @@ mod tests {
         assert_eq!(result.unwrap(), node_id);
     }
 
+    #[test]
+    fn tenant_test() {
+        let signer = MultiSigner::new();
+        let quota = TenantQuota { max_nodes: 1, max_channels: 1 };
+        signer.add_tenant("alice", Arc::new(DummyPersister), quota.clone()).unwrap();
+        signer.add_tenant("bob", Arc::new(DummyPersister), quota.clone()).unwrap();
+        assert!(signer.add_tenant("bob", Arc::new(DummyPersister), quota).is_err());
+
+        let alice_seed = hex_decode(TEST_SEED[0]).unwrap();
+        let bob_seed = hex_decode(TEST_SEED[1]).unwrap();
+        let alice_node_id =
+            signer.new_tenant_node_from_seed("alice", TEST_NODE_CONFIG, &alice_seed).unwrap();
+        let bob_node_id =
+            signer.new_tenant_node_from_seed("bob", TEST_NODE_CONFIG, &bob_seed).unwrap();
+        assert_eq!(signer.get_tenant_node_ids("alice").unwrap(), vec![alice_node_id]);
+
+        // Tenants can only reach their own nodes
+        assert!(signer.get_tenant_node("alice", &alice_node_id).is_ok());
+        let err = signer.get_tenant_node("alice", &bob_node_id).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        let err = signer.get_tenant_node("carol", &alice_node_id).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        let err =
+            signer.new_tenant_node_from_seed("alice", TEST_NODE_CONFIG, &bob_seed).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        let err = signer.new_node_from_seed(TEST_NODE_CONFIG, &bob_seed).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        // Quotas
+        let err =
+            signer.new_tenant_node_from_seed("alice", TEST_NODE_CONFIG, &[3; 32]).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        signer.check_tenant_channel_quota("alice").unwrap();
+        let node = signer.get_tenant_node("alice", &alice_node_id).unwrap();
+        node.new_channel(None, &node).unwrap();
+        let err = signer.check_tenant_channel_quota("alice").unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        signer.check_tenant_channel_quota("bob").unwrap();
+    }
+
     #[test]
     fn bad_node_lookup_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
@@ -25,7 +25,7 @@ use lightning::ln::script::ShutdownScript;
 use super::derive::{self, KeyDerivationStyle};
 use crate::channel::ChannelId;
 use crate::util::transaction_utils::MAX_VALUE_MSAT;
-use crate::util::{byte_utils, transaction_utils};
+use crate::util::{bolt12, byte_utils, transaction_utils};
 use bitcoin::secp256k1::ecdsa::RecoverableSignature;
 use bitcoin::secp256k1::schnorr;
 use bitcoin::secp256k1::XOnlyPublicKey;
@@ -45,6 +45,7 @@ pub struct MyKeysManager {
     inbound_payment_key: KeyMaterial,
     channel_seed_base: [u8; 32],
     account_extended_key: ExtendedPrivKey,
+    taproot_account_extended_key: ExtendedPrivKey,
     destination_script: Script,
     ldk_shutdown_pubkey: PublicKey,
     #[allow(dead_code)]
@@ -106,6 +107,8 @@ impl MyKeysManager {
         let channel_seed_base = key_derive.channels_seed(seed);
         let account_extended_key =
             key_derivation_style.get_account_extended_key(&secp_ctx, network, seed);
+        let taproot_account_extended_key =
+            key_derivation_style.get_taproot_account_extended_key(&secp_ctx, network, seed);
 
         let rand_bytes_master_key = master_key
             .ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(4).unwrap())
@@ -138,6 +141,7 @@ impl MyKeysManager {
             inbound_payment_key: KeyMaterial(inbound_pmt_key_bytes),
             channel_seed_base,
             account_extended_key,
+            taproot_account_extended_key,
             destination_script,
             ldk_shutdown_pubkey,
             channel_master_key,
@@ -173,18 +177,7 @@ impl MyKeysManager {
         merkleroot: &[u8; 32],
         publictweak_opt: Option<&[u8]>,
     ) -> Result<schnorr::Signature, ()> {
-        // BIP340 init
-        let mut sha = Sha256::engine();
-        sha.input("lightning".as_bytes());
-        sha.input(messagename);
-        sha.input(fieldname);
-        let tag_hash = Sha256::from_engine(sha).into_inner();
-        let mut sha = Sha256::engine();
-        sha.input(&tag_hash);
-        sha.input(&tag_hash);
-        // BIP340 done, compute hash of message
-        sha.input(merkleroot);
-        let sig_hash = Sha256::from_engine(sha).into_inner();
+        let msg = bolt12::signature_message(messagename, fieldname, merkleroot);
 
         let kp = if let Some(publictweak) = publictweak_opt {
             // Compute the tweaked key
@@ -199,7 +192,6 @@ impl MyKeysManager {
         } else {
             KeyPair::from_secret_key(&self.secp_ctx, self.node_secret)
         };
-        let msg = Message::from_slice(&sig_hash).unwrap();
         Ok(self.secp_ctx.sign_schnorr_no_aux_rand(&msg, &kp))
     }
 
@@ -208,6 +200,11 @@ impl MyKeysManager {
         &self.account_extended_key
     }
 
+    /// Get the layer-1 xprv for taproot (BIP86) outputs
+    pub fn get_taproot_account_extended_key(&self) -> &ExtendedPrivKey {
+        &self.taproot_account_extended_key
+    }
+
     /// Convert a commitment secret to a commitment point
     pub fn per_commitment_point<X: Signing>(
         secp_ctx: &Secp256k1<X>,
@@ -0,0 +1,420 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut, Txid, Witness};
+    use lightning::ln::chan_utils::make_funding_redeemscript;
+    use lightning::ln::PaymentHash;
+    use test_log::test;
+
+    use crate::channel::{ChannelId, ChannelSetup, TypedSignature};
+    use crate::node::{Node, SpendType, SPLICE_BURIED_DEPTH};
+    use crate::sync::Arc;
+    use crate::tx::tx::{CommitmentInfo2, HTLCInfo2};
+    use crate::util::crypto_utils::payload_for_p2wsh;
+    use crate::util::key_utils::*;
+    use crate::util::status::{Code, Status};
+    use crate::util::test_utils::*;
+    use crate::wallet::Wallet;
+
+    const TO_HOLDER_VALUE_SAT: u64 = 1_998_000;
+    const WALLET_INPUT_SAT: u64 = 500_000;
+    const FEE_SAT: u64 = 1_000;
+
+    // A channel with a holder and counterparty commitment and no HTLCs
+    fn setup_channel(offered_htlcs: Vec<HTLCInfo2>) -> (ChannelSetup, Arc<Node>, ChannelId) {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let to_counterparty_value_sat = 1_000_000;
+        node.with_ready_channel(&channel_id, |chan| {
+            let estate = &mut chan.enforcement_state;
+            estate.next_holder_commit_num = 5;
+            estate.next_counterparty_commit_num = 5;
+            estate.next_counterparty_revoke_num = 4;
+            estate.current_counterparty_point = Some(make_test_pubkey(4));
+            estate.current_holder_commit_info = Some(CommitmentInfo2::new(
+                false,
+                make_test_pubkey(100),
+                to_counterparty_value_sat,
+                make_test_pubkey(101),
+                make_test_pubkey(102),
+                TO_HOLDER_VALUE_SAT,
+                setup.counterparty_selected_contest_delay,
+                offered_htlcs.clone(),
+                vec![],
+                7500,
+            ));
+            estate.current_counterparty_commit_info = Some(CommitmentInfo2::new(
+                true,
+                make_test_pubkey(103),
+                TO_HOLDER_VALUE_SAT,
+                make_test_pubkey(104),
+                make_test_pubkey(105),
+                to_counterparty_value_sat,
+                setup.holder_selected_contest_delay,
+                vec![],
+                offered_htlcs.clone(),
+                7500,
+            ));
+            Ok(())
+        })
+        .expect("state setup");
+        (setup, node, channel_id)
+    }
+
+    fn funding_script_pubkey(node: &Node, channel_id: &ChannelId) -> Script {
+        node.with_ready_channel(channel_id, |chan| {
+            let redeemscript = make_funding_redeemscript(
+                &chan.keys.pubkeys().funding_pubkey,
+                &chan.keys.counterparty_pubkeys().funding_pubkey,
+            );
+            Ok(payload_for_p2wsh(&redeemscript).script_pubkey())
+        })
+        .unwrap()
+    }
+
+    // Splice in a wallet input
+    fn make_splice_in_tx(setup: &ChannelSetup, funding_script_pubkey: Script) -> Transaction {
+        let wallet_input = TxIn {
+            previous_output: OutPoint { txid: Txid::from_slice(&[3u8; 32]).unwrap(), vout: 1 },
+            script_sig: Script::new(),
+            sequence: 0xffffffff,
+            witness: Witness::default(),
+        };
+        let funding_input =
+            TxIn { previous_output: setup.funding_outpoint, ..wallet_input.clone() };
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![funding_input, wallet_input],
+            output: vec![TxOut {
+                value: setup.channel_value_sat + WALLET_INPUT_SAT - FEE_SAT,
+                script_pubkey: funding_script_pubkey,
+            }],
+        }
+    }
+
+    // Splice in the wallet input.  The whole change in the channel value is
+    // ours, so we pay the fee.
+    fn splice(node: &Node, channel_id: &ChannelId, tx: &Transaction) -> Result<(), Status> {
+        let values_sat = vec![0, WALLET_INPUT_SAT];
+        let spendtypes = vec![SpendType::Invalid, SpendType::P2wpkh];
+        let opaths = vec![vec![]; tx.output.len()];
+        let channel_value_sat =
+            node.with_ready_channel(channel_id, |chan| Ok(chan.setup.channel_value_sat))?;
+        let holder_delta_sat = tx.output[0].value as i64 - channel_value_sat as i64;
+        node.splice_channel(channel_id, tx, 0, holder_delta_sat, &values_sat, &spendtypes, &opaths)
+    }
+
+    fn sign_splice(
+        node: &Node,
+        channel_id: &ChannelId,
+        tx: &Transaction,
+    ) -> Result<(bitcoin::secp256k1::ecdsa::Signature, Vec<Vec<Vec<u8>>>), Status> {
+        let ipaths = vec![vec![], vec![5]];
+        let values_sat = vec![0, WALLET_INPUT_SAT];
+        let spendtypes = vec![SpendType::Invalid, SpendType::P2wpkh];
+        node.sign_splice_tx(channel_id, tx, &ipaths, &values_sat, &spendtypes, &vec![])
+    }
+
+    #[test]
+    fn splice_in_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        assert_status_ok!(splice(&node, &channel_id, &tx));
+
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_eq!(chan.setup.funding_outpoint, OutPoint { txid: tx.txid(), vout: 0 });
+            assert_eq!(chan.setup.channel_value_sat, tx.output[0].value);
+            let splice = chan.enforcement_state.splice.as_ref().unwrap();
+            assert_eq!(splice.prior_funding_outpoint, setup.funding_outpoint);
+            assert_eq!(splice.prior_channel_value_sat, setup.channel_value_sat);
+            assert_eq!(splice.holder_delta_sat, (WALLET_INPUT_SAT - FEE_SAT) as i64);
+            assert!(chan.enforcement_state.holder_commit_is_pre_splice());
+            assert_eq!(chan.monitor.get_state().funding_txids.last(), Some(&tx.txid()));
+            Ok(())
+        })
+        .unwrap();
+
+        // policy-splice-commitment-countersigned
+        let res = sign_splice(&node, &channel_id, &tx);
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: no holder commitment validated for the new funding output"
+        );
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.next_holder_commit_num += 1;
+            Ok(())
+        })
+        .unwrap();
+
+        let (sig, witvec) = sign_splice(&node, &channel_id, &tx).expect("sign splice");
+        assert_eq!(witvec.len(), 2);
+        assert!(witvec[0].is_empty());
+        assert_eq!(witvec[1].len(), 2);
+
+        let (funding_pubkey, redeemscript) = node
+            .with_ready_channel(&channel_id, |chan| {
+                let funding_pubkey = chan.keys.pubkeys().funding_pubkey;
+                let redeemscript = make_funding_redeemscript(
+                    &funding_pubkey,
+                    &chan.keys.counterparty_pubkeys().funding_pubkey,
+                );
+                Ok((funding_pubkey, redeemscript))
+            })
+            .unwrap();
+        check_signature(
+            &tx,
+            0,
+            TypedSignature::all(sig),
+            &funding_pubkey,
+            setup.channel_value_sat,
+            &redeemscript,
+        );
+    }
+
+    #[test]
+    fn sign_splice_unvalidated_input_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        assert_status_ok!(splice(&node, &channel_id, &tx));
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.next_holder_commit_num += 1;
+            Ok(())
+        })
+        .unwrap();
+
+        // the funding input is not ours
+        let ipaths = vec![vec![5], vec![5]];
+        let values_sat = vec![0, WALLET_INPUT_SAT];
+        let spendtypes = vec![SpendType::P2wpkh, SpendType::P2wpkh];
+        let res = node.sign_splice_tx(&channel_id, &tx, &ipaths, &values_sat, &spendtypes, &vec![]);
+        assert_invalid_argument_err!(
+            res,
+            "input 0: spend type P2wpkh differs from the validated splice"
+        );
+
+        // the wallet input value is taken from the validation
+        let (_, witvec) = sign_splice(&node, &channel_id, &tx).expect("sign splice");
+        let values_sat = vec![0, WALLET_INPUT_SAT + 1];
+        let spendtypes = vec![SpendType::Invalid, SpendType::P2wpkh];
+        let (_, witvec1) = node
+            .sign_splice_tx(
+                &channel_id,
+                &tx,
+                &vec![vec![], vec![5]],
+                &values_sat,
+                &spendtypes,
+                &vec![],
+            )
+            .expect("sign splice");
+        assert_eq!(witvec, witvec1);
+    }
+
+    #[test]
+    fn abort_splice_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        assert_status_ok!(splice(&node, &channel_id, &tx));
+        assert_status_ok!(node.abort_splice(&channel_id));
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_eq!(chan.setup.funding_outpoint, setup.funding_outpoint);
+            assert_eq!(chan.setup.channel_value_sat, setup.channel_value_sat);
+            assert!(chan.enforcement_state.splice.is_none());
+            assert!(!chan.monitor.get_state().funding_txids.contains(&tx.txid()));
+            Ok(())
+        })
+        .unwrap();
+
+        // replace the splice with one paying a higher fee
+        let mut tx = tx;
+        tx.output[0].value -= FEE_SAT;
+        assert_status_ok!(splice(&node, &channel_id, &tx));
+        node.with_ready_channel(&channel_id, |chan| {
+            let splice = chan.enforcement_state.splice.as_ref().unwrap();
+            assert_eq!(splice.holder_delta_sat, (WALLET_INPUT_SAT - 2 * FEE_SAT) as i64);
+            Ok(())
+        })
+        .unwrap();
+
+        // policy-splice-abort-uncommitted
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.next_counterparty_commit_num += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_failed_precondition_err!(
+            node.abort_splice(&channel_id),
+            "policy failure: commitments spending the new funding output were already made"
+        );
+    }
+
+    #[test]
+    fn splice_previous_not_buried_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        assert_status_ok!(splice(&node, &channel_id, &tx));
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.next_holder_commit_num += 1;
+            Ok(())
+        })
+        .unwrap();
+
+        let spliced_setup =
+            node.with_ready_channel(&channel_id, |chan| Ok(chan.setup.clone())).unwrap();
+        let tx2 = make_splice_in_tx(&spliced_setup, funding_script_pubkey(&node, &channel_id));
+        assert_failed_precondition_err!(
+            splice(&node, &channel_id, &tx2),
+            "policy failure: validate_splice_tx: previous splice has 0 confirmations, 6 required"
+        );
+
+        node.with_ready_channel(&channel_id, |chan| {
+            let mut state = chan.monitor.get_state();
+            state.height += SPLICE_BURIED_DEPTH;
+            state.splice_height = Some(state.height + 1 - SPLICE_BURIED_DEPTH);
+            Ok(())
+        })
+        .unwrap();
+        assert_status_ok!(splice(&node, &channel_id, &tx2));
+    }
+
+    #[test]
+    fn splice_out_to_wallet_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let wallet_address = node.get_native_address(&vec![9]).unwrap();
+        let mut tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        tx.input.truncate(1);
+        tx.output[0].value = setup.channel_value_sat - 600_000;
+        tx.output.push(TxOut {
+            value: 600_000 - FEE_SAT,
+            script_pubkey: wallet_address.script_pubkey(),
+        });
+        let values_sat = vec![0];
+        let spendtypes = vec![SpendType::Invalid];
+        let opaths = vec![vec![], vec![9]];
+        assert_status_ok!(node.splice_channel(
+            &channel_id,
+            &tx,
+            0,
+            -600_000,
+            &values_sat,
+            &spendtypes,
+            &opaths
+        ));
+        node.with_ready_channel(&channel_id, |chan| {
+            let splice = chan.enforcement_state.splice.as_ref().unwrap();
+            assert_eq!(splice.holder_delta_sat, -600_000);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn splice_out_too_large_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let wallet_address = node.get_native_address(&vec![9]).unwrap();
+        let mut tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        tx.input.truncate(1);
+        tx.output[0].value = 500_000;
+        tx.output.push(TxOut {
+            value: setup.channel_value_sat - 500_000 - FEE_SAT,
+            script_pubkey: wallet_address.script_pubkey(),
+        });
+        let res = node.splice_channel(
+            &channel_id,
+            &tx,
+            0,
+            -((setup.channel_value_sat - 500_000) as i64),
+            &vec![0],
+            &vec![SpendType::Invalid],
+            &vec![vec![], vec![9]],
+        );
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_splice_tx: splice takes 2500000 out of a balance of 1998000"
+        );
+    }
+
+    #[test]
+    fn splice_out_to_unknown_address_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let mut tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        tx.input.truncate(1);
+        tx.output[0].value = setup.channel_value_sat - 600_000;
+        tx.output.push(TxOut {
+            value: 600_000 - FEE_SAT,
+            script_pubkey: payload_for_p2wsh(&Script::new()).script_pubkey(),
+        });
+        let res = node.splice_channel(
+            &channel_id,
+            &tx,
+            0,
+            -600_000,
+            &vec![0],
+            &vec![SpendType::Invalid],
+            &vec![vec![], vec![]],
+        );
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_splice_tx: our inputs of 0 less our outputs of 0 \
+             don't match a balance change of -600000 and a fee of 1000"
+        );
+
+        // the counterparty's splice-out doesn't change our balance
+        assert_status_ok!(node.splice_channel(
+            &channel_id,
+            &tx,
+            0,
+            0,
+            &vec![0],
+            &vec![SpendType::Invalid],
+            &vec![vec![], vec![]],
+        ));
+    }
+
+    #[test]
+    fn splice_with_htlcs_test() {
+        let htlc =
+            HTLCInfo2 { value_sat: 10_000, payment_hash: PaymentHash([1; 32]), cltv_expiry: 500 };
+        let (setup, node, channel_id) = setup_channel(vec![htlc]);
+        let tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        let res = splice(&node, &channel_id, &tx);
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: validate_splice_tx: cannot splice with HTLCs in flight"
+        );
+    }
+
+    #[test]
+    fn splice_bad_funding_script_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let script_pubkey = node.get_native_address(&vec![9]).unwrap().script_pubkey();
+        let tx = make_splice_in_tx(&setup, script_pubkey.clone());
+        let res = splice(&node, &channel_id, &tx);
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().code(), Code::FailedPrecondition);
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_eq!(chan.setup.funding_outpoint, setup.funding_outpoint);
+            assert!(chan.enforcement_state.splice.is_none());
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn splice_missing_funding_input_test() {
+        let (setup, node, channel_id) = setup_channel(vec![]);
+        let mut tx = make_splice_in_tx(&setup, funding_script_pubkey(&node, &channel_id));
+        tx.input[0].previous_output.vout += 1;
+        let res = splice(&node, &channel_id, &tx);
+        assert_failed_precondition_err!(
+            res,
+            format!(
+                "policy failure: decode_splice_tx: funding outpoint {} is not spent",
+                setup.funding_outpoint
+            )
+            .as_str()
+        );
+    }
+}
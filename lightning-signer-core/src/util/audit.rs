@@ -0,0 +1,322 @@
+//! Tamper-evident audit log of handled requests.
+//!
+//! Each handled request is recorded with its type, channel, relevant txid and
+//! commitment number, and the decision.  Entries are hash-chained - each entry
+//! commits to the hash of the previous one - so that deleting, reordering or
+//! editing entries is detectable by [`verify`].
+//!
+//! Entries are serialized as tab separated lines, with the entry hash last.
+
+use crate::prelude::*;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::Txid;
+
+const EMPTY_FIELD: &str = "-";
+const NUM_FIELDS: usize = 12;
+
+/// The outcome of handling a request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The request was handled and a reply was produced
+    Allowed,
+    /// The request was rejected, with the reason
+    Rejected(String),
+}
+
+/// The audited details of a handled request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// The handler client id
+    pub client_id: u64,
+    /// The CLN database id of the peer connection, or zero for the root handler
+    pub dbid: u64,
+    /// The channel, if the request was handled in a channel context
+    pub channel_id: Option<String>,
+    /// The message type name
+    pub message: String,
+    /// The txid of the transaction in the request, if any
+    pub txid: Option<Txid>,
+    /// The commitment number in the request, if any
+    pub commitment_number: Option<u64>,
+    /// The outcome
+    pub decision: Decision,
+}
+
+/// A hash-chained audit log entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Sequence number, starting at zero
+    pub seq: u64,
+    /// The hash of the previous entry, or all zeroes for the first entry
+    pub prev_hash: [u8; 32],
+    /// The audited details
+    pub record: AuditRecord,
+    /// The hash of this entry
+    pub hash: [u8; 32],
+}
+
+impl AuditEntry {
+    fn new(seq: u64, prev_hash: [u8; 32], record: AuditRecord) -> Self {
+        let hash = compute_hash(&prev_hash, &body(seq, &prev_hash, &record));
+        AuditEntry { seq, prev_hash, record, hash }
+    }
+
+    /// Serialize to a single line, without a line terminator
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}", body(self.seq, &self.prev_hash, &self.record), self.hash.to_hex())
+    }
+
+    /// Parse a line produced by `to_line`.  The hash is not checked.
+    pub fn from_line(line: &str) -> Result<Self, VerifyError> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != NUM_FIELDS {
+            return Err(VerifyError::Malformed(format!("expected {} fields", NUM_FIELDS)));
+        }
+        let decision = match (fields[9], fields[10]) {
+            ("allowed", EMPTY_FIELD) => Decision::Allowed,
+            ("rejected", reason) => Decision::Rejected(reason.to_string()),
+            (d, _) => return Err(VerifyError::Malformed(format!("bad decision {}", d))),
+        };
+        let record = AuditRecord {
+            client_id: parse_field(fields[2], "client_id")?,
+            dbid: parse_field(fields[3], "dbid")?,
+            channel_id: optional_field(fields[4]).map(|s| s.to_string()),
+            message: fields[5].to_string(),
+            txid: optional_field(fields[6]).map(|s| parse_field(s, "txid")).transpose()?,
+            commitment_number: optional_field(fields[7])
+                .map(|s| parse_field(s, "commitment_number"))
+                .transpose()?,
+            decision,
+        };
+        Ok(AuditEntry {
+            seq: parse_field(fields[0], "seq")?,
+            prev_hash: parse_hash(fields[1])?,
+            record,
+            hash: parse_hash(fields[11])?,
+        })
+    }
+}
+
+/// The tip of the audit hash chain
+#[derive(Clone, Debug, Default)]
+pub struct AuditChain {
+    next_seq: u64,
+    last_hash: [u8; 32],
+}
+
+impl AuditChain {
+    /// Start a new chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continue an existing chain after its last entry
+    pub fn resume(last: &AuditEntry) -> Self {
+        AuditChain { next_seq: last.seq + 1, last_hash: last.hash }
+    }
+
+    /// Append a record to the chain, returning the entry to be stored
+    pub fn append(&mut self, record: AuditRecord) -> AuditEntry {
+        let entry = AuditEntry::new(self.next_seq, self.last_hash, record);
+        self.next_seq += 1;
+        self.last_hash = entry.hash;
+        entry
+    }
+}
+
+/// Audit log storage.
+///
+/// Implementations are expected to append the record to an `AuditChain`
+/// and durably store the resulting entry.
+pub trait AuditSink: Send + Sync {
+    /// Record a handled request
+    fn record(&self, record: AuditRecord);
+}
+
+/// An audit log verification failure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The line could not be parsed
+    Malformed(String),
+    /// An entry is missing or out of order
+    BadSequence {
+        /// The expected sequence number
+        expected: u64,
+        /// The sequence number found
+        actual: u64,
+    },
+    /// An entry does not commit to the previous entry
+    BrokenChain(u64),
+    /// An entry was modified
+    BadHash(u64),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Malformed(m) => write!(f, "malformed entry: {}", m),
+            VerifyError::BadSequence { expected, actual } =>
+                write!(f, "expected entry {} but found {}", expected, actual),
+            VerifyError::BrokenChain(seq) =>
+                write!(f, "entry {} does not follow the previous entry", seq),
+            VerifyError::BadHash(seq) => write!(f, "entry {} hash mismatch", seq),
+        }
+    }
+}
+
+/// Verify a hash-chained audit log, given its lines in order.
+///
+/// Returns the number of entries on success.  Note that truncation of the tail
+/// of the log can only be detected by comparing the last hash against an
+/// externally recorded value.
+pub fn verify<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Result<u64, VerifyError> {
+    let mut chain = AuditChain::new();
+    for line in lines {
+        let entry = AuditEntry::from_line(line)?;
+        if entry.seq != chain.next_seq {
+            return Err(VerifyError::BadSequence { expected: chain.next_seq, actual: entry.seq });
+        }
+        if entry.prev_hash != chain.last_hash {
+            return Err(VerifyError::BrokenChain(entry.seq));
+        }
+        let expected = chain.append(entry.record.clone());
+        if expected.hash != entry.hash {
+            return Err(VerifyError::BadHash(entry.seq));
+        }
+    }
+    Ok(chain.next_seq)
+}
+
+// The serialized entry, without the hash
+fn body(seq: u64, prev_hash: &[u8; 32], record: &AuditRecord) -> String {
+    let (decision, reason) = match &record.decision {
+        Decision::Allowed => ("allowed", EMPTY_FIELD.to_string()),
+        Decision::Rejected(reason) => ("rejected", sanitize(reason)),
+    };
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        seq,
+        prev_hash.to_hex(),
+        record.client_id,
+        record.dbid,
+        record.channel_id.as_ref().map(|s| s.as_str()).unwrap_or(EMPTY_FIELD),
+        record.message,
+        record.txid.map(|t| t.to_string()).unwrap_or(EMPTY_FIELD.to_string()),
+        record.commitment_number.map(|n| n.to_string()).unwrap_or(EMPTY_FIELD.to_string()),
+        decision,
+        reason,
+    )
+}
+
+fn compute_hash(prev_hash: &[u8; 32], body: &str) -> [u8; 32] {
+    let mut engine = Sha256Hash::engine();
+    engine.input(prev_hash);
+    engine.input(body.as_bytes());
+    Sha256Hash::from_engine(engine).into_inner()
+}
+
+// Keep the reason on one line and distinguishable from an empty field
+fn sanitize(reason: &str) -> String {
+    let s: String = reason.chars().map(|c| if c == '\t' || c == '\n' { ' ' } else { c }).collect();
+    if s.is_empty() || s == EMPTY_FIELD {
+        "unknown".to_string()
+    } else {
+        s
+    }
+}
+
+fn optional_field(s: &str) -> Option<&str> {
+    if s == EMPTY_FIELD {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+fn parse_field<T: FromStr>(s: &str, name: &str) -> Result<T, VerifyError> {
+    s.parse().map_err(|_| VerifyError::Malformed(format!("bad {}", name)))
+}
+
+fn parse_hash(s: &str) -> Result<[u8; 32], VerifyError> {
+    let v = Vec::<u8>::from_hex(s).map_err(|_| VerifyError::Malformed("bad hash".to_string()))?;
+    let mut hash = [0u8; 32];
+    if v.len() != hash.len() {
+        return Err(VerifyError::Malformed("bad hash length".to_string()));
+    }
+    hash.copy_from_slice(&v);
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_record(message: &str, decision: Decision) -> AuditRecord {
+        AuditRecord {
+            client_id: 1,
+            dbid: 2,
+            channel_id: Some("0102".to_string()),
+            message: message.to_string(),
+            txid: None,
+            commitment_number: Some(3),
+            decision,
+        }
+    }
+
+    fn make_log() -> Vec<String> {
+        let mut chain = AuditChain::new();
+        let records = [
+            make_record("ReadyChannel", Decision::Allowed),
+            make_record("SignRemoteCommitmentTx2", Decision::Allowed),
+            make_record("SignLocalCommitmentTx2", Decision::Rejected("policy\tfailure".into())),
+        ];
+        records.iter().map(|r| chain.append(r.clone()).to_line()).collect()
+    }
+
+    #[test]
+    fn roundtrip_test() {
+        let lines = make_log();
+        let entry = AuditEntry::from_line(&lines[2]).unwrap();
+        assert_eq!(entry.seq, 2);
+        assert_eq!(entry.record.decision, Decision::Rejected("policy failure".into()));
+        assert_eq!(verify(lines.iter().map(|l| l.as_str())), Ok(3));
+    }
+
+    #[test]
+    fn resume_test() {
+        let mut lines = make_log();
+        let last = AuditEntry::from_line(lines.last().unwrap()).unwrap();
+        let mut chain = AuditChain::resume(&last);
+        lines.push(chain.append(make_record("SignMutualCloseTx2", Decision::Allowed)).to_line());
+        assert_eq!(verify(lines.iter().map(|l| l.as_str())), Ok(4));
+    }
+
+    #[test]
+    fn tamper_test() {
+        let lines = make_log();
+
+        let mut deleted = lines.clone();
+        deleted.remove(1);
+        assert_eq!(
+            verify(deleted.iter().map(|l| l.as_str())),
+            Err(VerifyError::BadSequence { expected: 1, actual: 2 })
+        );
+
+        let mut edited = lines.clone();
+        edited[1] = edited[1].replace("\t3\tallowed", "\t4\tallowed");
+        assert_eq!(verify(edited.iter().map(|l| l.as_str())), Err(VerifyError::BadHash(1)));
+
+        // rewriting an entry with a fresh hash breaks the link from the next entry
+        let mut rewritten = lines.clone();
+        let mut entry = AuditEntry::from_line(&rewritten[1]).unwrap();
+        entry.record.decision = Decision::Rejected("hidden".into());
+        let entry = AuditEntry::new(entry.seq, entry.prev_hash, entry.record);
+        rewritten[1] = entry.to_line();
+        assert_eq!(verify(rewritten.iter().map(|l| l.as_str())), Err(VerifyError::BrokenChain(2)));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::prelude::*;
+use core::time::Duration;
+
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{schnorr, Message, PublicKey, Secp256k1, XOnlyPublicKey};
+
+/// The TLV types reserved for signatures, which are not part of the merkle tree
+pub const SIGNATURE_TYPES: core::ops::RangeInclusive<u64> = 240..=1000;
+
+/// The TLV types that an invoice copies from the offer and invoice_request
+pub const INVOICE_REQUEST_TYPES: core::ops::Range<u64> = 0..160;
+
+const INVREQ_AMOUNT_TYPE: u64 = 82;
+const INVREQ_PAYER_ID_TYPE: u64 = 88;
+const INVOICE_CREATED_AT_TYPE: u64 = 164;
+const INVOICE_RELATIVE_EXPIRY_TYPE: u64 = 166;
+const INVOICE_PAYMENT_HASH_TYPE: u64 = 168;
+const INVOICE_AMOUNT_TYPE: u64 = 170;
+const INVOICE_NODE_ID_TYPE: u64 = 176;
+const SIGNATURE_TYPE: u64 = 240;
+
+/// The default invoice_relative_expiry, in seconds
+pub const DEFAULT_RELATIVE_EXPIRY: u64 = 7200;
+
+/// A record in a BOLT 12 TLV stream
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlvRecord<'a> {
+    /// The record type
+    pub typ: u64,
+    /// The encoded type
+    pub type_bytes: &'a [u8],
+    /// The record value
+    pub value: &'a [u8],
+    /// The whole encoded record
+    pub bytes: &'a [u8],
+}
+
+fn read_bigsize(data: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let first = *data.get(*pos).ok_or_else(|| "truncated bigsize".to_string())?;
+    let (len, min) = match first {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x10000),
+        0xff => (8, 0x100000000),
+        b => {
+            *pos += 1;
+            return Ok(b as u64);
+        }
+    };
+    let bytes =
+        data.get(*pos + 1..*pos + 1 + len).ok_or_else(|| "truncated bigsize".to_string())?;
+    let val = bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    if val < min {
+        return Err("non-minimal bigsize".to_string());
+    }
+    *pos += 1 + len;
+    Ok(val)
+}
+
+fn read_tu64(value: &[u8]) -> Result<u64, String> {
+    if value.len() > 8 || value.first() == Some(&0) {
+        return Err("invalid truncated integer".to_string());
+    }
+    Ok(value.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+/// Split a TLV stream into records.  Types must be strictly increasing.
+pub fn parse_tlv_stream(data: &[u8]) -> Result<Vec<TlvRecord>, String> {
+    let mut records: Vec<TlvRecord> = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let start = pos;
+        let typ = read_bigsize(data, &mut pos)?;
+        let type_end = pos;
+        let len = read_bigsize(data, &mut pos)? as usize;
+        let end = pos.checked_add(len).filter(|end| *end <= data.len());
+        let end = end.ok_or_else(|| format!("truncated value for type {}", typ))?;
+        if let Some(prev) = records.last() {
+            if typ <= prev.typ {
+                return Err(format!("type {} out of order after {}", typ, prev.typ));
+            }
+        }
+        records.push(TlvRecord {
+            typ,
+            type_bytes: &data[start..type_end],
+            value: &data[pos..end],
+            bytes: &data[start..end],
+        });
+        pos = end;
+    }
+    Ok(records)
+}
+
+fn tagged_hash_engine(tag: &[u8]) -> bitcoin::hashes::sha256::HashEngine {
+    let tag_hash = Sha256::hash(tag).into_inner();
+    let mut engine = Sha256::engine();
+    engine.input(&tag_hash);
+    engine.input(&tag_hash);
+    engine
+}
+
+fn tagged_hash(tag: &[u8], msg: &[u8]) -> [u8; 32] {
+    let mut engine = tagged_hash_engine(tag);
+    engine.input(msg);
+    Sha256::from_engine(engine).into_inner()
+}
+
+fn branch_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (lesser, greater) = if a < b { (a, b) } else { (b, a) };
+    let mut engine = tagged_hash_engine(b"LnBranch");
+    engine.input(lesser);
+    engine.input(greater);
+    Sha256::from_engine(engine).into_inner()
+}
+
+/// The merkle root of the non-signature records, which is what gets signed
+pub fn merkle_root(records: &[TlvRecord]) -> [u8; 32] {
+    let mut leaves = Vec::new();
+    if let Some(first) = records.first() {
+        let mut nonce_tag = b"LnNonce".to_vec();
+        nonce_tag.extend_from_slice(first.bytes);
+        for record in records.iter().filter(|r| !SIGNATURE_TYPES.contains(&r.typ)) {
+            leaves.push(tagged_hash(b"LnLeaf", record.bytes));
+            leaves.push(tagged_hash(&nonce_tag, record.type_bytes));
+        }
+    }
+    // Unpaired nodes are carried up to the next level
+    while leaves.len() > 1 {
+        leaves = leaves
+            .chunks(2)
+            .map(|pair| if pair.len() == 2 { branch_hash(&pair[0], &pair[1]) } else { pair[0] })
+            .collect();
+    }
+    leaves.pop().unwrap_or([0; 32])
+}
+
+/// The message signed for field `fieldname` of message `messagename`
+pub fn signature_message(messagename: &[u8], fieldname: &[u8], merkle_root: &[u8; 32]) -> Message {
+    let mut tag = b"lightning".to_vec();
+    tag.extend_from_slice(messagename);
+    tag.extend_from_slice(fieldname);
+    let hash = tagged_hash(&tag, merkle_root);
+    Message::from_slice(&hash).expect("32 bytes")
+}
+
+/// The fields of a BOLT 12 invoice_request that the signer tracks
+#[derive(Clone, Debug)]
+pub struct Bolt12InvoiceRequest {
+    /// The merkle root, as a unique ID
+    pub merkle_root: [u8; 32],
+    /// The requested amount, if the offer didn't fix it
+    pub amount_msat: Option<u64>,
+    /// The payer's key
+    pub payer_id: Option<PublicKey>,
+}
+
+impl Bolt12InvoiceRequest {
+    /// Parse an invoice_request TLV stream
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let records = parse_tlv_stream(data)?;
+        let mut request = Bolt12InvoiceRequest {
+            merkle_root: merkle_root(&records),
+            amount_msat: None,
+            payer_id: None,
+        };
+        for record in records.iter() {
+            match record.typ {
+                INVREQ_AMOUNT_TYPE => request.amount_msat = Some(read_tu64(record.value)?),
+                INVREQ_PAYER_ID_TYPE =>
+                    request.payer_id = Some(
+                        PublicKey::from_slice(record.value)
+                            .map_err(|_| "invalid invreq_payer_id".to_string())?,
+                    ),
+                t if t >= INVOICE_REQUEST_TYPES.end && !SIGNATURE_TYPES.contains(&t) =>
+                    return Err(format!("unexpected type {} in invoice_request", t)),
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// The fields of a BOLT 12 invoice that the signer tracks
+#[derive(Clone, Debug)]
+pub struct Bolt12Invoice {
+    /// The merkle root, as a unique ID
+    pub merkle_root: [u8; 32],
+    /// The merkle root of the invoice_request this invoice answers
+    pub invoice_request_merkle_root: [u8; 32],
+    /// The payment hash
+    pub payment_hash: [u8; 32],
+    /// The invoiced amount
+    pub amount_msat: u64,
+    /// The payee's key
+    pub node_id: PublicKey,
+    /// Creation time, as duration since the UNIX epoch
+    pub created_at: Duration,
+    /// Expiry, as duration since creation
+    pub relative_expiry: Duration,
+    /// The payee's signature, if the invoice is signed
+    pub signature: Option<schnorr::Signature>,
+}
+
+impl Bolt12Invoice {
+    /// Parse an invoice TLV stream.  The mandatory fields must be present,
+    /// but the signature is not checked.
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let records = parse_tlv_stream(data)?;
+        let mut payment_hash = None;
+        let mut amount_msat = None;
+        let mut node_id = None;
+        let mut created_at = None;
+        let mut relative_expiry = DEFAULT_RELATIVE_EXPIRY;
+        let mut signature = None;
+        for record in records.iter() {
+            match record.typ {
+                INVOICE_CREATED_AT_TYPE => created_at = Some(read_tu64(record.value)?),
+                INVOICE_RELATIVE_EXPIRY_TYPE => relative_expiry = read_tu64(record.value)?,
+                INVOICE_PAYMENT_HASH_TYPE => {
+                    let mut hash = [0; 32];
+                    if record.value.len() != 32 {
+                        return Err("invalid invoice_payment_hash".to_string());
+                    }
+                    hash.copy_from_slice(record.value);
+                    payment_hash = Some(hash);
+                }
+                INVOICE_AMOUNT_TYPE => amount_msat = Some(read_tu64(record.value)?),
+                INVOICE_NODE_ID_TYPE =>
+                    node_id = Some(
+                        PublicKey::from_slice(record.value)
+                            .map_err(|_| "invalid invoice_node_id".to_string())?,
+                    ),
+                SIGNATURE_TYPE =>
+                    signature = Some(
+                        schnorr::Signature::from_slice(record.value)
+                            .map_err(|_| "invalid signature".to_string())?,
+                    ),
+                _ => {}
+            }
+        }
+        let invoice_request_records: Vec<TlvRecord> =
+            records.iter().filter(|r| INVOICE_REQUEST_TYPES.contains(&r.typ)).cloned().collect();
+        Ok(Bolt12Invoice {
+            merkle_root: merkle_root(&records),
+            invoice_request_merkle_root: merkle_root(&invoice_request_records),
+            payment_hash: payment_hash.ok_or_else(|| "missing invoice_payment_hash".to_string())?,
+            amount_msat: amount_msat.ok_or_else(|| "missing invoice_amount".to_string())?,
+            node_id: node_id.ok_or_else(|| "missing invoice_node_id".to_string())?,
+            created_at: Duration::from_secs(
+                created_at.ok_or_else(|| "missing invoice_created_at".to_string())?,
+            ),
+            relative_expiry: Duration::from_secs(relative_expiry),
+            signature,
+        })
+    }
+
+    /// Check the payee's signature over the invoice
+    pub fn verify_signature(&self) -> Result<(), String> {
+        let signature = self.signature.as_ref().ok_or_else(|| "missing signature".to_string())?;
+        let xonly = XOnlyPublicKey::from_slice(&self.node_id.serialize()[1..])
+            .map_err(|_| "invalid invoice_node_id".to_string())?;
+        let msg = signature_message(b"invoice", b"signature", &self.merkle_root);
+        Secp256k1::verification_only()
+            .verify_schnorr(signature, &msg, &xonly)
+            .map_err(|_| "invalid signature".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
+
+    fn root_hex(stream_hex: &str) -> String {
+        let data = Vec::from_hex(stream_hex).unwrap();
+        merkle_root(&parse_tlv_stream(&data).unwrap()).to_hex()
+    }
+
+    #[test]
+    fn merkle_root_test() {
+        // Test vectors from the BOLT 12 specification
+        assert_eq!(
+            root_hex("010203e8"),
+            "b013756c8fee86503a0b4abdab4cddeb1af5d344ca6fc2fa8b6c08938caa6f93"
+        );
+        assert_eq!(
+            root_hex("010203e802080000010000020003"),
+            "c3774abbf4815aa54ccaa026bff6581f01f3be5fe814c620a252534f434bc0d1"
+        );
+        assert_eq!(
+            root_hex(
+                "010203e802080000010000020003\
+                 03310266e4598d1d3c415f572a8488830b60f7e744ed9235eb0b1ba93283b315c035180000000000\
+                 0000010000000000000002"
+            ),
+            "ab2e79b1283b0b31e0b035258de23782df6b89a38cfa7237bde69aed1a658c5d"
+        );
+    }
+
+    #[test]
+    fn merkle_root_ignores_signature_test() {
+        let mut signed = "010203e8".to_string();
+        signed.push_str("f040");
+        signed.push_str(&"00".repeat(64));
+        assert_eq!(root_hex(&signed), root_hex("010203e8"));
+    }
+
+    #[test]
+    fn parse_tlv_stream_test() {
+        let data = Vec::from_hex("0203e8").unwrap();
+        assert_eq!(parse_tlv_stream(&data).unwrap_err(), "truncated value for type 2");
+        let data = Vec::from_hex("02010101020202").unwrap();
+        assert_eq!(parse_tlv_stream(&data).unwrap_err(), "type 1 out of order after 2");
+        let data = Vec::from_hex("fd00010100").unwrap();
+        assert_eq!(parse_tlv_stream(&data).unwrap_err(), "non-minimal bigsize");
+    }
+}
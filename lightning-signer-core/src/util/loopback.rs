@@ -191,7 +191,7 @@ impl LoopbackChannelSigner {
 
     fn option_anchor_outputs(&self) -> bool {
         let setup = self.get_channel_setup().expect("not ready");
-        setup.commitment_type == CommitmentType::Anchors
+        setup.option_anchor_outputs()
     }
 }
 
@@ -516,6 +516,7 @@ impl BaseSign for LoopbackChannelSigner {
             counterparty_selected_contest_delay: counterparty_parameters.selected_contest_delay,
             counterparty_shutdown_script: None, // TODO
             commitment_type: CommitmentType::StaticRemoteKey, // TODO
+            holder_funding_sat: None,
         };
         let node = self.signer.get_node(&self.node_id).expect("no such node");
 
@@ -1,3 +1,7 @@
+/// Tamper-evident audit log
+pub mod audit;
+/// BOLT 12 TLV parsing
+pub mod bolt12;
 /// Byte to integer conversion
 pub mod byte_utils;
 /// Cryptographic utilities
@@ -27,6 +31,8 @@ pub mod test_utils;
 pub mod functional_test_utils;
 /// Key utilities
 pub mod key_utils;
+/// Request rate limiting
+pub mod rate_limit;
 /// Status error results
 pub mod status;
 /// Transaction utilities
@@ -0,0 +1,270 @@
+use crate::prelude::*;
+use core::time::Duration;
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::channel::ChannelId;
+use crate::util::status::{resource_exhausted, ErrorDetails, Status};
+
+// Once this many buckets are tracked, idle buckets are dropped
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// A source of time for rate limiting
+pub trait Clock: SendSync {
+    /// The time since an arbitrary fixed origin
+    fn now(&self) -> Duration;
+}
+
+/// The system clock
+#[cfg(feature = "std")]
+pub struct StdClock;
+
+#[cfg(feature = "std")]
+impl SendSync for StdClock {}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+// A token is this many units, so that a rate per minute adds a whole
+// number of units per millisecond
+const UNITS_PER_TOKEN: u64 = 60_000;
+
+/// A token bucket holding up to `burst` tokens, refilled at a steady rate.
+///
+/// Tokens are counted in fractional units, so that slow refill rates work
+/// without floating point.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    capacity: u64,
+    per_min: u64,
+    units: u64,
+    last: Duration,
+}
+
+impl TokenBucket {
+    /// A full bucket, refilled at `per_sec` tokens per second
+    pub fn new(per_sec: u32, burst: u32, now: Duration) -> Self {
+        Self::with_rate(per_sec as u64 * 60, burst, now)
+    }
+
+    /// A full bucket, refilled at `per_min` tokens per minute
+    pub fn new_per_minute(per_min: u32, burst: u32, now: Duration) -> Self {
+        Self::with_rate(per_min as u64, burst, now)
+    }
+
+    fn with_rate(per_min: u64, burst: u32, now: Duration) -> Self {
+        let capacity = burst as u64 * UNITS_PER_TOKEN;
+        TokenBucket { capacity, per_min, units: capacity, last: now }
+    }
+
+    fn refill(&mut self, now: Duration) {
+        // The clock may go backwards, in which case nothing is added
+        let elapsed_ms = now.checked_sub(self.last).unwrap_or_default().as_millis() as u64;
+        let added = elapsed_ms.saturating_mul(self.per_min);
+        self.units = self.units.saturating_add(added).min(self.capacity);
+        self.last = self.last.max(now);
+    }
+
+    /// Take a token, returning false if none are available
+    pub fn try_take(&mut self, now: Duration) -> bool {
+        self.refill(now);
+        if self.units < UNITS_PER_TOKEN {
+            return false;
+        }
+        self.units -= UNITS_PER_TOKEN;
+        true
+    }
+
+    fn is_full(&mut self, now: Duration) -> bool {
+        self.refill(now);
+        self.units == self.capacity
+    }
+}
+
+/// Request rate limits.  A zero rate means unlimited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimits {
+    /// Sustained requests per second for each node
+    pub node_per_sec: u32,
+    /// Requests a node can make in a burst
+    pub node_burst: u32,
+    /// Sustained requests per second for each channel
+    pub channel_per_sec: u32,
+    /// Requests a channel can make in a burst
+    pub channel_burst: u32,
+}
+
+/// Per-node and per-channel request rate limiting
+pub struct RateLimiter {
+    limits: RateLimits,
+    nodes: Mutex<OrderedMap<PublicKey, TokenBucket>>,
+    channels: Mutex<OrderedMap<(PublicKey, ChannelId), TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// A limiter enforcing `limits`
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            nodes: Mutex::new(OrderedMap::new()),
+            channels: Mutex::new(OrderedMap::new()),
+        }
+    }
+
+    /// The limits enforced
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// Account for a node-level request
+    pub fn check_node(&self, node_id: &PublicKey, now: Duration) -> Result<(), Status> {
+        let per_sec = self.limits.node_per_sec;
+        if per_sec == 0 {
+            return Ok(());
+        }
+        let burst = self.limits.node_burst.max(1);
+        let mut nodes = self.nodes.lock().unwrap();
+        if take(&mut nodes, node_id.clone(), per_sec, burst, now) {
+            return Ok(());
+        }
+        Err(resource_exhausted(format!(
+            "node {} exceeded {} requests per second",
+            node_id, per_sec
+        ))
+        .with_details(ErrorDetails {
+            kind: Some("rate_limit".to_string()),
+            rule: Some("rate-limit-node".to_string()),
+            allowed: Some(per_sec.to_string()),
+            ..Default::default()
+        }))
+    }
+
+    /// Account for a channel-level request.
+    ///
+    /// Channels are keyed by node as well, since channel IDs are only unique
+    /// within a node.  The node limit is checked separately.
+    pub fn check_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        now: Duration,
+    ) -> Result<(), Status> {
+        let per_sec = self.limits.channel_per_sec;
+        if per_sec == 0 {
+            return Ok(());
+        }
+        let burst = self.limits.channel_burst.max(1);
+        let mut channels = self.channels.lock().unwrap();
+        if take(&mut channels, (node_id.clone(), channel_id.clone()), per_sec, burst, now) {
+            return Ok(());
+        }
+        Err(resource_exhausted(format!(
+            "channel {} exceeded {} requests per second",
+            channel_id, per_sec
+        ))
+        .with_details(ErrorDetails {
+            kind: Some("rate_limit".to_string()),
+            rule: Some("rate-limit-channel".to_string()),
+            allowed: Some(per_sec.to_string()),
+            ..Default::default()
+        })
+        .with_channel_id(channel_id))
+    }
+}
+
+fn take<K: Ord + Clone>(
+    buckets: &mut OrderedMap<K, TokenBucket>,
+    key: K,
+    per_sec: u32,
+    burst: u32,
+    now: Duration,
+) -> bool {
+    // A full bucket is equivalent to no bucket, so forget idle ones rather
+    // than letting a client grow the map with made up keys
+    if buckets.len() >= MAX_IDLE_BUCKETS {
+        // BTreeMap::retain is newer than our MSRV
+        let idle: Vec<K> = buckets
+            .iter_mut()
+            .filter_map(|(key, bucket)| if bucket.is_full(now) { Some(key.clone()) } else { None })
+            .collect();
+        for key in idle {
+            buckets.remove(&key);
+        }
+    }
+    buckets.entry(key).or_insert_with(|| TokenBucket::new(per_sec, burst, now)).try_take(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::key_utils::make_test_pubkey;
+    use crate::util::status::Code;
+
+    #[test]
+    fn token_bucket_test() {
+        let start = Duration::from_secs(1000);
+        let mut bucket = TokenBucket::new(2, 3, start);
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+        assert!(!bucket.try_take(start + Duration::from_millis(499)));
+        assert!(bucket.try_take(start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(start + Duration::from_millis(500)));
+        // The bucket does not fill past its capacity
+        let later = start + Duration::from_secs(100);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+        // A clock going backwards adds nothing
+        assert!(!bucket.try_take(start));
+
+        let mut bucket = TokenBucket::new_per_minute(1, 1, start);
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start + Duration::from_millis(59_999)));
+        assert!(bucket.try_take(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn rate_limiter_test() {
+        let now = Duration::from_secs(1000);
+        let node_id = make_test_pubkey(1);
+        let other_node_id = make_test_pubkey(2);
+        let channel_id = ChannelId::new(&[1; 32]);
+        let other_channel_id = ChannelId::new(&[2; 32]);
+        let limiter = RateLimiter::new(RateLimits {
+            node_per_sec: 1,
+            node_burst: 3,
+            channel_per_sec: 1,
+            channel_burst: 1,
+        });
+        limiter.check_channel(&node_id, &channel_id, now).unwrap();
+        let err = limiter.check_channel(&node_id, &channel_id, now).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        assert_eq!(err.details().rule.as_deref(), Some("rate-limit-channel"));
+        assert_eq!(err.details().channel_id, Some(channel_id.to_string()));
+        limiter.check_channel(&node_id, &other_channel_id, now).unwrap();
+        // The same channel ID on another node has its own bucket
+        limiter.check_channel(&other_node_id, &channel_id, now).unwrap();
+        limiter.check_channel(&node_id, &channel_id, now + Duration::from_secs(1)).unwrap();
+
+        for _ in 0..3 {
+            limiter.check_node(&node_id, now).unwrap();
+        }
+        let err = limiter.check_node(&node_id, now).unwrap_err();
+        assert_eq!(err.details().rule.as_deref(), Some("rate-limit-node"));
+        assert!(limiter.check_node(&other_node_id, now).is_ok());
+        assert!(limiter.check_node(&node_id, now + Duration::from_secs(1)).is_ok());
+
+        let unlimited = RateLimiter::new(RateLimits::default());
+        for _ in 0..100 {
+            unlimited.check_node(&node_id, now).unwrap();
+            unlimited.check_channel(&node_id, &channel_id, now).unwrap();
+        }
+    }
+}
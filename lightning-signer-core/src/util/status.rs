@@ -14,6 +14,266 @@ pub struct Status {
     code: Code,
     /// A relevant error message, found in the `grpc-message` header.
     message: String,
+    /// Machine readable details, found in the `grpc-status-details-bin` header.
+    details: ErrorDetails,
+}
+
+/// Machine readable details of an error, so that clients can classify failures
+/// without parsing the message.
+///
+/// All fields are optional, since most errors only know some of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorDetails {
+    /// The kind of validation error, e.g. `policy` or `transaction_format`
+    pub kind: Option<String>,
+    /// The policy rule which failed, e.g. `policy-commitment-htlc-count-limit`,
+    /// if the check which raised the error is tagged with one
+    pub rule: Option<String>,
+    /// The channel the request was for, in hex
+    pub channel_id: Option<String>,
+    /// The commitment number being signed or validated
+    pub commitment_number: Option<u64>,
+    /// The offending value
+    pub value: Option<String>,
+    /// The allowed value or limit that was violated
+    pub allowed: Option<String>,
+}
+
+impl ErrorDetails {
+    /// Whether no details are known
+    pub fn is_empty(&self) -> bool {
+        self == &ErrorDetails::default()
+    }
+
+    /// Fill in the fields which are unknown here from `other`
+    pub fn merge(&mut self, other: ErrorDetails) {
+        self.kind = self.kind.take().or(other.kind);
+        self.rule = self.rule.take().or(other.rule);
+        self.channel_id = self.channel_id.take().or(other.channel_id);
+        self.commitment_number = self.commitment_number.or(other.commitment_number);
+        self.value = self.value.take().or(other.value);
+        self.allowed = self.allowed.take().or(other.allowed);
+    }
+
+    /// Encode as a protobuf `google.rpc.Status` with the given code and
+    /// message, carrying the details as a single `google.rpc.ErrorInfo`.
+    ///
+    /// This is the standard payload of the `grpc-status-details-bin` header.
+    /// The rule, or failing that the kind, is the `ErrorInfo` reason and all
+    /// known fields are in its metadata.
+    pub fn encode_status(&self, code: i32, message: &str) -> Vec<u8> {
+        let mut info = Vec::new();
+        let reason = self.rule.as_ref().or(self.kind.as_ref());
+        if let Some(reason) = reason {
+            proto::put_bytes(&mut info, 1, reason.as_bytes());
+        }
+        proto::put_bytes(&mut info, 2, ERROR_DOMAIN.as_bytes());
+        for (key, value) in self.metadata() {
+            let mut entry = Vec::new();
+            proto::put_bytes(&mut entry, 1, key.as_bytes());
+            proto::put_bytes(&mut entry, 2, value.as_bytes());
+            proto::put_bytes(&mut info, 3, &entry);
+        }
+
+        let mut any = Vec::new();
+        proto::put_bytes(&mut any, 1, ERROR_INFO_TYPE_URL.as_bytes());
+        proto::put_bytes(&mut any, 2, &info);
+
+        let mut out = Vec::new();
+        if code != 0 {
+            proto::put_varint_field(&mut out, 1, code as u32 as u64);
+        }
+        if !message.is_empty() {
+            proto::put_bytes(&mut out, 2, message.as_bytes());
+        }
+        proto::put_bytes(&mut out, 3, &any);
+        out
+    }
+
+    /// Decode from a protobuf `google.rpc.Status`, as produced by
+    /// [`ErrorDetails::encode_status`].
+    ///
+    /// Details other than an `ErrorInfo` in our domain are ignored, and so is
+    /// malformed input.
+    pub fn decode_status(bytes: &[u8]) -> ErrorDetails {
+        let mut details = ErrorDetails::default();
+        for (field, any) in proto::Fields::new(bytes) {
+            let any = match (field, any) {
+                (3, proto::Value::Bytes(any)) => any,
+                _ => continue,
+            };
+            let mut type_url = None;
+            let mut info = None;
+            for field in proto::Fields::new(any) {
+                match field {
+                    (1, proto::Value::Bytes(b)) => type_url = Some(b),
+                    (2, proto::Value::Bytes(b)) => info = Some(b),
+                    _ => {}
+                }
+            }
+            let info = match (type_url, info) {
+                (Some(url), Some(info)) if url == ERROR_INFO_TYPE_URL.as_bytes() => info,
+                _ => continue,
+            };
+            let mut domain = None;
+            let mut metadata = Vec::new();
+            for field in proto::Fields::new(info) {
+                match field {
+                    (2, proto::Value::Bytes(b)) => domain = Some(b),
+                    (3, proto::Value::Bytes(entry)) => {
+                        let mut key = None;
+                        let mut value = None;
+                        for field in proto::Fields::new(entry) {
+                            match field {
+                                (1, proto::Value::Bytes(b)) => key = Some(b),
+                                (2, proto::Value::Bytes(b)) => value = Some(b),
+                                _ => {}
+                            }
+                        }
+                        if let Some(key) = key {
+                            metadata.push((key, value.unwrap_or(&[])));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if domain != Some(ERROR_DOMAIN.as_bytes()) {
+                continue;
+            }
+            for (key, value) in metadata {
+                let value = String::from_utf8_lossy(value).into_owned();
+                match key {
+                    b"kind" => details.kind = Some(value),
+                    b"rule" => details.rule = Some(value),
+                    b"channel_id" => details.channel_id = Some(value),
+                    b"commitment_number" => details.commitment_number = value.parse().ok(),
+                    b"value" => details.value = Some(value),
+                    b"allowed" => details.allowed = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        details
+    }
+
+    // The known fields as `ErrorInfo` metadata entries
+    fn metadata(&self) -> Vec<(&'static str, String)> {
+        let mut out = Vec::new();
+        let mut put = |key: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                out.push((key, value));
+            }
+        };
+        put("kind", self.kind.clone());
+        put("rule", self.rule.clone());
+        put("channel_id", self.channel_id.clone());
+        put("commitment_number", self.commitment_number.map(|n| n.to_string()));
+        put("value", self.value.clone());
+        put("allowed", self.allowed.clone());
+        out
+    }
+}
+
+/// The `ErrorInfo` domain of errors raised by the signer
+pub const ERROR_DOMAIN: &str = "lightning-signer";
+
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+// Just enough of the protobuf wire format for google.rpc.Status, so that the
+// core crate doesn't need prost.
+mod proto {
+    use crate::prelude::*;
+
+    const VARINT: u64 = 0;
+    const FIXED64: u64 = 1;
+    const LENGTH_DELIMITED: u64 = 2;
+    const FIXED32: u64 = 5;
+
+    fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    pub(super) fn put_varint_field(out: &mut Vec<u8>, field: u64, v: u64) {
+        put_varint(out, field << 3 | VARINT);
+        put_varint(out, v);
+    }
+
+    pub(super) fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        put_varint(out, field << 3 | LENGTH_DELIMITED);
+        put_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    pub(super) enum Value<'a> {
+        Bytes(&'a [u8]),
+        // a scalar, which we don't need
+        Other,
+    }
+
+    /// Iterate over the fields of a message, stopping at the first malformed one
+    pub(super) struct Fields<'a> {
+        buf: &'a [u8],
+    }
+
+    impl<'a> Fields<'a> {
+        pub(super) fn new(buf: &'a [u8]) -> Self {
+            Fields { buf }
+        }
+
+        fn varint(&mut self) -> Option<u64> {
+            let mut v = 0u64;
+            for shift in (0..64).step_by(7) {
+                let (b, rest) = self.buf.split_first()?;
+                self.buf = rest;
+                v |= ((b & 0x7f) as u64) << shift;
+                if b & 0x80 == 0 {
+                    return Some(v);
+                }
+            }
+            None
+        }
+
+        fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+            if len > self.buf.len() {
+                return None;
+            }
+            let (head, rest) = self.buf.split_at(len);
+            self.buf = rest;
+            Some(head)
+        }
+    }
+
+    impl<'a> Iterator for Fields<'a> {
+        type Item = (u64, Value<'a>);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.buf.is_empty() {
+                return None;
+            }
+            let field = (|| {
+                let key = self.varint()?;
+                let value = match key & 7 {
+                    VARINT => self.varint().map(|_| Value::Other)?,
+                    FIXED64 => self.take(8).map(|_| Value::Other)?,
+                    LENGTH_DELIMITED => {
+                        let len = self.varint()?;
+                        Value::Bytes(self.take(len as usize)?)
+                    }
+                    FIXED32 => self.take(4).map(|_| Value::Other)?,
+                    _ => return None,
+                };
+                Some((key >> 3, value))
+            })();
+            if field.is_none() {
+                self.buf = &[];
+            }
+            field
+        }
+    }
 }
 
 /// gRPC compatible error status code
@@ -25,6 +285,12 @@ pub enum Code {
     /// Client specified an invalid argument.
     InvalidArgument = 3,
 
+    /// The caller does not have permission to execute the specified operation.
+    PermissionDenied = 7,
+
+    /// Some resource has been exhausted, such as a quota.
+    ResourceExhausted = 8,
+
     /// The system is not in a state required for the operation’s execution.
     FailedPrecondition = 9,
 
@@ -35,7 +301,21 @@ pub enum Code {
 impl Status {
     /// Create a new `Status` with the associated code and message.
     pub fn new(code: Code, message: impl Into<String>) -> Self {
-        Status { code, message: message.into() }
+        Status { code, message: message.into(), details: ErrorDetails::default() }
+    }
+
+    /// Attach machine readable details, keeping any already known
+    pub fn with_details(mut self, details: ErrorDetails) -> Self {
+        self.details.merge(details);
+        self
+    }
+
+    /// Attach the channel id, unless already known
+    pub fn with_channel_id(mut self, channel_id: &impl fmt::Display) -> Self {
+        if self.details.channel_id.is_none() {
+            self.details.channel_id = Some(channel_id.to_string());
+        }
+        self
     }
 
     /// Get the gRPC `Code` of this `Status`.
@@ -48,11 +328,26 @@ impl Status {
         &self.message
     }
 
+    /// Get the machine readable details of this `Status`.
+    pub fn details(&self) -> &ErrorDetails {
+        &self.details
+    }
+
     /// Construct an invalid argument status
     pub fn invalid_argument(message: impl Into<String>) -> Status {
         Self::new(Code::InvalidArgument, message)
     }
 
+    /// Construct a permission denied status
+    pub fn permission_denied(message: impl Into<String>) -> Status {
+        Self::new(Code::PermissionDenied, message)
+    }
+
+    /// Construct a resource exhausted status, used for quotas
+    pub fn resource_exhausted(message: impl Into<String>) -> Status {
+        Self::new(Code::ResourceExhausted, message)
+    }
+
     /// Construct a failed precondition status, used for policy violation
     pub fn failed_precondition(message: impl Into<String>) -> Status {
         Self::new(Code::FailedPrecondition, message)
@@ -75,6 +370,10 @@ impl fmt::Debug for Status {
             builder.field("message", &self.message);
         }
 
+        if !self.details.is_empty() {
+            builder.field("details", &self.details);
+        }
+
         builder.finish()
     }
 }
@@ -95,7 +394,8 @@ use core::convert::TryInto;
 impl From<Status> for tonic::Status {
     fn from(s: Status) -> Self {
         let code = s.code() as i32;
-        tonic::Status::new(code.try_into().unwrap(), s.message())
+        let details = bytes::Bytes::from(s.details().encode_status(code, s.message()));
+        tonic::Status::with_details(code.try_into().unwrap(), s.message(), details)
     }
 }
 
@@ -108,6 +408,20 @@ pub fn invalid_argument(msg: impl Into<String>) -> Status {
     Status::invalid_argument(s)
 }
 
+/// The caller is not allowed to access a resource
+pub fn permission_denied(msg: impl Into<String>) -> Status {
+    let s = msg.into();
+    error!("PERMISSION DENIED: {}", &s);
+    Status::permission_denied(s)
+}
+
+/// A quota was exceeded
+pub fn resource_exhausted(msg: impl Into<String>) -> Status {
+    let s = msg.into();
+    error!("RESOURCE EXHAUSTED: {}", &s);
+    Status::resource_exhausted(s)
+}
+
 pub(crate) fn internal_error(msg: impl Into<String>) -> Status {
     let s = msg.into();
     error!("INTERNAL ERROR: {}", &s);
@@ -130,6 +444,56 @@ impl From<ValidationError> for Status {
         error!("FAILED PRECONDITION: {}", &s);
         #[cfg(feature = "backtrace")]
         error!("BACKTRACE:\n{:?}", &ve.resolved_backtrace());
-        Status::failed_precondition(s)
+        Status::failed_precondition(s).with_details(ve.details)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_details_encode_test() {
+        let details = ErrorDetails {
+            kind: Some("policy".to_string()),
+            rule: Some("policy-commitment-htlc-count-limit".to_string()),
+            channel_id: None,
+            commitment_number: Some(42),
+            value: Some("two\nlines".to_string()),
+            allowed: Some("1000".to_string()),
+        };
+        let encoded = details.encode_status(Code::FailedPrecondition as i32, "oops");
+        assert_eq!(ErrorDetails::decode_status(&encoded), details);
+        // truncated or foreign input yields no details
+        assert!(ErrorDetails::decode_status(&encoded[..encoded.len() - 1]).is_empty());
+        assert!(ErrorDetails::decode_status(b"garbage\nrule=x").is_empty());
+    }
+
+    #[test]
+    fn error_details_wire_format_test() {
+        let details = ErrorDetails { rule: Some("r".to_string()), ..Default::default() };
+        let encoded = details.encode_status(9, "m");
+        let mut info = vec![0x0a, 1, b'r', 0x12, 16];
+        info.extend_from_slice(b"lightning-signer");
+        info.extend_from_slice(&[0x1a, 9, 0x0a, 4]);
+        info.extend_from_slice(b"rule");
+        info.extend_from_slice(&[0x12, 1, b'r']);
+        let mut any = vec![0x0a, 40];
+        any.extend_from_slice(b"type.googleapis.com/google.rpc.ErrorInfo");
+        any.extend_from_slice(&[0x12, info.len() as u8]);
+        any.extend_from_slice(&info);
+        let mut expected = vec![0x08, 9, 0x12, 1, b'm', 0x1a, any.len() as u8];
+        expected.extend_from_slice(&any);
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn status_details_test() {
+        let status = Status::failed_precondition("oops")
+            .with_details(ErrorDetails { rule: Some("a".to_string()), ..Default::default() })
+            .with_channel_id(&"0011")
+            .with_details(ErrorDetails { rule: Some("b".to_string()), ..Default::default() });
+        assert_eq!(status.details().rule.as_deref(), Some("a"));
+        assert_eq!(status.details().channel_id.as_deref(), Some("0011"));
     }
 }
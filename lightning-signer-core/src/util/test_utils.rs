@@ -252,6 +252,7 @@ pub fn make_test_chain_state() -> ChainState {
         funding_depth: 0,
         funding_double_spent_depth: 0,
         closing_depth: 0,
+        splice_depth: 0,
     }
 }
 
@@ -267,6 +268,7 @@ pub fn make_test_channel_setup() -> ChannelSetup {
         counterparty_selected_contest_delay: 7,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::StaticRemoteKey,
+        holder_funding_sat: None,
     }
 }
 
@@ -549,6 +551,7 @@ pub fn test_chan_ctx_with_push_val(
         counterparty_selected_contest_delay: 7,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::StaticRemoteKey,
+        holder_funding_sat: None,
     };
 
     node_ctx.node.new_channel(Some(channel_id.clone()), &node_ctx.node).expect("new_channel");
@@ -766,6 +769,7 @@ pub fn funding_tx_sign(
         &tx_ctx.ispnds,
         tx_ctx.iuckeys.clone(),
         &tx_ctx.opaths,
+        &vec![],
     )
 }
 
@@ -979,7 +983,7 @@ pub fn counterparty_sign_holder_commitment(
             for htlc in tx.htlcs() {
                 let htlc_tx = build_htlc_transaction(
                     &commitment_txid,
-                    tx.feerate_per_kw(),
+                    chan_ctx.setup.htlc_feerate_per_kw(tx.feerate_per_kw()),
                     chan_ctx.setup.counterparty_selected_contest_delay,
                     htlc,
                     chan_ctx.setup.option_anchor_outputs(),
@@ -1616,6 +1620,7 @@ pub fn create_test_channel_setup(dummy_pubkey: PublicKey) -> ChannelSetup {
         counterparty_selected_contest_delay: 11,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::Legacy,
+        holder_funding_sat: None,
     }
 }
 
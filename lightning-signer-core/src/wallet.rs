@@ -20,4 +20,7 @@ pub trait Wallet {
 
     /// Returns the wrapped segwit address at path
     fn get_wrapped_address(&self, child_path: &Vec<u32>) -> Result<Address, Status>;
+
+    /// Returns the taproot (BIP86 key path only) address at path
+    fn get_taproot_address(&self, child_path: &Vec<u32>) -> Result<Address, Status>;
 }
@@ -43,6 +43,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "InputDescriptor.redeem_script",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
         )
+        .field_attribute(
+            "InputDescriptor.prev_tx",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
         .field_attribute(
             "OutputDescriptor.witscript",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
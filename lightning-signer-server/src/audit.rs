@@ -0,0 +1,212 @@
+//! File storage for the signer audit log
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use lightning_signer::util::audit::{verify, AuditChain, AuditEntry, AuditRecord, AuditSink};
+use log::{error, warn};
+
+/// An append-only audit log file, one entry per line
+pub struct FileAuditSink {
+    state: Mutex<(fs::File, AuditChain)>,
+}
+
+impl FileAuditSink {
+    /// Open or create the log, continuing the hash chain of an existing log.
+    ///
+    /// The existing log is verified first.  A partially written last line, left
+    /// by a crash in the middle of an append, is truncated.  Any other damage
+    /// to the log is an error, so that a tampered log is not extended.
+    pub fn new(path: &str) -> io::Result<Self> {
+        let mut chain = AuditChain::new();
+        match fs::read(path) {
+            Ok(data) => {
+                let complete_len = data.iter().rposition(|b| *b == b'\n').map(|i| i + 1);
+                let complete_len = complete_len.unwrap_or(0);
+                if complete_len < data.len() {
+                    warn!(
+                        "truncating {} bytes of partially written entry from audit log {}",
+                        data.len() - complete_len,
+                        path
+                    );
+                    let file = OpenOptions::new().write(true).open(path)?;
+                    file.set_len(complete_len as u64)?;
+                    file.sync_all()?;
+                }
+                let text = std::str::from_utf8(&data[..complete_len])
+                    .map_err(|e| invalid_data(format!("audit log {}: {}", path, e)))?;
+                let lines: Vec<&str> = text.lines().collect();
+                verify(lines.iter().cloned())
+                    .map_err(|e| invalid_data(format!("audit log {}: {}", path, e)))?;
+                if let Some(line) = lines.last() {
+                    let entry = AuditEntry::from_line(line)
+                        .map_err(|e| invalid_data(format!("audit log {}: {}", path, e)))?;
+                    chain = AuditChain::resume(&entry);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileAuditSink { state: Mutex::new((file, chain)) })
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl AuditSink for FileAuditSink {
+    fn record(&self, record: AuditRecord) {
+        let mut state = self.state.lock().unwrap();
+        let (file, chain) = &mut *state;
+        let entry = chain.append(record);
+        // the log must not silently lose entries
+        writeln!(file, "{}", entry.to_line()).and_then(|_| file.sync_data()).unwrap_or_else(|e| {
+            error!("failed to write audit log: {}", e);
+            panic!("failed to write audit log: {}", e)
+        });
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub use grpc::AuditService;
+
+#[cfg(feature = "grpc")]
+mod grpc {
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use tonic::body::BoxBody;
+    use tonic::codegen::{http, BoxFuture, Service};
+    use tonic::transport::{Body, NamedService};
+    use tonic::Code;
+
+    use lightning_signer::util::audit::{AuditRecord, AuditSink, Decision};
+
+    /// A gRPC service wrapper which records each request in the audit log, if any.
+    ///
+    /// Only the method and the outcome are recorded, since the request body is
+    /// opaque at this layer.
+    #[derive(Clone)]
+    pub struct AuditService<S> {
+        inner: S,
+        sink: Option<Arc<dyn AuditSink>>,
+    }
+
+    impl<S> AuditService<S> {
+        /// Wrap a service
+        pub fn new(inner: S, sink: Option<Arc<dyn AuditSink>>) -> Self {
+            AuditService { inner, sink }
+        }
+    }
+
+    impl<S: NamedService> NamedService for AuditService<S> {
+        const NAME: &'static str = S::NAME;
+    }
+
+    impl<S> Service<http::Request<Body>> for AuditService<S>
+    where
+        S: Service<http::Request<Body>, Response = http::Response<BoxBody>>,
+        S::Future: Send + 'static,
+        S::Error: Send + 'static,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+            let message = req.uri().path().rsplit('/').next().unwrap_or("").to_string();
+            let fut = self.inner.call(req);
+            let sink = match self.sink.as_ref() {
+                Some(sink) => Arc::clone(sink),
+                None => return Box::pin(fut),
+            };
+            Box::pin(async move {
+                let response = fut.await?;
+                // Errors are returned as trailers-only responses, so the status is in the headers
+                let decision = match tonic::Status::from_header_map(response.headers()) {
+                    Some(status) if status.code() != Code::Ok =>
+                        Decision::Rejected(status.message().to_string()),
+                    _ => Decision::Allowed,
+                };
+                sink.record(AuditRecord {
+                    client_id: 0,
+                    dbid: 0,
+                    channel_id: None,
+                    message,
+                    txid: None,
+                    commitment_number: None,
+                    decision,
+                });
+                Ok(response)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lightning_signer::util::audit::Decision;
+    use std::fs::read_to_string;
+
+    fn make_record(message: &str) -> AuditRecord {
+        AuditRecord {
+            client_id: 0,
+            dbid: 0,
+            channel_id: None,
+            message: message.to_string(),
+            txid: None,
+            commitment_number: None,
+            decision: Decision::Allowed,
+        }
+    }
+
+    #[test]
+    fn resume_and_torn_tail_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let path = path.to_str().unwrap();
+
+        let sink = FileAuditSink::new(path).unwrap();
+        sink.record(make_record("SignOnchainTx"));
+        sink.record(make_record("SignInvoice"));
+        drop(sink);
+
+        // a crash in the middle of an append
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"2\t00ff").unwrap();
+        drop(file);
+
+        let sink = FileAuditSink::new(path).unwrap();
+        sink.record(make_record("SignMessage"));
+        drop(sink);
+
+        let log = read_to_string(path).unwrap();
+        assert_eq!(verify(log.lines()), Ok(3));
+        assert!(log.ends_with('\n'));
+    }
+
+    #[test]
+    fn tampered_log_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let path = path.to_str().unwrap();
+
+        let sink = FileAuditSink::new(path).unwrap();
+        sink.record(make_record("SignOnchainTx"));
+        sink.record(make_record("SignInvoice"));
+        drop(sink);
+
+        let log = read_to_string(path).unwrap();
+        fs::write(path, log.replace("SignOnchainTx", "SignMessage")).unwrap();
+        let err = FileAuditSink::new(path).err().expect("tampered log accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
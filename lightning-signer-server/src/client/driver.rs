@@ -1,25 +1,144 @@
-use tonic::{transport, Request};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::{transport, Request, Status};
 
+use remotesigner::admin_client::AdminClient;
 use remotesigner::signer_client::SignerClient;
 
+use crate::server::admin_auth::{self, AdminAuth};
+
 use crate::server::remotesigner;
 use crate::server::remotesigner::node_config::KeyDerivationStyle;
 use crate::server::remotesigner::{
-    AddAllowlistRequest, Bip32Seed, ChainParams, ChannelNonce, GetPerCommitmentPointRequest,
-    InitRequest, ListAllowlistRequest, ListChannelsRequest, ListNodesRequest, NewChannelRequest,
-    NodeConfig, NodeId, PingRequest, RemoveAllowlistRequest,
+    AddAllowlistRequest, Bip32Seed, ChainParams, ChannelNonce, ForgetChannelRequest,
+    GetBalanceReportRequest, GetChannelInfoRequest, GetNodeInfoRequest,
+    GetPerCommitmentPointRequest, GetPolicyRequest, GetRoutingIncomeReportRequest,
+    GetTrackerInfoRequest, InitRequest, ListAllowlistRequest, ListChannelsRequest,
+    ListNodesRequest, NewChannelRequest, NodeConfig, NodeId, PingRequest, RemoveAllowlistRequest,
+    SignMessageRequest,
 };
 
 use bip39::{Language, Mnemonic};
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1};
 use rand::{OsRng, Rng};
+use serde::Serialize;
+
+/// A client of the admin service, presenting the operator token
+pub type AdminSignerClient = AdminClient<InterceptedService<transport::Channel, AdminAuth>>;
 
-pub async fn connect() -> Result<SignerClient<transport::Channel>, Box<dyn std::error::Error>> {
-    Ok(SignerClient::connect("http://127.0.0.1:50051").await?)
+/// Presents the tenant token to a multi-tenant signer service, if there is one
+#[derive(Clone)]
+pub struct TenantAuth(Option<AdminAuth>);
+
+impl Interceptor for TenantAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        match &mut self.0 {
+            Some(auth) => auth.call(request),
+            None => Ok(request),
+        }
+    }
 }
 
-pub async fn ping(
-    client: &mut SignerClient<transport::Channel>,
-) -> Result<(), Box<dyn std::error::Error>> {
+/// A client of the signer service
+pub type TenantSignerClient = SignerClient<InterceptedService<transport::Channel, TenantAuth>>;
+
+pub async fn connect(
+    tenant_token_path: Option<&str>,
+) -> Result<TenantSignerClient, Box<dyn std::error::Error>> {
+    let auth = match tenant_token_path {
+        Some(path) => {
+            let token = admin_auth::read_token(path)
+                .map_err(|e| format!("could not read tenant token from {}: {}", path, e))?;
+            Some(AdminAuth::new(&token)?)
+        }
+        None => None,
+    };
+    let channel = transport::Endpoint::from_static("http://127.0.0.1:50051").connect().await?;
+    Ok(SignerClient::with_interceptor(channel, TenantAuth(auth)))
+}
+
+pub async fn connect_admin(
+    token_path: &str,
+) -> Result<AdminSignerClient, Box<dyn std::error::Error>> {
+    let token = admin_auth::read_token(token_path)
+        .map_err(|e| format!("could not read admin token from {}: {}", token_path, e))?;
+    let channel = transport::Endpoint::from_shared(format!(
+        "http://127.0.0.1:{}",
+        admin_auth::DEFAULT_ADMIN_PORT
+    ))?
+    .connect()
+    .await?;
+    Ok(AdminClient::with_interceptor(channel, AdminAuth::new(&token)?))
+}
+
+/// Print a list of strings, one per line or as a JSON array
+fn print_list(items: &[String], json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(items)?);
+    } else {
+        for item in items {
+            println!("{}", item);
+        }
+    }
+    Ok(())
+}
+
+/// Print named values, as `name value` lines or as a JSON object
+fn print_fields(fields: &[(&str, String)], json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if json {
+        let object: serde_json::Map<String, serde_json::Value> =
+            fields.iter().map(|(name, value)| (name.to_string(), value.clone().into())).collect();
+        println!("{}", serde_json::to_string_pretty(&object)?);
+    } else {
+        for (name, value) in fields {
+            println!("{} {}", name, value);
+        }
+    }
+    Ok(())
+}
+
+/// Print a flat message as `name value` lines or as a JSON object
+fn print_message<T: Serialize>(message: &T, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(message)?);
+    } else if let serde_json::Value::Object(object) = serde_json::to_value(message)? {
+        for (name, value) in object {
+            println!("{} {}", name, value);
+        }
+    }
+    Ok(())
+}
+
+/// Print a nested message as `name value` lines, with dotted names for
+/// nested fields and indexes for repeated fields
+fn print_flattened(name: &str, value: &serde_json::Value) {
+    let join = |field: &str| {
+        if name.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", name, field)
+        }
+    };
+    match value {
+        serde_json::Value::Object(object) =>
+            for (field, value) in object {
+                print_flattened(&join(field), value);
+            },
+        serde_json::Value::Array(items) =>
+            for (i, item) in items.iter().enumerate() {
+                print_flattened(&join(&i.to_string()), item);
+            },
+        // Absent optional fields
+        serde_json::Value::Null => {}
+        serde_json::Value::String(value) => println!("{} {}", name, value),
+        value => println!("{} {}", name, value),
+    }
+}
+
+pub async fn ping(client: &mut TenantSignerClient) -> Result<(), Box<dyn std::error::Error>> {
     let ping_request = Request::new(PingRequest { message: "hello".into() });
 
     let response = client.ping(ping_request).await?;
@@ -29,23 +148,27 @@ pub async fn ping(
 }
 
 pub async fn new_node(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut AdminSignerClient,
     network_name: String,
+    tenant: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mnemonic = Mnemonic::generate_in(Language::English, 12).unwrap();
-    new_node_with_mnemonic(client, mnemonic, network_name).await
+    new_node_with_mnemonic(client, mnemonic, network_name, tenant).await
 }
 
 pub async fn new_node_with_mnemonic(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut AdminSignerClient,
     mnemonic: Mnemonic,
     network_name: String,
+    tenant: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let secret = mnemonic.to_seed("");
     let init_request = Request::new(InitRequest {
         node_config: Some(NodeConfig { key_derivation_style: KeyDerivationStyle::Native as i32 }),
         chainparams: Some(ChainParams { network_name }),
         coldstart: true,
+        node_id: None,
+        tenant,
         hsm_secret: Some(Bip32Seed { data: secret.to_vec() }),
     });
 
@@ -57,55 +180,271 @@ pub async fn new_node_with_mnemonic(
     Ok(())
 }
 
+/// Create a node with a seed generated by the signer.  The mnemonic is only
+/// shown to the signer operator.
+pub async fn new_node_with_signer_seed(
+    client: &mut AdminSignerClient,
+    network_name: String,
+    tenant: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let init_request = Request::new(InitRequest {
+        node_config: Some(NodeConfig { key_derivation_style: KeyDerivationStyle::Native as i32 }),
+        chainparams: Some(ChainParams { network_name }),
+        coldstart: true,
+        node_id: None,
+        tenant,
+        hsm_secret: None,
+    });
+
+    let response = client.init(init_request).await?;
+    let node_id = response.into_inner().node_id.expect("missing node_id").data;
+
+    eprintln!("mnemonic: see the signer console or mnemonic file");
+    println!("{}", hex::encode(&node_id));
+    Ok(())
+}
+
 pub async fn list_nodes(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut AdminSignerClient,
+    tenant: String,
+    json: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let list_request = Request::new(ListNodesRequest {});
+    let list_request = Request::new(ListNodesRequest { tenant });
 
     let response = client.list_nodes(list_request).await?.into_inner();
-    let mut node_ids: Vec<&Vec<u8>> = response.node_ids.iter().map(|id| &id.data).collect();
+    let mut node_ids: Vec<String> =
+        response.node_ids.iter().map(|id| hex::encode(&id.data)).collect();
     node_ids.sort();
+    print_list(&node_ids, json)
+}
+
+pub async fn get_node_info(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let info_request =
+        Request::new(GetNodeInfoRequest { node_id: Some(NodeId { data: node_id.clone() }) });
+
+    let response = client.get_node_info(info_request).await?.into_inner();
+    let xpub = response.xpub.map(|x| x.encoded).unwrap_or_default();
+    let bolt12_pubkey = response.bolt12_pubkey.map(|k| hex::encode(&k.data)).unwrap_or_default();
+    print_fields(
+        &[
+            ("node_id", hex::encode(&node_id)),
+            ("network", response.network_name),
+            ("xpub", xpub),
+            ("bolt12_pubkey", bolt12_pubkey),
+        ],
+        json,
+    )
+}
+
+pub async fn sign_message(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+    message: &str,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sign_request = Request::new(SignMessageRequest {
+        node_id: Some(NodeId { data: node_id }),
+        message: message.as_bytes().to_vec(),
+    });
+
+    let response = client.sign_message(sign_request).await?.into_inner();
+    let signature = response.signature.ok_or("missing signature")?.data;
+    print_fields(&[("signature", hex::encode(&signature))], json)
+}
+
+/// Whether `signature` is a recoverable signature of `message` by `node_id`,
+/// as made by the SignMessage RPC
+pub fn verify_message(
+    node_id: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if signature.len() != 65 {
+        return Err(format!("signature must be 65 bytes, got {}", signature.len()).into());
+    }
+    let node_id = PublicKey::from_slice(node_id)?;
+    let mut buffer = b"Lightning Signed Message:".to_vec();
+    buffer.extend(message);
+    let hash = Sha256dHash::hash(&buffer);
+    let encmsg = secp256k1::Message::from_slice(&hash[..])?;
+    let recovery_id = RecoveryId::from_i32(signature[64] as i32)?;
+    let sig = RecoverableSignature::from_compact(&signature[..64], recovery_id)?;
+    let signer = Secp256k1::verification_only().recover_ecdsa(&encmsg, &sig)?;
+    Ok(signer == node_id)
+}
+
+pub async fn get_policy(
+    client: &mut AdminSignerClient,
+    node_id: Vec<u8>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let policy_request = Request::new(GetPolicyRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.get_policy(policy_request).await?.into_inner();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+    } else {
+        print_message(&response.policy.ok_or("missing policy")?, false)?;
+        for change in response.history {
+            println!("changed by {} at {}", change.changed_by, change.timestamp);
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_routing_income_report(
+    client: &mut AdminSignerClient,
+    node_id: Vec<u8>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report_request =
+        Request::new(GetRoutingIncomeReportRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.get_routing_income_report(report_request).await?.into_inner();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return Ok(());
+    }
+    for income in &response.channels {
+        let nonce = income.channel_nonce.as_ref().map(|n| hex::encode(&n.data)).unwrap_or_default();
+        println!(
+            "channel {} forwards {} forwarded_sat {} fees_sat {}",
+            nonce, income.forwards, income.forwarded_sat, income.fees_sat
+        );
+    }
+    print_fields(
+        &[
+            ("total_fees_sat", response.total_fees_sat.to_string()),
+            ("excess_amount_sat", response.excess_amount_sat.to_string()),
+            ("drained_amount_sat", response.drained_amount_sat.to_string()),
+        ],
+        false,
+    )
+}
+
+pub async fn get_tracker_tip(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let info_request =
+        Request::new(GetTrackerInfoRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.get_tracker_info(info_request).await?.into_inner();
+    print_fields(
+        &[("height", response.height.to_string()), ("tip_block_hash", response.tip_block_hash)],
+        json,
+    )
+}
+
+pub async fn list_tracker_watches(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let info_request =
+        Request::new(GetTrackerInfoRequest { node_id: Some(NodeId { data: node_id }) });
 
-    for node_id in node_ids {
-        println!("{}", hex::encode(node_id));
+    let response = client.get_tracker_info(info_request).await?.into_inner();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response.watches)?);
+        return Ok(());
+    }
+    for watch in response.watches {
+        println!("channel {}", watch.funding_outpoint);
+        for txid in watch.txids {
+            println!("  txid {}", txid);
+        }
+        for outpoint in watch.outpoints {
+            println!("  outpoint {}", outpoint);
+        }
+        for outpoint in watch.seen {
+            println!("  seen {}", outpoint);
+        }
     }
     Ok(())
 }
 
 pub async fn list_channels(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut TenantSignerClient,
     node_id: Vec<u8>,
+    archived: bool,
+    json: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let list_request =
-        Request::new(ListChannelsRequest { node_id: Some(NodeId { data: node_id }) });
+        Request::new(ListChannelsRequest { node_id: Some(NodeId { data: node_id }), archived });
 
     let response = client.list_channels(list_request).await?.into_inner();
-    let mut channel_ids: Vec<&Vec<u8>> =
-        response.channel_nonces.iter().map(|id| &id.data).collect();
-    channel_ids.sort();
+    let mut channel_nonces: Vec<String> =
+        response.channel_nonces.iter().map(|id| hex::encode(&id.data)).collect();
+    channel_nonces.sort();
+    print_list(&channel_nonces, json)
+}
 
-    for channel_nonce in channel_ids {
-        println!("{}", hex::encode(channel_nonce));
+pub async fn get_channel_info(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+    nonce_hex: &str,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let info_request = Request::new(GetChannelInfoRequest {
+        node_id: Some(NodeId { data: node_id }),
+        channel_nonce: Some(ChannelNonce { data: hex::decode(nonce_hex)? }),
+    });
+
+    let response = client.get_channel_info(info_request).await?.into_inner();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+    } else {
+        print_flattened("", &serde_json::to_value(&response)?);
     }
     Ok(())
 }
 
+pub async fn forget_channel(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+    nonce_hex: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let forget_request = Request::new(ForgetChannelRequest {
+        node_id: Some(NodeId { data: node_id }),
+        channel_nonce: Some(ChannelNonce { data: hex::decode(nonce_hex)? }),
+    });
+
+    client.forget_channel(forget_request).await?;
+    Ok(())
+}
+
+pub async fn get_balance_report(
+    client: &mut TenantSignerClient,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report_request =
+        Request::new(GetBalanceReportRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.get_balance_report(report_request).await?.into_inner();
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(())
+}
+
 pub async fn list_allowlist(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut AdminSignerClient,
     node_id: Vec<u8>,
+    json: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let list_request =
         Request::new(ListAllowlistRequest { node_id: Some(NodeId { data: node_id }) });
 
     let response = client.list_allowlist(list_request).await?.into_inner();
-    for addr in response.addresses {
-        println!("{}", addr);
-    }
-    Ok(())
+    print_list(&response.addresses, json)
 }
 
 pub async fn add_allowlist(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut AdminSignerClient,
     node_id: Vec<u8>,
     addresses: Vec<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -117,7 +456,7 @@ pub async fn add_allowlist(
 }
 
 pub async fn remove_allowlist(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut AdminSignerClient,
     node_id: Vec<u8>,
     addresses: Vec<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -129,7 +468,7 @@ pub async fn remove_allowlist(
 }
 
 pub async fn new_channel(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut TenantSignerClient,
     node_id: Vec<u8>,
     nonce_hex: Option<&str>,
     no_nonce: bool,
@@ -159,7 +498,8 @@ pub async fn new_channel(
 }
 
 pub async fn integration_test(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut TenantSignerClient,
+    admin_client: &mut AdminSignerClient,
 ) -> Result<(), Box<dyn std::error::Error>> {
     ping(client).await?;
 
@@ -167,10 +507,12 @@ pub async fn integration_test(
         node_config: Some(NodeConfig { key_derivation_style: KeyDerivationStyle::Native as i32 }),
         chainparams: None,
         coldstart: true,
+        node_id: None,
+        tenant: String::new(),
         hsm_secret: Some(Bip32Seed { data: vec![0u8; 32] }),
     });
 
-    let response = client.init(init_request).await?;
+    let response = admin_client.init(init_request).await?;
     let node_id = response.into_inner().node_id.expect("missing node_id").data;
 
     println!("new node {}", hex::encode(&node_id));
@@ -203,3 +545,22 @@ pub async fn integration_test(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lightning_signer::util::test_utils::{init_node, TEST_NODE_CONFIG, TEST_SEED};
+
+    #[test]
+    fn verify_message_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let node_id = node.get_id().serialize();
+        let other_node_id = init_node(TEST_NODE_CONFIG, TEST_SEED[0]).get_id().serialize();
+        let message = b"hello".to_vec();
+        let signature = node.sign_message(&message).unwrap();
+        assert!(verify_message(&node_id, &message, &signature).unwrap());
+        assert!(!verify_message(&node_id, b"goodbye", &signature).unwrap());
+        assert!(!verify_message(&other_node_id, &message, &signature).unwrap());
+        assert!(verify_message(&node_id, &message, &signature[..64]).is_err());
+    }
+}
@@ -15,10 +15,12 @@ fn make_test_subapp() -> App<'static> {
 
 #[tokio::main]
 async fn test_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
+    let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
+    let mut admin_client = driver::connect_admin(matches.value_of("admin-cookie").unwrap()).await?;
 
     match matches.subcommand() {
-        Some(("integration", _)) => driver::integration_test(&mut client).await?,
+        Some(("integration", _)) =>
+            driver::integration_test(&mut client, &mut admin_client).await?,
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
             println!("missing sub-command");
@@ -29,8 +31,8 @@ async fn test_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error:
 }
 
 #[tokio::main]
-async fn ping_subcommand() -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
+async fn ping_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
     driver::ping(&mut client).await
 }
 
@@ -45,6 +47,11 @@ fn make_node_subapp() -> App<'static> {
                      .long("mnemonic")
                      .short('m')
                      .takes_value(false))
+                .arg(Arg::new("signer-seed")
+                     .about("let the signer generate the seed, the mnemonic is only shown to the signer operator")
+                     .long("signer-seed")
+                     .conflicts_with("mnemonic")
+                     .takes_value(false))
                 .arg(Arg::new("network")
                      .about("network name")
                      .long("network")
@@ -52,27 +59,114 @@ fn make_node_subapp() -> App<'static> {
                      .possible_values(&NETWORK_NAMES)
                      .default_value(NETWORK_NAMES[0]),
                 )
+                .arg(Arg::new("tenant")
+                     .about("create the node for this tenant of a multi-tenant signer")
+                     .long("tenant")
+                     .takes_value(true)
+                     .default_value(""),
+                )
+        )
+        .subcommand(
+            App::new("list").about("List configured nodes.").arg(
+                Arg::new("tenant")
+                    .about("only list the nodes of this tenant")
+                    .long("tenant")
+                    .takes_value(true)
+                    .default_value(""),
+            ),
+        )
+        .subcommand(
+            App::new("balance")
+                .about("Report the node's balance as seen by the signer, as JSON.  Requires --node."),
+        )
+        .subcommand(
+            App::new("info").about("Show the node ID, network and public keys.  Requires --node."),
+        )
+        .subcommand(
+            App::new("sign-message")
+                .about("Sign a message with the node key.  Requires --node.")
+                .arg(Arg::new("message").takes_value(true).required(true).about("the message")),
+        )
+        .subcommand(
+            App::new("verify-message")
+                .about("Check that a message was signed by the node, failing if not.  Requires --node.")
+                .arg(Arg::new("message").takes_value(true).required(true).about("the message"))
+                .arg(
+                    Arg::new("signature")
+                        .takes_value(true)
+                        .required(true)
+                        .about("the signature, in hex as output by sign-message"),
+                ),
+        )
+        .subcommand(
+            App::new("policy").about("Show the node's active policy and its change history.  Requires --node."),
+        )
+        .subcommand(
+            App::new("routing-income")
+                .about("Report the routing income per channel and the excess amount.  Requires --node."),
         )
-        .subcommand(App::new("list").about("List configured nodes."))
 }
 
 #[tokio::main]
 async fn node_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
+    let json = matches.is_present("json");
+    let node_id = || hex::decode(matches.value_of("node").expect("missing node_id"));
+
+    // These are on the signer service, or local, the rest needs the operator token
+    match matches.subcommand() {
+        Some(("balance", _)) => {
+            let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
+            return driver::get_balance_report(&mut client, node_id()?).await;
+        }
+        Some(("info", _)) => {
+            let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
+            return driver::get_node_info(&mut client, node_id()?, json).await;
+        }
+        Some(("sign-message", submatches)) => {
+            let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
+            let message = submatches.value_of("message").unwrap();
+            return driver::sign_message(&mut client, node_id()?, message, json).await;
+        }
+        Some(("verify-message", submatches)) => {
+            let message = submatches.value_of("message").unwrap();
+            let signature = hex::decode(submatches.value_of("signature").unwrap())?;
+            let valid = driver::verify_message(&node_id()?, message.as_bytes(), &signature)?;
+            if json {
+                println!("{}", serde_json::json!({ "valid": valid }));
+            }
+            if !valid {
+                return Err("signature is not by this node".into());
+            }
+            if !json {
+                println!("valid");
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    let mut client = driver::connect_admin(matches.value_of("admin-cookie").unwrap()).await?;
 
     match matches.subcommand() {
         Some(("new", matches)) => {
             let network_name = matches.value_of_t("network").expect("network");
+            let tenant = matches.value_of_t("tenant").expect("tenant");
             if matches.is_present("mnemonic") {
                 let mut buf = String::new();
                 io::stdin().read_line(&mut buf).expect("stdin");
                 let mnemonic = Mnemonic::parse(buf.trim())?;
-                driver::new_node_with_mnemonic(&mut client, mnemonic, network_name).await?
+                driver::new_node_with_mnemonic(&mut client, mnemonic, network_name, tenant).await?
+            } else if matches.is_present("signer-seed") {
+                driver::new_node_with_signer_seed(&mut client, network_name, tenant).await?
             } else {
-                driver::new_node(&mut client, network_name).await?
+                driver::new_node(&mut client, network_name, tenant).await?
             }
         }
-        Some(("list", _)) => driver::list_nodes(&mut client).await?,
+        Some(("list", matches)) =>
+            driver::list_nodes(&mut client, matches.value_of_t("tenant").expect("tenant"), json)
+                .await?,
+        Some(("policy", _)) => driver::get_policy(&mut client, node_id()?, json).await?,
+        Some(("routing-income", _)) =>
+            driver::get_routing_income_report(&mut client, node_id()?, json).await?,
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
             println!("missing sub-command");
@@ -101,12 +195,29 @@ fn make_chan_subapp() -> App<'static> {
                         .about("optional nonce, otherwise one will be generated and displayed"),
                 ),
         )
-        .subcommand(App::new("list").about("List channels in a node"))
+        .subcommand(
+            App::new("list").about("List channels in a node").arg(
+                Arg::new("archived")
+                    .about("list the channels archived after they were closed and swept")
+                    .long("archived")
+                    .takes_value(false),
+            ),
+        )
+        .subcommand(
+            App::new("info")
+                .about("Show the signer's view of a channel")
+                .arg(Arg::new("nonce").takes_value(true).required(true).about("channel nonce")),
+        )
+        .subcommand(
+            App::new("forget")
+                .about("Forget a channel that never became usable, e.g. after a failed open")
+                .arg(Arg::new("nonce").takes_value(true).required(true).about("channel nonce")),
+        )
 }
 
 #[tokio::main]
 async fn chan_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
+    let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
     // TODO give a nice error message if node_id is missing
     let node_id = hex::decode(matches.value_of("node").expect("missing node_id"))?;
 
@@ -119,7 +230,24 @@ async fn chan_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error:
                 matches.is_present("no-nonce"),
             )
             .await?,
-        Some(("list", _)) => driver::list_channels(&mut client, node_id).await?,
+        Some(("list", submatches)) =>
+            driver::list_channels(
+                &mut client,
+                node_id,
+                submatches.is_present("archived"),
+                matches.is_present("json"),
+            )
+            .await?,
+        Some(("info", submatches)) =>
+            driver::get_channel_info(
+                &mut client,
+                node_id,
+                submatches.value_of("nonce").unwrap(),
+                matches.is_present("json"),
+            )
+            .await?,
+        Some(("forget", matches)) =>
+            driver::forget_channel(&mut client, node_id, matches.value_of("nonce").unwrap()).await?,
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
             println!("missing sub-command");
@@ -154,12 +282,13 @@ fn make_allowlist_subapp() -> App<'static> {
 
 #[tokio::main]
 async fn alst_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
+    let mut client = driver::connect_admin(matches.value_of("admin-cookie").unwrap()).await?;
     // TODO give a nice error message if node_id is missing
     let node_id = hex::decode(matches.value_of("node").expect("missing node_id"))?;
 
     match matches.subcommand() {
-        Some(("list", _)) => driver::list_allowlist(&mut client, node_id).await?,
+        Some(("list", _)) =>
+            driver::list_allowlist(&mut client, node_id, matches.is_present("json")).await?,
         Some(("add", matches)) => {
             let addrs = vec![matches.value_of("address").expect("missing address").to_string()];
             driver::add_allowlist(&mut client, node_id, addrs).await?
@@ -177,11 +306,40 @@ async fn alst_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
+fn make_tracker_subapp() -> App<'static> {
+    App::new("tracker")
+        .about("inspect a node's chain tracker")
+        .subcommand(App::new("tip").about("Show the height and block hash of the tracker's tip"))
+        .subcommand(
+            App::new("watches")
+                .about("List the transactions and outpoints watched for each channel"),
+        )
+}
+
+#[tokio::main]
+async fn tracker_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = driver::connect(matches.value_of("tenant-token-file")).await?;
+    let node_id = hex::decode(matches.value_of("node").expect("missing node_id"))?;
+    let json = matches.is_present("json");
+
+    match matches.subcommand() {
+        Some(("tip", _)) => driver::get_tracker_tip(&mut client, node_id, json).await?,
+        Some(("watches", _)) => driver::list_tracker_watches(&mut client, node_id, json).await?,
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_tracker_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let test_subapp = make_test_subapp();
     let node_subapp = make_node_subapp();
     let chan_subapp = make_chan_subapp();
     let alst_subapp = make_allowlist_subapp();
+    let tracker_subapp = make_tracker_subapp();
     let app = App::new(CLIENT_APP_NAME)
         .about("a CLI utility which communicates with a running Validating Lightning Signer server via gRPC")
         .arg(
@@ -192,19 +350,43 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .global(true)
                 .validator(|v| hex::decode(v)),
         )
+        .arg(
+            Arg::new("admin-cookie")
+                .about("file containing the operator token for the admin service")
+                .long("admin-cookie")
+                .takes_value(true)
+                .global(true)
+                .default_value(".lightning-signer/testnet/.admin_cookie"),
+        )
+        .arg(
+            Arg::new("tenant-token-file")
+                .about("file containing the tenant token for a multi-tenant signer service")
+                .long("tenant-token-file")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("json")
+                .about("output JSON instead of text, for scripting")
+                .long("json")
+                .takes_value(false)
+                .global(true),
+        )
         .subcommand(test_subapp)
         .subcommand(node_subapp)
         .subcommand(chan_subapp)
         .subcommand(alst_subapp)
+        .subcommand(tracker_subapp)
         .subcommand(App::new("ping"));
     let matches = app.clone().get_matches();
 
     match matches.subcommand() {
         Some(("test", submatches)) => test_subcommand(submatches)?,
-        Some(("ping", _)) => ping_subcommand()?,
+        Some(("ping", submatches)) => ping_subcommand(submatches)?,
         Some(("node", submatches)) => node_subcommand(submatches)?,
         Some(("channel", submatches)) => chan_subcommand(submatches)?,
         Some(("allowlist", submatches)) => alst_subcommand(submatches)?,
+        Some(("tracker", submatches)) => tracker_subcommand(submatches)?,
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => panic!("unmatched command?!"),
     };
@@ -10,7 +10,10 @@ extern crate tonic;
 
 use lightning_signer::lightning;
 
+pub mod audit;
 pub mod fslogger;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod persist;
 pub mod util;
 #[macro_use]
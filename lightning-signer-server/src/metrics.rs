@@ -0,0 +1,368 @@
+//! Prometheus metrics, served over HTTP
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use bitcoin::secp256k1::PublicKey;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use log::{error, info};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+use lightning_signer::chain::tracker::ChainTracker;
+use lightning_signer::channel::{Channel, ChannelId, ChannelSlot, ChannelStub};
+use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::{Node, NodeConfig};
+use lightning_signer::persist::{model, Persist};
+use lightning_signer::util::status::ErrorDetails;
+use vls_frontend::FollowerHeights;
+
+lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "vls_requests_total",
+        "Requests handled, by method and result",
+        &["method", "result"]
+    )
+    .unwrap();
+    static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "vls_request_duration_seconds",
+        "Request handling latency, by method",
+        &["method"]
+    )
+    .unwrap();
+    static ref POLICY_VIOLATIONS: IntCounterVec = register_int_counter_vec!(
+        "vls_policy_violations_total",
+        "Policy violations, by rule",
+        &["rule"]
+    )
+    .unwrap();
+    static ref CHANNELS: IntGaugeVec =
+        register_int_gauge_vec!("vls_channels", "Channels, by node and state", &["node", "state"])
+            .unwrap();
+    static ref TRACKER_HEIGHT: IntGaugeVec =
+        register_int_gauge_vec!("vls_tracker_height", "Chain tracker tip height", &["node"])
+            .unwrap();
+    static ref TRACKER_LAG: IntGaugeVec = register_int_gauge_vec!(
+        "vls_tracker_lag_blocks",
+        "Blocks the chain tracker is behind the frontend chain source",
+        &["tracker"]
+    )
+    .unwrap();
+    static ref PERSIST_DURATION: HistogramVec = register_histogram_vec!(
+        "vls_persist_duration_seconds",
+        "Persister write latency, by operation",
+        &["op"]
+    )
+    .unwrap();
+}
+
+const CHANNEL_STATES: [&str; 4] = ["stub", "pending", "open", "closing"];
+
+/// Record a handled request
+pub fn observe_request(method: &str, started: Instant, ok: bool) {
+    let result = if ok { "ok" } else { "error" };
+    REQUESTS.with_label_values(&[method, result]).inc();
+    REQUEST_DURATION.with_label_values(&[method]).observe(started.elapsed().as_secs_f64());
+}
+
+/// Record a policy violation, given the details and message of a failed precondition
+pub fn observe_policy_violation(details: &ErrorDetails, message: &str) {
+    let rule = details.rule.clone().unwrap_or_else(|| policy_rule(message));
+    POLICY_VIOLATIONS.with_label_values(&[&rule]).inc();
+}
+
+// Fallback for errors without a rule.
+// Validation errors are formatted as "<kind>: <function>: <details>"
+fn policy_rule(message: &str) -> String {
+    let mut parts = message.splitn(3, ": ");
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(kind), Some(function), Some(_)) => format!("{}: {}", kind, function),
+        (Some(kind), _, _) => kind.to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn channel_state(slot: &ChannelSlot) -> &'static str {
+    match slot {
+        ChannelSlot::Stub(_) => "stub",
+        ChannelSlot::Ready(chan) => {
+            let state = chan.monitor.get_state();
+            if state.closing_height.is_some() {
+                "closing"
+            } else if state.funding_height.is_some() {
+                "open"
+            } else {
+                "pending"
+            }
+        }
+    }
+}
+
+/// Update the channel and chain tracker gauges for a node
+pub fn update_node_gauges(node: &Node) {
+    let node_label = node.log_prefix();
+    let mut counts = [0i64; CHANNEL_STATES.len()];
+    for slot in node.channels().values() {
+        let state = channel_state(&*slot.lock().unwrap());
+        let index = CHANNEL_STATES.iter().position(|s| *s == state).expect("state");
+        counts[index] += 1;
+    }
+    for (state, count) in CHANNEL_STATES.iter().zip(counts.iter()) {
+        CHANNELS.with_label_values(&[&node_label, state]).set(*count);
+    }
+    TRACKER_HEIGHT.with_label_values(&[&node_label]).set(node.get_tracker().height() as i64);
+}
+
+/// Update the tracker lag gauges from the frontend chain followers
+pub fn update_follower_gauges(heights: &[FollowerHeights]) {
+    for h in heights {
+        let lag = h.source_height.saturating_sub(h.tracker_height);
+        TRACKER_LAG.with_label_values(&[&h.tracker]).set(lag as i64);
+    }
+}
+
+/// Serve the metrics on `/metrics` until the server fails.
+///
+/// `refresh` is called before each scrape, to update gauges that are sampled
+/// rather than updated as events happen.
+pub async fn serve(addr: SocketAddr, refresh: Arc<dyn Fn() + Send + Sync>) {
+    let make_svc = make_service_fn(move |_conn| {
+        let refresh = Arc::clone(&refresh);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let refresh = Arc::clone(&refresh);
+                async move { Ok::<_, Infallible>(respond(req, &*refresh)) }
+            }))
+        }
+    });
+    info!("metrics available on http://{}/metrics", addr);
+    if let Err(e) = Server::bind(&addr).serve(make_svc).await {
+        error!("metrics server error: {}", e);
+    }
+}
+
+fn respond(req: Request<Body>, refresh: &(dyn Fn() + Send + Sync)) -> Response<Body> {
+    if req.uri().path() != "/metrics" {
+        let mut not_found = Response::new(Body::empty());
+        *not_found.status_mut() = StatusCode::NOT_FOUND;
+        return not_found;
+    }
+    refresh();
+    let encoder = TextEncoder::new();
+    let mut buf = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buf).expect("encode metrics");
+    Response::builder()
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buf))
+        .expect("metrics response")
+}
+
+/// A persister wrapper which records write latency
+pub struct MetricsPersister {
+    inner: Arc<dyn Persist>,
+}
+
+impl MetricsPersister {
+    /// Wrap a persister
+    pub fn new(inner: Arc<dyn Persist>) -> Self {
+        MetricsPersister { inner }
+    }
+
+    fn timed<T>(&self, op: &str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        PERSIST_DURATION.with_label_values(&[op]).observe(started.elapsed().as_secs_f64());
+        result
+    }
+}
+
+impl Persist for MetricsPersister {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) {
+        self.timed("new_node", || self.inner.new_node(node_id, config, seed))
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) {
+        self.timed("delete_node", || self.inner.delete_node(node_id))
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), ()> {
+        self.timed("new_channel", || self.inner.new_channel(node_id, stub))
+    }
+
+    fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>) {
+        self.timed("new_chain_tracker", || self.inner.new_chain_tracker(node_id, tracker))
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), ()> {
+        self.timed("update_tracker", || self.inner.update_tracker(node_id, tracker))
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, ()> {
+        self.inner.get_tracker(node_id)
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), ()> {
+        self.timed("update_channel", || self.inner.update_channel(node_id, channel))
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<model::ChannelEntry, ()> {
+        self.inner.get_channel(node_id, channel_id)
+    }
+
+    fn get_node_channels(&self, node_id: &PublicKey) -> Vec<(ChannelId, model::ChannelEntry)> {
+        self.inner.get_node_channels(node_id)
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()> {
+        self.timed("delete_channel", || self.inner.delete_channel(node_id, id0))
+    }
+
+    fn archive_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()> {
+        self.timed("archive_channel", || self.inner.archive_channel(node_id, id0))
+    }
+
+    fn get_node_archived_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Vec<(ChannelId, model::ChannelEntry)> {
+        self.inner.get_node_archived_channels(node_id)
+    }
+
+    fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()> {
+        self.timed("update_node_allowlist", || self.inner.update_node_allowlist(node_id, allowlist))
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Vec<String> {
+        self.inner.get_node_allowlist(node_id)
+    }
+
+    fn add_node_policy_change(
+        &self,
+        node_id: &PublicKey,
+        change: &model::PolicyChangeEntry,
+    ) -> Result<(), ()> {
+        self.timed("add_node_policy_change", || self.inner.add_node_policy_change(node_id, change))
+    }
+
+    fn get_node_policy_changes(&self, node_id: &PublicKey) -> Vec<model::PolicyChangeEntry> {
+        self.inner.get_node_policy_changes(node_id)
+    }
+
+    fn update_node_keysends(
+        &self,
+        node_id: &PublicKey,
+        keysends: Vec<model::KeysendEntry>,
+    ) -> Result<(), ()> {
+        self.timed("update_node_keysends", || self.inner.update_node_keysends(node_id, keysends))
+    }
+
+    fn get_node_keysends(&self, node_id: &PublicKey) -> Vec<model::KeysendEntry> {
+        self.inner.get_node_keysends(node_id)
+    }
+
+    fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)> {
+        self.inner.get_nodes()
+    }
+
+    fn clear_database(&self) {
+        self.inner.clear_database()
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub use grpc::MetricsService;
+
+#[cfg(feature = "grpc")]
+mod grpc {
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+
+    use tonic::body::BoxBody;
+    use tonic::codegen::{http, BoxFuture, Service};
+    use tonic::transport::{Body, NamedService};
+    use tonic::Code;
+
+    use lightning_signer::util::status::ErrorDetails;
+
+    /// A gRPC service wrapper which records request metrics
+    #[derive(Clone)]
+    pub struct MetricsService<S> {
+        inner: S,
+    }
+
+    impl<S> MetricsService<S> {
+        /// Wrap a service
+        pub fn new(inner: S) -> Self {
+            MetricsService { inner }
+        }
+    }
+
+    impl<S: NamedService> NamedService for MetricsService<S> {
+        const NAME: &'static str = S::NAME;
+    }
+
+    impl<S> Service<http::Request<Body>> for MetricsService<S>
+    where
+        S: Service<http::Request<Body>, Response = http::Response<BoxBody>>,
+        S::Future: Send + 'static,
+        S::Error: Send + 'static,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+            let method = req.uri().path().rsplit('/').next().unwrap_or("").to_string();
+            let started = Instant::now();
+            let fut = self.inner.call(req);
+            Box::pin(async move {
+                let response = fut.await?;
+                // Errors are returned as trailers-only responses, so the status is in the headers
+                let status = tonic::Status::from_header_map(response.headers());
+                let ok = status.as_ref().map(|s| s.code() == Code::Ok).unwrap_or(true);
+                if let Some(status) = status.as_ref() {
+                    if status.code() == Code::FailedPrecondition {
+                        let details = ErrorDetails::decode_status(status.details());
+                        super::observe_policy_violation(&details, status.message());
+                    }
+                }
+                super::observe_request(&method, started, ok);
+                Ok(response)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_rule_test() {
+        assert_eq!(
+            policy_rule("policy failure: validate_onchain_tx: fee below minimum: 10 < 100"),
+            "policy failure: validate_onchain_tx"
+        );
+        assert_eq!(policy_rule("unbalanced payments:  00ff"), "unbalanced payments");
+        assert_eq!(policy_rule(""), "");
+    }
+}
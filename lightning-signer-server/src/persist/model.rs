@@ -3,6 +3,7 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::iter::FromIterator;
 
+use crate::lightning::ln::PaymentHash;
 use bitcoin::consensus::{deserialize, serialize};
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{Network, OutPoint};
@@ -17,13 +18,15 @@ use lightning_signer::channel::ChannelSetup;
 use lightning_signer::monitor::ChainMonitor;
 use lightning_signer::monitor::State as ChainMonitorState;
 use lightning_signer::persist::model::{
-    ChannelEntry as CoreChannelEntry, NodeEntry as CoreNodeEntry,
+    ChannelEntry as CoreChannelEntry, KeysendEntry as CoreKeysendEntry, NodeEntry as CoreNodeEntry,
+    PolicyChangeEntry as CorePolicyChangeEntry,
 };
+use lightning_signer::policy::simple_validator::SimplePolicy;
 use lightning_signer::policy::validator::EnforcementState;
 
 use super::ser_util::{
     ChainMonitorStateDef, ChannelIdHandler, ChannelSetupDef, EnforcementStateDef, ListenSlotDef,
-    OutPointDef,
+    OutPointDef, PaymentHashDef, PublicKeyHandler, SimplePolicyDef,
 };
 
 #[serde_as]
@@ -56,6 +59,9 @@ pub struct ChannelEntry {
     pub id: Option<ChannelId>,
     #[serde_as(as = "EnforcementStateDef")]
     pub enforcement_state: EnforcementState,
+    // The block height at which a stub was created, if still a stub
+    #[serde(default)]
+    pub blockheight: Option<u32>,
 }
 
 impl From<ChannelEntry> for CoreChannelEntry {
@@ -65,6 +71,7 @@ impl From<ChannelEntry> for CoreChannelEntry {
             channel_setup: e.channel_setup,
             id: e.id,
             enforcement_state: e.enforcement_state,
+            blockheight: e.blockheight,
         }
     }
 }
@@ -75,6 +82,76 @@ pub struct AllowlistItemEntry {
     pub allowlist: Vec<String>,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub struct PolicyChangeEntry {
+    #[serde_as(as = "SimplePolicyDef")]
+    pub policy: SimplePolicy,
+    pub changed_by: String,
+    pub timestamp: u64,
+}
+
+impl From<&CorePolicyChangeEntry> for PolicyChangeEntry {
+    fn from(e: &CorePolicyChangeEntry) -> Self {
+        PolicyChangeEntry {
+            policy: e.policy.clone(),
+            changed_by: e.changed_by.clone(),
+            timestamp: e.timestamp,
+        }
+    }
+}
+
+impl From<PolicyChangeEntry> for CorePolicyChangeEntry {
+    fn from(e: PolicyChangeEntry) -> Self {
+        CorePolicyChangeEntry { policy: e.policy, changed_by: e.changed_by, timestamp: e.timestamp }
+    }
+}
+
+/// The policy history of a node, oldest first
+#[derive(Serialize, Deserialize)]
+pub struct PolicyHistoryEntry {
+    pub changes: Vec<PolicyChangeEntry>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub struct KeysendEntry {
+    #[serde_as(as = "PaymentHashDef")]
+    pub payment_hash: PaymentHash,
+    #[serde_as(as = "PublicKeyHandler")]
+    pub payee: PublicKey,
+    pub amount_msat: u64,
+    pub added_height: u32,
+}
+
+impl From<&CoreKeysendEntry> for KeysendEntry {
+    fn from(e: &CoreKeysendEntry) -> Self {
+        KeysendEntry {
+            payment_hash: e.payment_hash,
+            payee: e.payee,
+            amount_msat: e.amount_msat,
+            added_height: e.added_height,
+        }
+    }
+}
+
+impl From<KeysendEntry> for CoreKeysendEntry {
+    fn from(e: KeysendEntry) -> Self {
+        CoreKeysendEntry {
+            payment_hash: e.payment_hash,
+            payee: e.payee,
+            amount_msat: e.amount_msat,
+            added_height: e.added_height,
+        }
+    }
+}
+
+/// The keysend payments of a node
+#[derive(Serialize, Deserialize)]
+pub struct KeysendListEntry {
+    pub keysends: Vec<KeysendEntry>,
+}
+
 /// Fully qualified channel ID
 #[derive(Clone)]
 pub struct NodeChannelId(Vec<u8>);
@@ -7,7 +7,8 @@ use lightning_signer::channel::{Channel, ChannelId, ChannelStub};
 use lightning_signer::monitor::ChainMonitor;
 use lightning_signer::node::NodeConfig;
 use lightning_signer::persist::model::{
-    ChannelEntry as CoreChannelEntry, NodeEntry as CoreNodeEntry,
+    ChannelEntry as CoreChannelEntry, KeysendEntry as CoreKeysendEntry, NodeEntry as CoreNodeEntry,
+    PolicyChangeEntry as CorePolicyChangeEntry,
 };
 use lightning_signer::persist::Persist;
 use lightning_signer::policy::validator::EnforcementState;
@@ -15,7 +16,10 @@ use log::error;
 
 use crate::persist::model::ChainTrackerEntry;
 use crate::persist::model::NodeChannelId;
-use crate::persist::model::{AllowlistItemEntry, ChannelEntry, NodeEntry};
+use crate::persist::model::{
+    AllowlistItemEntry, ChannelEntry, KeysendEntry, KeysendListEntry, NodeEntry, PolicyChangeEntry,
+    PolicyHistoryEntry,
+};
 
 /// A persister that uses the kv crate and JSON serialization for values.
 pub struct KVJsonPersister<'a> {
@@ -23,6 +27,9 @@ pub struct KVJsonPersister<'a> {
     pub channel_bucket: Bucket<'a, NodeChannelId, Json<ChannelEntry>>,
     pub allowlist_bucket: Bucket<'a, Vec<u8>, Json<AllowlistItemEntry>>,
     pub chain_tracker_bucket: Bucket<'a, Vec<u8>, Json<ChainTrackerEntry>>,
+    pub policy_bucket: Bucket<'a, Vec<u8>, Json<PolicyHistoryEntry>>,
+    pub archive_bucket: Bucket<'a, NodeChannelId, Json<ChannelEntry>>,
+    pub keysend_bucket: Bucket<'a, Vec<u8>, Json<KeysendListEntry>>,
 }
 
 impl KVJsonPersister<'_> {
@@ -34,7 +41,19 @@ impl KVJsonPersister<'_> {
         let allowlist_bucket = store.bucket(Some("allowlists")).expect("create allowlist bucket");
         let chain_tracker_bucket =
             store.bucket(Some("chain_tracker")).expect("create chain tracker bucket");
-        Self { node_bucket, channel_bucket, allowlist_bucket, chain_tracker_bucket }
+        let policy_bucket = store.bucket(Some("policies")).expect("create policy bucket");
+        let archive_bucket =
+            store.bucket(Some("archived_channels")).expect("create archive bucket");
+        let keysend_bucket = store.bucket(Some("keysends")).expect("create keysend bucket");
+        Self {
+            node_bucket,
+            channel_bucket,
+            allowlist_bucket,
+            chain_tracker_bucket,
+            policy_bucket,
+            archive_bucket,
+            keysend_bucket,
+        }
     }
 }
 
@@ -56,8 +75,14 @@ impl<'a> Persist for KVJsonPersister<'a> {
             let id: NodeChannelId = item_res.unwrap().key().unwrap();
             self.channel_bucket.remove(id).unwrap();
         }
+        for item_res in self.archive_bucket.iter_prefix(NodeChannelId::new_prefix(node_id)) {
+            let id: NodeChannelId = item_res.unwrap().key().unwrap();
+            self.archive_bucket.remove(id).unwrap();
+        }
         let key = node_id.serialize().to_vec();
         self.node_bucket.remove(key.clone()).unwrap();
+        self.policy_bucket.remove(key.clone()).unwrap();
+        self.keysend_bucket.remove(key.clone()).unwrap();
         self.chain_tracker_bucket.remove(key).unwrap();
     }
 
@@ -72,6 +97,7 @@ impl<'a> Persist for KVJsonPersister<'a> {
                     channel_setup: None,
                     id: None,
                     enforcement_state: EnforcementState::new(0),
+                    blockheight: Some(stub.blockheight),
                 };
                 if txn.get(id.clone()).unwrap().is_some() {
                     return Err(TransactionError::Abort(kv::Error::Message(
@@ -121,6 +147,7 @@ impl<'a> Persist for KVJsonPersister<'a> {
                     channel_setup: Some(channel.setup.clone()),
                     id: channel.id.clone(),
                     enforcement_state: channel.enforcement_state.clone(),
+                    blockheight: None,
                 };
                 if txn.get(node_channel_id.clone()).unwrap().is_none() {
                     return Err(TransactionError::Abort(kv::Error::Message(
@@ -159,6 +186,41 @@ impl<'a> Persist for KVJsonPersister<'a> {
         res
     }
 
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()> {
+        let id = NodeChannelId::new(node_id, id0);
+        if !self.channel_bucket.contains(id.clone()).unwrap() {
+            return Err(());
+        }
+        self.channel_bucket.remove(id).expect("delete channel");
+        self.channel_bucket.flush().expect("flush");
+        Ok(())
+    }
+
+    fn archive_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), ()> {
+        let id = NodeChannelId::new(node_id, id0);
+        let value = self.channel_bucket.get(id.clone()).unwrap().ok_or_else(|| ())?;
+        // Write the archive first, so that a crash can't lose the channel
+        self.archive_bucket.set(id.clone(), value).expect("archive channel");
+        self.archive_bucket.flush().expect("flush");
+        self.channel_bucket.remove(id).expect("remove archived channel");
+        self.channel_bucket.flush().expect("flush");
+        Ok(())
+    }
+
+    fn get_node_archived_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Vec<(ChannelId, CoreChannelEntry)> {
+        let mut res = Vec::new();
+        for item_res in self.archive_bucket.iter_prefix(NodeChannelId::new_prefix(node_id)) {
+            let item = item_res.unwrap();
+            let value: Json<ChannelEntry> = item.value().unwrap();
+            let key: NodeChannelId = item.key().unwrap();
+            res.push((key.channel_id(), CoreChannelEntry::from(value.0)));
+        }
+        res
+    }
+
     fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()> {
         let key = node_id.serialize().to_vec();
         let entry = AllowlistItemEntry { allowlist };
@@ -183,6 +245,51 @@ impl<'a> Persist for KVJsonPersister<'a> {
         entry2.unwrap().0.allowlist
     }
 
+    fn add_node_policy_change(
+        &self,
+        node_id: &PublicKey,
+        change: &CorePolicyChangeEntry,
+    ) -> Result<(), ()> {
+        let key = node_id.serialize().to_vec();
+        let mut entry = match self.policy_bucket.get(key.clone()).expect("get policy history") {
+            Some(entry) => entry.0,
+            None => PolicyHistoryEntry { changes: Vec::new() },
+        };
+        entry.changes.push(PolicyChangeEntry::from(change));
+        self.policy_bucket.set(key, Json(entry)).expect("update transaction");
+        self.policy_bucket.flush().expect("flush");
+        Ok(())
+    }
+
+    fn get_node_policy_changes(&self, node_id: &PublicKey) -> Vec<CorePolicyChangeEntry> {
+        let key = node_id.serialize().to_vec();
+        match self.policy_bucket.get(key).expect("get policy history") {
+            Some(entry) => entry.0.changes.into_iter().map(CorePolicyChangeEntry::from).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn update_node_keysends(
+        &self,
+        node_id: &PublicKey,
+        keysends: Vec<CoreKeysendEntry>,
+    ) -> Result<(), ()> {
+        let key = node_id.serialize().to_vec();
+        let entry =
+            KeysendListEntry { keysends: keysends.iter().map(KeysendEntry::from).collect() };
+        self.keysend_bucket.set(key, Json(entry)).expect("update transaction");
+        self.keysend_bucket.flush().expect("flush");
+        Ok(())
+    }
+
+    fn get_node_keysends(&self, node_id: &PublicKey) -> Vec<CoreKeysendEntry> {
+        let key = node_id.serialize().to_vec();
+        match self.keysend_bucket.get(key).expect("get keysends") {
+            Some(entry) => entry.0.keysends.into_iter().map(CoreKeysendEntry::from).collect(),
+            None => Vec::new(),
+        }
+    }
+
     fn get_nodes(&self) -> Vec<(PublicKey, CoreNodeEntry)> {
         let mut res = Vec::new();
         for item_res in self.node_bucket.iter() {
@@ -197,7 +304,10 @@ impl<'a> Persist for KVJsonPersister<'a> {
 
     fn clear_database(&self) {
         self.channel_bucket.clear().unwrap();
+        self.archive_bucket.clear().unwrap();
         self.node_bucket.clear().unwrap();
+        self.policy_bucket.clear().unwrap();
+        self.keysend_bucket.clear().unwrap();
     }
 }
 
@@ -207,13 +317,15 @@ mod tests {
 
     use crate::lightning;
     use lightning::chain::keysinterface::InMemorySigner;
+    use lightning::ln::PaymentHash;
     use lightning::util::ser::Writeable;
     use tempfile::TempDir;
     use test_log::test;
 
     use lightning_signer::channel::ChannelSlot;
     use lightning_signer::node::Node;
-    use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+    use lightning_signer::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
+    use lightning_signer::util::key_utils::make_test_pubkey;
     use lightning_signer::util::test_utils::*;
 
     use crate::persist::ser_util::VecWriter;
@@ -230,6 +342,128 @@ mod tests {
         (persister, dir, path_str.to_string())
     }
 
+    #[test]
+    fn round_trip_policy_test() {
+        let channel_id0 = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[0]).unwrap());
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, _stub, seed) = make_node_and_channel(channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+
+        let mut policy = make_simple_policy(TEST_NODE_CONFIG.network);
+        policy.require_invoices = true;
+        let change = CorePolicyChangeEntry {
+            policy: policy.clone(),
+            changed_by: "operator".to_string(),
+            timestamp: 1234,
+        };
+        persister.add_node_policy_change(&node_id, &change).unwrap();
+        assert_eq!(persister.get_node_policy_changes(&node_id), vec![change.clone()]);
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory);
+        let restored_node = nodes.get(&node_id).unwrap();
+        assert_eq!(restored_node.get_policy(), Some(policy));
+        assert_eq!(restored_node.policy_changes(), vec![change]);
+
+        persister.delete_node(&node_id);
+        assert!(persister.get_node_policy_changes(&node_id).is_empty());
+    }
+
+    #[test]
+    fn round_trip_keysend_test() {
+        let channel_id0 = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[0]).unwrap());
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, _stub, seed) = make_node_and_channel(channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+
+        let hash = PaymentHash([1; 32]);
+        let entry = CoreKeysendEntry {
+            payment_hash: hash,
+            payee: make_test_pubkey(11),
+            amount_msat: 1000,
+            added_height: 100,
+        };
+        persister.update_node_keysends(&node_id, vec![entry.clone()]).unwrap();
+        assert_eq!(persister.get_node_keysends(&node_id), vec![entry]);
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory);
+        let restored_node = nodes.get(&node_id).unwrap();
+        {
+            let state = restored_node.get_state();
+            let keysend = state.keysends.get(&hash).unwrap();
+            assert_eq!(keysend.payee, make_test_pubkey(11));
+            assert_eq!(keysend.amount_msat, 1000);
+            assert_eq!(keysend.added_height, 100);
+        }
+
+        persister.delete_node(&node_id);
+        assert!(persister.get_node_keysends(&node_id).is_empty());
+    }
+
+    #[test]
+    fn archive_channel_test() {
+        let channel_id0 = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[0]).unwrap());
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, stub, seed) = make_node_and_channel(channel_id0.clone());
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+        persister.new_channel(&node_id, &stub).unwrap();
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node_arc.ready_channel(channel_id0.clone(), None, setup, &vec![]).unwrap();
+        persister.update_channel(&node_id, &channel).unwrap();
+
+        let unknown_id = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[1]).unwrap());
+        assert!(persister.archive_channel(&node_id, &unknown_id).is_err());
+        persister.archive_channel(&node_id, &channel_id0).unwrap();
+        assert!(persister.get_node_channels(&node_id).is_empty());
+        assert!(persister.get_channel(&node_id, &channel_id0).is_err());
+        let archived = persister.get_node_archived_channels(&node_id);
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].0, channel_id0);
+        let archived_setup = archived[0].1.channel_setup.as_ref().unwrap();
+        assert_eq!(archived_setup.funding_outpoint, channel.setup.funding_outpoint);
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory);
+        assert!(nodes.get(&node_id).unwrap().channels().is_empty());
+
+        persister.delete_node(&node_id);
+        assert!(persister.get_node_archived_channels(&node_id).is_empty());
+    }
+
+    #[test]
+    fn delete_channel_test() {
+        let channel_id0 = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[0]).unwrap());
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, stub, seed) = make_node_and_channel(channel_id0.clone());
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+        persister.new_channel(&node_id, &stub).unwrap();
+        let entry = persister.get_channel(&node_id, &channel_id0).unwrap();
+        assert_eq!(entry.blockheight, Some(stub.blockheight));
+
+        let unknown_id = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[1]).unwrap());
+        assert!(persister.delete_channel(&node_id, &unknown_id).is_err());
+        persister.delete_channel(&node_id, &channel_id0).unwrap();
+        assert!(persister.get_channel(&node_id, &channel_id0).is_err());
+        assert!(persister.delete_channel(&node_id, &channel_id0).is_err());
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory);
+        assert!(nodes.get(&node_id).unwrap().channels().is_empty());
+    }
+
     #[test]
     fn round_trip_signer_test() {
         let channel_id0 = ChannelId::new(&hex_decode(TEST_CHANNEL_ID[0]).unwrap());
@@ -4,6 +4,7 @@
 //! transformation from the remote type - implemented via `From` / `Into`.
 
 use std::borrow::Cow;
+use std::collections::BTreeMap as OrderedMap;
 use std::collections::BTreeSet as Set;
 
 use crate::lightning;
@@ -20,7 +21,8 @@ use serde_with::{DeserializeAs, SerializeAs};
 
 use lightning_signer::channel::{ChannelId, ChannelSetup, CommitmentType};
 use lightning_signer::monitor::State as ChainMonitorState;
-use lightning_signer::policy::validator::EnforcementState;
+use lightning_signer::policy::simple_validator::SimplePolicy;
+use lightning_signer::policy::validator::{EnforcementState, SpliceInfo};
 use lightning_signer::tx::tx::{CommitmentInfo2, HTLCInfo2};
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -173,6 +175,7 @@ pub enum CommitmentTypeDef {
     Legacy,
     StaticRemoteKey,
     Anchors,
+    AnchorsZeroFeeHtlc,
 }
 
 #[derive(Deserialize)]
@@ -246,6 +249,8 @@ pub struct ChannelSetupDef {
     pub counterparty_shutdown_script: Option<Script>,
     #[serde_as(as = "CommitmentTypeDef")]
     pub commitment_type: CommitmentType,
+    #[serde(default)]
+    pub holder_funding_sat: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -368,6 +373,40 @@ impl<'de> DeserializeAs<'de, CommitmentInfo2> for CommitmentInfo2Def {
     }
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SpliceInfo")]
+pub struct SpliceInfoDef {
+    #[serde_as(as = "OutPointDef")]
+    pub prior_funding_outpoint: OutPoint,
+    pub prior_channel_value_sat: u64,
+    pub holder_delta_sat: i64,
+    pub next_holder_commit_num: u64,
+    pub next_counterparty_commit_num: u64,
+    pub holder_input_values: Vec<Option<u64>>,
+}
+
+#[derive(Deserialize)]
+struct SpliceInfoHelper(#[serde(with = "SpliceInfoDef")] SpliceInfo);
+
+impl SerializeAs<SpliceInfo> for SpliceInfoDef {
+    fn serialize_as<S>(value: &SpliceInfo, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        SpliceInfoDef::serialize(value, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, SpliceInfo> for SpliceInfoDef {
+    fn deserialize_as<D>(deserializer: D) -> Result<SpliceInfo, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        SpliceInfoHelper::deserialize(deserializer).map(|h| h.0)
+    }
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "EnforcementState")]
@@ -386,6 +425,9 @@ pub struct EnforcementStateDef {
     pub mutual_close_signed: bool,
     #[serde(default)] // TODO remove default once everyone upgrades
     pub initial_holder_value: u64,
+    #[serde(default)]
+    #[serde_as(as = "Option<SpliceInfoDef>")]
+    pub splice: Option<SpliceInfo>,
 }
 
 #[derive(Deserialize)]
@@ -456,6 +498,11 @@ pub struct ChainMonitorStateDef {
     funding_outpoint: Option<OutPoint>,
     funding_double_spent_height: Option<u32>,
     closing_height: Option<u32>,
+    #[serde_as(as = "Vec<(OutPointDef, _)>")]
+    #[serde(default)]
+    closing_outpoints: OrderedMap<OutPoint, Option<u32>>,
+    #[serde(default)]
+    splice_height: Option<u32>,
 }
 
 #[derive(Deserialize)]
@@ -481,6 +528,58 @@ impl<'de> DeserializeAs<'de, ChainMonitorState> for ChainMonitorStateDef {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SimplePolicy")]
+pub struct SimplePolicyDef {
+    pub min_delay: u16,
+    pub max_delay: u16,
+    pub max_channel_size_sat: u64,
+    pub epsilon_sat: u64,
+    pub max_htlcs: usize,
+    pub max_htlc_value_sat: u64,
+    pub use_chain_state: bool,
+    pub min_feerate_per_kw: u32,
+    pub max_feerate_per_kw: u32,
+    pub min_fee: u64,
+    pub max_fee: u64,
+    pub require_invoices: bool,
+    pub enforce_balance: bool,
+    pub max_routing_fee_msat: u64,
+    pub max_keysend_per_payee_msat: u64,
+    pub max_keysend_msat: u64,
+    #[serde(default)]
+    pub keysend_window_blocks: u32,
+    pub excess_drain_interval_blocks: u32,
+    pub excess_drain_percent: u8,
+    #[serde(default)]
+    pub stub_timeout_blocks: u32,
+    #[serde(default)]
+    pub max_stubs: u32,
+    #[serde(default)]
+    pub require_seen_bolt12_invoices: bool,
+}
+
+#[derive(Deserialize)]
+struct SimplePolicyHelper(#[serde(with = "SimplePolicyDef")] SimplePolicy);
+
+impl SerializeAs<SimplePolicy> for SimplePolicyDef {
+    fn serialize_as<S>(value: &SimplePolicy, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        SimplePolicyDef::serialize(value, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, SimplePolicy> for SimplePolicyDef {
+    fn deserialize_as<D>(deserializer: D) -> Result<SimplePolicy, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        SimplePolicyHelper::deserialize(deserializer).map(|h| h.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,144 @@
+//! Operator credentials for the Admin gRPC service.
+//!
+//! The operator presents a bearer token in the `authorization` metadata.
+//! The token is either read from a file supplied by the operator, or
+//! generated at startup and written to a cookie file in the data directory.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::str::FromStr;
+
+use rand::{OsRng, Rng};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// The default port for the Admin service
+pub const DEFAULT_ADMIN_PORT: &str = "50052";
+
+/// The name of the cookie file in the network data directory
+pub const ADMIN_COOKIE_FILE: &str = ".admin_cookie";
+
+const AUTHORIZATION: &str = "authorization";
+
+/// Read an operator token from a file
+pub fn read_token(path: &str) -> io::Result<String> {
+    let token = fs::read_to_string(path)?.trim().to_string();
+    if token.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is empty", path)));
+    }
+    Ok(token)
+}
+
+/// Generate a random operator token and write it to a cookie file
+/// only readable by the operator
+pub fn write_cookie(path: &str) -> io::Result<String> {
+    let mut rng = OsRng::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let mut secret = [0u8; 32];
+    rng.fill_bytes(&mut secret);
+    let token = hex::encode(&secret);
+
+    // Remove a stale cookie, so that the permissions apply to a fresh file
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    file.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+// Compare without leaking the position of the first difference
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Server side check of the operator token
+#[derive(Clone)]
+pub struct AdminAuthChecker {
+    token: String,
+}
+
+impl AdminAuthChecker {
+    /// Check requests against this token
+    pub fn new(token: String) -> Self {
+        AdminAuthChecker { token }
+    }
+}
+
+impl Interceptor for AdminAuthChecker {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let presented = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match presented {
+            Some(token) if constant_time_eq(token.as_bytes(), self.token.as_bytes()) => Ok(request),
+            _ => Err(Status::unauthenticated("missing or invalid admin credentials")),
+        }
+    }
+}
+
+/// Client side injection of the operator token
+#[derive(Clone)]
+pub struct AdminAuth {
+    header: MetadataValue<Ascii>,
+}
+
+impl AdminAuth {
+    /// Present this token
+    pub fn new(token: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let header = MetadataValue::from_str(&format!("Bearer {}", token))?;
+        Ok(AdminAuth { header })
+    }
+}
+
+impl Interceptor for AdminAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        request.metadata_mut().insert(AUTHORIZATION, self.header.clone());
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_auth_test() {
+        let mut checker = AdminAuthChecker::new("secret".to_string());
+        assert_eq!(
+            checker.call(Request::new(())).unwrap_err().code(),
+            tonic::Code::Unauthenticated
+        );
+
+        let request = AdminAuth::new("secret").unwrap().call(Request::new(())).unwrap();
+        assert!(checker.call(request).is_ok());
+
+        let request = AdminAuth::new("secreT").unwrap().call(Request::new(())).unwrap();
+        assert_eq!(checker.call(request).unwrap_err().code(), tonic::Code::Unauthenticated);
+
+        let request = AdminAuth::new("secret2").unwrap().call(Request::new(())).unwrap();
+        assert!(checker.call(request).is_err());
+    }
+
+    #[test]
+    fn cookie_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(ADMIN_COOKIE_FILE);
+        let path = path.to_str().unwrap();
+        let token = write_cookie(path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(read_token(path).unwrap(), token);
+        let token2 = write_cookie(path).unwrap();
+        assert_ne!(token, token2);
+        assert_eq!(read_token(path).unwrap(), token2);
+    }
+}
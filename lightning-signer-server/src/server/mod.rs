@@ -1,5 +1,9 @@
 #[cfg(feature = "grpc")]
+pub mod admin_auth;
+#[cfg(feature = "grpc")]
 pub mod driver;
 pub mod nodefront;
 #[cfg(feature = "grpc")]
 pub mod remotesigner;
+#[cfg(feature = "grpc")]
+pub mod tenant;
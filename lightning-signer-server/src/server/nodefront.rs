@@ -4,6 +4,7 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use log::error;
 
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::util::merkleblock::PartialMerkleTree;
@@ -85,6 +86,14 @@ impl ChainTrack for NodeFront {
             .get_tracker()
             .add_block(header, txs, txs_proof)
             .unwrap_or_else(|e| panic!("{}: add_block failed: {:?}", self.node.log_prefix(), e));
+        // Closing transactions and sweeps may now be buried deep enough
+        if let Err(e) = self.node.archive_closed_channels() {
+            error!("{}: archive failed: {:?}", self.node.log_prefix(), e);
+        }
+        // Stubs that never became ready may have timed out
+        if let Err(e) = self.node.forget_stale_stubs() {
+            error!("{}: forgetting stale stubs failed: {:?}", self.node.log_prefix(), e);
+        }
     }
 
     async fn remove_block(
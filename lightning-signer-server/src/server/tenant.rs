@@ -0,0 +1,364 @@
+//! Tenants of a hosted signer.
+//!
+//! Each tenant presents its own bearer token to the signer service, can only
+//! address its own nodes, and is persisted in its own namespace under the
+//! data directory.  The operator configures tenants and their quotas in a
+//! JSON file.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::error;
+use serde::Deserialize;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use lightning_signer::signer::multi_signer::TenantQuota;
+use lightning_signer::util::rate_limit::{Clock, StdClock, TokenBucket};
+
+use crate::server::admin_auth::constant_time_eq;
+
+/// The directory under the network data directory holding tenant namespaces
+pub const TENANTS_DIR: &str = "tenants";
+
+const AUTHORIZATION: &str = "authorization";
+// How long a measurement of a tenant's storage is trusted
+const STORAGE_REFRESH: Duration = Duration::from_secs(60);
+
+/// A tenant, as configured by the operator
+#[derive(Clone, Debug, Deserialize)]
+pub struct TenantConfig {
+    /// The tenant name, which also names its persistence namespace
+    pub name: String,
+    /// The bearer token the tenant presents to the signer service
+    pub token: String,
+    /// Maximum number of nodes
+    pub max_nodes: usize,
+    /// Maximum number of channels, across all of the tenant's nodes
+    pub max_channels: usize,
+    /// Maximum number of signer service requests per minute, which may
+    /// also come in a single burst, or unlimited if zero
+    pub max_requests_per_minute: u32,
+    /// Maximum size of the tenant's persistence namespace on disk
+    pub max_storage_bytes: u64,
+}
+
+impl TenantConfig {
+    /// The quotas enforced by the signer
+    pub fn quota(&self) -> TenantQuota {
+        TenantQuota { max_nodes: self.max_nodes, max_channels: self.max_channels }
+    }
+}
+
+/// Read the tenant configuration, a JSON array of [TenantConfig]
+pub fn read_tenants(path: &str) -> io::Result<Vec<TenantConfig>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let configs: Vec<TenantConfig> = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| invalid(format!("{}: {}", path, e)))?;
+    for (i, config) in configs.iter().enumerate() {
+        let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if config.name.is_empty() || !config.name.chars().all(is_name_char) {
+            return Err(invalid(format!("{}: bad tenant name {:?}", path, config.name)));
+        }
+        if config.token.is_empty() {
+            return Err(invalid(format!("{}: empty token for tenant {}", path, config.name)));
+        }
+        if configs[..i].iter().any(|c| c.name == config.name || c.token == config.token) {
+            return Err(invalid(format!("{}: duplicate tenant {}", path, config.name)));
+        }
+    }
+    Ok(configs)
+}
+
+/// The on-disk size of a directory tree
+pub fn storage_used(path: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() { storage_used(&entry.path())? } else { metadata.len() };
+    }
+    Ok(total)
+}
+
+/// The authenticated tenant of a signer service request, attached to
+/// the request extensions by [TenantAuthChecker]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TenantId(pub String);
+
+// The storage a tenant was last measured to use
+struct StorageUsage {
+    used: u64,
+    measured_at: Duration,
+}
+
+/// The configured tenants and their request rate and storage accounting
+pub struct Tenants {
+    configs: BTreeMap<String, TenantConfig>,
+    data_path: String,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<BTreeMap<String, TokenBucket>>,
+    storage: Mutex<BTreeMap<String, StorageUsage>>,
+}
+
+impl Tenants {
+    /// Tenants with namespaces under `data_path`
+    pub fn new(configs: Vec<TenantConfig>, data_path: &str) -> Self {
+        Self::new_with_clock(configs, data_path, Arc::new(StdClock))
+    }
+
+    /// Tenants with namespaces under `data_path`, using `clock` for accounting
+    pub fn new_with_clock(
+        configs: Vec<TenantConfig>,
+        data_path: &str,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Tenants {
+            configs: configs.into_iter().map(|c| (c.name.clone(), c)).collect(),
+            data_path: data_path.to_string(),
+            clock,
+            buckets: Mutex::new(BTreeMap::new()),
+            storage: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Whether the signer is single-tenant
+    pub fn is_empty(&self) -> bool {
+        self.configs.is_empty()
+    }
+
+    /// The tenant configurations
+    pub fn configs(&self) -> impl Iterator<Item = &TenantConfig> {
+        self.configs.values()
+    }
+
+    /// The persistence namespace of a tenant
+    pub fn namespace_path(&self, name: &str) -> String {
+        format!("{}/{}/{}", self.data_path, TENANTS_DIR, name)
+    }
+
+    /// Check that the tenant's persistence namespace is below its quota.
+    ///
+    /// The namespace is only measured again once the last measurement is
+    /// older than a minute, rather than on every check.
+    pub fn check_storage(&self, name: &str) -> Result<(), Status> {
+        let config = self
+            .configs
+            .get(name)
+            .ok_or_else(|| Status::permission_denied(format!("no such tenant: {}", name)))?;
+        let now = self.clock.now();
+        let mut storage = self.storage.lock().unwrap();
+        let is_fresh = |usage: &StorageUsage| {
+            now.checked_sub(usage.measured_at).map(|age| age < STORAGE_REFRESH).unwrap_or(false)
+        };
+        let used = match storage.get(name) {
+            Some(usage) if is_fresh(usage) => usage.used,
+            _ => {
+                let path = self.namespace_path(name);
+                let used = storage_used(Path::new(&path)).map_err(|e| {
+                    error!("could not measure {}: {}", path, e);
+                    Status::internal("could not measure tenant storage")
+                })?;
+                storage.insert(name.to_string(), StorageUsage { used, measured_at: now });
+                used
+            }
+        };
+        if used >= config.max_storage_bytes {
+            error!("RESOURCE EXHAUSTED: tenant {} uses {} bytes", name, used);
+            return Err(Status::resource_exhausted(format!(
+                "tenant {} is at its storage quota of {} bytes",
+                name, config.max_storage_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    fn authenticate(&self, token: &str) -> Option<&TenantConfig> {
+        self.configs.values().find(|c| constant_time_eq(token.as_bytes(), c.token.as_bytes()))
+    }
+
+    fn check_rate(&self, config: &TenantConfig) -> Result<(), Status> {
+        let per_min = config.max_requests_per_minute;
+        if per_min == 0 {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(config.name.clone())
+            .or_insert_with(|| TokenBucket::new_per_minute(per_min, per_min, now));
+        if !bucket.try_take(now) {
+            return Err(Status::resource_exhausted(format!(
+                "tenant {} is at its quota of {} requests per minute",
+                config.name, per_min
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Server side authentication of tenants on the signer service.
+///
+/// If no tenants are configured, requests pass without a tenant.
+#[derive(Clone)]
+pub struct TenantAuthChecker {
+    tenants: Arc<Tenants>,
+}
+
+impl TenantAuthChecker {
+    /// Check requests against these tenants
+    pub fn new(tenants: Arc<Tenants>) -> Self {
+        TenantAuthChecker { tenants }
+    }
+}
+
+impl Interceptor for TenantAuthChecker {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if self.tenants.is_empty() {
+            return Ok(request);
+        }
+        let presented = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let config = presented
+            .and_then(|token| self.tenants.authenticate(token))
+            .ok_or_else(|| Status::unauthenticated("missing or invalid tenant credentials"))?;
+        self.tenants.check_rate(config)?;
+        let tenant_id = TenantId(config.name.clone());
+        request.extensions_mut().insert(tenant_id);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::admin_auth::AdminAuth;
+
+    // A clock that only moves when told to
+    struct TestClock(Mutex<Duration>);
+
+    impl lightning_signer::SendSync for TestClock {}
+
+    impl Clock for TestClock {
+        fn now(&self) -> Duration {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    impl TestClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    fn make_config(name: &str, token: &str) -> TenantConfig {
+        TenantConfig {
+            name: name.to_string(),
+            token: token.to_string(),
+            max_nodes: 1,
+            max_channels: 10,
+            max_requests_per_minute: 2,
+            max_storage_bytes: 100,
+        }
+    }
+
+    #[test]
+    fn tenant_auth_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let configs = vec![make_config("alice", "secret1"), make_config("bob", "secret2")];
+        let clock = Arc::new(TestClock(Mutex::new(Duration::from_secs(1000))));
+        let tenants =
+            Arc::new(Tenants::new_with_clock(configs, dir.path().to_str().unwrap(), clock.clone()));
+        let mut checker = TenantAuthChecker::new(Arc::clone(&tenants));
+        assert_eq!(
+            checker.call(Request::new(())).unwrap_err().code(),
+            tonic::Code::Unauthenticated
+        );
+
+        let request = AdminAuth::new("secret2").unwrap().call(Request::new(())).unwrap();
+        let request = checker.call(request).unwrap();
+        assert_eq!(request.extensions().get::<TenantId>(), Some(&TenantId("bob".to_string())));
+
+        let request = AdminAuth::new("secret3").unwrap().call(Request::new(())).unwrap();
+        assert_eq!(checker.call(request).unwrap_err().code(), tonic::Code::Unauthenticated);
+
+        let request = AdminAuth::new("secret2").unwrap().call(Request::new(())).unwrap();
+        assert!(checker.call(request).is_ok());
+        let request = AdminAuth::new("secret2").unwrap().call(Request::new(())).unwrap();
+        assert_eq!(checker.call(request).unwrap_err().code(), tonic::Code::ResourceExhausted);
+        let request = AdminAuth::new("secret1").unwrap().call(Request::new(())).unwrap();
+        assert!(checker.call(request).is_ok());
+
+        // The quota refills steadily, rather than all at once a minute later
+        let config = tenants.configs.get("bob").unwrap();
+        clock.advance(Duration::from_secs(29));
+        assert!(tenants.check_rate(config).is_err());
+        clock.advance(Duration::from_secs(1));
+        assert!(tenants.check_rate(config).is_ok());
+        assert!(tenants.check_rate(config).is_err());
+
+        let unlimited = TenantConfig { max_requests_per_minute: 0, ..config.clone() };
+        for _ in 0..10 {
+            assert!(tenants.check_rate(&unlimited).is_ok());
+        }
+
+        let mut checker = TenantAuthChecker::new(Arc::new(Tenants::new(vec![], "")));
+        let request = checker.call(Request::new(())).unwrap();
+        assert!(request.extensions().get::<TenantId>().is_none());
+    }
+
+    #[test]
+    fn tenant_storage_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(TestClock(Mutex::new(Duration::from_secs(1000))));
+        let tenants = Tenants::new_with_clock(
+            vec![make_config("alice", "secret")],
+            dir.path().to_str().unwrap(),
+            clock.clone(),
+        );
+        let path = tenants.namespace_path("alice");
+        fs::create_dir_all(format!("{}/db", path)).unwrap();
+        fs::write(format!("{}/db/a", path), [0u8; 60]).unwrap();
+        assert!(tenants.check_storage("alice").is_ok());
+        fs::write(format!("{}/b", path), [0u8; 40]).unwrap();
+        assert_eq!(storage_used(Path::new(&path)).unwrap(), 100);
+        // The last measurement is still fresh
+        assert!(tenants.check_storage("alice").is_ok());
+        clock.advance(STORAGE_REFRESH);
+        assert_eq!(
+            tenants.check_storage("alice").unwrap_err().code(),
+            tonic::Code::ResourceExhausted
+        );
+        assert_eq!(tenants.check_storage("bob").unwrap_err().code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn read_tenants_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tenants.json");
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            r#"[{"name": "alice", "token": "secret", "max_nodes": 1, "max_channels": 10,
+                 "max_requests_per_minute": 600, "max_storage_bytes": 1000000}]"#,
+        )
+        .unwrap();
+        let configs = read_tenants(path).unwrap();
+        assert_eq!(configs[0].quota(), TenantQuota { max_nodes: 1, max_channels: 10 });
+
+        fs::write(
+            path,
+            r#"[{"name": "../alice", "token": "secret", "max_nodes": 1, "max_channels": 10,
+                 "max_requests_per_minute": 600, "max_storage_bytes": 1000000}]"#,
+        )
+        .unwrap();
+        assert!(read_tenants(path).is_err());
+    }
+}
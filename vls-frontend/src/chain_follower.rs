@@ -1,6 +1,7 @@
 use std::collections::BTreeSet as OrderedSet;
 use std::fmt::{self, Display, Formatter};
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -27,6 +28,8 @@ pub struct ChainFollower {
     client: BitcoindClient,
     state: Mutex<State>,
     update_interval: u64,
+    tracker_height: AtomicU32,
+    source_height: AtomicU32,
 }
 
 #[derive(Debug, PartialEq)]
@@ -76,6 +79,8 @@ impl ChainFollower {
             client,
             state: Mutex::new(State::Scanning),
             update_interval,
+            tracker_height: AtomicU32::new(0),
+            source_height: AtomicU32::new(0),
         })
     }
 
@@ -86,10 +91,24 @@ impl ChainFollower {
         });
     }
 
+    /// The log prefix of the followed tracker
+    pub fn tracker_log_prefix(&self) -> String {
+        self.tracker.log_prefix()
+    }
+
+    /// The last seen tracker height and chain source height
+    pub fn heights(&self) -> (u32, u32) {
+        (self.tracker_height.load(Ordering::Relaxed), self.source_height.load(Ordering::Relaxed))
+    }
+
     async fn run(&self) {
         let mut interval = time::interval(Duration::from_millis(self.update_interval));
         loop {
             interval.tick().await;
+            match self.client.get_blockchain_info().await {
+                Ok(info) => self.source_height.store(info.latest_height as u32, Ordering::Relaxed),
+                Err(err) => error!("{}: {}", self.tracker.log_prefix(), err),
+            }
             loop {
                 match self.update().await {
                     Ok(next) => {
@@ -112,6 +131,7 @@ impl ChainFollower {
 
         // Fetch the current tip from the tracker
         let (height0, hash0) = self.tracker.tip_info().await;
+        self.tracker_height.store(height0, Ordering::Relaxed);
 
         // Fetch the next block hash from bitcoind
         let hash = match self.client.get_block_hash(height0 + 1).await? {
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use tokio::task;
 
@@ -8,24 +8,38 @@ use log::info;
 
 use crate::{chain_follower::ChainFollower, ChainTrack, ChainTrackDirectory};
 
+/// The heights of a followed tracker and of the chain source
+#[derive(Clone, Debug)]
+pub struct FollowerHeights {
+    /// The log prefix of the tracker
+    pub tracker: String,
+    /// The last seen tracker tip height
+    pub tracker_height: u32,
+    /// The last seen chain source (bitcoind) height
+    pub source_height: u32,
+}
+
 pub struct Frontend {
     pub signer: Arc<dyn ChainTrackDirectory>,
     pub rpc_url: Url,
+    followers: Arc<Mutex<Vec<Arc<ChainFollower>>>>,
 }
 
 impl Frontend {
     /// Create a new Frontend
     pub fn new(signer: Arc<dyn ChainTrackDirectory>, rpc_url: Url) -> Frontend {
-        Frontend { signer, rpc_url }
+        Frontend { signer, rpc_url, followers: Arc::new(Mutex::new(Vec::new())) }
     }
 
     /// Start a task which creates a chain follower for each existing tracker
     pub fn start(&self) {
         let signer = Arc::clone(&self.signer);
         let rpc_url = self.rpc_url.clone();
+        let followers = Arc::clone(&self.followers);
         task::spawn(async move {
             for tracker in signer.trackers().await {
                 let cf_arc = ChainFollower::new(tracker, &rpc_url).await;
+                followers.lock().unwrap().push(Arc::clone(&cf_arc));
                 ChainFollower::start(cf_arc).await;
             }
         });
@@ -35,6 +49,20 @@ impl Frontend {
     /// Start a chain follower for a specific tracker
     pub async fn start_follower(&self, tracker: Arc<dyn ChainTrack>) {
         let cf_arc = ChainFollower::new(tracker, &self.rpc_url).await;
+        self.followers.lock().unwrap().push(Arc::clone(&cf_arc));
         ChainFollower::start(cf_arc).await;
     }
+
+    /// The heights of all followed trackers
+    pub fn follower_heights(&self) -> Vec<FollowerHeights> {
+        self.followers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|cf| {
+                let (tracker_height, source_height) = cf.heights();
+                FollowerHeights { tracker: cf.tracker_log_prefix(), tracker_height, source_height }
+            })
+            .collect()
+    }
 }
@@ -15,7 +15,7 @@ use lightning_signer::bitcoin;
 mod chain_follower;
 pub mod frontend;
 
-pub use self::frontend::Frontend;
+pub use self::frontend::{FollowerHeights, Frontend};
 
 /// Provides ChainTracks for nodes in a signer
 #[async_trait]
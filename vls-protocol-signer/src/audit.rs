@@ -0,0 +1,67 @@
+//! Audit log records for protocol messages.
+//!
+//! The hash-chained log itself is in [`lightning_signer::util::audit`].
+
+use alloc::string::ToString;
+
+use lightning_signer::bitcoin::consensus::{deserialize, Decodable};
+use lightning_signer::bitcoin::util::psbt::PartiallySignedTransaction;
+use lightning_signer::bitcoin::{Transaction, Txid};
+use lightning_signer::channel::ChannelId;
+use vls_protocol::msgs::Message;
+
+pub use lightning_signer::util::audit::*;
+
+/// Extract the audited details from a message, before it is handled.
+///
+/// The decision is initially `Allowed`, and should be updated once the
+/// request is handled.
+pub fn record_from_message(
+    msg: &Message,
+    client_id: u64,
+    dbid: u64,
+    channel_id: Option<&ChannelId>,
+) -> AuditRecord {
+    let (txid, commitment_number) = extract_tx_details(msg);
+    AuditRecord {
+        client_id,
+        dbid,
+        channel_id: channel_id.map(|c| c.to_string()),
+        message: msg.message_name().to_string(),
+        txid,
+        commitment_number,
+        decision: Decision::Allowed,
+    }
+}
+
+fn txid_of(tx_bytes: &[u8]) -> Option<Txid> {
+    deserialize::<Transaction>(tx_bytes).ok().map(|tx| tx.txid())
+}
+
+fn extract_tx_details(msg: &Message) -> (Option<Txid>, Option<u64>) {
+    match msg {
+        Message::SignWithdrawal(m) => {
+            let txid = PartiallySignedTransaction::consensus_decode(m.psbt.0.as_slice())
+                .ok()
+                .map(|psbt| psbt.extract_tx().txid());
+            (txid, None)
+        }
+        Message::SignRemoteHtlcTx(m) => (txid_of(&m.tx.0), None),
+        Message::SignRemoteCommitmentTx(m) => (txid_of(&m.tx.0), Some(m.commitment_number)),
+        Message::SignRemoteCommitmentTx2(m) => (None, Some(m.commitment_number)),
+        Message::SignDelayedPaymentToUs(m) => (txid_of(&m.tx.0), Some(m.commitment_number)),
+        Message::SignRemoteHtlcToUs(m) => (txid_of(&m.tx.0), None),
+        Message::SignLocalHtlcTx(m) => (txid_of(&m.tx.0), Some(m.commitment_number)),
+        Message::SignMutualCloseTx(m) => (txid_of(&m.tx.0), None),
+        Message::ValidateCommitmentTx(m) => (txid_of(&m.tx.0), Some(m.commitment_number)),
+        Message::ValidateCommitmentTx2(m) => (None, Some(m.commitment_number)),
+        Message::SignLocalCommitmentTx2(m) => (None, Some(m.commitment_number)),
+        Message::ValidateRevocation(m) => (None, Some(m.commitment_number)),
+        Message::SignPenaltyToUs(m) => (txid_of(&m.tx.0), None),
+        Message::SignCommitmentTx(m) => (txid_of(&m.tx.0), Some(m.commitment_number)),
+        Message::CheckFutureSecret(m) => (None, Some(m.commitment_number)),
+        Message::GetPerCommitmentPoint(m) => (None, Some(m.commitment_number)),
+        Message::GetPerCommitmentPoint2(m) => (None, Some(m.commitment_number)),
+        _ => (None, None),
+    }
+}
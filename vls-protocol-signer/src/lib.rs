@@ -2,6 +2,8 @@
 
 extern crate alloc;
 
+pub mod audit;
 pub mod handler;
+pub mod noise;
 pub use lightning_signer;
 pub use vls_protocol;
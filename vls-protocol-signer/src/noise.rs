@@ -0,0 +1,564 @@
+//! Noise_XK encrypted transport for the serial link to an embedded signer.
+//!
+//! The handshake follows BOLT-8 (Noise_XK_secp256k1_ChaChaPoly_SHA256), with a
+//! VLS-specific prologue.  The initiator (the proxy) must know and pin the
+//! static key of the device, and the device pins the static key of the proxy.
+//! After the handshake, the stream carries the usual SerBolt framing, with each
+//! write sent as one or more encrypted frames.
+
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use lightning_signer::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use lightning_signer::bitcoin::hashes::sha256::Hash as Sha256Hash;
+use lightning_signer::bitcoin::hashes::{Hash, HashEngine};
+use lightning_signer::bitcoin::secp256k1::ecdh::SharedSecret;
+use lightning_signer::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, Signing};
+use vls_protocol::serde_bolt::{self, Read, Write};
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XK_secp256k1_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"vls-serial";
+const VERSION: u8 = 0;
+const ACT_ONE_TWO_LEN: usize = 50;
+const ACT_THREE_LEN: usize = 66;
+const TAG_LEN: usize = 16;
+const MAX_FRAME_LEN: usize = 65535;
+const KEY_ROTATION_INTERVAL: u64 = 1000;
+
+/// Noise handshake or transport error
+#[derive(Debug)]
+pub enum Error {
+    /// Underlying stream error
+    Io(serde_bolt::Error),
+    /// The peer sent an unknown handshake version
+    BadVersion(u8),
+    /// The peer sent an invalid public key
+    BadKey,
+    /// Decryption failed - wrong key or tampered data
+    Decrypt,
+    /// The initiator authenticated with a static key other than the pinned one
+    UnknownPeer(PublicKey),
+}
+
+impl From<serde_bolt::Error> for Error {
+    fn from(e: serde_bolt::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+fn to_bolt_error(e: Error) -> serde_bolt::Error {
+    match e {
+        Error::Io(e) => e,
+        e => serde_bolt::Error::Message(alloc::format!("noise: {:?}", e)),
+    }
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut engine = Sha256Hash::engine();
+    for part in parts {
+        engine.input(part);
+    }
+    Sha256Hash::from_engine(engine).into_inner()
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut engine = HmacEngine::<Sha256Hash>::new(key);
+    for part in parts {
+        engine.input(part);
+    }
+    Hmac::from_engine(engine).into_inner()
+}
+
+// BOLT-8 HKDF, producing two 32-byte keys
+fn hkdf(salt: &[u8; 32], ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let prk = hmac(salt, &[ikm]);
+    let t1 = hmac(&prk, &[&[1]]);
+    let t2 = hmac(&prk, &[&t1, &[2]]);
+    (t1, t2)
+}
+
+fn ecdh(point: &PublicKey, scalar: &SecretKey) -> [u8; 32] {
+    SharedSecret::new(point, scalar).secret_bytes()
+}
+
+fn nonce(n: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&n.to_le_bytes());
+    nonce
+}
+
+fn encrypt(key: &[u8; 32], n: u64, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .encrypt(Nonce::from_slice(&nonce(n)), Payload { msg: plaintext, aad: ad })
+        .expect("encrypt")
+}
+
+fn decrypt(key: &[u8; 32], n: u64, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce(n)), Payload { msg: ciphertext, aad: ad })
+        .map_err(|_| Error::Decrypt)
+}
+
+// The underlying stream may return fewer bytes than requested, so keep reading
+// until the buffer is full
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        let len = reader.read(&mut buf[pos..])?;
+        if len == 0 {
+            return Err(Error::Io(serde_bolt::Error::Eof));
+        }
+        pos += len;
+    }
+    Ok(())
+}
+
+// Symmetric handshake state
+struct HandshakeState {
+    h: [u8; 32],
+    ck: [u8; 32],
+}
+
+impl HandshakeState {
+    fn new(responder_static: &PublicKey) -> Self {
+        let h = sha256(&[PROTOCOL_NAME]);
+        let ck = h;
+        let h = sha256(&[&h, PROLOGUE]);
+        let h = sha256(&[&h, &responder_static.serialize()]);
+        HandshakeState { h, ck }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.h = sha256(&[&self.h, data]);
+    }
+
+    fn mix_key(&mut self, ikm: &[u8; 32]) -> [u8; 32] {
+        let (ck, temp_k) = hkdf(&self.ck, ikm);
+        self.ck = ck;
+        temp_k
+    }
+
+    fn split(&self) -> ([u8; 32], [u8; 32]) {
+        hkdf(&self.ck, &[])
+    }
+}
+
+// One direction of the transport
+struct CipherState {
+    ck: [u8; 32],
+    key: [u8; 32],
+    n: u64,
+}
+
+impl CipherState {
+    fn new(ck: [u8; 32], key: [u8; 32]) -> Self {
+        CipherState { ck, key, n: 0 }
+    }
+
+    fn next_nonce(&mut self) -> u64 {
+        let n = self.n;
+        self.n += 1;
+        n
+    }
+
+    fn maybe_rotate(&mut self) {
+        if self.n == KEY_ROTATION_INTERVAL {
+            let (ck, key) = hkdf(&self.ck, &self.key);
+            self.ck = ck;
+            self.key = key;
+            self.n = 0;
+        }
+    }
+
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let n = self.next_nonce();
+        let ciphertext = encrypt(&self.key, n, &[], plaintext);
+        self.maybe_rotate();
+        ciphertext
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let n = self.next_nonce();
+        let plaintext = decrypt(&self.key, n, &[], ciphertext)?;
+        self.maybe_rotate();
+        Ok(plaintext)
+    }
+}
+
+/// A stream wrapped in a Noise_XK session.
+///
+/// Implements the serde_bolt `Read` and `Write` traits, so it can be used
+/// in place of the underlying stream by the SerBolt framing functions.
+pub struct NoiseStream<S: Read + Write> {
+    inner: S,
+    sending: CipherState,
+    receiving: CipherState,
+    remote_static: PublicKey,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+}
+
+impl<S: Read + Write> NoiseStream<S> {
+    /// Perform the initiator side of the handshake, against a pinned responder static key
+    pub fn connect<C: Signing>(
+        mut inner: S,
+        secp_ctx: &Secp256k1<C>,
+        local_static: &SecretKey,
+        ephemeral: &SecretKey,
+        remote_static: &PublicKey,
+    ) -> Result<Self, Error> {
+        let mut state = HandshakeState::new(remote_static);
+
+        // Act one
+        let e_pub = PublicKey::from_secret_key(secp_ctx, ephemeral);
+        state.mix_hash(&e_pub.serialize());
+        let temp_k1 = state.mix_key(&ecdh(remote_static, ephemeral));
+        let c = encrypt(&temp_k1, 0, &state.h, &[]);
+        state.mix_hash(&c);
+        let mut act = Vec::with_capacity(ACT_ONE_TWO_LEN);
+        act.push(VERSION);
+        act.extend_from_slice(&e_pub.serialize());
+        act.extend_from_slice(&c);
+        inner.write_all(&act)?;
+
+        // Act two
+        let mut act = [0u8; ACT_ONE_TWO_LEN];
+        read_exact(&mut inner, &mut act)?;
+        if act[0] != VERSION {
+            return Err(Error::BadVersion(act[0]));
+        }
+        let re = PublicKey::from_slice(&act[1..34]).map_err(|_| Error::BadKey)?;
+        state.mix_hash(&re.serialize());
+        let temp_k2 = state.mix_key(&ecdh(&re, ephemeral));
+        decrypt(&temp_k2, 0, &state.h, &act[34..])?;
+        state.mix_hash(&act[34..]);
+
+        // Act three
+        let s_pub = PublicKey::from_secret_key(secp_ctx, local_static);
+        let c = encrypt(&temp_k2, 1, &state.h, &s_pub.serialize());
+        state.mix_hash(&c);
+        let temp_k3 = state.mix_key(&ecdh(&re, local_static));
+        let t = encrypt(&temp_k3, 0, &state.h, &[]);
+        let mut act = Vec::with_capacity(ACT_THREE_LEN);
+        act.push(VERSION);
+        act.extend_from_slice(&c);
+        act.extend_from_slice(&t);
+        inner.write_all(&act)?;
+
+        let (sk, rk) = state.split();
+        Ok(Self::new(inner, state.ck, sk, rk, *remote_static))
+    }
+
+    /// Perform the responder side of the handshake, against a pinned initiator static key.
+    ///
+    /// Fails with `Error::UnknownPeer` if the initiator authenticates with any other key.
+    pub fn accept<C: Signing>(
+        inner: S,
+        secp_ctx: &Secp256k1<C>,
+        local_static: &SecretKey,
+        ephemeral: &SecretKey,
+        remote_static: &PublicKey,
+    ) -> Result<Self, Error> {
+        Self::try_accept(inner, secp_ctx, local_static, ephemeral, remote_static)
+            .map_err(|(e, _)| e)
+    }
+
+    /// Like `accept`, but gives back the underlying stream on failure, so that
+    /// the handshake can be retried with a fresh ephemeral key.
+    pub fn try_accept<C: Signing>(
+        mut inner: S,
+        secp_ctx: &Secp256k1<C>,
+        local_static: &SecretKey,
+        ephemeral: &SecretKey,
+        remote_static: &PublicKey,
+    ) -> Result<Self, (Error, S)> {
+        match Self::accept_handshake(&mut inner, secp_ctx, local_static, ephemeral, remote_static) {
+            Ok((ck, sk, rk, rs)) => Ok(Self::new(inner, ck, sk, rk, rs)),
+            Err(e) => Err((e, inner)),
+        }
+    }
+
+    // Returns the chaining key, the sending and receiving keys, and the initiator static key
+    fn accept_handshake<C: Signing>(
+        inner: &mut S,
+        secp_ctx: &Secp256k1<C>,
+        local_static: &SecretKey,
+        ephemeral: &SecretKey,
+        remote_static: &PublicKey,
+    ) -> Result<([u8; 32], [u8; 32], [u8; 32], PublicKey), Error> {
+        let s_pub = PublicKey::from_secret_key(secp_ctx, local_static);
+        let mut state = HandshakeState::new(&s_pub);
+
+        // Act one
+        let mut act = [0u8; ACT_ONE_TWO_LEN];
+        read_exact(inner, &mut act)?;
+        if act[0] != VERSION {
+            return Err(Error::BadVersion(act[0]));
+        }
+        let re = PublicKey::from_slice(&act[1..34]).map_err(|_| Error::BadKey)?;
+        state.mix_hash(&re.serialize());
+        let temp_k1 = state.mix_key(&ecdh(&re, local_static));
+        decrypt(&temp_k1, 0, &state.h, &act[34..])?;
+        state.mix_hash(&act[34..]);
+
+        // Act two
+        let e_pub = PublicKey::from_secret_key(secp_ctx, ephemeral);
+        state.mix_hash(&e_pub.serialize());
+        let temp_k2 = state.mix_key(&ecdh(&re, ephemeral));
+        let c = encrypt(&temp_k2, 0, &state.h, &[]);
+        state.mix_hash(&c);
+        let mut act = Vec::with_capacity(ACT_ONE_TWO_LEN);
+        act.push(VERSION);
+        act.extend_from_slice(&e_pub.serialize());
+        act.extend_from_slice(&c);
+        inner.write_all(&act)?;
+
+        // Act three
+        let mut act = [0u8; ACT_THREE_LEN];
+        read_exact(inner, &mut act)?;
+        if act[0] != VERSION {
+            return Err(Error::BadVersion(act[0]));
+        }
+        let c = &act[1..50];
+        let rs_bytes = decrypt(&temp_k2, 1, &state.h, c)?;
+        let rs = PublicKey::from_slice(&rs_bytes).map_err(|_| Error::BadKey)?;
+        state.mix_hash(c);
+        let temp_k3 = state.mix_key(&ecdh(&rs, ephemeral));
+        decrypt(&temp_k3, 0, &state.h, &act[50..])?;
+        if rs != *remote_static {
+            return Err(Error::UnknownPeer(rs));
+        }
+
+        let (rk, sk) = state.split();
+        Ok((state.ck, sk, rk, rs))
+    }
+
+    fn new(inner: S, ck: [u8; 32], sk: [u8; 32], rk: [u8; 32], remote_static: PublicKey) -> Self {
+        NoiseStream {
+            inner,
+            sending: CipherState::new(ck, sk),
+            receiving: CipherState::new(ck, rk),
+            remote_static,
+            read_buf: Vec::new(),
+            read_pos: 0,
+        }
+    }
+
+    /// The static key of the remote side
+    pub fn remote_static(&self) -> &PublicKey {
+        &self.remote_static
+    }
+
+    fn write_frame(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let len = (buf.len() as u16).to_be_bytes();
+        let mut frame = self.sending.encrypt(&len);
+        frame.extend_from_slice(&self.sending.encrypt(buf));
+        self.inner.write_all(&frame)?;
+        Ok(())
+    }
+
+    // Returns false on a clean EOF at a frame boundary
+    fn fill_buf(&mut self) -> Result<bool, Error> {
+        let mut len_buf = [0u8; 2 + TAG_LEN];
+        let n = self.inner.read(&mut len_buf)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        read_exact(&mut self.inner, &mut len_buf[n..])?;
+        let len_bytes = self.receiving.decrypt(&len_buf)?;
+        let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+        let mut body = alloc::vec![0u8; len + TAG_LEN];
+        read_exact(&mut self.inner, &mut body)?;
+        self.read_buf = self.receiving.decrypt(&body)?;
+        self.read_pos = 0;
+        Ok(true)
+    }
+
+    fn available(&self) -> usize {
+        self.read_buf.len() - self.read_pos
+    }
+}
+
+impl<S: Read + Write> Read for NoiseStream<S> {
+    type Error = serde_bolt::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> serde_bolt::Result<usize> {
+        let mut nread = 0;
+        while nread < buf.len() {
+            if self.available() == 0 && !self.fill_buf().map_err(to_bolt_error)? {
+                // we are at EOF
+                return if nread != 0 { Ok(nread) } else { Err(serde_bolt::Error::Eof) };
+            }
+            let n = min(self.available(), buf.len() - nread);
+            buf[nread..nread + n].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + n]);
+            self.read_pos += n;
+            nread += n;
+        }
+        Ok(nread)
+    }
+
+    fn peek(&mut self) -> serde_bolt::Result<Option<u8>> {
+        if self.available() == 0 && !self.fill_buf().map_err(to_bolt_error)? {
+            return Ok(None);
+        }
+        Ok(self.read_buf.get(self.read_pos).cloned())
+    }
+}
+
+impl<S: Read + Write> Write for NoiseStream<S> {
+    type Error = serde_bolt::Error;
+
+    fn write_all(&mut self, buf: &[u8]) -> serde_bolt::Result<()> {
+        for chunk in buf.chunks(MAX_FRAME_LEN) {
+            self.write_frame(chunk).map_err(to_bolt_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    // An in-memory pipe, one end per side
+    #[derive(Clone)]
+    struct Pipe {
+        incoming: Arc<Mutex<VecDeque<u8>>>,
+        outgoing: Arc<Mutex<VecDeque<u8>>>,
+        // the most bytes returned by one read, to exercise short reads
+        max_read: usize,
+    }
+
+    impl Read for Pipe {
+        type Error = serde_bolt::Error;
+
+        fn read(&mut self, buf: &mut [u8]) -> serde_bolt::Result<usize> {
+            // wait a bit for the other side, then treat a short buffer as EOF
+            for _ in 0..100 {
+                if self.incoming.lock().unwrap().len() >= buf.len() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            let mut incoming = self.incoming.lock().unwrap();
+            let n = min(min(buf.len(), incoming.len()), self.max_read);
+            for b in buf[..n].iter_mut() {
+                *b = incoming.pop_front().unwrap();
+            }
+            Ok(n)
+        }
+
+        fn peek(&mut self) -> serde_bolt::Result<Option<u8>> {
+            Ok(self.incoming.lock().unwrap().front().cloned())
+        }
+    }
+
+    impl Write for Pipe {
+        type Error = serde_bolt::Error;
+
+        fn write_all(&mut self, buf: &[u8]) -> serde_bolt::Result<()> {
+            self.outgoing.lock().unwrap().extend(buf);
+            Ok(())
+        }
+    }
+
+    fn make_pipes(max_read: usize) -> (Pipe, Pipe) {
+        let a = Arc::new(Mutex::new(VecDeque::new()));
+        let b = Arc::new(Mutex::new(VecDeque::new()));
+        (
+            Pipe { incoming: a.clone(), outgoing: b.clone(), max_read },
+            Pipe { incoming: b, outgoing: a, max_read },
+        )
+    }
+
+    fn key(b: u8) -> SecretKey {
+        SecretKey::from_slice(&[b; 32]).unwrap()
+    }
+
+    #[test]
+    fn handshake_and_transport_test() {
+        check_handshake_and_transport(usize::MAX);
+    }
+
+    #[test]
+    fn short_read_test() {
+        check_handshake_and_transport(7);
+    }
+
+    fn check_handshake_and_transport(max_read: usize) {
+        let secp_ctx = Secp256k1::new();
+        let (initiator_pipe, responder_pipe) = make_pipes(max_read);
+        let device_key = key(1);
+        let device_pub = PublicKey::from_secret_key(&secp_ctx, &device_key);
+        let proxy_key = key(3);
+        let proxy_pub = PublicKey::from_secret_key(&secp_ctx, &proxy_key);
+
+        let responder = std::thread::spawn(move || {
+            let secp_ctx = Secp256k1::new();
+            NoiseStream::accept(responder_pipe, &secp_ctx, &device_key, &key(2), &proxy_pub)
+        });
+        let mut initiator =
+            NoiseStream::connect(initiator_pipe, &secp_ctx, &proxy_key, &key(4), &device_pub)
+                .expect("connect");
+        let mut responder = responder.join().unwrap().expect("accept");
+        assert_eq!(*responder.remote_static(), proxy_pub);
+
+        for i in 0..(KEY_ROTATION_INTERVAL as usize + 10) {
+            let msg = alloc::format!("message {}", i);
+            initiator.write_all(msg.as_bytes()).unwrap();
+            let mut buf = alloc::vec![0u8; msg.len()];
+            assert_eq!(responder.read(&mut buf).unwrap(), msg.len());
+            assert_eq!(buf, msg.as_bytes());
+        }
+        responder.write_all(b"reply").unwrap();
+        assert_eq!(initiator.peek().unwrap(), Some(b'r'));
+        let mut buf = [0u8; 5];
+        initiator.read(&mut buf).unwrap();
+        assert_eq!(&buf, b"reply");
+    }
+
+    #[test]
+    fn wrong_pinned_key_test() {
+        let secp_ctx = Secp256k1::new();
+        let (initiator_pipe, responder_pipe) = make_pipes(usize::MAX);
+        let wrong_pub = PublicKey::from_secret_key(&secp_ctx, &key(9));
+        let proxy_pub = PublicKey::from_secret_key(&secp_ctx, &key(3));
+        // act one is buffered, and the missing act two is an EOF
+        assert!(
+            NoiseStream::connect(initiator_pipe, &secp_ctx, &key(3), &key(4), &wrong_pub).is_err()
+        );
+        let res = NoiseStream::try_accept(responder_pipe, &secp_ctx, &key(1), &key(2), &proxy_pub);
+        let (err, responder_pipe) = res.err().expect("accept should fail");
+        assert!(matches!(err, Error::Decrypt));
+        // the stream is given back, and a retry sees the closed initiator
+        let res = NoiseStream::accept(responder_pipe, &secp_ctx, &key(1), &key(3), &proxy_pub);
+        assert!(matches!(res, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn unknown_initiator_test() {
+        let secp_ctx = Secp256k1::new();
+        let (initiator_pipe, responder_pipe) = make_pipes(usize::MAX);
+        let device_key = key(1);
+        let device_pub = PublicKey::from_secret_key(&secp_ctx, &device_key);
+        let pinned_pub = PublicKey::from_secret_key(&secp_ctx, &key(3));
+        let intruder_key = key(5);
+        let intruder_pub = PublicKey::from_secret_key(&secp_ctx, &intruder_key);
+
+        let responder = std::thread::spawn(move || {
+            let secp_ctx = Secp256k1::new();
+            NoiseStream::accept(responder_pipe, &secp_ctx, &device_key, &key(2), &pinned_pub)
+        });
+        // the initiator cannot tell that it was rejected until it uses the session
+        NoiseStream::connect(initiator_pipe, &secp_ctx, &intruder_key, &key(4), &device_pub)
+            .expect("connect");
+        let res = responder.join().unwrap();
+        assert!(matches!(res, Err(Error::UnknownPeer(k)) if k == intruder_pub));
+    }
+}
@@ -1,4 +1,8 @@
 // Bit positions from c-lightning/common/features.h:
 pub const OPT_STATIC_REMOTEKEY: usize = 12;
 pub const OPT_ANCHOR_OUTPUTS: usize = 20;
+pub const OPT_ANCHORS_ZERO_FEE_HTLC_TX: usize = 22;
+// From the simple taproot channels proposal, not yet supported
+pub const OPT_SIMPLE_TAPROOT: usize = 80;
+pub const OPT_SIMPLE_TAPROOT_STAGING: usize = 180;
 pub const OPT_MAX: usize = 32;
@@ -594,6 +594,81 @@ pub struct RemoveBlock {
 #[message_id(2106)]
 pub struct RemoveBlockReply {}
 
+/// Request a node-wide balance report
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2007)]
+pub struct BalanceReport {}
+
+/// Totals in satoshi, see `lightning_signer::node::BalanceReport`
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2107)]
+pub struct BalanceReportReply {
+    pub num_channels: u32,
+    pub claimable_sat: u64,
+    pub offered_htlc_sat: u64,
+    pub received_htlc_sat: u64,
+    pub sweeping_sat: u64,
+}
+
+/// Forget a channel that never became usable, e.g. after a failed open
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2008)]
+pub struct ForgetChannel {
+    pub node_id: PubKey,
+    pub dbid: u64,
+}
+
+///
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2108)]
+pub struct ForgetChannelReply {}
+
+/// Ready a dual-funded channel, where both sides contribute to the funding
+/// output.  Replied to with `ReadyChannelReply`.
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2009)]
+pub struct ReadyDualFundedChannel {
+    pub channel: ReadyChannel,
+    pub local_funding_value: u64,
+}
+
+/// Sign a BOLT 12 TLV stream, so that the signer sees the invoice fields.
+/// Replied to with `SignBolt12Reply`.
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2010)]
+pub struct SignBolt12Tlv {
+    pub message_name: WireString,
+    pub field_name: WireString,
+    pub tlv_stream: LargeBytes,
+    pub public_tweak: Vec<u8>,
+}
+
+/// Add a BOLT 12 invoice we are about to pay, signed by the payee
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2011)]
+pub struct AddBolt12Invoice {
+    pub tlv_stream: LargeBytes,
+}
+
+///
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2111)]
+pub struct AddBolt12InvoiceReply {}
+
+/// Add a keysend payment we are about to send, which has no invoice
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2012)]
+pub struct AddKeysend {
+    pub payee: PubKey,
+    pub payment_hash: Sha256,
+    pub amount_msat: u64,
+}
+
+///
+#[derive(SerBolt, Debug, Serialize, Deserialize)]
+#[message_id(2112)]
+pub struct AddKeysendReply {}
+
 /// An unknown message
 #[derive(Debug, Serialize)]
 pub struct Unknown {
@@ -673,6 +748,16 @@ pub enum Message {
     AddBlockReply(AddBlockReply),
     RemoveBlock(RemoveBlock),
     RemoveBlockReply(RemoveBlockReply),
+    BalanceReport(BalanceReport),
+    BalanceReportReply(BalanceReportReply),
+    ForgetChannel(ForgetChannel),
+    ForgetChannelReply(ForgetChannelReply),
+    ReadyDualFundedChannel(ReadyDualFundedChannel),
+    SignBolt12Tlv(SignBolt12Tlv),
+    AddBolt12Invoice(AddBolt12Invoice),
+    AddBolt12InvoiceReply(AddBolt12InvoiceReply),
+    AddKeysend(AddKeysend),
+    AddKeysendReply(AddKeysendReply),
     Unknown(Unknown),
 }
 
@@ -867,6 +952,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn message_name_test() {
+        let msg = Ping { id: 1, message: WireString(b"hello".to_vec()) };
+        assert_eq!(from_vec(msg.as_vec()).unwrap().message_name(), "Ping");
+        let unknown = from_vec(vec![0xff, 0xff]).unwrap();
+        assert_eq!(unknown.message_name(), "Unknown");
+    }
+
     // ignore tests for now, the trace capture was not on the lightning-signer branch
     #[test]
     #[ignore]
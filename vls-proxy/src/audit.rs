@@ -0,0 +1,15 @@
+//! The signer audit log
+
+use std::env;
+use std::sync::Arc;
+
+use lightning_signer_server::audit::FileAuditSink;
+use vls_protocol_signer::audit::AuditSink;
+
+/// Open the audit log at the path in VLS_AUDIT_LOG, if set
+pub fn audit_sink_from_env() -> Option<Arc<dyn AuditSink>> {
+    env::var("VLS_AUDIT_LOG").ok().map(|path| {
+        let sink = FileAuditSink::new(&path).expect(&format!("open audit log {}", path));
+        Arc::new(sink) as Arc<dyn AuditSink>
+    })
+}
@@ -0,0 +1,37 @@
+//! Verify the hash chain of a signer audit log
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::exit;
+
+use clap::{App, Arg};
+use vls_protocol_signer::audit::{verify, AuditEntry};
+
+pub fn main() {
+    let app = App::new("vls-audit-verify")
+        .about("Verify the hash chain of a signer audit log")
+        .arg(Arg::new("path").about("audit log file").required(true))
+        .arg(Arg::from("--print print the entries after verifying"));
+    let matches = app.get_matches();
+    let path = matches.value_of("path").unwrap();
+
+    let file = File::open(path).expect("open audit log");
+    let lines: Vec<String> =
+        BufReader::new(file).lines().collect::<Result<_, _>>().expect("read audit log");
+
+    match verify(lines.iter().map(|l| l.as_str())) {
+        Ok(count) => {
+            if matches.is_present("print") {
+                for line in lines.iter() {
+                    println!("{:?}", AuditEntry::from_line(line).expect("parsed").record);
+                }
+            }
+            let last_hash = lines.last().and_then(|l| l.rsplit('\t').next()).unwrap_or("-");
+            println!("ok: {} entries, last hash {}", count, last_hash);
+        }
+        Err(e) => {
+            println!("FAILED: {}", e);
+            exit(1);
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -10,34 +13,37 @@ use tokio::task::spawn_blocking;
 use bitcoin::Network;
 use log::{debug, error, info};
 use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
-use secp256k1::PublicKey;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
 
 use lightning_signer::bitcoin;
 use lightning_signer::bitcoin::secp256k1;
 use vls_protocol::model::Secret;
-use vls_protocol::{msgs, msgs::Message, serde_bolt, serde_bolt::WireString, Error, Result};
+use vls_protocol::msgs::{self, DeBolt, Message, SerBolt};
+use vls_protocol::{serde_bolt, serde_bolt::WireString, Error, Result};
 use vls_protocol_client::SignerPort;
+use vls_protocol_signer::noise::NoiseStream;
 use vls_protocol_signer::vls_protocol;
 use vls_proxy::client::Client;
+use vls_proxy::recorder::{SessionClient, SessionRecorder};
 use vls_proxy::util::{read_allowlist, read_integration_test_seed};
 
-pub struct SerialWrap {
+/// Raw access to the serial port
+pub struct SerialPort {
     inner: File,
     peek: Option<u8>,
-    sequence: u16,
 }
 
-impl SerialWrap {
+impl SerialPort {
     fn new(inner: File) -> Self {
         let fd = inner.as_raw_fd();
         let mut termios = tcgetattr(fd).expect("tcgetattr");
         cfmakeraw(&mut termios);
         tcsetattr(fd, SetArg::TCSANOW, &termios).expect("tcsetattr");
-        Self { inner, peek: None, sequence: 0 }
+        Self { inner, peek: None }
     }
 }
 
-impl serde_bolt::Read for SerialWrap {
+impl serde_bolt::Read for SerialPort {
     type Error = serde_bolt::Error;
 
     fn read(&mut self, mut buf: &mut [u8]) -> serde_bolt::Result<usize> {
@@ -82,7 +88,7 @@ impl serde_bolt::Read for SerialWrap {
     }
 }
 
-impl serde_bolt::Write for SerialWrap {
+impl serde_bolt::Write for SerialPort {
     type Error = serde_bolt::Error;
 
     fn write_all(&mut self, buf: &[u8]) -> serde_bolt::Result<()> {
@@ -91,10 +97,114 @@ impl serde_bolt::Write for SerialWrap {
     }
 }
 
-pub fn connect(serial_port: String) -> anyhow::Result<SerialWrap> {
+enum SerialStream {
+    Plain(SerialPort),
+    Noise(NoiseStream<SerialPort>),
+}
+
+/// The serial link to the device, optionally encrypted with Noise_XK
+pub struct SerialWrap {
+    inner: SerialStream,
+    sequence: u16,
+}
+
+impl SerialWrap {
+    fn new(inner: SerialStream) -> Self {
+        Self { inner, sequence: 0 }
+    }
+}
+
+impl serde_bolt::Read for SerialWrap {
+    type Error = serde_bolt::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> serde_bolt::Result<usize> {
+        match &mut self.inner {
+            SerialStream::Plain(s) => serde_bolt::Read::read(s, buf),
+            SerialStream::Noise(s) => serde_bolt::Read::read(s, buf),
+        }
+    }
+
+    fn peek(&mut self) -> serde_bolt::Result<Option<u8>> {
+        match &mut self.inner {
+            SerialStream::Plain(s) => serde_bolt::Read::peek(s),
+            SerialStream::Noise(s) => serde_bolt::Read::peek(s),
+        }
+    }
+}
+
+impl serde_bolt::Write for SerialWrap {
+    type Error = serde_bolt::Error;
+
+    fn write_all(&mut self, buf: &[u8]) -> serde_bolt::Result<()> {
+        match &mut self.inner {
+            SerialStream::Plain(s) => serde_bolt::Write::write_all(s, buf),
+            SerialStream::Noise(s) => serde_bolt::Write::write_all(s, buf),
+        }
+    }
+}
+
+// The proxy static key, which the device pins
+const NOISE_KEY_FILE: &str = "serial_noise_key";
+
+// The device static key to pin, if the link should be encrypted
+fn read_device_noise_pubkey() -> anyhow::Result<Option<PublicKey>> {
+    match env::var("VLS_SERIAL_NOISE_PUBKEY") {
+        Ok(s) => Ok(Some(PublicKey::from_str(&s).map_err(|e| {
+            anyhow::anyhow!("VLS_SERIAL_NOISE_PUBKEY must be a hex pubkey: {}", e)
+        })?)),
+        Err(_) => Ok(None),
+    }
+}
+
+// Load the proxy static key, generating it on first use.  The device only
+// accepts the key it was provisioned with, so it must survive restarts.
+fn read_or_create_noise_key(
+    rng: &mut secp256k1::rand::rngs::ThreadRng,
+) -> anyhow::Result<SecretKey> {
+    match fs::read(NOISE_KEY_FILE) {
+        Ok(data) => Ok(SecretKey::from_slice(&data)
+            .map_err(|e| anyhow::anyhow!("bad {}: {}", NOISE_KEY_FILE, e))?),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let key = SecretKey::new(rng);
+            let mut file =
+                OpenOptions::new().write(true).create_new(true).mode(0o600).open(NOISE_KEY_FILE)?;
+            file.write_all(&key.secret_bytes())?;
+            file.sync_all()?;
+            info!("created {}", NOISE_KEY_FILE);
+            Ok(key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn open_stream(port: SerialPort) -> anyhow::Result<SerialStream> {
+    if let Some(device_pubkey) = read_device_noise_pubkey()? {
+        let secp_ctx = Secp256k1::new();
+        let mut rng = secp256k1::rand::thread_rng();
+        let local_static = read_or_create_noise_key(&mut rng)?;
+        info!("noise static key {}", PublicKey::from_secret_key(&secp_ctx, &local_static));
+        let ephemeral = SecretKey::new(&mut rng);
+        let stream =
+            NoiseStream::connect(port, &secp_ctx, &local_static, &ephemeral, &device_pubkey)
+                .map_err(|e| anyhow::anyhow!("noise handshake failed: {:?}", e))?;
+        info!("noise session established with {}", device_pubkey);
+        Ok(SerialStream::Noise(stream))
+    } else {
+        Ok(SerialStream::Plain(port))
+    }
+}
+
+/// Connect to the device and initialize the signer.
+///
+/// The init exchange is recorded too, if a recorder is given, so that a
+/// replay of the session starts from the same signer state.
+pub fn connect(
+    serial_port: String,
+    recorder: Option<&SessionRecorder>,
+) -> anyhow::Result<SerialWrap> {
     info!("connecting to {}", serial_port);
     let file = File::options().read(true).write(true).open(serial_port)?;
-    let mut serial = SerialWrap::new(file);
+    let mut serial = SerialWrap::new(open_stream(SerialPort::new(file))?);
     let allowlist =
         read_allowlist().into_iter().map(|s| WireString(s.as_bytes().to_vec())).collect::<Vec<_>>();
     let seed = read_integration_test_seed().map(|s| Secret(s)).or(Some(Secret([1; 32])));
@@ -106,11 +216,16 @@ pub fn connect(serial_port: String) -> anyhow::Result<SerialWrap> {
         dev_seed: seed,
         dev_allowlist: allowlist,
     };
+    let request = init.as_vec();
     let sequence = 0;
     msgs::write_serial_request_header(&mut serial, sequence, 0)?;
-    msgs::write(&mut serial, init)?;
+    msgs::write_vec(&mut serial, request.clone())?;
     msgs::read_serial_response_header(&mut serial, sequence)?;
-    let init_reply: msgs::HsmdInit2Reply = msgs::read_message(&mut serial)?;
+    let reply = msgs::read_raw(&mut serial)?;
+    if let Some(recorder) = recorder {
+        recorder.record(None, &request, &reply);
+    }
+    let init_reply = msgs::HsmdInit2Reply::from_vec(reply)?;
     info!("init reply {:?}", init_reply);
     Ok(serial)
 }
@@ -123,21 +238,26 @@ pub struct ClientId {
 
 pub struct SerialSignerPort {
     serial: Arc<Mutex<SerialWrap>>,
+    recorder: Option<Arc<SessionRecorder>>,
 }
 
 #[async_trait]
 impl SignerPort for SerialSignerPort {
     async fn handle_message(&self, message: Vec<u8>) -> Result<Vec<u8>> {
         let serial = Arc::clone(&self.serial);
+        let recorder = self.recorder.clone();
         spawn_blocking(move || {
             let mut serial_guard = serial.lock().unwrap();
             let serial = &mut *serial_guard;
             let dbid = 0;
             msgs::write_serial_request_header(serial, serial.sequence, dbid)?;
-            msgs::write_vec(serial, message)?;
+            msgs::write_vec(serial, message.clone())?;
             msgs::read_serial_response_header(serial, serial.sequence)?;
             serial.sequence = serial.sequence.wrapping_add(1);
             let reply = msgs::read_raw(serial)?;
+            if let Some(recorder) = recorder {
+                recorder.record(None, &message, &reply);
+            }
             Ok(reply)
         })
         .await
@@ -145,13 +265,13 @@ impl SignerPort for SerialSignerPort {
     }
 
     fn clone(&self) -> Box<dyn SignerPort> {
-        Box::new(Self { serial: self.serial.clone() })
+        Box::new(Self { serial: self.serial.clone(), recorder: self.recorder.clone() })
     }
 }
 
 impl SerialSignerPort {
-    pub fn new(serial: Arc<Mutex<SerialWrap>>) -> Self {
-        SerialSignerPort { serial }
+    pub fn new(serial: Arc<Mutex<SerialWrap>>, recorder: Option<Arc<SessionRecorder>>) -> Self {
+        SerialSignerPort { serial, recorder }
     }
 }
 
@@ -163,19 +283,29 @@ pub struct SignerLoop<C: 'static + Client> {
     log_prefix: String,
     serial: Arc<Mutex<SerialWrap>>,
     client_id: Option<ClientId>,
+    recorder: Option<Arc<SessionRecorder>>,
 }
 
 impl<C: 'static + Client> SignerLoop<C> {
     /// Create a loop for the root (lightningd) connection, but doesn't start it yet
-    pub fn new(client: C, serial: Arc<Mutex<SerialWrap>>) -> Self {
+    pub fn new(
+        client: C,
+        serial: Arc<Mutex<SerialWrap>>,
+        recorder: Option<Arc<SessionRecorder>>,
+    ) -> Self {
         let log_prefix = format!("{}/{}", std::process::id(), client.id());
-        Self { client, log_prefix, serial, client_id: None }
+        Self { client, log_prefix, serial, client_id: None, recorder }
     }
 
     // Create a loop for a non-root connection
-    fn new_for_client(client: C, serial: Arc<Mutex<SerialWrap>>, client_id: ClientId) -> Self {
+    fn new_for_client(
+        client: C,
+        serial: Arc<Mutex<SerialWrap>>,
+        client_id: ClientId,
+        recorder: Option<Arc<SessionRecorder>>,
+    ) -> Self {
         let log_prefix = format!("{}/{}", std::process::id(), client.id());
-        Self { client, log_prefix, serial, client_id: Some(client_id) }
+        Self { client, log_prefix, serial, client_id: Some(client_id), recorder }
     }
 
     /// Start the read loop
@@ -201,8 +331,12 @@ impl<C: 'static + Client> SignerLoop<C> {
                     info!("new client {} -> {}", self.log_prefix, new_client.id());
                     let peer_id = PublicKey::from_slice(&m.peer_id.0).expect("client pubkey"); // we don't expect a bad key from lightningd parent
                     let client_id = ClientId { peer_id, dbid: m.dbid };
-                    let mut new_loop =
-                        SignerLoop::new_for_client(new_client, self.serial.clone(), client_id);
+                    let mut new_loop = SignerLoop::new_for_client(
+                        new_client,
+                        self.serial.clone(),
+                        client_id,
+                        self.recorder.clone(),
+                    );
                     thread::spawn(move || new_loop.start());
                 }
                 Message::Memleak(_) => {
@@ -225,10 +359,17 @@ impl<C: 'static + Client> SignerLoop<C> {
         let serial = &mut *serial_guard;
         let dbid = self.client_id.as_ref().map(|c| c.dbid).unwrap_or(0);
         msgs::write_serial_request_header(serial, serial.sequence, dbid)?;
-        msgs::write_vec(serial, message)?;
+        msgs::write_vec(serial, message.clone())?;
         msgs::read_serial_response_header(serial, serial.sequence)?;
         serial.sequence = serial.sequence.wrapping_add(1);
         let reply = msgs::read_raw(serial)?;
+        if let Some(recorder) = self.recorder.as_ref() {
+            let client = self
+                .client_id
+                .as_ref()
+                .map(|c| SessionClient { peer_id: c.peer_id.serialize(), dbid: c.dbid });
+            recorder.record(client, &message, &reply);
+        }
         Ok(reply)
     }
 }
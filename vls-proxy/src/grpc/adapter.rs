@@ -15,6 +15,7 @@ use super::hsmd::{
     hsmd_server, HsmRequestContext, PingReply, PingRequest, SignerRequest, SignerResponse,
 };
 use super::incoming::TcpIncoming;
+use crate::recorder::{session_recorder_from_env, SessionClient, SessionRecorder};
 use std::sync::atomic::{AtomicU64, Ordering};
 use tonic::transport::Error;
 use triggered::{Listener, Trigger};
@@ -32,6 +33,7 @@ pub struct ProtocolAdapter {
     #[allow(unused)]
     shutdown_trigger: Trigger,
     shutdown_signal: Listener,
+    recorder: Option<Arc<SessionRecorder>>,
 }
 
 pub type SignerStream =
@@ -42,6 +44,7 @@ impl ProtocolAdapter {
         receiver: Receiver<ChannelRequest>,
         shutdown_trigger: Trigger,
         shutdown_signal: Listener,
+        recorder: Option<Arc<SessionRecorder>>,
     ) -> Self {
         ProtocolAdapter {
             receiver: Arc::new(Mutex::new(receiver)),
@@ -51,6 +54,7 @@ impl ProtocolAdapter {
             })),
             shutdown_trigger,
             shutdown_signal,
+            recorder,
         }
     }
     // Get requests from the parent process and feed them to gRPC.
@@ -107,6 +111,7 @@ impl ProtocolAdapter {
     pub fn start_stream_reader(&self, mut stream: Streaming<SignerResponse>) -> JoinHandle<()> {
         let requests = self.requests.clone();
         let shutdown_signal = self.shutdown_signal.clone();
+        let recorder = self.recorder.clone();
         tokio::spawn(async move {
             loop {
                 tokio::select! {
@@ -120,6 +125,9 @@ impl ProtocolAdapter {
                                 info!("got signer response {}", resp.request_id);
                                 if !resp.error.is_empty() {
                                     error!("signer error: {}", resp.error);
+                                    if let Some(details) = resp.error_details.as_ref() {
+                                        error!("signer error details: {:?}", details);
+                                    }
                                     // all signer errors are fatal
                                     // TODO exit more cleanly
                                     // Right now there's no clean way to stop the UNIX fd reader
@@ -131,6 +139,12 @@ impl ProtocolAdapter {
                                 let mut reqs = requests.lock().await;
                                 let channel_req_opt = reqs.requests.remove(&resp.request_id);
                                 if let Some(channel_req) = channel_req_opt {
+                                    if let Some(recorder) = recorder.as_ref() {
+                                        let client = channel_req.client_id.as_ref().map(|c| {
+                                            SessionClient { peer_id: c.peer_id, dbid: c.dbid }
+                                        });
+                                        recorder.record(client, &channel_req.message, &resp.message);
+                                    }
                                     let reply = ChannelReply { reply: resp.message };
                                     let send_res = channel_req.reply_tx.send(reply);
                                     if send_res.is_err() {
@@ -197,8 +211,12 @@ impl HsmdService {
     /// Create the service
     pub fn new(shutdown_trigger: Trigger, shutdown_signal: Listener) -> Self {
         let (sender, receiver) = mpsc::channel(1000);
-        let adapter =
-            ProtocolAdapter::new(receiver, shutdown_trigger.clone(), shutdown_signal.clone());
+        let adapter = ProtocolAdapter::new(
+            receiver,
+            shutdown_trigger.clone(),
+            shutdown_signal.clone(),
+            session_recorder_from_env(),
+        );
 
         HsmdService { shutdown_trigger, adapter, sender }
     }
@@ -1,8 +1,10 @@
 use super::hsmd::{self, PingRequest, SignerRequest, SignerResponse};
-use crate::util::{read_allowlist, read_integration_test_seed};
+use crate::audit::audit_sink_from_env;
+use crate::util::{rate_limits_from_env, read_allowlist, read_integration_test_seed};
 use http::Uri;
 use lightning_signer::bitcoin::Network;
 use lightning_signer::persist::Persist;
+use lightning_signer::util::rate_limit::StdClock;
 use lightning_signer::util::status::Status;
 use lightning_signer_server::persist::persist_json::KVJsonPersister;
 use log::{error, info};
@@ -13,6 +15,7 @@ use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
+use url::Url;
 use vls_protocol_signer::handler::{Error, Handler, RootHandler};
 use vls_protocol_signer::vls_protocol::model::PubKey;
 use vls_protocol_signer::vls_protocol::msgs;
@@ -30,18 +33,32 @@ pub async fn start_signer_localhost(port: u16) {
         .expect("uri"); // infallible by construction
 
     let network = Network::Testnet; // FIXME
-    connect("remote_hsmd.kv", uri, network).await;
+    connect("remote_hsmd.kv", uri, network, None).await;
     info!("signer stopping");
 }
 
+/// Where to serve metrics, and the chain source to measure the tracker lag against
+pub struct MetricsConfig {
+    /// Serve Prometheus metrics on this localhost port
+    pub port: u16,
+    /// The bitcoind RPC URL, for the tracker lag gauge
+    pub rpc_url: Option<Url>,
+}
+
 /// Signer binary entry point
 #[tokio::main(worker_threads = 2)]
-pub async fn start_signer(datadir: &str, uri: Uri, network: Network) {
-    connect(datadir, uri, network).await;
+pub async fn start_signer(
+    datadir: &str,
+    uri: Uri,
+    network: Network,
+    metrics: Option<MetricsConfig>,
+) {
+    connect(datadir, uri, network, metrics).await;
     info!("signer stopping");
 }
 
-async fn connect(datadir: &str, uri: Uri, network: Network) {
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+async fn connect(datadir: &str, uri: Uri, network: Network, metrics: Option<MetricsConfig>) {
     let data_path = format!("{}/{}", datadir, network.to_string());
     let mut client = hsmd::hsmd_client::HsmdClient::connect(uri).await.expect("client connect");
     let result = client.ping(PingRequest { message: "hello".to_string() }).await.expect("ping");
@@ -50,8 +67,30 @@ async fn connect(datadir: &str, uri: Uri, network: Network) {
     let (sender, receiver) = mpsc::channel(1);
     let response_stream = ReceiverStream::new(receiver);
     let persister: Arc<dyn Persist> = Arc::new(KVJsonPersister::new(&data_path));
+    #[cfg(feature = "metrics")]
+    let persister: Arc<dyn Persist> = if metrics.is_some() {
+        Arc::new(metrics::MetricsPersister::new(persister))
+    } else {
+        persister
+    };
     let allowlist = read_allowlist();
-    let root_handler = RootHandler::new(0, read_integration_test_seed(), persister, allowlist);
+    let mut root_handler = RootHandler::new(0, read_integration_test_seed(), persister, allowlist);
+    if let Some(audit) = audit_sink_from_env() {
+        root_handler = root_handler.with_audit(audit);
+    }
+    match rate_limits_from_env() {
+        Ok(Some(limits)) =>
+            root_handler = root_handler.with_rate_limits(limits, Arc::new(StdClock)),
+        Ok(None) => {}
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(config) = metrics {
+        metrics::start(config, Arc::clone(&root_handler.node));
+    }
 
     let mut request_stream = client.signer_stream(response_stream).await.unwrap().into_inner();
 
@@ -74,6 +113,7 @@ async fn connect(datadir: &str, uri: Uri, network: Network) {
                             request_id,
                             message: vec![],
                             error: format!("{:?}", e),
+                            error_details: error_details(&e),
                         };
                         let res = sender.send(response).await;
                         if res.is_err() {
@@ -93,13 +133,27 @@ async fn connect(datadir: &str, uri: Uri, network: Network) {
 
 fn handle(request: SignerRequest, root_handler: &RootHandler) -> StdResult<SignerResponse, Error> {
     let msg = msgs::from_vec(request.message)?;
+    #[cfg(feature = "metrics")]
+    let (method, started) = (msg.message_name(), std::time::Instant::now());
+    let result = handle_message(request.request_id, request.context, msg, root_handler);
+    #[cfg(feature = "metrics")]
+    metrics::observe(method, started, &result);
+    result
+}
+
+fn handle_message(
+    request_id: u64,
+    context: Option<hsmd::HsmRequestContext>,
+    msg: msgs::Message,
+    root_handler: &RootHandler,
+) -> StdResult<SignerResponse, Error> {
     info!(
         "signer got request {} dbid {} - {:?}",
-        request.request_id,
-        request.context.as_ref().map(|c| c.dbid).unwrap_or(0),
+        request_id,
+        context.as_ref().map(|c| c.dbid).unwrap_or(0),
         msg
     );
-    let reply = if let Some(context) = request.context {
+    let reply = if let Some(context) = context {
         let peer = PubKey(
             context
                 .peer_id
@@ -111,7 +165,99 @@ fn handle(request: SignerRequest, root_handler: &RootHandler) -> StdResult<Signe
     } else {
         root_handler.handle(msg)?
     };
-    info!("signer sending reply {} - {:?}", request.request_id, reply);
+    info!("signer sending reply {} - {:?}", request_id, reply);
     let ser_res = reply.as_vec();
-    Ok(SignerResponse { request_id: request.request_id, message: ser_res, error: String::new() })
+    Ok(SignerResponse { request_id, message: ser_res, error: String::new(), error_details: None })
+}
+
+fn error_details(e: &Error) -> Option<hsmd::ErrorDetails> {
+    let status = match e {
+        Error::SigningError(status) => status,
+        Error::ProtocolError(_) => return None,
+    };
+    let details = status.details();
+    Some(hsmd::ErrorDetails {
+        code: status.code() as i32,
+        kind: details.kind.clone().unwrap_or_default(),
+        rule: details.rule.clone().unwrap_or_default(),
+        channel_id: details.channel_id.clone().unwrap_or_default(),
+        commitment_number: details.commitment_number.unwrap_or(0),
+        has_commitment_number: details.commitment_number.is_some(),
+        value: details.value.clone().unwrap_or_default(),
+        allowed: details.allowed.clone().unwrap_or_default(),
+    })
+}
+
+#[cfg(feature = "metrics")]
+mod metrics {
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use bitcoind_client::BitcoindClient;
+    use lightning_signer::node::Node;
+    use lightning_signer::util::status::Code;
+    use lightning_signer_server::metrics;
+    pub use lightning_signer_server::metrics::MetricsPersister;
+    use log::error;
+    use url::Url;
+    use vls_frontend::FollowerHeights;
+    use vls_protocol_signer::handler::Error;
+
+    use super::MetricsConfig;
+
+    const SOURCE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Record a handled request
+    pub fn observe<T>(method: &str, started: Instant, result: &Result<T, Error>) {
+        if let Err(Error::SigningError(status)) = result {
+            if status.code() == Code::FailedPrecondition {
+                metrics::observe_policy_violation(status.details(), status.message());
+            }
+        }
+        metrics::observe_request(method, started, result.is_ok());
+    }
+
+    /// Serve the metrics.
+    ///
+    /// Blocks are fed to this signer by the node, so the tracker lag is
+    /// measured against our own view of the chain source, if configured.
+    pub fn start(config: MetricsConfig, node: Arc<Node>) {
+        let source_height = Arc::new(AtomicU32::new(0));
+        if let Some(rpc_url) = config.rpc_url {
+            tokio::spawn(poll_source_height(rpc_url, Arc::clone(&source_height)));
+        }
+        let refresh = move || {
+            metrics::update_node_gauges(&node);
+            let source_height = source_height.load(Ordering::Relaxed);
+            if source_height > 0 {
+                metrics::update_follower_gauges(&[FollowerHeights {
+                    tracker: node.log_prefix(),
+                    tracker_height: node.get_tracker().height(),
+                    source_height,
+                }]);
+            }
+        };
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+        tokio::spawn(metrics::serve(addr, Arc::new(refresh)));
+    }
+
+    async fn poll_source_height(rpc_url: Url, source_height: Arc<AtomicU32>) {
+        let client = BitcoindClient::new(
+            rpc_url.host_str().expect("rpc host").to_owned(),
+            rpc_url.port().expect("rpc port"),
+            rpc_url.username().to_owned(),
+            rpc_url.password().to_owned().expect("rpc password").to_owned(),
+        )
+        .await;
+        let mut interval = tokio::time::interval(SOURCE_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match client.get_blockchain_info().await {
+                Ok(info) => source_height.store(info.latest_height as u32, Ordering::Relaxed),
+                Err(err) => error!("metrics chain source: {}", err),
+            }
+        }
+    }
 }
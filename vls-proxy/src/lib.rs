@@ -1,8 +1,10 @@
 //! Proxy connection from node to VLS.
 //! In particular, a replacement for CLN's hsmd binary.
 
+pub mod audit;
 pub mod client;
 pub mod connection;
 pub mod grpc;
 pub mod portfront;
+pub mod recorder;
 pub mod util;
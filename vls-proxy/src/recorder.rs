@@ -0,0 +1,143 @@
+//! Recording of complete hsmd sessions, for replay with `vls-replay`
+
+use std::convert::TryInto;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use lightning_signer::bitcoin::hashes::hex::{FromHex, ToHex};
+use log::error;
+
+/// The client a request was made for, if not the root (lightningd) client
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SessionClient {
+    pub peer_id: [u8; 33],
+    pub dbid: u64,
+}
+
+/// A request and the signer's reply
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionRecord {
+    pub client: Option<SessionClient>,
+    pub request: Vec<u8>,
+    pub reply: Vec<u8>,
+}
+
+impl SessionRecord {
+    /// Format as a line of tab separated fields: dbid, peer id, request, reply.
+    ///
+    /// The client fields are "-" for the root client.
+    pub fn to_line(&self) -> String {
+        let (dbid, peer_id) = match &self.client {
+            Some(c) => (c.dbid.to_string(), c.peer_id.to_hex()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        format!("{}\t{}\t{}\t{}", dbid, peer_id, self.request.to_hex(), self.reply.to_hex())
+    }
+
+    /// Parse a line produced by [`SessionRecord::to_line`]
+    pub fn from_line(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 4 {
+            return Err(format!("expected 4 fields, got {}", fields.len()));
+        }
+        let client = match (fields[0], fields[1]) {
+            ("-", "-") => None,
+            (dbid, peer_id) => {
+                let dbid = dbid.parse().map_err(|_| format!("bad dbid {}", dbid))?;
+                let peer_id = Vec::from_hex(peer_id)
+                    .ok()
+                    .and_then(|v| v.as_slice().try_into().ok())
+                    .ok_or_else(|| format!("bad peer id {}", peer_id))?;
+                Some(SessionClient { peer_id, dbid })
+            }
+        };
+        let request = Vec::from_hex(fields[2]).map_err(|_| "bad request hex".to_string())?;
+        let reply = Vec::from_hex(fields[3]).map_err(|_| "bad reply hex".to_string())?;
+        Ok(SessionRecord { client, request, reply })
+    }
+}
+
+/// Appends session records to a file, one per line
+pub struct SessionRecorder {
+    file: Mutex<File>,
+}
+
+impl SessionRecorder {
+    /// Open or create the file, appending to an existing session
+    pub fn new(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SessionRecorder { file: Mutex::new(file) })
+    }
+
+    /// Record a request and its reply
+    pub fn record(&self, client: Option<SessionClient>, request: &[u8], reply: &[u8]) {
+        let record = SessionRecord { client, request: request.to_vec(), reply: reply.to_vec() };
+        let mut file = self.file.lock().unwrap();
+        // a partial recording is still useful, so don't fail the signer over it
+        if let Err(e) = writeln!(file, "{}", record.to_line()) {
+            error!("failed to write session recording: {}", e);
+        }
+    }
+}
+
+/// Open the session recording at the path in VLS_SESSION_RECORD, if set
+pub fn session_recorder_from_env() -> Option<Arc<SessionRecorder>> {
+    env::var("VLS_SESSION_RECORD").ok().map(|path| {
+        let recorder =
+            SessionRecorder::new(&path).expect(&format!("open session recording {}", path));
+        Arc::new(recorder)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn make_records() -> Vec<SessionRecord> {
+        vec![
+            SessionRecord {
+                client: None,
+                request: vec![0x03, 0xf3, 0x00],
+                reply: vec![0x00, 0x6f],
+            },
+            SessionRecord {
+                client: Some(SessionClient { peer_id: [2; 33], dbid: 7 }),
+                request: vec![0x00, 0x05],
+                reply: vec![],
+            },
+        ]
+    }
+
+    #[test]
+    fn line_round_trip_test() {
+        for record in make_records() {
+            assert_eq!(SessionRecord::from_line(&record.to_line()), Ok(record));
+        }
+        assert!(SessionRecord::from_line("-\t-\t00").is_err());
+        assert!(SessionRecord::from_line("x\t02\t00\t00").is_err());
+        assert!(SessionRecord::from_line("1\t0202\t00\t00").is_err());
+        assert!(SessionRecord::from_line("-\t-\tzz\t00").is_err());
+    }
+
+    #[test]
+    fn recorder_round_trip_test() {
+        let path = env::temp_dir().join(format!("vls-session-test-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let records = make_records();
+
+        // a second recorder appends to the same session
+        for record in &records {
+            let recorder = SessionRecorder::new(path).unwrap();
+            recorder.record(record.client, &record.request, &record.reply);
+        }
+
+        let text = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let read: Vec<_> = text.lines().map(|l| SessionRecord::from_line(l).unwrap()).collect();
+        assert_eq!(read, records);
+    }
+}
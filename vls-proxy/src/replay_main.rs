@@ -0,0 +1,102 @@
+//! Replay a recorded hsmd session against a fresh signer and compare the replies.
+//!
+//! Sessions are recorded by the proxies when VLS_SESSION_RECORD is set.  The recording
+//! should start from a fresh signer state.  Chain updates made directly by an in-process
+//! frontend (`remote_hsmd_vls`) are not recorded, so chain dependent replies may differ.
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::exit;
+use std::sync::Arc;
+
+use clap::{App, Arg};
+use lightning_signer::bitcoin::hashes::hex::{FromHex, ToHex};
+use lightning_signer::persist::{DummyPersister, Persist};
+use vls_protocol::model::PubKey;
+use vls_protocol::msgs;
+use vls_protocol_signer::handler::{Handler, RootHandler};
+use vls_proxy::recorder::SessionRecord;
+use vls_proxy::util::{read_allowlist, read_integration_test_seed, setup_logging};
+
+// Decode a message for display, falling back to hex
+fn describe(v: &[u8]) -> String {
+    match msgs::from_vec(v.to_vec()) {
+        Ok(msg) => format!("{:?}", msg),
+        Err(_) => v.to_hex(),
+    }
+}
+
+pub fn main() {
+    let app = App::new("vls-replay")
+        .about("Replay a recorded hsmd session against a fresh signer and compare the replies")
+        .arg(Arg::new("session").about("session recording file").required(true))
+        .arg(
+            Arg::new("seed")
+                .about("node seed in hex, defaults to the hsm_secret file")
+                .long("seed")
+                .takes_value(true),
+        )
+        .arg(Arg::from("--stop stop at the first mismatch"))
+        .arg(Arg::from("--verbose log signer activity"));
+    let matches = app.get_matches();
+    if matches.is_present("verbose") {
+        setup_logging("replay", "info");
+    }
+
+    let seed = match matches.value_of("seed") {
+        Some(hex) => Some(
+            Vec::from_hex(hex)
+                .ok()
+                .and_then(|v| v.as_slice().try_into().ok())
+                .expect("seed must be 32 bytes of hex"),
+        ),
+        None => read_integration_test_seed(),
+    };
+    if seed.is_none() {
+        eprintln!("no seed - use --seed or provide an hsm_secret file");
+        exit(2);
+    }
+
+    // a fresh signer, so that nothing is carried over from a previous run
+    let persister: Arc<dyn Persist> = Arc::new(DummyPersister);
+    let root_handler = RootHandler::new(0, seed, persister, read_allowlist());
+
+    let path = matches.value_of("session").unwrap();
+    let file = File::open(path).expect("open session recording");
+    let mut count = 0;
+    let mut mismatches = 0;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.expect("read session recording");
+        let record = SessionRecord::from_line(&line)
+            .unwrap_or_else(|e| panic!("line {}: malformed record: {}", index + 1, e));
+        let msg = msgs::from_vec(record.request.clone())
+            .unwrap_or_else(|e| panic!("line {}: bad request: {:?}", index + 1, e));
+        let result = match &record.client {
+            Some(c) => root_handler.for_new_client(0, PubKey(c.peer_id), c.dbid).handle(msg),
+            None => root_handler.handle(msg),
+        };
+        count += 1;
+        let reply = match result {
+            Ok(reply) => reply.as_vec(),
+            Err(e) => {
+                println!("line {}: error {:?}", index + 1, e);
+                Vec::new()
+            }
+        };
+        if reply != record.reply {
+            mismatches += 1;
+            println!("line {}: reply mismatch for {}", index + 1, describe(&record.request));
+            println!("  recorded: {}", describe(&record.reply));
+            println!("  replayed: {}", describe(&reply));
+            if matches.is_present("stop") {
+                break;
+            }
+        }
+    }
+
+    println!("{} requests replayed, {} mismatches", count, mismatches);
+    if mismatches > 0 {
+        exit(1);
+    }
+}
@@ -19,13 +19,14 @@ use embedded::{connect, SerialSignerPort, SignerLoop};
 use vls_frontend::Frontend;
 use vls_proxy::client::UnixClient;
 use vls_proxy::portfront::SignerPortFront;
+use vls_proxy::recorder::session_recorder_from_env;
 use vls_proxy::util::{bitcoind_rpc_url, create_runtime, setup_logging};
 use vls_proxy::*;
 
 mod embedded;
 
 fn run_test(serial_port: String) -> anyhow::Result<()> {
-    let mut serial = connect(serial_port)?;
+    let mut serial = connect(serial_port, None)?;
     let mut id = 0u16;
     let mut sequence = 1;
 
@@ -81,9 +82,10 @@ pub fn main() -> anyhow::Result<()> {
     } else {
         let conn = UnixConnection::new(parent_fd);
         let client = UnixClient::new(conn);
-        let serial = Arc::new(Mutex::new(connect(serial_port)?));
+        let recorder = session_recorder_from_env();
+        let serial = Arc::new(Mutex::new(connect(serial_port, recorder.as_deref())?));
 
-        let signer_port = SerialSignerPort::new(serial.clone());
+        let signer_port = SerialSignerPort::new(serial.clone(), recorder.clone());
         let frontend = Frontend::new(
             Arc::new(SignerPortFront { signer_port: Box::new(signer_port) }),
             Url::parse(&bitcoind_rpc_url()).expect("malformed rpc url"),
@@ -94,7 +96,7 @@ pub fn main() -> anyhow::Result<()> {
             frontend.start();
         });
 
-        let mut signer_loop = SignerLoop::new(client, serial);
+        let mut signer_loop = SignerLoop::new(client, serial, recorder);
         signer_loop.start();
     }
 
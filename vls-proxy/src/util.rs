@@ -5,6 +5,7 @@ use std::io::{BufRead, BufReader};
 use std::str::FromStr;
 use std::{env, fs};
 
+use lightning_signer::util::rate_limit::RateLimits;
 use tokio::runtime::{self, Runtime};
 
 pub fn read_allowlist() -> Vec<String> {
@@ -18,6 +19,25 @@ pub fn read_allowlist() -> Vec<String> {
     }
 }
 
+/// Request rate limits from `VLS_RATE_LIMITS`, formatted as
+/// `node_per_sec,node_burst,channel_per_sec,channel_burst`, if set
+pub fn rate_limits_from_env() -> Result<Option<RateLimits>, String> {
+    let spec = match env::var("VLS_RATE_LIMITS") {
+        Ok(spec) => spec,
+        Err(_) => return Ok(None),
+    };
+    let values = spec
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|e| format!("bad VLS_RATE_LIMITS {}: {}", spec, e))?;
+    match values[..] {
+        [node_per_sec, node_burst, channel_per_sec, channel_burst] =>
+            Ok(Some(RateLimits { node_per_sec, node_burst, channel_per_sec, channel_burst })),
+        _ => Err(format!("VLS_RATE_LIMITS needs four values: {}", spec)),
+    }
+}
+
 pub fn read_integration_test_seed() -> Option<[u8; 32]> {
     let result = fs::read("hsm_secret");
     if let Ok(data) = result {
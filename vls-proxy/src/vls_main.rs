@@ -1,6 +1,7 @@
 //! A single-binary hsmd drop-in replacement for CLN, using the VLS library
 
-use std::thread;
+use std::time::Duration;
+use std::{process, thread};
 
 use clap::{App, AppSettings, Arg};
 use log::{error, info};
@@ -8,9 +9,11 @@ use url::Url;
 
 use connection::UnixConnection;
 use lightning_signer::persist::Persist;
+use lightning_signer::util::rate_limit::StdClock;
 use lightning_signer::Arc;
 use vls_frontend::Frontend;
-use vls_protocol::{msgs, msgs::Message, Error, Result};
+use vls_protocol::msgs::{self, Message, SerBolt};
+use vls_protocol::{Error, Result};
 use vls_protocol_signer::vls_protocol;
 
 use client::{Client, UnixClient};
@@ -20,25 +23,42 @@ use util::{create_runtime, read_allowlist};
 use vls_protocol_signer::handler::{Handler, RootHandler};
 
 mod test;
+use vls_proxy::audit::audit_sink_from_env;
+use vls_proxy::recorder::{session_recorder_from_env, SessionClient, SessionRecorder};
 use vls_proxy::util::{
-    add_hsmd_args, bitcoind_rpc_url, handle_hsmd_version, read_integration_test_seed, setup_logging,
+    add_hsmd_args, bitcoind_rpc_url, handle_hsmd_version, rate_limits_from_env,
+    read_integration_test_seed, setup_logging,
 };
 use vls_proxy::*;
 
-fn signer_loop<C: 'static + Client, H: Handler>(client: C, handler: H) {
+// How long a rate limited request waits before it is tried again
+const RATE_LIMIT_WAIT: Duration = Duration::from_millis(100);
+
+fn signer_loop<C: 'static + Client, H: Handler>(
+    client: C,
+    handler: H,
+    session: Option<SessionClient>,
+    recorder: Option<Arc<SessionRecorder>>,
+) {
     let id = handler.client_id();
     let pid = std::process::id();
     info!("loop {} {}: start", pid, id);
-    match do_signer_loop(client, handler) {
+    match do_signer_loop(client, handler, session, recorder) {
         Ok(()) => info!("loop {} {}: done", pid, id),
         Err(Error::Eof) => info!("loop {} {}: ending", pid, id),
         Err(e) => error!("loop {} {}: error {:?}", pid, id, e),
     }
 }
 
-fn do_signer_loop<C: 'static + Client, H: Handler>(mut client: C, handler: H) -> Result<()> {
+fn do_signer_loop<C: 'static + Client, H: Handler>(
+    mut client: C,
+    handler: H,
+    session: Option<SessionClient>,
+    recorder: Option<Arc<SessionRecorder>>,
+) -> Result<()> {
     loop {
-        let msg = client.read()?;
+        let raw_msg = client.read_raw()?;
+        let msg = msgs::from_vec(raw_msg.clone())?;
         info!("loop {} {}: got {:x?}", std::process::id(), handler.client_id(), msg);
         match msg {
             Message::ClientHsmFd(m) => {
@@ -50,12 +70,19 @@ fn do_signer_loop<C: 'static + Client, H: Handler>(mut client: C, handler: H) ->
                     handler.client_id(),
                     new_client.id()
                 );
+                let new_session = SessionClient { peer_id: m.peer_id.0, dbid: m.dbid };
                 let handler = handler.for_new_client(new_client.id(), m.peer_id, m.dbid);
-                thread::spawn(move || signer_loop(new_client, handler));
+                let recorder = recorder.clone();
+                thread::spawn(move || {
+                    signer_loop(new_client, handler, Some(new_session), recorder)
+                });
             }
             msg => {
-                let reply = handler.handle(msg).expect("handle");
+                let reply = handle_throttled(&handler, msg, &raw_msg)?;
                 let v = reply.as_vec();
+                if let Some(recorder) = recorder.as_ref() {
+                    recorder.record(session, &raw_msg, &v);
+                }
                 client.write_vec(v).unwrap();
                 info!("replied {} {}", std::process::id(), handler.client_id());
             }
@@ -63,6 +90,25 @@ fn do_signer_loop<C: 'static + Client, H: Handler>(mut client: C, handler: H) ->
     }
 }
 
+// hsmd clients have no way to handle an error, so a request over the
+// rate limit waits until it is allowed rather than failing
+fn handle_throttled<H: Handler>(
+    handler: &H,
+    mut msg: Message,
+    raw_msg: &[u8],
+) -> Result<Box<dyn SerBolt>> {
+    loop {
+        match handler.handle(msg) {
+            Err(e) if e.is_rate_limited() => {
+                info!("loop {} {}: rate limited, waiting", process::id(), handler.client_id());
+                thread::sleep(RATE_LIMIT_WAIT);
+                msg = msgs::from_vec(raw_msg.to_vec())?;
+            }
+            reply => return Ok(reply.expect("handle")),
+        }
+    }
+}
+
 pub fn main() {
     setup_logging("hsmd  ", "info");
     let app = App::new("signer")
@@ -81,8 +127,19 @@ pub fn main() {
         let client = UnixClient::new(conn);
         let persister: Arc<dyn Persist> = Arc::new(KVJsonPersister::new("remote_hsmd_vls.kv"));
         let allowlist = read_allowlist();
-        let handler =
+        let mut handler =
             RootHandler::new(client.id(), read_integration_test_seed(), persister, allowlist);
+        if let Some(audit) = audit_sink_from_env() {
+            handler = handler.with_audit(audit);
+        }
+        match rate_limits_from_env() {
+            Ok(Some(limits)) => handler = handler.with_rate_limits(limits, Arc::new(StdClock)),
+            Ok(None) => {}
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
 
         let frontend = Frontend::new(
             Arc::new(SingleFront { node: Arc::clone(&handler.node) }),
@@ -94,6 +151,6 @@ pub fn main() {
             frontend.start();
         });
 
-        signer_loop(client, handler);
+        signer_loop(client, handler, None, session_recorder_from_env());
     }
 }
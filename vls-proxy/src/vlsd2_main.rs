@@ -4,9 +4,11 @@ use lightning_signer::bitcoin::Network;
 use lightning_signer_server::NETWORK_NAMES;
 use util::setup_logging;
 
+pub mod audit;
 pub mod client;
 pub mod connection;
 pub mod grpc;
+pub mod recorder;
 pub mod util;
 
 const DEFAULT_DIR: &str = ".lightning-signer";
@@ -39,10 +41,32 @@ pub fn main() {
                 .possible_values(&NETWORK_NAMES)
                 .default_value(NETWORK_NAMES[0]),
         );
+    #[cfg(feature = "metrics")]
+    let app = app
+        .arg(
+            Arg::new("metrics-port")
+                .about("serve Prometheus metrics on this localhost port")
+                .long("metrics-port")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("metrics-rpc")
+                .about("bitcoind RPC URL, to report how far the chain tracker lags behind")
+                .long("metrics-rpc")
+                .takes_value(true)
+                .value_name("URL"),
+        );
     let matches = app.get_matches();
     let uri_s = matches.value_of("connect").unwrap();
     let uri = uri_s.parse().expect("uri parse");
     let datadir = matches.value_of("datadir").unwrap();
     let network: Network = matches.value_of_t("network").expect("network");
-    start_signer(datadir, uri, network);
+    #[cfg(feature = "metrics")]
+    let metrics = matches.value_of("metrics-port").map(|p| grpc::signer::MetricsConfig {
+        port: p.parse().expect("metrics port"),
+        rpc_url: matches.value_of("metrics-rpc").map(|u| u.parse().expect("malformed rpc url")),
+    });
+    #[cfg(not(feature = "metrics"))]
+    let metrics = None;
+    start_signer(datadir, uri, network, metrics);
 }
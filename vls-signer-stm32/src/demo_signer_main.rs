@@ -10,20 +10,29 @@ use alloc::string::String;
 use alloc::vec::Vec;
 
 use cortex_m_rt::entry;
+use rand_core::RngCore;
+use stm32f4xx_hal::rng::Rng;
 
 #[allow(unused_imports)]
-use log::{debug, info, trace};
+use log::{debug, error, info, trace};
 
-use device::heap_bytes_used;
+use device::{heap_bytes_used, Display, FreeTimer};
+use lightning_signer::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 use lightning_signer::persist::{DummyPersister, Persist};
 use lightning_signer::Arc;
 use vls_protocol::model::PubKey;
 use vls_protocol::msgs::{self, read_serial_request_header, write_serial_response_header, Message};
-use vls_protocol::serde_bolt::WireString;
+use vls_protocol::serde_bolt::{Read, WireString, Write};
 use vls_protocol_signer::handler::{Handler, RootHandler};
 use vls_protocol_signer::lightning_signer;
+use vls_protocol_signer::noise::NoiseStream;
 use vls_protocol_signer::vls_protocol;
 
+#[cfg(feature = "sdio")]
+const NOISE_SECRET_FILE: &str = "noise_secret";
+#[cfg(feature = "sdio")]
+const NOISE_PEER_FILE: &str = "noise_peer";
+
 mod device;
 mod logger;
 #[cfg(feature = "sdio")]
@@ -38,7 +47,7 @@ fn main() -> ! {
     device::init_allocator();
 
     #[allow(unused)]
-    let (mut delay, timer1, timer2, mut serial, mut sdio, mut disp) = device::make_devices();
+    let (mut delay, timer1, timer2, serial, mut sdio, mut disp, mut rng) = device::make_devices();
 
     logger::set_timer(timer1.clone());
 
@@ -50,15 +59,56 @@ fn main() -> ! {
 
         let res = sdio.read_block(0, &mut block);
         info!("sdcard read result {:?}", res);
-
-        sdcard::test(sdio);
     }
 
+    #[cfg(feature = "sdio")]
+    let noise_keys = {
+        let fs = sdcard::open(sdio).expect("open sdcard");
+        sdcard::test(&fs);
+        read_noise_keys(&fs, &mut rng)
+    };
+    #[cfg(not(feature = "sdio"))]
+    let noise_keys = None;
+
     timer::start_tim2_interrupt(timer2);
 
     disp.clear_screen();
     disp.show_text("init");
 
+    // If the noise keys are not provisioned, the serial link is not encrypted
+    match noise_keys {
+        Some((static_key, proxy_key)) => {
+            let secp_ctx = Secp256k1::signing_only();
+            let mut serial = serial;
+            loop {
+                let ephemeral = random_key(&mut rng);
+                match NoiseStream::try_accept(
+                    serial,
+                    &secp_ctx,
+                    &static_key,
+                    &ephemeral,
+                    &proxy_key,
+                ) {
+                    Ok(stream) => {
+                        info!("noise session established with {}", stream.remote_static());
+                        run_signer(stream, timer1, disp)
+                    }
+                    Err((e, inner)) => {
+                        error!("noise handshake failed: {:?}", e);
+                        let mut error_d = format!("{:?}", e);
+                        error_d.truncate(20);
+                        disp.clear_screen();
+                        disp.show_texts(&[String::from("handshake failed"), error_d]);
+                        serial = inner;
+                    }
+                }
+            }
+        }
+        None => run_signer(serial, timer1, disp),
+    }
+}
+
+fn run_signer<S: Read + Write>(mut serial: S, timer1: FreeTimer, mut disp: Display) -> ! {
     let persister: Arc<dyn Persist> = Arc::new(DummyPersister);
     let (sequence, dbid) = read_serial_request_header(&mut serial).expect("read init header");
     assert_eq!(dbid, 0);
@@ -110,6 +160,41 @@ fn main() -> ! {
     }
 }
 
+// The noise keys are provisioned per device, on the SD card.  The device static
+// secret is generated on first boot.  The proxy static key is pinned by writing
+// it, as hex, to the peer file.
+#[cfg(feature = "sdio")]
+fn read_noise_keys(fs: &sdcard::FS, rng: &mut Rng) -> Option<(SecretKey, PublicKey)> {
+    let secp_ctx = Secp256k1::signing_only();
+    let static_key = match sdcard::read_file(fs, NOISE_SECRET_FILE).expect("read noise secret") {
+        Some(data) => SecretKey::from_slice(&data).expect("noise secret"),
+        None => {
+            let key = random_key(rng);
+            sdcard::write_file(fs, NOISE_SECRET_FILE, &key.secret_bytes())
+                .expect("write noise secret");
+            key
+        }
+    };
+    info!("noise static key {}", PublicKey::from_secret_key(&secp_ctx, &static_key));
+    let peer = sdcard::read_file(fs, NOISE_PEER_FILE).expect("read noise peer")?;
+    let hex = core::str::from_utf8(&peer).expect("noise peer").trim();
+    Some((static_key, PublicKey::from_slice(&from_hex(hex)).expect("noise peer")))
+}
+
+fn random_key(rng: &mut Rng) -> SecretKey {
+    let mut bytes = [0u8; 32];
+    rng.fill_bytes(&mut bytes);
+    SecretKey::from_slice(&bytes).expect("random key")
+}
+
+#[cfg(feature = "sdio")]
+fn from_hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("bad hex"))
+        .collect()
+}
+
 fn from_wire_string(s: &WireString) -> String {
     String::from_utf8(s.0.to_vec()).expect("malformed string")
 }
@@ -1,4 +1,5 @@
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::cmp::min;
 use fatfs::{
     DefaultTimeProvider, Dir, File, FileSystem, FsOptions, IoBase, LossyOemCpConverter,
@@ -153,7 +154,7 @@ impl Seek for Card {
     }
 }
 
-type FS = FileSystem<Card, DefaultTimeProvider, LossyOemCpConverter>;
+pub type FS = FileSystem<Card, DefaultTimeProvider, LossyOemCpConverter>;
 
 pub fn open(sdio: Sdio<SdCard>) -> Result<FS, fatfs::Error<()>> {
     let blocks = sdio.card().map(|c| c.block_count()).unwrap();
@@ -176,6 +177,33 @@ pub fn open(sdio: Sdio<SdCard>) -> Result<FS, fatfs::Error<()>> {
     Ok(fs)
 }
 
+/// Read a whole file in the root directory, or None if it does not exist
+pub fn read_file(fs: &FS, name: &str) -> Result<Option<Vec<u8>>, fatfs::Error<()>> {
+    let mut file = match fs.root_dir().open_file(name) {
+        Ok(file) => file,
+        Err(fatfs::Error::NotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[0..n]);
+    }
+    Ok(Some(data))
+}
+
+/// Create or replace a file in the root directory
+pub fn write_file(fs: &FS, name: &str, data: &[u8]) -> Result<(), fatfs::Error<()>> {
+    let mut file = fs.root_dir().create_file(name)?;
+    file.truncate()?;
+    file.write_all(data)?;
+    file.flush()
+}
+
 pub fn copy_dir<TP: TimeProvider, OCC: OemCpConverter>(
     from_dir: Dir<Card, TP, OCC>,
     to_dir: Dir<Card, TP, OCC>,
@@ -251,8 +279,7 @@ pub fn init_sdio(sdio: &mut Sdio<SdCard>, delay: &mut SysDelay) {
 // cp -r /a /b
 // ls /
 // cat /readme*
-pub fn test(sdio: Sdio<SdCard>) {
-    let fs = open(sdio).unwrap();
+pub fn test(fs: &FS) {
     let root_dir = fs.root_dir();
 
     if let Ok(from_dir) = root_dir.open_dir("a") {
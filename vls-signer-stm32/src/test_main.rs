@@ -28,7 +28,7 @@ fn main() -> ! {
     device::init_allocator();
 
     #[allow(unused)]
-    let (mut delay, timer1, timer2, mut serial, mut sdio, mut disp) = device::make_devices();
+    let (mut delay, timer1, timer2, mut serial, mut sdio, mut disp, _rng) = device::make_devices();
 
     let mut counter = 0;
 
@@ -217,6 +217,7 @@ impl JSNode {
             counterparty_selected_contest_delay: s.counterparty_selected_contest_delay,
             counterparty_shutdown_script: None,
             commitment_type: CommitmentType::Legacy,
+            holder_funding_sat: None,
         };
         let _channel =
             self.node.ready_channel(id.0.clone(), None, setup, &vec![]).map_err(from_status)?;